@@ -0,0 +1,7512 @@
+mod options;
+mod persistent;
+
+use options::{Layout, LayoutOptions};
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ToTokens, quote};
+use syn::{
+    Data, DeriveInput, Field, Fields, GenericArgument, GenericParam, Generics, Ident, Item,
+    ItemMod, Lifetime, LifetimeParam, LitStr, PathArguments, Token, Type, parse::ParseStream,
+    parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned,
+};
+
+/// Implement a Struct-of-Arrays or Array-of-Structs collection of a single struct
+///
+/// Example:
+///
+/// ```rust
+/// /// The struct `NodesLayout` is created as a `struct-of-arrays`
+/// #[soaaos::layout("struct-of-arrays")]
+/// // #[layout("aos")] // For Array-of-Structs
+/// struct Node<R> where R: std::fmt::Debug + PartialEq {
+///   name: String,
+///   operation: u8,
+///   arg2: R,
+/// }
+///
+/// #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// enum Fruit {
+///   Apple,
+///   Banana,
+/// }
+///
+/// fn main() {
+///    let mut nodes = NodesLayout::<Fruit>::new();
+///
+///    nodes.add(Node { name: "Node1".to_string(), operation: 1, arg2: Fruit::Apple });
+///    nodes.add(Node { name: "Node2".to_string(), operation: 2, arg2: Fruit::Banana });
+///    assert_eq!(nodes.len(), 2);
+///    assert_eq!(nodes.operation.iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+///    assert_eq!(format!("{nodes:?}"), r#"NodesLayout { name: ["Node1", "Node2"], operation: [1, 2], arg2: [Apple, Banana] }"#);
+/// }
+/// ```
+///
+/// Provides:
+///
+/// * `new()`                          - Create an empty layout, in a `const fn` unless a column is `lazy`, `sparse`, `delta`, `bitmap_index` or `search_index`, or the layout is `hashcons`
+/// * `with_capacity(usize)`             - Initialize the layout with the given size for all `Vec`s
+/// * `add(&mut self, node: Node)`       - Add the node to the layout
+/// * `get_*(&self, id: NodeId)`         - Get `&field` of the node at the given index
+/// * `get_*_mut(&mut self, id: NodeId)` - Get `&mut field` of the node at the given index
+/// * `get_*_range(a..b)`, `get_*_range_mut(a..b)` - Get `&[field]` or `&mut [field]` of the nodes in a range of Ids, checking the range once (struct-of-arrays fields stored in a slice)
+/// * `set_*(&mut self, id: NodeId, val)`  - Overwrite the field of the node at the given index
+/// * `compare_and_set_*(id, &expected, val)` - Overwrite the field only if it holds `expected`, otherwise returning its value
+/// * `add_partial(&mut self, ...)`      - Add a node from only the fields without a `#[soa(default)]`
+/// * `push_with(|node| ...)`            - Add a defaulted node and fill it in place through a `NodeRefMut`
+/// * `get(id)`, `get_mut(id)`           - Get a `NodeRef`/`NodeRefMut` view of the node at the given index
+/// * `Display` for `NodeRef`, `NodeRefMut` - A single line of `field=value` pairs, when every field implements `Display`
+/// * `NodeRef::to_owned()`, `NodeRefMut::replace(node)` - Clone a view into an owned node, or swap every field of the node for the fields of another (`replace` only when no field is frozen or written with `set_*` alone)
+/// * `update(id, |node| ...)`           - Hand the `NodeRefMut` view of a node to a closure, checking the index once
+/// * `filter_map_*(|id, val| ...)`    - Scan a single field with the Id of each node, skipping nodes or stopping early through a `ControlFlow`
+/// * `with_*_mut(|column, rest| ...)` - Borrow a field stored in a slice mutably, and every other column immutably through a `NodesWithout*` view (struct-of-arrays only)
+/// * `neighbors(id)`, `remap_links(&mapping)` - Iterate the Ids in the link fields (`[NodeId; N]` or `[Option<NodeId>; N]`) of a node, or rewrite every link through a `NodesIdMap`
+/// * `id_of_*(&val)`                   - Get the Id of the node holding a reference obtained from a field stored in a slice, by its address (struct-of-arrays only)
+/// * `advise_sequential_*()`, `advise_dontneed_*()` - Hint the OS that a `page_aligned` column is about to be scanned in order, or won't be read soon, through `posix_madvise` (struct-of-arrays only)
+/// * `id_of_min_*()`, `id_of_max_*()`   - Get the Id of the first node holding the extreme value of an `Ord` field
+/// * `join_by_*(&other, |row| key)`  - Pair each node with the rows of another layout whose key equals the field, through a hash join
+/// * `value_counts_*()`, `histogram_*(bins)` - Count the nodes holding each value of a `Hash` field, or in equal-width buckets of a numeric field
+/// * `sample_weighted(rng, k, |node| weight)`, `sample_weighted_by_*(rng, k)` - Draw `k` Ids with replacement, in proportion to a weight or to a numeric field, through an alias table
+/// * `find_row(&node)`, `dedup_rows()` - Find the node equal to `node` in every field, or remove every duplicate node in one compaction, returning a `NodesIdMap` sending each duplicate to the node it duplicated, by row hash
+/// * `stable_id(id)`, `resolve(stable)` - Get a `NodeStableId` addressing a node by the hash of its contents, and its current Id after nodes are removed, reordered or compacted
+/// * `get_many([id; N])`                - Get the `NodeRef` views of several nodes, checking every index first
+/// * `select(&ids)`, `select_into(&ids, &mut selection)` - Copy the given nodes into a `NodesSelection` of a contiguous `Vec` per field, reusing its buffers with `select_into`
+/// * `reverse()`, `rotate_left(usize)`, `rotate_right(usize)` - Reorder all nodes in place, returning a `NodesIdMap` of the moved Ids like `reorder(&order)`, `shuffle(rng)` and every sort
+/// * `remove(id)`                       - Remove a node, shifting every later node down by one, also returning a `NodesIdMap` of the moved Ids
+/// * `swap_remove(id)`, `retain(|node| ...)` - Remove nodes by moving the last node into place, or keep only some nodes, returning a `NodesIdMap` of the moved Ids
+/// * `apply_id_map(&map)`               - Rewrite the fields holding Ids of the layout (`NodeId`, `Option<NodeId>` or arrays of either) through a `NodesIdMap`
+/// * `to_dot(writer, |node| label)`     - Write the graph of the fields holding Ids of the layout (`NodeId`, `Option<NodeId>` or arrays of either) as Graphviz DOT, labeling each node
+/// * `gather(&self, ids: &[NodeId])`    - Copy the given nodes, in order, into a new layout
+/// * `into_columns()`, `from_columns(columns)` - Move every column out into a `NodesColumns` of `Vec`s, or back into a layout (struct-of-arrays only)
+/// * `extend_from_layout(&other, ids)` - Copy the given nodes of another layout, in order, to the end of the layout, returning their new Ids
+/// * `bulk_load(rows, options)`         - Add many rows at once, sorted, deduplicated by key, with room reserved up front and a progress callback, as configured by a `BulkLoadOptions`
+/// * `scatter_*(&mut self, writes)`     - Write `(NodeId, value)` pairs into a single field
+/// * `chunks::<N>()`, `*_chunks::<N>()` - Iterate blocks of `N` nodes as `&[T; N]` arrays, then `chunks_remainder::<N>()` (struct-of-arrays only)
+/// * `*_simd_chunks::<N>()`, `*_simd_chunks_mut::<N>(f)` - `Simd<T, N>` views of each `Vec` column (requires the nightly `portable_simd` feature)
+/// * `iter_cloned()`, `to_rows()`       - Get owned copies of every node
+/// * `cursor(&mut self)`                - Walk the layout with `peek`, `peek_mut`, `advance` and `seek`
+/// * `try_from_iter(iter)`              - Build a layout from `Result<Node, E>`s, stopping at the first error
+/// * `NodeBuilder::new().field(val)`    - Chain per-field setters, then `build()` or `build_into(&mut layout)`
+/// * `get_field_dyn(name, id)`          - Get a field by name as `&dyn Any`
+/// * `columns_dyn()`                    - Iterate type-erased handles to each column
+/// * `transaction(|tx| ...)`            - Buffer adds, sets and removes on a `NodesTransaction`, committed together only if they are all valid
+/// * `query().op_eq(3).arg1_gt(10)`     - Match nodes with per-field predicates (`_eq`, `_ne`, `_lt`, `_le`, `_gt`, `_ge`), most selective first
+/// * `profile_scan()`                   - Measure the bytes/sec of scanning each column and every row on the current machine
+/// * `descriptor()`, `C_HEADER`       - A `#[repr(C)]` `NodesDescriptor` of the columns (or rows and field offsets), and the C header defining it
+/// * `SCHEMA_HASH`                      - Hash of the field names and types, checked when deserializing
+/// * `derived::{Table, TableRows, RowHash}` impls - Used by `soaaos::derived::Derived` to keep a derived layout up to date
+/// * `RowAccess` impl                   - `row(id)`, `row_mut(id)` and `rows()` returning the `NodeRef`/`NodeRefMut` views, for algorithms generic over any layout
+/// * `ShardedNodesLayout`               - A `soaaos::sharded::Sharded` layout, appended to by many threads through per-thread shards flushed in batches
+/// * `GhostNodesLayout<'id>`            - A `soaaos::ghost::GhostLayout`, shared by reference and written through the one `GhostToken` of its brand, coordinating the writes to interlinked layouts
+/// * `StaticNodesLayout`               - A read-only layout borrowing its columns (or nodes) from slices, built from literal rows in a `static` by `soaaos::static_layout!`, with getters, iterators and `to_layout()` (not with `brand`)
+/// * `stream()`, `from_stream(stream)`  - Async `Stream` adaptors (requires the `futures` feature)
+/// * `diff_json_patch(&other)`          - Get the changes to `other` as a JSON Patch (RFC 6902) of the serialized layout (requires the `json` feature)
+/// * `write_chunks(writer, rows)`, `append_chunk(&mut reader)` - Save the layout as lines of `rows` serialized nodes, or load it back one chunk at a time (requires the `json` feature)
+/// * `PROTO_SCHEMA`, `to_proto()`, `from_proto(bytes)` - A protobuf message with a repeated field per field, and the conversions of the layout from and to it, when every field is a primitive, `String` or the Id of the layout (requires the `prost` feature). With the `rayon` feature, `par_from_proto(bytes)` decodes each field on its own task
+/// * `to_sqlite(&conn, "table")`, `from_sqlite(&conn, "table")` - Dump the layout to a new table with a column per field in a single transaction, or load it back (requires the `sqlite` feature)
+/// * `diff_pretty(&other, context)`     - Render the diff of two layouts by row with ANSI colors and `context` unchanged rows (requires the `color` feature)
+/// * `fuzz(data)`                      - Apply a sequence of adds, gets, removes, sorts and diffs decoded with `arbitrary` from a fuzzer's bytes to the layout and to a `Vec` of its rows, panicking where they disagree (requires the `fuzz` feature, and the struct to derive `Arbitrary`)
+///
+/// Field options, given with `#[soa(...)]` on a field:
+///
+/// * `default`, `default = expr`        - Value used when the field isn't given to a builder, `add_partial` or `push_with`
+/// * `lazy`                             - Store the column as a `LazyColumn`, only allocated once a value other than the `#[soa(default = expr)]` of the field (or `Default::default()`) is written
+/// * `sparse`                           - Store the column as a `SparseColumn`, only keeping the non-default values
+/// * `rle`                              - Store the column as an `RleColumn` of runs, written with `set_*` and with a `*_runs()` iterator
+/// * `delta`                            - Store an integer column as a `DeltaColumn` of varint deltas, read by value and written with `set_*`
+/// * `segmented`                        - Store the column as a `SegmentedColumn` of fixed-size segments that are never reallocated
+/// * `page_aligned`                     - Store the column as a `PageAlignedColumn`, starting on a page boundary and padded to whole pages for zero-copy export
+/// * `spill`                            - Store the column as a `SpillColumn`, writing full segments to a temporary file, read by value and written with `set_*`
+/// * `bitmap_index`                     - Store a low-cardinality column as a `BitmapColumn` with a roaring bitmap per value, so `query()` equality predicates intersect bitmaps; adds `*_bitmap(&val)` (requires the `roaring` feature)
+/// * `btree_index`                      - Store the column as a `BTreeColumn` keeping a sorted index per value, written with `set_*`; adds `range_by_*(range)` returning the matching Ids ordered by value
+/// * `bloom`                            - Store the column as a `BloomColumn` with a bloom filter over its values, written with `set_*`; adds `maybe_contains_*(&val)` rejecting most missing values in O(1), and `contains_*(&val)` scanning only when the filter can't
+/// * `search_index`                     - Store a text column as a `SearchColumn` with an inverted index of its terms, written with `set_*`; adds `search_*("terms")` returning the Ids of the nodes containing every term (requires the `search` feature)
+/// * `atomic`                           - Store an integer column as an `AtomicColumn`, bumped through `&self` with `fetch_add_*`, `load_*_acquire`, `store_*_release` and `get_*_atomic` (struct-of-arrays only)
+/// * `cell`                             - Store a `Copy` column as a `CellColumn`, written through `&self` with `set_*` (struct-of-arrays only, not with `watch`, `hashcons`, `versioned` or `epochs`)
+/// * `zstd`                             - Store a rarely read column as a zstd compressed `ZstdColumn`, read by value and written with `set_*` (requires the `zstd` feature)
+/// * `group = "name"`                    - Store the field with the other fields of the group `name`, in a single `name` column of a generated tuple struct (e.g. `NodeName(x, y)`), for fields that are always read together (struct-of-arrays only, on non-generic structs)
+/// * `references`                       - The field holds the Id (or `Option` of the Id) of a node in another layout: generates `remove_cascade_*(removed)` to remove (or set to `None`) the nodes referencing a removed node
+/// * `frozen`                           - The value is only written when the node is added: no `get_*_mut`, `set_*`, `scatter_*` or other writer is generated, and the field is left out of the `NodeRefMut` view (not with `atomic`, `cell` or `references`)
+/// * `unique`                           - No two nodes may hold the same value, checked when a `transaction()` is committed
+/// * `key`                              - Look nodes up by the field through an index of its values, in the order they were added: `get_index_of(&key)`, `get_index(usize)` and `move_index(from, to)`, like an `IndexMap` (not with `atomic` or `cell`)
+///
+/// Layout options, given after the layout with `#[layout("soa", ...)]`:
+///
+/// * `segmented`                        - Segment every column, so references from getters stay valid across `add` (implements `StableAddresses`)
+/// * `page_aligned`                     - Store every column as a page aligned `PageAlignedColumn`
+/// * `id = Name`                        - Use the existing Id type `Name` instead of generating one
+/// * `brand`                            - Add a marker type parameter to the layout and its Id (`NodesLayout<Brand>`, `NodeId<Brand>`), so Ids can't be used with a layout of another brand. `NodesLayout::scope(|layout| ...)` brands a new layout with a lifetime unique to the closure
+/// * `watch`                            - Keep the subscribers of the layout, with `subscribe()` returning a receiver of an `Event` for every add, write, removal and reorder (requires the `crossbeam` feature)
+/// * `zerocopy`                         - Give the struct a `#[repr(C)]` and derive the `zerocopy` byte conversion traits for it, when every field type qualifies (requires the `zerocopy` feature, which derives them for generated Ids)
+/// * `flatbuffers`                      - Generate `FLATBUFFERS_SCHEMA`, a FlatBuffers table with a vector per field, with `to_flatbuffer()` and `from_flatbuffer(bytes)` converting the layout from and to it, and `par_from_flatbuffer(bytes)` reading each vector on its own task with the `rayon` feature. Fields must be primitives, `String` or the Id of the layout.
+/// * `hashcons`                         - Keep an index of the rows by the hash of their contents, with `add_unique(node)` returning the Id of an equal node instead of adding a duplicate
+/// * `versioned`                        - Keep the version of each row, bumped by every add, write, removal and reorder, with `version(id)`, `current_version()` and `changed_since(version)` returning the Ids of the rows changed since
+/// * `epochs`                           - Keep the epoch each row was last added or written in, in a `u32` per row, with `advance_epoch()` starting the next epoch (e.g. once per frame), `epoch(id)` and `changed_since_epoch(epoch)`
+/// * `ring = N`                         - Hold at most `N` nodes as a circular buffer: adding a node to a full layout overwrites the oldest node in its slot, handing it to the callback set with `on_evict(|node| ...)`, so the Ids of the other nodes stay valid until they are evicted. `iter_ids_by_age()` walks the nodes from the oldest, and reordering the nodes makes their new order the order of eviction. Building the layout from more nodes (`from_columns`, `default_rows`, `gather`, deserializing) only keeps the newest `N`
+/// * `extract(Name = (field, ...))`    - Generate the struct `Name` of only these fields, deriving the same traits, with a layout of the same kind that `extract::<Name>()` copies their columns into, for handing a slim copy to code that only needs those fields. Repeatable, not on generic structs.
+/// * `derives(Trait, ...)`              - Derive exactly these traits on the layout, instead of implementing each of `Debug, Clone, PartialEq, Eq, Hash` that every column implements, and `PartialOrd, Ord` comparing the rows in order when every field type implements them. The row views are only `Debug` if it is listed.
+///
+/// `#[layout("persistent")]` generates an immutable struct-of-arrays layout whose columns are
+/// `PersistentVec`s: `add(node)` returns a new layout with the Id of the node, and `set_*(id, val)`
+/// returns a new layout, both sharing every unchanged part of the columns with the old layout. It
+/// only supports the `id` option, and the `default` field option.
+///
+/// The methods and trait impls needing more of the field types than storing them (`Clone`, `Hash`,
+/// `Ord`, `Serialize`, ...) are always generated, with the bound on the field types in their
+/// `where` clause. A field type that doesn't meet the bound doesn't fail to compile: the method
+/// or impl is silently missing, and calling it fails with an unsatisfied bound at the call site.
+///
+/// `#[layout]` can also be given to a module, generating a layout for every struct in it. A struct
+/// may give its own `#[layout(...)]` to override the module's options. On a module:
+///
+/// * `id = Name`                        - Generate a single Id type `Name` used by every layout in the module
+/// * `error = Name`                     - Generate an error enum `Name` wrapping (and `From` every) layout error
+///
+#[proc_macro_attribute]
+pub fn layout(attr: TokenStream, item: TokenStream) -> TokenStream {
+    // Parse the type of layout and any layout options
+    let layout_options = parse_macro_input!(attr as LayoutOptions);
+
+    // A module generates a layout for each struct inside it, anything else must be a struct
+    let output = match parse_macro_input!(item as Item) {
+        Item::Mod(item_mod) => expand_module(&layout_options, item_mod),
+        item => match syn::parse2::<DeriveInput>(item.into_token_stream()) {
+            Ok(input) => expand_struct(&layout_options, input),
+            Err(err) => err.to_compile_error(),
+        },
+    };
+
+    output.into()
+}
+
+/// Create an empty struct-of-arrays layout of an anonymous struct with the given fields
+///
+/// Avoids declaring a named struct for quick experiments and tests. Since the row struct can't be
+/// named, nodes are added with `add_partial`, which takes every field without a `#[soa(default)]`
+/// in order. The row struct only derives `Debug` and `Clone`, so fields such as floats that
+/// aren't `Eq` or `Hash` can be given.
+///
+/// Example:
+///
+/// ```rust
+/// let mut scores = soaaos::soa! { name: String, score: u32 };
+///
+/// let id = scores.add_partial("alice".to_string(), 10);
+/// scores.add_partial("bob".to_string(), 7);
+///
+/// assert_eq!(scores.get_score(id).unwrap(), &10);
+/// assert_eq!(scores.score().sum::<u32>(), 17);
+/// ```
+#[proc_macro]
+pub fn soa(item: TokenStream) -> TokenStream {
+    let fields = parse_macro_input!(item with parse_named_fields);
+
+    quote! {
+        {
+            #[::soaaos::layout("soa")]
+            #[allow(dead_code)]
+            #[derive(Debug, Clone)]
+            struct Row {
+                #fields
+            }
+
+            RowsLayout::new()
+        }
+    }
+    .into()
+}
+
+/// Build the read-only layout of literal rows of a struct with `#[layout]`, in a `static` or a
+/// `const`, so lookup tables live in read-only memory
+///
+/// The rows are struct literals giving the same fields, and build a `StaticNodesLayout` of a
+/// struct `Node`: slices of every field for a struct-of-arrays layout, or a slice of the rows for
+/// an array-of-structs layout. `to_layout()` copies it into a `NodesLayout`.
+///
+/// Example:
+///
+/// ```rust
+/// #[soaaos::layout("soa")]
+/// #[derive(Debug, Clone, PartialEq)]
+/// struct Opcode {
+///     name: &'static str,
+///     operands: u8,
+/// }
+///
+/// static OPCODES: StaticOpcodesLayout = soaaos::static_layout![
+///     Opcode { name: "nop", operands: 0 },
+///     Opcode { name: "add", operands: 2 },
+/// ];
+///
+/// assert_eq!(OPCODES.len(), 2);
+/// assert_eq!(OPCODES.operands, &[0, 2]);
+/// assert_eq!(OPCODES.get_name(OpcodeId(1)).unwrap(), &"add");
+/// ```
+#[proc_macro]
+pub fn static_layout(item: TokenStream) -> TokenStream {
+    let rows =
+        parse_macro_input!(item with Punctuated::<syn::ExprStruct, Token![,]>::parse_terminated);
+    match expand_static_layout(rows) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Split the literal rows given to `static_layout!` into a slice of each field. The generated
+/// `StaticNodesLayout` is built from the form of its layout, so only the slices or only the rows
+/// are evaluated in a `static`.
+fn expand_static_layout(rows: Punctuated<syn::ExprStruct, Token![,]>) -> syn::Result<TokenStream2> {
+    let Some(first) = rows.first() else {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "`static_layout!` needs at least one row",
+        ));
+    };
+
+    let mut fields = Vec::new();
+    for field in &first.fields {
+        match &field.member {
+            syn::Member::Named(ident) => fields.push(ident.clone()),
+            syn::Member::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "Only structs with named fields are supported for #[layout]",
+                ));
+            }
+        }
+    }
+
+    let mut columns = vec![Vec::new(); fields.len()];
+    for row in &rows {
+        if let Some(rest) = &row.rest {
+            return Err(syn::Error::new_spanned(
+                rest,
+                "every field of a `static_layout!` row must be given",
+            ));
+        }
+        if row.fields.len() != fields.len() {
+            return Err(syn::Error::new_spanned(
+                row,
+                "every row of `static_layout!` must give the same fields",
+            ));
+        }
+        for (field, column) in fields.iter().zip(&mut columns) {
+            let value = row
+                .fields
+                .iter()
+                .find(|value| matches!(&value.member, syn::Member::Named(ident) if ident == field))
+                .ok_or_else(|| {
+                    syn::Error::new_spanned(row, format!("missing field `{field}` in this row"))
+                })?;
+            column.push(&value.expr);
+        }
+    }
+
+    // The generated types live next to the struct: `a::Node` => `a::StaticNodesLayout`
+    let static_path = |format: &str| {
+        let mut path = first.path.clone();
+        let last = path.segments.last_mut().expect("Paths have a segment");
+        last.ident = Ident::new(
+            &format.replace("{}", &last.ident.to_string()),
+            last.ident.span(),
+        );
+        last.arguments = PathArguments::None;
+        path
+    };
+    let static_layout_path = static_path("Static{}sLayout");
+    let static_columns_path = static_path("Static{}sColumns");
+    let rows = rows.iter();
+
+    Ok(quote! {
+        if #static_layout_path::__IS_SOA {
+            #static_layout_path::__from_columns(#static_columns_path {
+                #(
+                    #fields: &[#(#columns),*],
+                )*
+            })
+        } else {
+            #static_layout_path::__from_rows(&[#(#rows),*])
+        }
+    })
+}
+
+/// Parse comma separated named fields, as inside the braces of a struct
+fn parse_named_fields(input: ParseStream) -> syn::Result<Punctuated<Field, Token![,]>> {
+    Punctuated::parse_terminated_with(input, Field::parse_named)
+}
+
+/// The C type of a field of the given type, for the primitive types with a C equivalent
+fn c_type(ty: &Type) -> Option<&'static str> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let ident = path.path.get_ident()?;
+    Some(match ident.to_string().as_str() {
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" => "uint64_t",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" => "int64_t",
+        "usize" => "size_t",
+        "isize" => "ptrdiff_t",
+        "f32" => "float",
+        "f64" => "double",
+        "bool" => "bool",
+        _ => return None,
+    })
+}
+
+/// The FlatBuffers type of the elements of a field of the given type, for the primitive types and
+/// strings
+fn flatbuffers_type(ty: &Type) -> Option<&'static str> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let ident = path.path.get_ident()?;
+    Some(match ident.to_string().as_str() {
+        "u8" => "ubyte",
+        "u16" => "ushort",
+        "u32" => "uint",
+        "u64" => "ulong",
+        "i8" => "byte",
+        "i16" => "short",
+        "i32" => "int",
+        "i64" => "long",
+        "f32" => "float",
+        "f64" => "double",
+        "bool" => "bool",
+        "String" => "string",
+        _ => return None,
+    })
+}
+
+/// The protobuf type of the elements of a field of the given type, for the primitive types and
+/// strings. Signed integers are zigzag encoded.
+fn proto_type(ty: &Type) -> Option<&'static str> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let ident = path.path.get_ident()?;
+    Some(match ident.to_string().as_str() {
+        "u8" | "u16" | "u32" => "uint32",
+        "u64" => "uint64",
+        "i8" | "i16" | "i32" => "sint32",
+        "i64" => "sint64",
+        "f32" => "float",
+        "f64" => "double",
+        "bool" => "bool",
+        "String" => "string",
+        _ => return None,
+    })
+}
+
+/// The field name in upper camel case, for the types generated per field (`arg_list` => `ArgList`)
+fn camel_case(ident: &Ident) -> String {
+    ident
+        .to_string()
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// The `T` of a field of type `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
+
+/// Generate the layouts of every struct in a `#[layout] mod`, along with the shared Id and error
+/// types given in the module's options.
+///
+/// A struct in the module may give its own `#[layout(...)]` to override the module's options.
+fn expand_module(module_options: &LayoutOptions, mut item_mod: ItemMod) -> TokenStream2 {
+    let Some((_, items)) = item_mod.content.take() else {
+        return syn::Error::new_spanned(
+            &item_mod,
+            "#[layout] can only be applied to modules with a body",
+        )
+        .to_compile_error();
+    };
+
+    let mut expanded = Vec::new();
+    let mut struct_idents = Vec::new();
+
+    for item in items {
+        let Item::Struct(mut item_struct) = item else {
+            expanded.push(item.into_token_stream());
+            continue;
+        };
+
+        // Take the struct's own `#[layout(...)]`, if any
+        let mut struct_options = None;
+        let mut error = None;
+        item_struct.attrs.retain(|attr| {
+            if !attr.path().is_ident("layout") {
+                return true;
+            }
+
+            match attr.parse_args::<LayoutOptions>() {
+                Ok(options) => struct_options = Some(options),
+                Err(err) => error = Some(err),
+            }
+            false
+        });
+        if let Some(err) = error {
+            return err.to_compile_error();
+        }
+
+        // The shared types always come from the module
+        let mut options = struct_options.unwrap_or_else(|| module_options.clone());
+        options.id.clone_from(&module_options.id);
+        options.error = None;
+
+        let input = match syn::parse2::<DeriveInput>(item_struct.into_token_stream()) {
+            Ok(input) => input,
+            Err(err) => return err.to_compile_error(),
+        };
+
+        struct_idents.push(input.ident.clone());
+        expanded.push(expand_struct(&options, input));
+    }
+
+    // The Id type shared by every layout in the module
+    if let Some(id_ident) = &module_options.id {
+        expanded.push(id_tokens(id_ident, None));
+    }
+
+    // The error type wrapping the errors of every layout in the module
+    if let Some(shared_error) = &module_options.error {
+        let error_idents: Vec<Ident> = struct_idents
+            .iter()
+            .map(|ident| Ident::new(&format!("{}sError", ident), ident.span()))
+            .collect();
+
+        expanded.push(quote! {
+            /// An error from any of the layouts in this module
+            #[derive(Debug)]
+            pub enum #shared_error {
+                #(
+                    #struct_idents(#error_idents),
+                )*
+            }
+
+            impl core::fmt::Display for #shared_error {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    match self {
+                        #(
+                            #shared_error::#struct_idents(err) => write!(f, "{}: {}", stringify!(#struct_idents), err),
+                        )*
+                    }
+                }
+            }
+
+            impl core::error::Error for #shared_error {
+                fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+                    match self {
+                        #(
+                            #shared_error::#struct_idents(err) => Some(err),
+                        )*
+                    }
+                }
+            }
+
+            #(
+                impl From<#error_idents> for #shared_error {
+                    fn from(err: #error_idents) -> Self {
+                        #shared_error::#struct_idents(err)
+                    }
+                }
+            )*
+        });
+    }
+
+    let attrs = &item_mod.attrs;
+    let vis = &item_mod.vis;
+    let mod_token = &item_mod.mod_token;
+    let ident = &item_mod.ident;
+
+    quote! {
+        #(#attrs)*
+        #vis #mod_token #ident {
+            #(#expanded)*
+        }
+    }
+}
+
+/// The Id type of a layout: an index into the layout. With a `brand`, the Id takes the brand of
+/// its layout as a marker type parameter.
+fn id_tokens(id_ident: &Ident, brand: Option<&Ident>) -> TokenStream2 {
+    // The generics of a branded Id (`<__Brand>`), used both on the type and its impls
+    let impl_generics = match brand {
+        Some(brand) => quote! { <#brand> },
+        None => quote! {},
+    };
+    let id_ty = quote! { #id_ident #impl_generics };
+
+    // Constructing an Id from its index
+    let id_new = match brand {
+        Some(_) => quote! { #id_ident::new },
+        None => quote! { #id_ident },
+    };
+
+    // With the `serde` feature, Ids are (de)serialized as a plain u32
+    let serde = if cfg!(feature = "serde") {
+        quote! {
+            impl #impl_generics ::soaaos::__private::serde::Serialize for #id_ty {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::soaaos::__private::serde::Serializer,
+                {
+                    serializer.serialize_u32(self.0)
+                }
+            }
+
+            impl<'de, #brand> ::soaaos::__private::serde::Deserialize<'de> for #id_ty {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::soaaos::__private::serde::Deserializer<'de>,
+                {
+                    <u32 as ::soaaos::__private::serde::Deserialize>::deserialize(deserializer).map(#id_new)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // With the `sqlite` feature, Ids are stored in an INTEGER column
+    let sqlite = if cfg!(feature = "sqlite") {
+        quote! {
+            impl #impl_generics ::soaaos::sqlite::Column for #id_ty {
+                const SQL_TYPE: &'static str = "INTEGER";
+
+                fn to_sql(&self) -> Result<::soaaos::sqlite::ToSqlOutput<'_>, ::soaaos::sqlite::Error> {
+                    ::soaaos::sqlite::Column::to_sql(&self.0)
+                }
+
+                fn from_sql(value: ::soaaos::sqlite::ValueRef<'_>) -> ::soaaos::sqlite::FromSqlResult<Self> {
+                    <u32 as ::soaaos::sqlite::Column>::from_sql(value).map(#id_new)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // With the `schemars` feature, the schema of an Id is the schema of a u32
+    let schemars = if cfg!(feature = "schemars") {
+        quote! {
+            impl #impl_generics ::soaaos::__private::schemars::JsonSchema for #id_ty {
+                fn inline_schema() -> bool {
+                    true
+                }
+
+                fn schema_name() -> std::borrow::Cow<'static, str> {
+                    stringify!(#id_ident).into()
+                }
+
+                fn json_schema(generator: &mut ::soaaos::__private::schemars::SchemaGenerator) -> ::soaaos::__private::schemars::Schema {
+                    <u32 as ::soaaos::__private::schemars::JsonSchema>::json_schema(generator)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // With the `zerocopy` feature, Ids can be read from and written to raw bytes
+    let zerocopy = if cfg!(feature = "zerocopy") {
+        quote! {
+            #[derive(
+                ::soaaos::__private::zerocopy::FromBytes,
+                ::soaaos::__private::zerocopy::IntoBytes,
+                ::soaaos::__private::zerocopy::KnownLayout,
+                ::soaaos::__private::zerocopy::Immutable,
+            )]
+            #[zerocopy(crate = "::soaaos::__private::zerocopy")]
+        }
+    } else {
+        quote! {}
+    };
+
+    // A branded Id implements the usual traits by hand, since deriving them would require the
+    // brand to implement them too
+    let definition = match brand {
+        Some(brand) => quote! {
+            /// The index into the `nodes` vec, only usable with layouts of the same brand
+            #[allow(dead_code)]
+            #[repr(transparent)]
+            #zerocopy
+            pub struct #id_ident<#brand = ()>(pub u32, core::marker::PhantomData<fn() -> #brand>);
+
+            impl<#brand> #id_ty {
+                /// Create the Id of the node at the given index
+                #[must_use]
+                pub const fn new(index: u32) -> Self {
+                    #id_ident(index, core::marker::PhantomData)
+                }
+            }
+
+            impl<#brand> core::fmt::Debug for #id_ty {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    f.debug_tuple(stringify!(#id_ident)).field(&self.0).finish()
+                }
+            }
+
+            impl<#brand> Clone for #id_ty {
+                fn clone(&self) -> Self {
+                    *self
+                }
+            }
+
+            impl<#brand> Copy for #id_ty {}
+
+            impl<#brand> PartialEq for #id_ty {
+                fn eq(&self, other: &Self) -> bool {
+                    self.0 == other.0
+                }
+            }
+
+            impl<#brand> Eq for #id_ty {}
+
+            impl<#brand> PartialOrd for #id_ty {
+                fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+                    Some(self.cmp(other))
+                }
+            }
+
+            impl<#brand> Ord for #id_ty {
+                fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+                    self.0.cmp(&other.0)
+                }
+            }
+
+            impl<#brand> core::hash::Hash for #id_ty {
+                fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                    self.0.hash(state);
+                }
+            }
+        },
+        None => quote! {
+            /// The index into the `nodes` vec
+            #[allow(dead_code)]
+            #[repr(transparent)]
+            #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+            #zerocopy
+            pub struct #id_ident(pub u32);
+        },
+    };
+
+    quote! {
+        #serde
+
+        #sqlite
+
+        #schemars
+
+        #definition
+        const _: () = assert!(size_of::<#id_ident>() == 4);
+        const _: () = assert!(size_of::<Option<#id_ident>>() == 8);
+        const _: () = assert!(size_of::<&#id_ident>() == 8);
+        impl #impl_generics #id_ty {
+            #[must_use]
+            pub fn null() -> Self {
+                #id_new(0)
+            }
+        }
+
+        impl #impl_generics std::convert::TryFrom<usize> for #id_ty {
+            type Error = std::num::TryFromIntError;
+
+            fn try_from(val: usize) -> std::result::Result<Self, Self::Error> {
+                Ok(#id_new(u32::try_from(val)?))
+            }
+        }
+    }
+}
+
+/// Generate the layout of a single struct
+fn expand_struct(layout_options: &LayoutOptions, mut input: DeriveInput) -> TokenStream2 {
+    let layout = layout_options.layout;
+
+    if let Some(error) = &layout_options.error {
+        return syn::Error::new_spanned(error, "`error` is only supported on modules")
+            .to_compile_error();
+    }
+
+    // Persistent layouts share none of the generated API of the mutable layouts
+    if layout_options.persistent {
+        return persistent::expand_persistent(layout_options, input);
+    }
+
+    // Parse (and remove) the `#[soa(...)]` options on each field.
+    let field_options = match options::take_field_options(&mut input, layout_options) {
+        Ok(field_options) => field_options,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    // The projections generated by `extract(...)` derive the same traits as the struct, without
+    // the derives added below for `zerocopy`
+    let source_derives: Vec<_> = input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .cloned()
+        .collect();
+
+    // With `zerocopy`, the struct is given a C layout and can be read from and written to raw
+    // bytes, which fails to compile if a field type doesn't qualify
+    if layout_options.zerocopy {
+        if !input.attrs.iter().any(|attr| attr.path().is_ident("repr")) {
+            input.attrs.push(parse_quote!(#[repr(C)]));
+        }
+        input.attrs.push(parse_quote! {
+            #[derive(
+                ::soaaos::__private::zerocopy::FromBytes,
+                ::soaaos::__private::zerocopy::IntoBytes,
+                ::soaaos::__private::zerocopy::KnownLayout,
+                ::soaaos::__private::zerocopy::Immutable,
+            )]
+        });
+        input
+            .attrs
+            .push(parse_quote!(#[zerocopy(crate = "::soaaos::__private::zerocopy")]));
+    }
+
+    let generics = input.generics.clone();
+
+    let (impl_generics, _ty_generics, where_clause) = generics.split_for_impl();
+
+    let struct_ident = input.ident.clone();
+    let struct_ident_ref = Ident::new(&format!("{}Ref", struct_ident), struct_ident.span());
+    let struct_ident_ref_mut = Ident::new(&format!("{}RefMut", struct_ident), struct_ident.span());
+
+    // Create the identifiers to be created
+    macro_rules! new_ident {
+        ($post:literal) => {
+            Ident::new(&format!($post, struct_ident), struct_ident.span())
+        };
+    }
+    let layout_struct_ident = new_ident!("{}sLayout");
+    let sharded_ident = new_ident!("Sharded{}sLayout");
+    let ghost_ident = new_ident!("Ghost{}sLayout");
+    let layout_iter_ident = new_ident!("{}sIter");
+    let layout_cursor_ident = new_ident!("{}sCursor");
+    let chunk_ident = new_ident!("{}sChunk");
+    let descriptor_ident = new_ident!("{}sDescriptor");
+    let column_dyn_ident = new_ident!("{}sColumnDyn");
+    let query_ident = new_ident!("{}sQuery");
+    let transaction_ident = new_ident!("{}sTransaction");
+    let transaction_error_ident = new_ident!("{}sTransactionError");
+    let migration_ident = new_ident!("{}sMigration");
+    let builder_ident = new_ident!("{}Builder");
+    let visitor_ident = new_ident!("{}sLayoutVisitor");
+    let error_ident = new_ident!("{}sError");
+    let columns_ident = new_ident!("{}sColumns");
+    let id_ident = new_ident!("{}Id");
+    let id_map_ident = new_ident!("{}sIdMap");
+    let stable_id_ident = new_ident!("{}StableId");
+    let selection_ident = new_ident!("{}sSelection");
+
+    // Only support structs with named fields.
+    let fields = if let Data::Struct(data) = &input.data {
+        if let Fields::Named(fields_named) = &data.fields {
+            fields_named.named.iter().collect::<Vec<_>>()
+        } else {
+            return syn::Error::new_spanned(
+                struct_ident,
+                "Only structs with named fields are supported for #[layout]",
+            )
+            .to_compile_error();
+        }
+    } else {
+        return syn::Error::new_spanned(struct_ident, "#[layout] can only be applied to structs")
+            .to_compile_error();
+    };
+
+    // The `#[soa(group = "...")]` fields of a struct-of-arrays layout are stored together in a
+    // single column named after the group, of a generated tuple struct (e.g. `NodePos`), with the
+    // position of each field in it. Array-of-structs layouts already store the fields of a node
+    // together.
+    let mut groups: Vec<(Ident, Ident, Vec<usize>)> = Vec::new();
+    let mut grouped: Vec<Option<(usize, syn::Index)>> = vec![None; fields.len()];
+    if layout == Layout::StructOfArrays {
+        for (index, options) in field_options.iter().enumerate() {
+            let Some(group) = &options.group else {
+                continue;
+            };
+            if fields
+                .iter()
+                .any(|field| field.ident.as_ref() == Some(group))
+            {
+                return syn::Error::new_spanned(group, "a group can't be named after a field")
+                    .to_compile_error();
+            }
+            if !generics.params.is_empty() {
+                return syn::Error::new_spanned(
+                    group,
+                    "`group` isn't supported on generic structs",
+                )
+                .to_compile_error();
+            }
+
+            let position = match groups.iter().position(|(name, ..)| name == group) {
+                Some(position) => position,
+                None => {
+                    let group_struct = Ident::new(
+                        &format!("{}{}", struct_ident, camel_case(group)),
+                        group.span(),
+                    );
+                    groups.push((group.clone(), group_struct, Vec::new()));
+                    groups.len() - 1
+                }
+            };
+            let members = &mut groups[position].2;
+            grouped[index] = Some((position, syn::Index::from(members.len())));
+            members.push(index);
+        }
+    }
+
+    // With `brand`, the layout, its views holding the layout and its Id take a trailing marker
+    // type parameter. The layout defaults it to `()` so that unbranded uses still name the type
+    // without it.
+    let brand = layout_options
+        .brand
+        .then(|| Ident::new("__Brand", struct_ident.span()));
+    if let (Some(_), Some(shared)) = (&brand, &layout_options.id) {
+        return syn::Error::new_spanned(shared, "`brand` can't be used with a shared `id`")
+            .to_compile_error();
+    }
+
+    let mut branded_generics = generics.clone();
+    let mut layout_def_generics = generics.clone();
+    if let Some(brand) = &brand {
+        branded_generics.params.push(parse_quote!(#brand));
+        layout_def_generics.params.push(parse_quote!(#brand = ()));
+    }
+    let (layout_impl_generics, layout_ty_generics, _) = branded_generics.split_for_impl();
+    let layout_ty = quote! { #layout_struct_ident #layout_ty_generics };
+
+    // The branded types also need the brand in their generics
+    let (id_ty, id_new, brand_generics) = match &brand {
+        Some(brand) => (
+            quote! { #id_ident<#brand> },
+            quote! { #id_ident::new },
+            quote! { <#brand> },
+        ),
+        None => (quote! { #id_ident }, quote! { #id_ident }, quote! {}),
+    };
+
+    // With the `stats` feature, the growth of the columns with a capacity (the fields stored in a
+    // `Vec` or a `PageAlignedColumn`, or the rows of an array-of-structs layout) is recorded
+    let tracked_columns: Vec<Ident> = match layout {
+        Layout::StructOfArrays => fields
+            .iter()
+            .zip(&field_options)
+            .filter(|(_, options)| {
+                matches!(
+                    options.storage,
+                    options::Storage::Vec | options::Storage::PageAligned
+                )
+            })
+            .map(|(field, _)| field.ident.clone().unwrap())
+            .chain(groups.iter().map(|(group, ..)| group.clone()))
+            .collect(),
+        Layout::ArrayOfStructs => vec![Ident::new("data", struct_ident.span())],
+    };
+    let tracked_names: Vec<LitStr> = tracked_columns
+        .iter()
+        .map(|ident| LitStr::new(&ident.to_string(), ident.span()))
+        .collect();
+    let tracked_capacities = quote! { &[#(self.#tracked_columns.capacity()),*] };
+    let record_stats = if cfg!(feature = "stats") {
+        quote! { self._stats.record(self.len(), &[#(#tracked_names),*], #tracked_capacities); }
+    } else {
+        quote! {}
+    };
+
+    // The fields of the layout that aren't columns, with their initial values: the marker of a
+    // `brand`, the subscribers of a `watch` layout and the growth tracker of the `stats` feature
+    let mut hidden_fields = TokenStream2::new();
+    let mut hidden_init = TokenStream2::new();
+    if let Some(brand) = &brand {
+        hidden_fields.extend(quote! { _brand: core::marker::PhantomData<fn() -> #brand>, });
+        hidden_init.extend(quote! { _brand: core::marker::PhantomData, });
+    }
+    if layout_options.watch {
+        hidden_fields.extend(quote! { _subscribers: ::soaaos::watch::Subscribers<#id_ty>, });
+        hidden_init.extend(quote! { _subscribers: ::soaaos::watch::Subscribers::new(), });
+    }
+    if layout_options.hashcons {
+        hidden_fields.extend(quote! { _hashcons: ::soaaos::hashcons::RowIndex, });
+        hidden_init.extend(quote! { _hashcons: ::soaaos::hashcons::RowIndex::new(), });
+    }
+    // The field of `#[soa(key)]`, with the index of the nodes by its value
+    let key_field = field_options.iter().position(|options| options.key);
+    if key_field.is_some() {
+        hidden_fields.extend(quote! { _key_index: ::soaaos::hashcons::SharedRowIndex, });
+        hidden_init.extend(quote! { _key_index: ::soaaos::hashcons::SharedRowIndex::new(), });
+    }
+    if layout_options.ring.is_some() {
+        hidden_fields.extend(quote! { _ring: ::soaaos::ring::Ring<#struct_ident #impl_generics>, });
+        hidden_init.extend(quote! { _ring: ::soaaos::ring::Ring::new(), });
+    }
+    if layout_options.versioned {
+        hidden_fields.extend(quote! { _versions: ::soaaos::version::RowVersions, });
+        hidden_init.extend(quote! { _versions: ::soaaos::version::RowVersions::new(), });
+    }
+    if layout_options.epochs {
+        hidden_fields.extend(quote! { _epochs: ::soaaos::version::RowEpochs, });
+        hidden_init.extend(quote! { _epochs: ::soaaos::version::RowEpochs::new(), });
+    }
+    if cfg!(feature = "stats") {
+        hidden_fields.extend(quote! { _stats: ::soaaos::stats::StatsTracker, });
+        hidden_init.extend(quote! { _stats: ::soaaos::stats::StatsTracker::new(), });
+    }
+
+    // `new()` is a `const fn` unless a column (or the row index of `hashcons` or a key) is created
+    // with `Default` or an allocated index
+    let const_new = if layout_options.hashcons
+        || key_field.is_some()
+        || (layout == Layout::StructOfArrays
+            && !field_options
+                .iter()
+                .all(|options| options.storage.const_new()))
+    {
+        quote! {}
+    } else {
+        quote! { const }
+    };
+
+    // With `watch`, every mutation sends an event to the subscribers of the layout
+    let notify = |event: TokenStream2| {
+        if layout_options.watch {
+            quote! { self._subscribers.notify(::soaaos::watch::Event::#event); }
+        } else {
+            quote! {}
+        }
+    };
+
+    // The Id type, or a re-export of the shared Id type given with `id = ...`
+    let id = match &layout_options.id {
+        Some(shared) if *shared == id_ident => quote! {},
+        Some(shared) => quote! {
+            #[allow(unused_imports)]
+            pub use #shared as #id_ident;
+        },
+        None => id_tokens(&id_ident, brand.as_ref()),
+    };
+
+    // Extract the field names and types.
+    let field_names: Vec<_> = fields
+        .iter()
+        .map(|f| f.ident.as_ref().expect("Expected named field"))
+        .collect();
+
+    let first_field = field_names
+        .first()
+        .expect("No fields found for this memory layout");
+
+    let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+
+    // A lazy column with a `#[soa(default = ...)]` is unallocated while every element is that
+    // default, given to the column through a generated `LazyDefault` type (e.g. NodeField1Default).
+    let (_, struct_ty_generics, _) = generics.split_for_impl();
+    let mut lazy_defaults = Vec::new();
+    let column_types: Vec<_> = fields
+        .iter()
+        .zip(&field_options)
+        .map(|(field, options)| match (options.storage, &options.default) {
+            (options::Storage::Lazy, Some(default)) => {
+                let field_name = field.ident.as_ref().unwrap();
+                let ty = &field.ty;
+                let vis = &input.vis;
+                let default_ident = Ident::new(
+                    &format!("{}{}Default", struct_ident, camel_case(field_name)),
+                    field_name.span(),
+                );
+                lazy_defaults.push(quote! {
+                    #[doc(hidden)]
+                    #vis struct #default_ident #generics (core::marker::PhantomData<fn() -> #struct_ident #struct_ty_generics>) #where_clause;
+
+                    impl #impl_generics ::soaaos::column::LazyDefault<#ty> for #default_ident #struct_ty_generics #where_clause {
+                        fn value() -> #ty {
+                            #default
+                        }
+                    }
+                });
+                parse_quote!(::soaaos::column::LazyColumn<#ty, #default_ident #struct_ty_generics>)
+            }
+            _ => options.storage.column_type(&field.ty),
+        })
+        .collect();
+
+    // The members of a struct-of-arrays layout holding the columns, in field order: the column of
+    // each field, or of its group in place of the first field of the group. Each member holds the
+    // given fields, in the tuple struct of the group for grouped fields.
+    let mut column_members: Vec<&Ident> = Vec::new();
+    let mut column_member_types: Vec<Type> = Vec::new();
+    let mut column_member_fields: Vec<Vec<usize>> = Vec::new();
+    let mut column_member_groups: Vec<Option<&Ident>> = Vec::new();
+    for (index, field_name) in field_names.iter().enumerate() {
+        match &grouped[index] {
+            None => {
+                column_members.push(field_name);
+                column_member_types.push(column_types[index].clone());
+                column_member_fields.push(vec![index]);
+                column_member_groups.push(None);
+            }
+            Some((group, _)) => {
+                let (name, group_struct, members) = &groups[*group];
+                if members[0] == index {
+                    column_members.push(name);
+                    column_member_types.push(parse_quote!(Vec<#group_struct>));
+                    column_member_fields.push(members.clone());
+                    column_member_groups.push(Some(group_struct));
+                }
+            }
+        }
+    }
+    let first_member = column_members[0];
+
+    // The element of each member built from an expression per field
+    let member_values = |values: &[TokenStream2]| -> Vec<TokenStream2> {
+        column_member_fields
+            .iter()
+            .zip(&column_member_groups)
+            .map(|(members, group)| match group {
+                Some(group) => {
+                    let values = members.iter().map(|index| &values[*index]);
+                    quote! { #group(#(#values),*) }
+                }
+                None => values[members[0]].clone(),
+            })
+            .collect()
+    };
+
+    // The elements of each member bound to a local (e.g. `__field1`), and each field read out of
+    // them
+    let member_locals: Vec<Ident> = column_members
+        .iter()
+        .map(|member| Ident::new(&format!("__{}", member), member.span()))
+        .collect();
+    let mut fields_of_locals: Vec<TokenStream2> = vec![quote! {}; fields.len()];
+    for (members, local) in column_member_fields.iter().zip(&member_locals) {
+        for index in members {
+            fields_of_locals[*index] = match &grouped[*index] {
+                Some((_, position)) => quote! { #local.#position },
+                None => quote! { #local },
+            };
+        }
+    }
+
+    // The column holding each field in a struct-of-arrays layout, with the position of the field
+    // in the elements of the column (e.g. `.0`) for grouped fields, and the projection of an
+    // element of the column to the field
+    let field_columns: Vec<&Ident> = (0..fields.len())
+        .map(|index| match &grouped[index] {
+            Some((group, _)) => &groups[*group].0,
+            None => field_names[index],
+        })
+        .collect();
+    let field_positions: Vec<TokenStream2> = grouped
+        .iter()
+        .map(|grouped| match grouped {
+            Some((_, position)) => quote! { .#position },
+            None => quote! {},
+        })
+        .collect();
+    let field_projections: Vec<TokenStream2> = grouped
+        .iter()
+        .map(|grouped| match grouped {
+            Some((_, position)) => quote! { .map(|group| &group.#position) },
+            None => quote! {},
+        })
+        .collect();
+    let field_mut_projections: Vec<TokenStream2> = grouped
+        .iter()
+        .map(|grouped| match grouped {
+            Some((_, position)) => quote! { .map(|group| &mut group.#position) },
+            None => quote! {},
+        })
+        .collect();
+
+    // The tuple struct of each group, deriving the same traits as the struct
+    let group_structs: Vec<_> = groups
+        .iter()
+        .map(|(group, group_struct, members)| {
+            let vis = &input.vis;
+            let types = members.iter().map(|index| field_types[*index]);
+            let doc = format!(
+                "The fields of a `{struct_ident}` in the `{group}` group, stored together in the \
+                 `{group}` column of a `{layout_struct_ident}`"
+            );
+            quote! {
+                #[doc = #doc]
+                #(#source_derives)*
+                #vis struct #group_struct(#(pub #types),*);
+            }
+        })
+        .collect();
+
+    // Create getter method names for each field (e.g. get_field1).
+    let getter_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("get_{}", ident), ident.span()))
+        .collect();
+
+    // Create getter method names for each field enumerated (e.g. get_field1_enumerated).
+    let getter_enumerated_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("get_{}_enumerated", ident), ident.span()))
+        .collect();
+
+    // Create getter mut method names for each field (e.g. get_field1_mut).
+    let getter_mut_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("get_{}_mut", ident), ident.span()))
+        .collect();
+
+    // Create scatter method names for each field (e.g. scatter_field1).
+    let scatter_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("scatter_{}", ident), ident.span()))
+        .collect();
+
+    // Create getter method names for each field (e.g. get_field1).
+    let error_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("NotFound_{}", ident), ident.span()))
+        .collect();
+
+    // Create the sort and extreme value method names for each field (e.g. sort_by_field1,
+    // id_of_min_field1).
+    let par_sort_by_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("par_sort_by_{}", ident), ident.span()))
+        .collect();
+    let sort_by_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("sort_by_{}", ident), ident.span()))
+        .collect();
+    let sort_unstable_by_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("sort_unstable_by_{}", ident), ident.span()))
+        .collect();
+    let id_of_min_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("id_of_min_{}", ident), ident.span()))
+        .collect();
+    let id_of_max_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("id_of_max_{}", ident), ident.span()))
+        .collect();
+
+    // Create the short-circuiting scan method names for each field (e.g. filter_map_field1).
+    let filter_map_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("filter_map_{}", ident), ident.span()))
+        .collect();
+
+    // Create the distribution summary method names for each field (e.g. value_counts_field1).
+    let value_counts_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("value_counts_{}", ident), ident.span()))
+        .collect();
+    let histogram_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("histogram_{}", ident), ident.span()))
+        .collect();
+    let sample_weighted_by_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("sample_weighted_by_{}", ident), ident.span()))
+        .collect();
+
+    // Create the join method names for each field (e.g. join_by_field1).
+    let join_by_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("join_by_{}", ident), ident.span()))
+        .collect();
+
+    // Create conditional setter method names for each field (e.g. compare_and_set_field1).
+    let compare_and_set_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("compare_and_set_{}", ident), ident.span()))
+        .collect();
+
+    // Create the builder error names for each field (e.g. Missing_field1).
+    let missing_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("Missing_{}", ident), ident.span()))
+        .collect();
+
+    // The value used for a field that isn't given: the `#[soa(default = ...)]` expression if there is
+    // one, otherwise `Default::default()`.
+    let default_values: Vec<_> = field_options
+        .iter()
+        .map(|options| match &options.default {
+            Some(default) => quote! { #default },
+            None => quote! { Default::default() },
+        })
+        .collect();
+    let default_members = member_values(&default_values);
+
+    // Only the fields without a `#[soa(default)]` need to implement `Default` to be defaulted.
+    let undefaulted_types: Vec<_> = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.default.is_none())
+        .map(|(field, _)| &field.ty)
+        .collect();
+
+    // The fields without a `#[soa(default)]`, which must be given when adding a partial node.
+    let (required_names, required_types): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.default.is_none())
+        .map(|(field, _)| (field.ident.as_ref().unwrap(), &field.ty))
+        .unzip();
+
+    // The value of each field when adding a partial node: required fields are passed by name.
+    let partial_values: Vec<_> = field_names
+        .iter()
+        .zip(&field_options)
+        .map(|(field_name, options)| match &options.default {
+            Some(default) => quote! { #default },
+            None => quote! { #field_name },
+        })
+        .collect();
+
+    // The value of each field when building from a builder: fields with a `#[soa(default)]` fall
+    // back to it, all other fields must be set.
+    let builder_values: Vec<_> = field_names
+        .iter()
+        .zip(&field_options)
+        .zip(&missing_names)
+        .map(
+            |((field_name, options), missing_name)| match &options.default {
+                Some(default) => quote! { self.#field_name.unwrap_or_else(|| #default) },
+                None => quote! { self.#field_name.ok_or(#error_ident::#missing_name)? },
+            },
+        )
+        .collect();
+
+    // The field names as string literals for the dynamic accessors (e.g. "field1").
+    let field_name_strs: Vec<LitStr> = field_names
+        .iter()
+        .map(|ident| LitStr::new(&ident.to_string(), ident.span()))
+        .collect();
+
+    // The ref iterator needs a lifetime prepending any given generics. Prepend a 'a lifetime to any
+    // given generics.
+    // <R> => <'a, R>
+    let mut generics_with_lifetime = generics.clone();
+    let lifetime = Lifetime::new("'a", impl_generics.span());
+    generics_with_lifetime.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(lifetime.clone())),
+    );
+
+    let (_, row_ty_generics, _) = generics_with_lifetime.split_for_impl();
+
+    // Same as above but for ellided lifetimes
+    // <R> => <'_, R>
+    let mut generics_with_ellided_lifetime = generics.clone();
+    let ellided_lifetime = Lifetime::new("'_", impl_generics.span());
+    generics_with_ellided_lifetime.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(ellided_lifetime.clone())),
+    );
+
+    // Same as above for the chunks of `__N` nodes, which also take the chunk length
+    // <'a, R> => <'a, const __N: usize, R>
+    let mut chunk_generics = generics_with_lifetime.clone();
+    chunk_generics
+        .params
+        .insert(1, parse_quote!(const __N: usize));
+    let mut chunk_ellided_generics = generics_with_ellided_lifetime.clone();
+    chunk_ellided_generics
+        .params
+        .insert(1, parse_quote!(const __N: usize));
+    let (_, chunk_ellided_generics, _) = chunk_ellided_generics.split_for_impl();
+
+    // Same as above for the views holding the layout, which also take its brand
+    let mut layout_generics_with_lifetime = branded_generics.clone();
+    layout_generics_with_lifetime.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(lifetime.clone())),
+    );
+    let mut layout_generics_with_ellided_lifetime = branded_generics.clone();
+    layout_generics_with_ellided_lifetime.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(ellided_lifetime.clone())),
+    );
+
+    // Fields whose column only stores elements by value (e.g. `#[soa(delta)]`) are read by value,
+    // and fields whose column can't hand out `&mut` (e.g. `#[soa(rle)]`) are only written with
+    // `set_*`. Both layouts generate the same API so code using one layout works with the other.
+    let by_value: Vec<bool> = field_options
+        .iter()
+        .map(|options| options.storage.by_value())
+        .collect();
+    let set_only: Vec<bool> = field_options
+        .iter()
+        .map(|options| options.storage.set_only())
+        .collect();
+
+    // `#[soa(frozen)]` fields are only written when a node is added, so they get neither `&mut`
+    // access nor a setter
+    let frozen: Vec<bool> = field_options.iter().map(|options| options.frozen).collect();
+
+    // The type returned when reading each field (e.g. &field1, or field1 when stored by value).
+    let read_types: Vec<_> = field_types
+        .iter()
+        .zip(&by_value)
+        .map(|(ty, by_value)| {
+            if *by_value {
+                quote! { #ty }
+            } else {
+                quote! { &#ty }
+            }
+        })
+        .collect();
+
+    // Same as above, for views borrowing the layout for 'a
+    let ref_types: Vec<_> = field_types
+        .iter()
+        .zip(&by_value)
+        .map(|(ty, by_value)| {
+            if *by_value {
+                quote! { #ty }
+            } else {
+                quote! { &#lifetime #ty }
+            }
+        })
+        .collect();
+
+    // The fields that can be borrowed mutably, with their types, getters and errors.
+    let mut_fields: Vec<usize> = (0..fields.len())
+        .filter(|i| !set_only[*i] && !frozen[*i])
+        .collect();
+    let mut_field_names: Vec<_> = mut_fields.iter().map(|i| field_names[*i]).collect();
+    let mut_field_types: Vec<_> = mut_fields.iter().map(|i| field_types[*i]).collect();
+    let mut_getter_names: Vec<_> = mut_fields.iter().map(|i| &getter_mut_names[*i]).collect();
+    let mut_error_names: Vec<_> = mut_fields.iter().map(|i| &error_names[*i]).collect();
+    let mut_field_columns: Vec<_> = mut_fields.iter().map(|i| field_columns[*i]).collect();
+    let mut_field_projections: Vec<_> = mut_fields
+        .iter()
+        .map(|i| &field_mut_projections[*i])
+        .collect();
+
+    // The columns holding a field that can be borrowed mutably, each borrowed once into a local
+    // by `get_mut` and `update`, with the error of their first such field, and each field
+    // borrowed out of the local
+    let mut_members: Vec<usize> = (0..column_members.len())
+        .filter(|member| {
+            column_member_fields[*member]
+                .iter()
+                .any(|i| mut_fields.contains(i))
+        })
+        .collect();
+    let mut_member_names: Vec<_> = mut_members.iter().map(|m| column_members[*m]).collect();
+    let mut_member_locals: Vec<_> = mut_members.iter().map(|m| &member_locals[*m]).collect();
+    let mut_member_errors: Vec<_> = mut_members
+        .iter()
+        .map(|m| {
+            let first = column_member_fields[*m]
+                .iter()
+                .find(|i| mut_fields.contains(i))
+                .unwrap();
+            &error_names[*first]
+        })
+        .collect();
+    let mut_field_borrows: Vec<_> = mut_fields
+        .iter()
+        .map(|i| match &grouped[*i] {
+            Some(_) => {
+                let field = &fields_of_locals[*i];
+                quote! { &mut #field }
+            }
+            None => fields_of_locals[*i].clone(),
+        })
+        .collect();
+
+    // Create setter method names for each field (e.g. set_field1).
+    let setter_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("set_{}", ident), ident.span()))
+        .collect();
+
+    // Fields stored in a `#[soa(cell)]` column are written through `&self`
+    let setter_receivers: Vec<_> = field_options
+        .iter()
+        .map(|options| {
+            if options.storage == options::Storage::Cell {
+                quote! { &self }
+            } else {
+                quote! { &mut self }
+            }
+        })
+        .collect();
+
+    // Any mutation other than adding nodes makes the row index of a `hashcons` layout (and the
+    // index of its key) stale. Added nodes are indexed by the next lookup.
+    let mut invalidate = TokenStream2::new();
+    if layout_options.hashcons {
+        invalidate.extend(quote! { self._hashcons.invalidate(); });
+    }
+    if key_field.is_some() {
+        invalidate.extend(quote! { self._key_index.invalidate(); });
+    }
+    let notify_changes = |event: TokenStream2| {
+        let notify = notify(event);
+        quote! { #notify #invalidate }
+    };
+
+    // The same mutations stamp the rows of a `versioned` or `epochs` layout. Added nodes are
+    // stamped by the next mutation.
+    let stamps_rows = layout_options.versioned || layout_options.epochs;
+    let version = |stamp: TokenStream2| {
+        let mut stamps = TokenStream2::new();
+        if layout_options.versioned {
+            stamps.extend(quote! { self._versions.#stamp; });
+        }
+        if layout_options.epochs {
+            stamps.extend(quote! { self._epochs.#stamp; });
+        }
+        stamps
+    };
+    let version_changed = version(quote! { changed(index.0 as usize, self.len()) });
+    let version_changed_at = version(quote! { changed(index, self.len()) });
+    let version_removed = version(quote! { removed(index) });
+    let version_changed_all = version(quote! { changed_all(self.len()) });
+    let version_cleared = version(quote! { clear() });
+
+    // The events for writing each field of the node with the Id `index`, or at the `usize`
+    // `index` for `changed_at`.
+    let changed: Vec<_> = field_name_strs
+        .iter()
+        .map(|name| {
+            let notify = notify_changes(quote! { Changed { id: index, field: #name } });
+            quote! { #notify #version_changed }
+        })
+        .collect();
+    let changed_at: Vec<_> = field_name_strs
+        .iter()
+        .map(|name| {
+            let notify =
+                notify_changes(quote! { Changed { id: #id_new(index as u32), field: #name } });
+            quote! { #notify #version_changed_at }
+        })
+        .collect();
+    let mut_changed: Vec<_> = mut_fields.iter().map(|i| &changed[*i]).collect();
+    let notify_added = notify(quote! { Added(id) });
+    let notify_added_each = if layout_options.watch {
+        quote! { for &id in &new_ids { #notify_added } }
+    } else {
+        quote! {}
+    };
+    let notify_removed = notify_changes(quote! { Removed(#id_new(index as u32)) });
+    let notify_removed = quote! { #notify_removed #version_removed };
+    // Reordering or clearing the nodes of a `ring` layout makes their new order the order of
+    // eviction
+    let ring_reset = if layout_options.ring.is_some() {
+        quote! { self._ring.reset(); }
+    } else {
+        quote! {}
+    };
+    let notify_reordered = notify_changes(quote! { Reordered });
+    let notify_reordered = quote! { #notify_reordered #version_changed_all #ring_reset };
+    let notify_cleared = notify_changes(quote! { Cleared });
+    let notify_cleared = quote! { #notify_cleared #version_cleared #ring_reset };
+
+    // Borrowing a field mutably counts as writing it, as long as the node exists
+    let notify_borrowed: Vec<_> = mut_changed
+        .iter()
+        .map(|changed| {
+            if layout_options.watch || !invalidate.is_empty() || stamps_rows {
+                quote! {
+                    if (index.0 as usize) < self.len() {
+                        #changed
+                    }
+                }
+            } else {
+                quote! {}
+            }
+        })
+        .collect();
+    let notify_borrowed_all = quote! { #(#notify_borrowed)* };
+
+    // Subscribing is only generated for `watch` layouts
+    let subscribe = if layout_options.watch {
+        quote! {
+            /// Subscribe to the mutations made through the methods of this layout, returning the
+            /// receiver of an event for each mutation. Dropping the receiver unsubscribes.
+            pub fn subscribe(&mut self) -> ::soaaos::watch::Receiver<::soaaos::watch::Event<#id_ty>> {
+                self._subscribers.subscribe()
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Reading a field of `item` in an array-of-structs layout, matching `read_types`.
+    let aos_reads: Vec<_> = field_names
+        .iter()
+        .zip(&by_value)
+        .map(|(field_name, by_value)| {
+            if *by_value {
+                quote! { item.#field_name.clone() }
+            } else {
+                quote! { &item.#field_name }
+            }
+        })
+        .collect();
+
+    // Cloning the field at `index` out of a struct-of-arrays layout.
+    let soa_cloned_reads: Vec<_> = field_names
+        .iter()
+        .zip(&by_value)
+        .zip(field_columns.iter().zip(&field_positions))
+        .map(|((field_name, by_value), (column, position))| {
+            if *by_value {
+                quote! { self.#field_name.get(index).unwrap() }
+            } else {
+                quote! { self.#column[index]#position.clone() }
+            }
+        })
+        .collect();
+
+    // Same as above out of the `other` layout.
+    let soa_other_cloned_reads: Vec<_> = field_names
+        .iter()
+        .zip(&by_value)
+        .zip(field_columns.iter().zip(&field_positions))
+        .map(|((field_name, by_value), (column, position))| {
+            if *by_value {
+                quote! { other.#field_name.get(index).unwrap() }
+            } else {
+                quote! { other.#column[index]#position.clone() }
+            }
+        })
+        .collect();
+
+    // Same as above for the element of each column, building the elements of group columns
+    let soa_member_cloned_reads = member_values(&soa_cloned_reads);
+    let members_from_columns: Vec<_> = column_member_fields
+        .iter()
+        .zip(&column_member_groups)
+        .map(|(members, group)| {
+            let names: Vec<_> = members.iter().map(|index| field_names[*index]).collect();
+            match group {
+                Some(group) => {
+                    let iters: Vec<_> = names
+                        .iter()
+                        .map(|name| Ident::new(&format!("__{}", name), name.span()))
+                        .collect();
+                    quote! {
+                        {
+                            #(
+                                let mut #iters = columns.#names.into_iter();
+                            )*
+                            (0..len).map(|_| #group(#(#iters.next().unwrap()),*)).collect()
+                        }
+                    }
+                }
+                None => quote! { #(columns.#names)*.into_iter().collect() },
+            }
+        })
+        .collect();
+    let item_members = member_values(
+        &field_names
+            .iter()
+            .map(|field_name| quote! { item.#field_name })
+            .collect::<Vec<_>>(),
+    );
+    let soa_other_member_cloned_reads = member_values(&soa_other_cloned_reads);
+
+    // Writing `value` to the field at `index` of a struct-of-arrays layout.
+    let soa_writes: Vec<_> = field_names
+        .iter()
+        .zip(&set_only)
+        .zip(field_columns.iter().zip(&field_positions))
+        .map(|((field_name, set_only), (column, position))| {
+            if *set_only {
+                quote! { self.#field_name.set(index, value); }
+            } else {
+                quote! { self.#column[index]#position = value; }
+            }
+        })
+        .collect();
+
+    // The fields that can be written after their node is added (not `#[soa(frozen)]`), with
+    // everything their writers (e.g. set_field1, scatter_field1) use.
+    let set_fields: Vec<usize> = (0..fields.len()).filter(|i| !frozen[*i]).collect();
+    let set_field_names: Vec<_> = set_fields.iter().map(|i| field_names[*i]).collect();
+    let set_field_types: Vec<_> = set_fields.iter().map(|i| field_types[*i]).collect();
+    let set_getter_names: Vec<_> = set_fields.iter().map(|i| &getter_names[*i]).collect();
+    let set_setter_names: Vec<_> = set_fields.iter().map(|i| &setter_names[*i]).collect();
+    let set_setter_receivers: Vec<_> = set_fields.iter().map(|i| &setter_receivers[*i]).collect();
+    let set_scatter_names: Vec<_> = set_fields.iter().map(|i| &scatter_names[*i]).collect();
+    let set_compare_and_set_names: Vec<_> = set_fields
+        .iter()
+        .map(|i| &compare_and_set_names[*i])
+        .collect();
+    let set_error_names: Vec<_> = set_fields.iter().map(|i| &error_names[*i]).collect();
+    let set_soa_writes: Vec<_> = set_fields.iter().map(|i| &soa_writes[*i]).collect();
+    let set_changed: Vec<_> = set_fields.iter().map(|i| &changed[*i]).collect();
+    let set_changed_at: Vec<_> = set_fields.iter().map(|i| &changed_at[*i]).collect();
+
+    // Reading the field at `index` as `&dyn Any`. Fields stored by value can't be borrowed.
+    let dyn_reads: Vec<_> = getter_names
+        .iter()
+        .zip(&by_value)
+        .map(|(getter_name, by_value)| {
+            if *by_value {
+                quote! { { let _ = index; None } }
+            } else {
+                quote! { self.#getter_name(index).ok().map(|val| val as &dyn core::any::Any) }
+            }
+        })
+        .collect();
+
+    // The run-length encoded fields, with their run iterator names (e.g. field1_runs).
+    let (rle_field_names, rle_field_types): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.storage == options::Storage::Rle)
+        .map(|(field, _)| (field.ident.as_ref().unwrap(), &field.ty))
+        .unzip();
+    let runs_names: Vec<Ident> = rle_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("{}_runs", ident), ident.span()))
+        .collect();
+
+    // Moving each column of a struct-of-arrays layout out into a `Vec`, as is for the columns
+    // already stored in one and by cloning every element of the others
+    let cloned_columns: Vec<usize> = (0..fields.len())
+        .filter(|i| field_options[*i].storage != options::Storage::Vec)
+        .collect();
+    let cloned_column_types: Vec<_> = cloned_columns.iter().map(|i| field_types[*i]).collect();
+    let into_column_vecs: Vec<_> = field_names
+        .iter()
+        .zip(&field_options)
+        .zip(&soa_cloned_reads)
+        .map(|((field_name, options), read)| {
+            if options.storage == options::Storage::Vec {
+                quote! { core::mem::take(&mut self.#field_name) }
+            } else {
+                quote! { (0..len).map(|index| #read).collect() }
+            }
+        })
+        .collect();
+    let into_columns_len = if cloned_columns.is_empty() {
+        quote! {}
+    } else {
+        quote! { let len = self.len(); }
+    };
+
+    // The fields stored in a contiguous slice (a `Vec` or a `PageAlignedColumn`), which can be
+    // split into arrays (e.g. field1_chunks).
+    let vec_fields: Vec<usize> = (0..fields.len())
+        .filter(|i| {
+            matches!(
+                field_options[*i].storage,
+                options::Storage::Vec | options::Storage::PageAligned
+            )
+        })
+        .collect();
+    let vec_field_names: Vec<_> = vec_fields.iter().map(|i| field_names[*i]).collect();
+    let vec_field_types: Vec<_> = vec_fields.iter().map(|i| field_types[*i]).collect();
+    let chunks_names: Vec<Ident> = vec_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("{}_chunks", ident), ident.span()))
+        .collect();
+    let simd_chunks_names: Vec<Ident> = vec_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("{}_simd_chunks", ident), ident.span()))
+        .collect();
+    let id_of_names: Vec<Ident> = vec_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("id_of_{}", ident), ident.span()))
+        .collect();
+    // The fields stored in a `PageAlignedColumn`, whose pages can be advised to the OS
+    let page_aligned_field_names: Vec<_> = (0..fields.len())
+        .filter(|i| field_options[*i].storage == options::Storage::PageAligned)
+        .map(|i| field_names[i])
+        .collect();
+    let advise_sequential_names: Vec<Ident> = page_aligned_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("advise_sequential_{}", ident), ident.span()))
+        .collect();
+    let advise_dontneed_names: Vec<Ident> = page_aligned_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("advise_dontneed_{}", ident), ident.span()))
+        .collect();
+    let mut_vec_fields: Vec<usize> = vec_fields.iter().copied().filter(|i| !frozen[*i]).collect();
+    let mut_vec_field_names: Vec<_> = mut_vec_fields.iter().map(|i| field_names[*i]).collect();
+    let mut_vec_field_types: Vec<_> = mut_vec_fields.iter().map(|i| field_types[*i]).collect();
+    let simd_chunks_mut_names: Vec<Ident> = mut_vec_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("{}_simd_chunks_mut", ident), ident.span()))
+        .collect();
+
+    // The getters of a contiguous range of Ids of each field stored in a slice, checking the
+    // range once. Borrowing the range mutably counts as writing each node in it.
+    let vec_error_names: Vec<_> = vec_fields.iter().map(|i| &error_names[*i]).collect();
+    let range_getter_names: Vec<Ident> = vec_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("get_{}_range", ident), ident.span()))
+        .collect();
+    let mut_vec_error_names: Vec<_> = mut_vec_fields.iter().map(|i| &error_names[*i]).collect();
+    let range_mut_getter_names: Vec<Ident> = mut_vec_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("get_{}_range_mut", ident), ident.span()))
+        .collect();
+    let range_borrowed: Vec<_> = mut_vec_fields
+        .iter()
+        .map(|i| {
+            if layout_options.watch || !invalidate.is_empty() || stamps_rows {
+                let changed_at = &changed_at[*i];
+                quote! {
+                    for index in range.clone() {
+                        #changed_at
+                    }
+                }
+            } else {
+                quote! {}
+            }
+        })
+        .collect();
+
+    // Each column stored in a contiguous slice can be borrowed mutably while every other column is
+    // borrowed immutably, through a view of the other columns (e.g. NodesWithoutField1)
+    let mut rest_views = TokenStream2::new();
+    let mut with_mut_methods = TokenStream2::new();
+    for i in &mut_vec_fields {
+        let field_name = field_names[*i];
+        let field_type = field_types[*i];
+        let name = &field_name_strs[*i];
+        let rest_ident = Ident::new(
+            &format!("{}sWithout{}", struct_ident, camel_case(field_name)),
+            field_name.span(),
+        );
+        let with_mut_name = Ident::new(&format!("with_{}_mut", field_name), field_name.span());
+        let other_names: Vec<_> = column_members
+            .iter()
+            .filter(|other| **other != field_name)
+            .collect();
+        let other_types: Vec<_> = column_members
+            .iter()
+            .zip(&column_member_types)
+            .filter(|(other, _)| **other != field_name)
+            .map(|(_, ty)| ty)
+            .collect();
+
+        // Borrowing the column mutably counts as writing every node
+        let notify_each = notify(quote! { Changed { id, field: #name } });
+        let notify_column = if layout_options.watch {
+            quote! {
+                for id in self.iter_ids() {
+                    #notify_each
+                }
+            }
+        } else {
+            quote! {}
+        };
+        let notify_column = quote! { #notify_column #version_changed_all };
+
+        let doc = format!(
+            "The columns of a layout other than `{field_name}`, borrowed while `{with_mut_name}` \
+             borrows `{field_name}` mutably"
+        );
+        rest_views.extend(quote! {
+            #[doc = #doc]
+            pub struct #rest_ident #generics_with_lifetime #where_clause {
+                #(
+                    pub #other_names: &#lifetime #other_types,
+                )*
+                _row: core::marker::PhantomData<&#lifetime #struct_ident #impl_generics>,
+            }
+        });
+        with_mut_methods.extend(quote! {
+            /// Call `f` with this field of every node borrowed mutably, and every other column
+            /// borrowed immutably. Returns the result of `f`.
+            pub fn #with_mut_name<__R>(
+                &mut self,
+                f: impl FnOnce(&mut [#field_type], #rest_ident #generics_with_ellided_lifetime) -> __R,
+            ) -> __R {
+                #notify_column
+                #invalidate
+
+                let rest = #rest_ident {
+                    #(
+                        #other_names: &self.#other_names,
+                    )*
+                    _row: core::marker::PhantomData,
+                };
+                f(&mut self.#field_name[..], rest)
+            }
+        });
+    }
+
+    // SIMD views of the columns stored in a `Vec` are only generated with the (nightly)
+    // `portable_simd` feature
+    let simd_chunks = if cfg!(feature = "portable_simd") {
+        quote! {
+            #(
+                /// Returns this field as `Simd` vectors of `N` lanes, and the remaining elements
+                /// that don't fill a vector.
+                pub fn #simd_chunks_names<const __N: usize>(&self) -> (impl Iterator<Item = core::simd::Simd<#vec_field_types, __N>> + '_, &[#vec_field_types])
+                where
+                    for<'__b> #vec_field_types: core::simd::SimdElement,
+                {
+                    let (chunks, rest) = self.#vec_field_names.as_chunks::<__N>();
+                    (chunks.iter().map(|chunk| core::simd::Simd::from_array(*chunk)), rest)
+                }
+            )*
+
+            #(
+                /// Call `f` with each `Simd` vector of `N` lanes of this field, writing the vector
+                /// back once `f` returns. Returns the remaining elements that don't fill a vector.
+                pub fn #simd_chunks_mut_names<const __N: usize>(&mut self, mut f: impl FnMut(&mut core::simd::Simd<#mut_vec_field_types, __N>)) -> &mut [#mut_vec_field_types]
+                where
+                    for<'__b> #mut_vec_field_types: core::simd::SimdElement,
+                {
+                    let (chunks, rest) = self.#mut_vec_field_names.as_chunks_mut::<__N>();
+                    for chunk in chunks {
+                        let mut lanes = core::simd::Simd::from_array(*chunk);
+                        f(&mut lanes);
+                        *chunk = lanes.to_array();
+                    }
+                    rest
+                }
+            )*
+        }
+    } else {
+        quote! {}
+    };
+
+    // The C header describing the `#[repr(C)]` descriptor of the layout. Struct-of-arrays layouts
+    // point to each contiguous column, while array-of-structs layouts point to the rows, with the
+    // offset of each field in a row. Fields without a C equivalent are `void` pointers.
+    let is_id_type =
+        |ty: &Type| matches!(ty, Type::Path(path) if path.path.get_ident() == Some(&id_ident));
+
+    // The map of the Ids moved by `swap_remove()` and `retain()`, over the brand of the layout
+    let id_map_ty = quote! { #id_map_ident #brand_generics };
+
+    // The fixed-arity link fields: arrays of Ids of this layout (e.g. `[NodeId; 4]`), or of
+    // optional Ids for missing links, borrowed from and written in their column
+    let links: Vec<(usize, bool)> = (0..fields.len())
+        .filter(|i| !by_value[*i] && !set_only[*i] && !frozen[*i])
+        .filter_map(|i| {
+            let Type::Array(array) = field_types[i] else {
+                return None;
+            };
+            if is_id_type(&array.elem) {
+                Some((i, false))
+            } else {
+                option_inner(&array.elem)
+                    .filter(|inner| is_id_type(inner))
+                    .map(|_| (i, true))
+            }
+        })
+        .collect();
+    let link_methods = if links.is_empty() {
+        quote! {}
+    } else {
+        let link_mut_getters: Vec<_> = links.iter().map(|(i, _)| &getter_mut_names[*i]).collect();
+        let link_iters: Vec<_> = links
+            .iter()
+            .map(|(i, optional)| {
+                let getter = &getter_names[*i];
+                if *optional {
+                    quote! { self.#getter(id)?.iter().flatten().copied() }
+                } else {
+                    quote! { self.#getter(id)?.iter().copied() }
+                }
+            })
+            .collect();
+        let link_remaps: Vec<_> = links
+            .iter()
+            .map(|(_, optional)| {
+                if *optional {
+                    quote! { link.as_mut() }
+                } else {
+                    quote! { Some(link) }
+                }
+            })
+            .collect();
+        quote! {
+            /// Returns the Ids linked from the node with the given Id, in field order and skipping
+            /// missing (`None`) links
+            pub fn neighbors(&self, id: #id_ty) -> Result<impl Iterator<Item = #id_ty> + '_, #error_ident> {
+                Ok(core::iter::empty() #(.chain(#link_iters))*)
+            }
+
+            /// Rewrite every link of every node to its new Id in `mapping`, such as the map
+            /// returned by `dedup_rows()` after merging the layout.
+            ///
+            /// Panics if a link is out of bounds of `mapping` or to a node it removed.
+            pub fn remap_links(&mut self, mapping: &#id_map_ty) {
+                for index in 0..self.len() {
+                    let id = #id_new(index as u32);
+                    #(
+                        for link in self.#link_mut_getters(id).expect("Id is in bounds").iter_mut() {
+                            if let Some(link) = #link_remaps {
+                                *link = mapping.remap(*link).expect("Link to a kept node");
+                            }
+                        }
+                    )*
+                }
+            }
+        }
+    };
+
+    // The content address of a node, which survives the reorganizations changing its dense Id.
+    // With the `serde` feature, it is (de)serialized as a plain u64.
+    let stable_id_serde = if cfg!(feature = "serde") {
+        quote! {
+            impl ::soaaos::__private::serde::Serialize for #stable_id_ident {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::soaaos::__private::serde::Serializer,
+                {
+                    serializer.serialize_u64(self.0)
+                }
+            }
+
+            impl<'de> ::soaaos::__private::serde::Deserialize<'de> for #stable_id_ident {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::soaaos::__private::serde::Deserializer<'de>,
+                {
+                    <u64 as ::soaaos::__private::serde::Deserialize>::deserialize(deserializer).map(#stable_id_ident)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let stable_id = quote! {
+        /// A reference to a node by the hash of its contents, returned by `stable_id(id)`
+        ///
+        /// Unlike the dense Id, it still finds the node with `resolve(stable)` after the nodes are
+        /// removed, reordered, deduplicated or compacted, and finds nothing once the node is
+        /// written or removed. Rows are hashed with the `DefaultHasher` of std, so stable Ids are
+        /// only comparable within one build of a program.
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct #stable_id_ident(pub u64);
+
+        #stable_id_serde
+    };
+
+    let id_map_def_generics = match &brand {
+        Some(brand) => quote! { <#brand = ()> },
+        None => quote! {},
+    };
+    let id_map = quote! {
+        /// The new Id of every node of a layout after an operation moving nodes, returned by
+        /// `remove()`, `swap_remove()`, `retain()`, `dedup_rows()`, `reorder()`, the sorts,
+        /// `shuffle()`, `reverse()` and the rotations. Only the moved and removed nodes are stored.
+        pub struct #id_map_ident #id_map_def_generics {
+            /// The number of nodes before the operation
+            len: usize,
+
+            /// The new Id of each moved node, or `None` for each removed node, by old index
+            changes: std::collections::HashMap<u32, Option<#id_ty>>,
+        }
+
+        impl #brand_generics #id_map_ty {
+            /// The map of a layout of `len` nodes none of which moved
+            pub fn identity(len: usize) -> Self {
+                Self {
+                    len,
+                    changes: std::collections::HashMap::new(),
+                }
+            }
+
+            /// The map of a layout of `len` nodes where the node with the Id `old` now has the Id
+            /// `f(old)`, or was removed with `None`
+            pub fn from_fn(len: usize, mut f: impl FnMut(#id_ty) -> Option<#id_ty>) -> Self {
+                let mut map = Self::identity(len);
+                for index in 0..len {
+                    let old = #id_new(index as u32);
+                    map.insert(old, f(old));
+                }
+                map
+            }
+
+            /// The map of moving the node at `order[i]` to the Id `i`, as `reorder(order)` does
+            pub fn from_order(order: &[#id_ty]) -> Self {
+                let mut map = Self::identity(order.len());
+                for (index, old) in order.iter().enumerate() {
+                    map.insert(*old, Some(#id_new(index as u32)));
+                }
+                map
+            }
+
+            /// The map of the operation of this map followed by the operation of `next`
+            pub fn then(&self, next: &Self) -> Self {
+                Self::from_fn(self.len, |old| self.remap(old).and_then(|id| next.remap(id)))
+            }
+
+            /// Record that the node with the Id `old` now has the Id `new`, or was removed with
+            /// `None`
+            pub fn insert(&mut self, old: #id_ty, new: Option<#id_ty>) {
+                if new != Some(old) {
+                    self.changes.insert(old.0, new);
+                }
+            }
+
+            /// Returns the new Id of the node with the Id `old`, or `None` if it was removed or
+            /// `old` is out of bounds
+            pub fn remap(&self, old: #id_ty) -> Option<#id_ty> {
+                if old.0 as usize >= self.len {
+                    return None;
+                }
+                self.changes.get(&old.0).copied().unwrap_or(Some(old))
+            }
+
+            /// Returns the new Id of each of the given Ids, in order, dropping the removed nodes
+            pub fn remap_slice(&self, ids: &[#id_ty]) -> Vec<#id_ty> {
+                ids.iter().filter_map(|id| self.remap(*id)).collect()
+            }
+
+            /// Returns `true` if no node moved or was removed
+            pub fn is_identity(&self) -> bool {
+                self.changes.is_empty()
+            }
+        }
+
+        impl #brand_generics Clone for #id_map_ty {
+            fn clone(&self) -> Self {
+                Self {
+                    len: self.len,
+                    changes: self.changes.clone(),
+                }
+            }
+        }
+
+        impl #brand_generics core::fmt::Debug for #id_map_ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_struct(stringify!(#id_map_ident))
+                    .field("len", &self.len)
+                    .field("changes", &self.changes)
+                    .finish()
+            }
+        }
+    };
+
+    // Every field holding Ids of this layout (`NodeId`, `Option<NodeId>` or an array of either),
+    // rewritten by `apply_id_map()`. A required Id of a removed node can't be rewritten.
+    let id_fields: Vec<_> = (0..fields.len())
+        .filter(|i| !by_value[*i] && !set_only[*i] && !frozen[*i])
+        .filter_map(|i| {
+            let (ty, array) = match field_types[i] {
+                Type::Array(array) => (&*array.elem, true),
+                ty => (ty, false),
+            };
+            let remap = if is_id_type(ty) {
+                quote! {
+                    *link = map.remap(*link).expect("a link refers to a removed node");
+                }
+            } else if option_inner(ty).is_some_and(&is_id_type) {
+                quote! { *link = link.and_then(|link| map.remap(link)); }
+            } else {
+                return None;
+            };
+
+            let getter = &getter_mut_names[i];
+            Some(if array {
+                quote! {
+                    for link in self.#getter(id).expect("Id is in bounds").iter_mut() {
+                        #remap
+                    }
+                }
+            } else {
+                quote! {
+                    let link = self.#getter(id).expect("Id is in bounds");
+                    #remap
+                }
+            })
+        })
+        .collect();
+    let apply_id_map = if id_fields.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            /// Rewrite every Id of this layout held by a field of a node through `map`, after an
+            /// operation moving nodes. Optional Ids of removed nodes are set to `None`.
+            ///
+            /// Panics if a required (not `Option`) Id refers to a removed node.
+            pub fn apply_id_map(&mut self, map: &#id_map_ty) {
+                for index in 0..self.len() {
+                    let id = #id_new(index as u32);
+                    #(
+                        {
+                            #id_fields
+                        }
+                    )*
+                }
+            }
+        }
+    };
+
+    // The edges of the graph implied by the fields holding Ids of this layout, written by
+    // `to_dot()` from the node at `index`. Each edge is labeled with its field, and its position
+    // in an array.
+    let dot_edges: Vec<_> = (0..fields.len())
+        .filter_map(|i| {
+            let ty = field_types[i];
+            let (elem, array) = match ty {
+                Type::Array(array) => (&*array.elem, true),
+                ty => (ty, false),
+            };
+            let edge = if is_id_type(elem) {
+                quote! { Some(*link) }
+            } else if option_inner(elem).is_some_and(&is_id_type) {
+                quote! { *link }
+            } else {
+                return None;
+            };
+
+            let getter = &getter_names[i];
+            let field_name_str = &field_name_strs[i];
+            Some(if array {
+                quote! {
+                    let links = self.#getter(id).unwrap();
+                    let links: &#ty = core::borrow::Borrow::borrow(&links);
+                    for (slot, link) in links.iter().enumerate() {
+                        if let Some(link) = #edge {
+                            writeln!(writer, "    n{index} -> n{} [label=\"{}[{slot}]\"];", link.0, #field_name_str)?;
+                        }
+                    }
+                }
+            } else {
+                quote! {
+                    let link = self.#getter(id).unwrap();
+                    let link: &#ty = core::borrow::Borrow::borrow(&link);
+                    if let Some(link) = #edge {
+                        writeln!(writer, "    n{index} -> n{} [label=\"{}\"];", link.0, #field_name_str)?;
+                    }
+                }
+            })
+        })
+        .collect();
+    let to_dot = if dot_edges.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            /// Write the graph implied by the fields holding Ids of this layout as Graphviz DOT,
+            /// with a vertex per node labeled by `label`, and an edge per link labeled by its
+            /// field. Missing (`None`) links are skipped.
+            pub fn to_dot(
+                &self,
+                mut writer: impl std::io::Write,
+                mut label: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> String,
+            ) -> std::io::Result<()> {
+                writeln!(writer, "digraph {} {{", stringify!(#layout_struct_ident))?;
+                for (index, node) in self.iter().enumerate() {
+                    let label = ::soaaos::__private::dot_escape(&label(node));
+                    writeln!(writer, "    n{index} [label=\"{label}\"];")?;
+                }
+                for index in 0..self.len() {
+                    let id = #id_new(index as u32);
+                    #(
+                        {
+                            #dot_edges
+                        }
+                    )*
+                }
+                writeln!(writer, "}}")?;
+                writer.flush()
+            }
+        }
+    };
+
+    let ring = match layout_options.ring {
+        Some(capacity) => quote! {
+            /// The most nodes the layout holds: adding a node to a full layout overwrites the
+            /// oldest node in its slot
+            pub const RING_CAPACITY: usize = #capacity;
+
+            /// Returns `true` if the layout holds `RING_CAPACITY` nodes, so adding a node evicts
+            /// the oldest one
+            pub fn is_full(&self) -> bool {
+                self.len() >= #capacity
+            }
+
+            /// Returns an iterator over the id of every node in the layout, from the oldest node to
+            /// the newest, which is the order of `iter_ids()` until the layout is full
+            pub fn iter_ids_by_age(&self) -> core::iter::Map<core::iter::Chain<core::ops::Range<u32>, core::ops::Range<u32>>, fn(u32) -> #id_ty> {
+                let head = self._ring.head() as u32;
+                (head..self.len() as u32).chain(0..head).map(#id_new)
+            }
+
+            /// Hand every node evicted from now on to `callback`, oldest first, replacing the
+            /// previous callback. Evicted nodes are dropped without a callback.
+            pub fn on_evict(&mut self, callback: impl FnMut(#struct_ident #impl_generics) + Send + Sync + 'static) {
+                self._ring.set(callback);
+            }
+        },
+        None => quote! {},
+    };
+
+    // Moving the last node into the place of the node removed by `swap_remove()` writes every
+    // field of that node, then removes the last node
+    let notify_swap_removed = if layout_options.watch || stamps_rows {
+        let removed = notify(quote! { Removed(#id_new(last as u32)) });
+        let version_removed = version(quote! { removed(last) });
+        quote! {
+            #version_removed
+            if index != last {
+                #(#changed_at)*
+            }
+            #removed
+        }
+    } else {
+        quote! {}
+    };
+    let notify_retained = if layout_options.watch || stamps_rows {
+        quote! {
+            for (index, keep) in keep.iter().enumerate().rev() {
+                if !*keep {
+                    #notify_removed
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Taking the field of the node removed by `swap_remove()` out of a struct-of-arrays layout,
+    // replacing it with the field of the last node, already removed as `moved`. Each column is
+    // taken once, so a group column takes the fields of the group together.
+    let soa_swap_takes: Vec<_> = column_members
+        .iter()
+        .zip(&column_member_fields)
+        .map(|(member, members)| {
+            let index = members[0];
+            if set_only[index] {
+                let read = &soa_cloned_reads[index];
+                quote! {
+                    let removed = #read;
+                    self.#member.set(index, moved);
+                    removed
+                }
+            } else {
+                quote! { core::mem::replace(&mut self.#member[index], moved) }
+            }
+        })
+        .collect();
+    let swap_cloned_types: Vec<_> = (0..fields.len())
+        .filter(|i| set_only[*i] && !by_value[*i])
+        .map(|i| field_types[i])
+        .collect();
+
+    // Once a `ring` layout is full, adding a node overwrites the oldest node in its slot (the head
+    // of the ring), hands it to the eviction callback and moves the head to the next slot, so the
+    // Ids of the other nodes don't change. Given the column values of the new node, returns early
+    // with the Id of its slot after running `then`.
+    let ring_overwrite = |values: &[TokenStream2], then: TokenStream2| {
+        let Some(capacity) = layout_options.ring else {
+            return quote! {};
+        };
+        let overwrite = match layout {
+            Layout::StructOfArrays => quote! {
+                #(
+                    let #member_locals = {
+                        let moved = #values;
+                        #soa_swap_takes
+                    };
+                )*
+                let node = #struct_ident {
+                    #(
+                        #field_names: #fields_of_locals,
+                    )*
+                };
+            },
+            Layout::ArrayOfStructs => quote! {
+                let node = core::mem::replace(&mut self.data[index], #(#values)*);
+            },
+        };
+        quote! {
+            if self.len() >= #capacity {
+                let index = self._ring.head();
+                #overwrite
+                self._ring.overwritten(#capacity, node);
+                #(#changed_at)*
+
+                let id = #id_new(index as u32);
+                #then
+                return id;
+            }
+        }
+    };
+    let ring_add = match layout {
+        Layout::StructOfArrays => ring_overwrite(&item_members, quote! {}),
+        Layout::ArrayOfStructs => ring_overwrite(&[quote! { item }], quote! {}),
+    };
+    let ring_push_with = ring_overwrite(
+        &default_members,
+        quote! { f(self.get_mut(id).expect("Node was just added")); },
+    );
+    // Building a `ring` layout from more than `RING_CAPACITY` nodes at once only keeps the newest
+    // of them, as if they had been added in order: the `Vec`s of `columns` are drained from the
+    // front, and `ids` and `n` are cut down
+    let (ring_from_columns, ring_gather, ring_default_rows) = match layout_options.ring {
+        Some(capacity) => (
+            quote! {
+                let skip = len.saturating_sub(#capacity);
+                let mut columns = columns;
+                #(
+                    columns.#field_names.drain(..skip);
+                )*
+            },
+            quote! { let ids = &ids[ids.len().saturating_sub(#capacity)..]; },
+            quote! { let n = n.min(#capacity); },
+        ),
+        None => (quote! {}, quote! {}, quote! {}),
+    };
+
+    // A `ring` layout adds the nodes copied by `extend_from_layout()` one at a time once they don't
+    // fit, only returning the Ids of the nodes it still holds
+    let ring_extend = match layout_options.ring {
+        Some(capacity) => {
+            let owned = match layout {
+                Layout::StructOfArrays => {
+                    quote! { other.get(*id).expect("Id is in bounds").to_owned() }
+                }
+                Layout::ArrayOfStructs => quote! { other.data[id.0 as usize].clone() },
+            };
+            quote! {
+                if self.len() + ids.len() > #capacity {
+                    let new_ids: Vec<#id_ty> = ids.iter().map(|id| self.add(#owned)).collect();
+                    return new_ids[new_ids.len().saturating_sub(#capacity)..].to_vec();
+                }
+            }
+        }
+        None => quote! {},
+    };
+    let ring_cloned_types = if layout_options.ring.is_some() {
+        swap_cloned_types.clone()
+    } else {
+        Vec::new()
+    };
+    let ring_add_bounds = if ring_cloned_types.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            where
+                #(
+                    for<'__b> #ring_cloned_types: Clone,
+                )*
+        }
+    };
+
+    // Removing nodes from a full `ring` layout rotates the oldest node back to the first slot,
+    // given the number of nodes removed before the head, which also moves the Ids of `map`
+    let (ring_removed, ring_retained) = if layout_options.ring.is_some() {
+        (
+            quote! {
+                let mid = self._ring.removed(usize::from(index < self._ring.head()), self.len());
+                let map = if mid != 0 {
+                    map.then(&self.rotate_left(mid))
+                } else {
+                    map
+                };
+            },
+            quote! {
+                let before_head = keep[..self._ring.head()].iter().filter(|keep| !**keep).count();
+                let mid = self._ring.removed(before_head, self.len());
+                if mid != 0 {
+                    map = map.then(&self.rotate_left(mid));
+                }
+            },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    let offset_names: Vec<Ident> = field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("{}_offset", ident), ident.span()))
+        .collect();
+    let c_field_type = |ty: &Type| {
+        if is_id_type(ty) {
+            id_ident.to_string()
+        } else {
+            c_type(ty).unwrap_or("void").to_string()
+        }
+    };
+    let c_header = {
+        let guard = format!("{}_H", layout_struct_ident.to_string().to_uppercase());
+        let mut header = format!(
+            "#ifndef {guard}\n#define {guard}\n\n#include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n\
+             /* The index of a node in a {layout_struct_ident} */\ntypedef uint32_t {id_ident};\n\n\
+             typedef struct {descriptor_ident} {{\n    size_t len;\n"
+        );
+        match layout {
+            Layout::StructOfArrays => {
+                for i in &vec_fields {
+                    header += &format!(
+                        "    const {} *{};\n",
+                        c_field_type(field_types[*i]),
+                        field_names[*i]
+                    );
+                }
+            }
+            Layout::ArrayOfStructs => {
+                header += "    size_t stride;\n    const void *data;\n";
+                for (field_name, ty) in field_names.iter().zip(&field_types) {
+                    header += &format!(
+                        "    /* {} */\n    size_t {field_name}_offset;\n",
+                        c_field_type(ty)
+                    );
+                }
+            }
+        }
+        header + &format!("}} {descriptor_ident};\n\n#endif /* {guard} */\n")
+    };
+
+    // The fields with a `#[soa(atomic)]` column, with their atomic accessor names (e.g.
+    // get_field1_atomic, fetch_add_field1).
+    let atomic_fields: Vec<usize> = (0..fields.len())
+        .filter(|i| field_options[*i].storage == options::Storage::Atomic)
+        .collect();
+    let atomic_field_names: Vec<_> = atomic_fields.iter().map(|i| field_names[*i]).collect();
+    let atomic_field_types: Vec<_> = atomic_fields.iter().map(|i| field_types[*i]).collect();
+    let atomic_error_names: Vec<_> = atomic_fields.iter().map(|i| &error_names[*i]).collect();
+    let atomic_names = |format: &str| -> Vec<Ident> {
+        atomic_field_names
+            .iter()
+            .map(|ident| Ident::new(&format.replace("{}", &ident.to_string()), ident.span()))
+            .collect()
+    };
+    let get_atomic_names = atomic_names("get_{}_atomic");
+    let fetch_add_names = atomic_names("fetch_add_{}");
+    let load_acquire_names = atomic_names("load_{}_acquire");
+    let store_release_names = atomic_names("store_{}_release");
+
+    // Create the query predicate method names for each field (e.g. field1_eq, field1_gt).
+    let predicate_names = |suffix: &str| -> Vec<Ident> {
+        field_names
+            .iter()
+            .map(|ident| Ident::new(&format!("{}_{}", ident, suffix), ident.span()))
+            .collect()
+    };
+    let eq_names = predicate_names("eq");
+    let ne_names = predicate_names("ne");
+    let lt_names = predicate_names("lt");
+    let le_names = predicate_names("le");
+    let gt_names = predicate_names("gt");
+    let ge_names = predicate_names("ge");
+
+    // The fields with a `#[soa(bitmap_index)]`, with their bitmap method names (e.g. field1_bitmap).
+    let (bitmap_field_names, bitmap_field_types): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.storage == options::Storage::Bitmap)
+        .map(|(field, _)| (field.ident.as_ref().unwrap(), &field.ty))
+        .unzip();
+    let bitmap_names: Vec<Ident> = bitmap_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("{}_bitmap", ident), ident.span()))
+        .collect();
+
+    // The fields with a `#[soa(btree_index)]`, with their range method names (e.g. range_by_field1).
+    let (btree_field_names, btree_field_types): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.storage == options::Storage::BTree)
+        .map(|(field, _)| (field.ident.as_ref().unwrap(), &field.ty))
+        .unzip();
+    let range_by_names: Vec<Ident> = btree_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("range_by_{}", ident), ident.span()))
+        .collect();
+
+    // The fields with a `#[soa(search_index)]`, with their search method names (e.g. search_field1).
+    let (search_field_names, search_field_types): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.storage == options::Storage::Search)
+        .map(|(field, _)| (field.ident.as_ref().unwrap(), &field.ty))
+        .unzip();
+    let search_names: Vec<Ident> = search_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("search_{}", ident), ident.span()))
+        .collect();
+
+    // The fields with a `#[soa(bloom)]`, with their filter and membership method names (e.g.
+    // maybe_contains_field1 and contains_field1).
+    let (bloom_field_names, bloom_field_types): (Vec<_>, Vec<_>) = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.storage == options::Storage::Bloom)
+        .map(|(field, _)| (field.ident.as_ref().unwrap(), &field.ty))
+        .unzip();
+    let maybe_contains_names: Vec<Ident> = bloom_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("maybe_contains_{}", ident), ident.span()))
+        .collect();
+    let contains_names: Vec<Ident> = bloom_field_names
+        .iter()
+        .map(|ident| Ident::new(&format!("contains_{}", ident), ident.span()))
+        .collect();
+
+    // With the `roaring` feature, equality predicates on fields with a bitmap index narrow the
+    // candidate nodes of a query by intersecting bitmaps, and only the candidates are scanned.
+    let (query_candidates_field, query_candidates_init, query_candidates) =
+        if cfg!(feature = "roaring") {
+            (
+                quote! { candidates: Option<::soaaos::__private::roaring::RoaringBitmap>, },
+                quote! { candidates: None, },
+                quote! {
+                    let mut candidates: Option<Vec<#id_ty>> = self
+                        .candidates
+                        .as_ref()
+                        .map(|candidates| candidates.iter().map(#id_new).collect());
+                },
+            )
+        } else {
+            (
+                quote! {},
+                quote! {},
+                quote! { let mut candidates: Option<Vec<#id_ty>> = None; },
+            )
+        };
+    let eq_bodies: Vec<_> = field_names
+        .iter()
+        .zip(&field_types)
+        .map(|(field_name, ty)| {
+            match bitmap_field_names
+                .iter()
+                .position(|name| name == field_name)
+            {
+                Some(index) => {
+                    let bitmap_name = &bitmap_names[index];
+                    quote! {
+                        let matching = self.layout.#bitmap_name(&val);
+                        let mut query = self;
+                        query.candidates = Some(match query.candidates.take() {
+                            Some(candidates) => candidates & matching,
+                            None => matching,
+                        });
+                        query
+                    }
+                }
+                None => quote! {
+                    self.column(
+                        |layout| layout.#field_name(),
+                        move |field| core::borrow::Borrow::<#ty>::borrow(&field) == &val,
+                    )
+                },
+            }
+        })
+        .collect();
+
+    // Fields holding the Id of a node in another layout (`#[soa(references)]`). When that node is
+    // removed, the nodes referencing it are removed, or set to `None` for `Option` fields, and
+    // references to later nodes are shifted down to match the removal.
+    let cascades: Vec<_> = fields
+        .iter()
+        .zip(&field_options)
+        .zip(getter_names.iter().zip(&setter_names))
+        .filter(|((_, options), _)| options.references)
+        .map(|((field, _), (getter_name, setter_name))| {
+            let field_name = field.ident.as_ref().unwrap();
+            let field_type = &field.ty;
+            let cascade_name =
+                Ident::new(&format!("remove_cascade_{}", field_name), field_name.span());
+
+            match option_inner(&field.ty) {
+                Some(referenced) => quote! {
+                    /// Set this field to `None` on every node referencing the `removed` node of the
+                    /// referenced layout, shifting references to later nodes down to match.
+                    ///
+                    /// Returns the ids of the nodes set to `None`, in descending order.
+                    pub fn #cascade_name(&mut self, removed: #referenced) -> Vec<#id_ty> {
+                        let mut affected = Vec::new();
+
+                        for index in (0..self.len()).rev() {
+                            let id = #id_new(index as u32);
+                            let Some(mut reference) = self.#getter_name(id).unwrap().clone() else {
+                                continue;
+                            };
+
+                            if reference.0 == removed.0 {
+                                self.#setter_name(id, None).unwrap();
+                                affected.push(id);
+                            } else if reference.0 > removed.0 {
+                                reference.0 -= 1;
+                                self.#setter_name(id, Some(reference)).unwrap();
+                            }
+                        }
+
+                        affected
+                    }
+                },
+                None => quote! {
+                    /// Remove every node referencing the `removed` node of the referenced layout,
+                    /// shifting references to later nodes down to match.
+                    ///
+                    /// Returns the ids the removed nodes had, in descending order, so that they can
+                    /// be cascaded in turn to layouts referencing this one.
+                    pub fn #cascade_name(&mut self, removed: #field_type) -> Vec<#id_ty> {
+                        let mut affected = Vec::new();
+
+                        for index in (0..self.len()).rev() {
+                            let id = #id_new(index as u32);
+                            let mut reference = self.#getter_name(id).unwrap().clone();
+
+                            if reference.0 == removed.0 {
+                                self.remove(id).unwrap();
+                                affected.push(id);
+                            } else if reference.0 > removed.0 {
+                                reference.0 -= 1;
+                                self.#setter_name(id, reference).unwrap();
+                            }
+                        }
+
+                        affected
+                    }
+                },
+            }
+        })
+        .collect();
+
+    // The checks run on the staged layout when a transaction is committed: the values of
+    // `#[soa(unique)]` fields must be distinct, and `#[soa(references)]` fields holding an Id of
+    // this layout must reference a node that exists.
+    let unique_types: Vec<_> = fields
+        .iter()
+        .zip(&field_options)
+        .filter(|(_, options)| options.unique)
+        .map(|(field, _)| &field.ty)
+        .collect();
+    let transaction_checks: Vec<_> = fields
+        .iter()
+        .zip(&field_options)
+        .zip(getter_names.iter().zip(&field_name_strs))
+        .filter_map(|((field, options), (getter_name, name))| {
+            let unique = options.unique.then(|| {
+                quote! {
+                    let mut seen = std::collections::HashSet::new();
+                    for index in 0..staged.len() {
+                        if !seen.insert(staged.#getter_name(#id_new(index as u32)).unwrap()) {
+                            return Err(#transaction_error_ident::Duplicate { row: index, field: #name });
+                        }
+                    }
+                }
+            });
+
+            let is_own_id = |ty: &Type| {
+                matches!(ty, Type::Path(path) if path.path.segments.last().is_some_and(|segment| segment.ident == id_ident))
+            };
+            let reference = match option_inner(&field.ty) {
+                Some(inner) if is_own_id(inner) => Some(quote! { reference }),
+                None if is_own_id(&field.ty) => Some(quote! { Some(reference) }),
+                _ => None,
+            };
+            let references = reference.filter(|_| options.references).map(|reference| {
+                quote! {
+                    for index in 0..staged.len() {
+                        let reference = staged.#getter_name(#id_new(index as u32)).unwrap().clone();
+                        if matches!(#reference, Some(reference) if reference.0 as usize >= staged.len()) {
+                            return Err(#transaction_error_ident::DanglingReference { row: index, field: #name });
+                        }
+                    }
+                }
+            });
+
+            (unique.is_some() || references.is_some()).then(|| quote! { #unique #references })
+        })
+        .collect();
+
+    // A committed transaction swaps in the staged layout, keeping the subscribers of this one
+    let transaction_commit = if layout_options.watch {
+        quote! {
+            staged._subscribers = core::mem::take(&mut self._subscribers);
+            *self = staged;
+            self._subscribers.notify(::soaaos::watch::Event::Reordered);
+        }
+    } else {
+        quote! {
+            *self = staged;
+        }
+    };
+
+    // Hash the field names and types (FNV-1a) so that layouts built from different definitions of
+    // the struct can be told apart, even across binaries. The macro only sees the tokens of each
+    // type, so the types are hashed as written: `core::any::type_name` would resolve them, but it
+    // isn't usable in a `const`.
+    let schema_hash = fields
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, field| {
+            let ident = field.ident.as_ref().expect("Expected named field");
+            let entry = format!("{}:{};", ident, field.ty.to_token_stream());
+            entry.bytes().fold(hash, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+            })
+        });
+
+    // Serialization is only generated with the `serde` feature. Both layouts are serialized as a
+    // map of field name to column so that data saved by one layout can be loaded by the other, and
+    // so that renamed or missing fields can be handled by a migration when loading.
+    let serde = if cfg!(feature = "serde") {
+        let ty_generics = &_ty_generics;
+        let field_count = field_names.len();
+
+        // Local variables holding each deserialized column (e.g. field1_column).
+        let column_vars: Vec<Ident> = field_names
+            .iter()
+            .map(|ident| Ident::new(&format!("{}_column", ident), ident.span()))
+            .collect();
+
+        // A `ring` layout only keeps the newest of the deserialized nodes
+        let ring_deserialize = match layout_options.ring {
+            Some(capacity) => quote! {
+                let skip = len.saturating_sub(#capacity);
+                let len = len - skip;
+                #(
+                    let mut #column_vars = #column_vars;
+                    #column_vars.drain(..skip);
+                )*
+            },
+            None => quote! {},
+        };
+
+        // Create the migration hook names for each field (e.g. default_field1).
+        let default_names: Vec<Ident> = field_names
+            .iter()
+            .map(|ident| Ident::new(&format!("default_{}", ident), ident.span()))
+            .collect();
+
+        // The fields of a group are serialized as a column each, like every other field
+        let serialized_columns: Vec<_> = (0..fields.len())
+            .map(|index| {
+                let column = field_columns[index];
+                match &grouped[index] {
+                    Some((_, position)) => quote! {
+                        &::soaaos::__private::SerializeIter(self.#column.iter().map(|group| &group.#position))
+                    },
+                    None => quote! { &self.#column },
+                }
+            })
+            .collect();
+        let deserialized_members: Vec<_> = column_member_fields
+            .iter()
+            .zip(&column_member_groups)
+            .map(|(members, group)| {
+                let vars: Vec<_> = members.iter().map(|index| &column_vars[*index]).collect();
+                match group {
+                    Some(group) => quote! {
+                        {
+                            #(
+                                let mut #vars = #vars.into_iter();
+                            )*
+                            (0..len).map(|_| #group(#(#vars.next().unwrap()),*)).collect()
+                        }
+                    },
+                    None => quote! { #(#vars)*.into() },
+                }
+            })
+            .collect();
+
+        let (serialize_columns, build_layout) = match layout {
+            Layout::StructOfArrays => (
+                quote! {
+                    #(
+                        map.serialize_entry(#field_name_strs, #serialized_columns)?;
+                    )*
+                },
+                quote! {
+                    #layout_struct_ident {
+                        #(
+                            #column_members: #deserialized_members,
+                        )*
+                        #hidden_init
+                    }
+                },
+            ),
+            Layout::ArrayOfStructs => (
+                quote! {
+                    #(
+                        map.serialize_entry(
+                            #field_name_strs,
+                            &::soaaos::__private::SerializeIter(self.data.iter().map(|item| &item.#field_names)),
+                        )?;
+                    )*
+                },
+                quote! {
+                    {
+                        #(
+                            let mut #column_vars = #column_vars.into_iter();
+                        )*
+
+                        #layout_struct_ident {
+                            data: (0..len)
+                                .map(|_| #struct_ident {
+                                    #(
+                                        #field_names: #column_vars.next().unwrap(),
+                                    )*
+                                })
+                                .collect(),
+                            #hidden_init
+                        }
+                    }
+                },
+            ),
+        };
+
+        // Bounds on the field types are given an unused `for<'__b>` so that they aren't checked
+        // eagerly. A bound naming no generic parameter, such as `String: Copy`, is a "trivial
+        // bound", which rustc rejects where it is declared when it doesn't hold (unless the
+        // unstable `trivial_bounds` feature is enabled). Binding a lifetime the bound never uses
+        // makes rustc treat it as generic, so it is only checked where the method or impl is used:
+        // a field type that doesn't implement the trait silently leaves the impl or method
+        // unusable instead of failing to compile the layout. This relies on rustc not looking
+        // through the unused binder, and is what every `for<'__b>` bound in this file is for.
+        let mut serialize_generics = branded_generics.clone();
+        for ty in &field_types {
+            serialize_generics
+                .make_where_clause()
+                .predicates
+                .push(parse_quote!(for<'__b> #ty: ::soaaos::__private::serde::Serialize));
+        }
+        let (serialize_impl_generics, _, serialize_where_clause) =
+            serialize_generics.split_for_impl();
+
+        let mut deserialize_generics = branded_generics.clone();
+        deserialize_generics.params.insert(0, parse_quote!('de));
+        for ty in &field_types {
+            deserialize_generics
+                .make_where_clause()
+                .predicates
+                .push(parse_quote!(#ty: ::soaaos::__private::serde::Deserialize<'de>));
+        }
+        let (deserialize_impl_generics, _, deserialize_where_clause) =
+            deserialize_generics.split_for_impl();
+
+        // The visitor is generic over the migration as well as the struct's generics
+        let generic_args: Vec<_> = branded_generics
+            .params
+            .iter()
+            .map(|param| match param {
+                GenericParam::Type(param) => param.ident.to_token_stream(),
+                GenericParam::Lifetime(param) => param.lifetime.to_token_stream(),
+                GenericParam::Const(param) => param.ident.to_token_stream(),
+            })
+            .collect();
+        let mut visitor_generics = branded_generics.clone();
+        visitor_generics.params.push(parse_quote!(__M));
+        let (_, visitor_ty_generics, _) = visitor_generics.split_for_impl();
+        let mut visitor_impl_generics = deserialize_generics.clone();
+        visitor_impl_generics.params.push(parse_quote!(__M));
+        visitor_impl_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(__M: #migration_ident #ty_generics));
+        let (visitor_impl_generics, _, visitor_where_clause) =
+            visitor_impl_generics.split_for_impl();
+
+        quote! {
+            /// Hooks used to load a layout serialized with an older definition of the struct.
+            ///
+            /// Every hook defaults to doing nothing, so an implementation only needs to override the
+            /// hooks for the fields that changed.
+            pub trait #migration_ident #impl_generics #where_clause {
+                /// Map a field name found in the serialized data to the current name of the field.
+                /// Fields that are neither current nor renamed are skipped.
+                fn rename_field(name: &str) -> Option<&'static str> {
+                    let _ = name;
+                    None
+                }
+
+                /// Whether data serialized with the given schema hash can be loaded. Only the current
+                /// schema is accepted by default.
+                fn accept_schema(hash: u64) -> bool {
+                    hash == <#layout_struct_ident #ty_generics>::SCHEMA_HASH
+                }
+
+                #(
+                    /// The value of this field for every node when it is missing from the serialized
+                    /// data. Called once per node.
+                    fn #default_names() -> Option<#field_types> {
+                        None
+                    }
+                )*
+            }
+
+            /// The migration used by `Deserialize`, only accepting the current fields
+            impl #impl_generics #migration_ident #ty_generics for () #where_clause {}
+
+            impl #serialize_impl_generics ::soaaos::__private::serde::Serialize for #layout_ty #serialize_where_clause {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: ::soaaos::__private::serde::Serializer,
+                {
+                    use ::soaaos::__private::serde::ser::SerializeMap;
+
+                    let mut map = serializer.serialize_map(Some(#field_count + 1))?;
+                    map.serialize_entry("$schema_hash", &Self::SCHEMA_HASH)?;
+                    #serialize_columns
+                    map.end()
+                }
+            }
+
+            impl #deserialize_impl_generics ::soaaos::__private::serde::Deserialize<'de> for #layout_ty #deserialize_where_clause {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: ::soaaos::__private::serde::Deserializer<'de>,
+                {
+                    Self::deserialize_with::<(), D>(deserializer)
+                }
+            }
+
+            impl #layout_impl_generics #layout_ty #where_clause {
+                /// Deserialize a layout, using the hooks in `M` to load renamed or missing fields
+                pub fn deserialize_with<'de, M, D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    M: #migration_ident #ty_generics,
+                    D: ::soaaos::__private::serde::Deserializer<'de>,
+                    #(
+                        #field_types: ::soaaos::__private::serde::Deserialize<'de>,
+                    )*
+                {
+                    deserializer.deserialize_map(#visitor_ident::<#(#generic_args,)* M>(core::marker::PhantomData))
+                }
+            }
+
+            #[doc(hidden)]
+            pub struct #visitor_ident #visitor_ty_generics (core::marker::PhantomData<fn() -> (__M, #layout_ty)>) #where_clause;
+
+            impl #visitor_impl_generics ::soaaos::__private::serde::de::Visitor<'de> for #visitor_ident #visitor_ty_generics #visitor_where_clause {
+                type Value = #layout_ty;
+
+                fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    write!(f, "a map of columns for {}", stringify!(#layout_struct_ident))
+                }
+
+                fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+                where
+                    A: ::soaaos::__private::serde::de::MapAccess<'de>,
+                {
+                    use ::soaaos::__private::serde::de::{Error, IgnoredAny};
+
+                    #(
+                        let mut #column_vars: Option<Vec<#field_types>> = None;
+                    )*
+
+                    while let Some(key) = map.next_key::<String>()? {
+                        if key == "$schema_hash" {
+                            let hash = map.next_value::<u64>()?;
+                            if !__M::accept_schema(hash) {
+                                return Err(A::Error::custom(format_args!(
+                                    "schema hash mismatch for {}: found {:#018x}, expected {:#018x}",
+                                    stringify!(#layout_struct_ident),
+                                    hash,
+                                    <#layout_ty>::SCHEMA_HASH
+                                )));
+                            }
+                            continue;
+                        }
+
+                        let name = match key.as_str() {
+                            #(
+                                #field_name_strs => #field_name_strs,
+                            )*
+                            other => match __M::rename_field(other) {
+                                Some(name) => name,
+                                None => {
+                                    map.next_value::<IgnoredAny>()?;
+                                    continue;
+                                }
+                            },
+                        };
+
+                        match name {
+                            #(
+                                #field_name_strs => {
+                                    if #column_vars.is_some() {
+                                        return Err(A::Error::duplicate_field(#field_name_strs));
+                                    }
+                                    #column_vars = Some(map.next_value()?);
+                                }
+                            )*
+                            _ => {
+                                map.next_value::<IgnoredAny>()?;
+                            }
+                        }
+                    }
+
+                    // The number of nodes is taken from the first column that is present
+                    let len = [#(#column_vars.as_ref().map(Vec::len),)*]
+                        .into_iter()
+                        .flatten()
+                        .next()
+                        .unwrap_or(0);
+
+                    #(
+                        let #column_vars = match #column_vars {
+                            Some(column) => column,
+                            None => {
+                                let Some(default) = __M::#default_names() else {
+                                    return Err(A::Error::missing_field(#field_name_strs));
+                                };
+
+                                core::iter::once(default)
+                                    .chain(core::iter::from_fn(__M::#default_names))
+                                    .take(len)
+                                    .collect()
+                            }
+                        };
+
+                        if #column_vars.len() != len {
+                            return Err(A::Error::custom(format_args!(
+                                "column `{}` has {} nodes but expected {}",
+                                #field_name_strs,
+                                #column_vars.len(),
+                                len
+                            )));
+                        }
+                    )*
+                    #ring_deserialize
+
+                    Ok(#build_layout)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The JSON Patch diff is only generated with the `json` feature
+    let diff_json_patch = if cfg!(feature = "json") {
+        quote! {
+            /// Returns the changes from this layout to `other` as a JSON Patch (RFC 6902) against
+            /// the serialized layout: a `replace` of each changed element, then a `remove` of each
+            /// row only in this layout and an `add` of each row only in `other`.
+            pub fn diff_json_patch(&self, other: &Self) -> Result<::soaaos::__private::serde_json::Value, ::soaaos::__private::serde_json::Error>
+            where
+                #(
+                    for<'__b> #field_types: ::soaaos::__private::serde::Serialize + PartialEq,
+                )*
+            {
+                use ::soaaos::__private::serde_json::{json, to_value, Value};
+
+                let mut patch = Vec::new();
+                let shared = self.len().min(other.len());
+
+                #(
+                    for index in 0..shared {
+                        let id = #id_new(index as u32);
+                        let value = other.#getter_names(id).unwrap();
+                        if self.#getter_names(id).unwrap() != value {
+                            patch.push(json!({
+                                "op": "replace",
+                                "path": format!("/{}/{index}", #field_name_strs),
+                                "value": to_value(value)?,
+                            }));
+                        }
+                    }
+                )*
+
+                for index in (shared..self.len()).rev() {
+                    #(
+                        patch.push(json!({
+                            "op": "remove",
+                            "path": format!("/{}/{index}", #field_name_strs),
+                        }));
+                    )*
+                }
+
+                for index in shared..other.len() {
+                    let id = #id_new(index as u32);
+                    #(
+                        patch.push(json!({
+                            "op": "add",
+                            "path": format!("/{}/-", #field_name_strs),
+                            "value": to_value(other.#getter_names(id).unwrap())?,
+                        }));
+                    )*
+                }
+
+                Ok(Value::Array(patch))
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The chunked persistence is only generated with the `json` feature. Each chunk is written as
+    // a line holding a serialized layout of its rows, so it is loaded with `Deserialize`.
+    let json_chunks = if cfg!(feature = "json") {
+        let field_count = field_names.len();
+
+        // The bounds of `extend_from_layout`, used to add the nodes of a chunk
+        let extend_bounds = match layout {
+            Layout::StructOfArrays => quote! {
+                #(
+                    for<'__b> #field_types: Clone,
+                )*
+            },
+            Layout::ArrayOfStructs => quote! {
+                for<'__b> #struct_ident #impl_generics: Clone,
+            },
+        };
+
+        quote! {
+            /// Write the nodes of the layout as chunks of `rows_per_chunk` nodes, each on its own
+            /// line as a serialized layout. The nodes are serialized in place, so at most a single
+            /// chunk is buffered by the writer. Returns the number of chunks written.
+            ///
+            /// Panics if `rows_per_chunk` is zero.
+            pub fn write_chunks(&self, mut writer: impl std::io::Write, rows_per_chunk: usize) -> std::io::Result<usize>
+            where
+                #(
+                    for<'__b> #field_types: ::soaaos::__private::serde::Serialize,
+                )*
+            {
+                use ::soaaos::__private::serde::ser::{SerializeMap, Serializer as _};
+
+                assert!(rows_per_chunk > 0, "rows_per_chunk must be greater than zero");
+
+                let mut chunks = 0;
+                for start in (0..self.len()).step_by(rows_per_chunk) {
+                    let rows = start..(start + rows_per_chunk).min(self.len());
+                    let mut serializer = ::soaaos::__private::serde_json::Serializer::new(&mut writer);
+                    let mut map = serializer.serialize_map(Some(#field_count + 1))?;
+                    map.serialize_entry("$schema_hash", &Self::SCHEMA_HASH)?;
+                    #(
+                        map.serialize_entry(
+                            #field_name_strs,
+                            &::soaaos::__private::SerializeIter(
+                                rows.clone().map(|index| self.#getter_names(#id_new(index as u32)).unwrap()),
+                            ),
+                        )?;
+                    )*
+                    map.end()?;
+                    writer.write_all(b"\n")?;
+                    chunks += 1;
+                }
+
+                writer.flush()?;
+                Ok(chunks)
+            }
+
+            /// Read the next chunk written by [`write_chunks`](Self::write_chunks) and add its nodes
+            /// to the end of the layout. Returns `false` once the reader is exhausted, so a layout
+            /// is loaded with `while layout.append_chunk(&mut reader)? {}`, and loading can be
+            /// stopped and resumed between any two chunks.
+            pub fn append_chunk(&mut self, reader: &mut impl std::io::BufRead) -> std::io::Result<bool>
+            where
+                #(
+                    for<'__b> #field_types: ::soaaos::__private::serde::de::DeserializeOwned,
+                )*
+                #extend_bounds
+            {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(false);
+                }
+
+                let chunk: Self = ::soaaos::__private::serde_json::from_str(&line)?;
+                let ids: Vec<#id_ty> = chunk.iter_ids().collect();
+                self.extend_from_layout(&chunk, &ids);
+                Ok(true)
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The colored diff is only generated with the `color` feature
+    let diff_pretty = if cfg!(feature = "color") {
+        quote! {
+            /// Returns the diff between two layouts rendered with ANSI colors, grouped by row, with
+            /// up to `context` unchanged rows shown around each changed row. Rows only in one of
+            /// the layouts are shown as removed or added.
+            pub fn diff_pretty(&self, other: &Self, context: usize) -> Option<String>
+            where
+                #(
+                    for<'__b> #field_types: PartialEq + core::fmt::Debug,
+                )*
+            {
+                let row = |layout: &Self, index: usize| {
+                    let id = #id_new(index as u32);
+                    (index < layout.len()).then(|| vec![
+                        #(
+                            format!("{:?}", layout.#getter_names(id).unwrap()),
+                        )*
+                    ])
+                };
+
+                ::soaaos::__private::render_diff(
+                    &[#(#field_name_strs),*],
+                    self.len().max(other.len()),
+                    |index| {
+                        let id = #id_new(index as u32);
+                        index >= self.len() || index >= other.len()
+                            #(|| self.#getter_names(id).unwrap() != other.#getter_names(id).unwrap())*
+                    },
+                    |index| (row(self, index), row(other, index)),
+                    context,
+                )
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Async stream adaptors are only generated with the `futures` feature
+    let futures = if cfg!(feature = "futures") {
+        quote! {
+            /// Returns a stream of views of every node in the layout
+            pub fn stream(&self) -> impl ::soaaos::__private::futures_util::stream::Stream<Item = #struct_ident_ref #generics_with_ellided_lifetime> {
+                ::soaaos::__private::futures_util::stream::iter(self.iter())
+            }
+
+            /// Build a layout from every node produced by the given stream.
+            ///
+            /// The layout is pre-allocated using the lower bound of the stream's size hint.
+            pub async fn from_stream(stream: impl ::soaaos::__private::futures_util::stream::Stream<Item = #struct_ident #impl_generics>) -> Self {
+                use ::soaaos::__private::futures_util::stream::StreamExt;
+
+                let mut stream = core::pin::pin!(stream);
+                let mut layout = Self::with_capacity(stream.size_hint().0);
+
+                while let Some(item) = stream.next().await {
+                    layout.add(item);
+                }
+
+                layout
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // A branded layout can be created in a scope, branded with a lifetime unique to the closure.
+    // The method is only on the layouts of the default brand, so the brand of `NodesLayout` in
+    // `NodesLayout::scope` is inferred.
+    let scope_method = if brand.is_some() {
+        let user_args: Vec<_> = generics
+            .params
+            .iter()
+            .map(|param| match param {
+                GenericParam::Type(param) => param.ident.to_token_stream(),
+                GenericParam::Lifetime(param) => param.lifetime.to_token_stream(),
+                GenericParam::Const(param) => param.ident.to_token_stream(),
+            })
+            .collect();
+        quote! {
+            impl #impl_generics #layout_struct_ident<#(#user_args,)* ()> #where_clause {
+                /// Call `f` with a new layout branded with a lifetime unique to this call, so
+                /// its Ids can't be used with any other scoped layout, nor outlive the closure
+                pub fn scope<__R>(
+                    f: impl for<'__id> FnOnce(#layout_struct_ident<#(#user_args,)* ::soaaos::brand::Scope<'__id>>) -> __R,
+                ) -> __R {
+                    f(#layout_struct_ident::new())
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The parameters of the sharded layout alias, without bounds since type aliases don't check
+    // them
+    let alias_params: Vec<_> = layout_def_generics
+        .params
+        .iter()
+        .map(|param| match param {
+            GenericParam::Type(param) => {
+                let ident = &param.ident;
+                match &param.default {
+                    Some(default) => quote! { #ident = #default },
+                    None => quote! { #ident },
+                }
+            }
+            GenericParam::Lifetime(param) => {
+                let lifetime = &param.lifetime;
+                quote! { #lifetime }
+            }
+            GenericParam::Const(param) => {
+                let ident = &param.ident;
+                let ty = &param.ty;
+                quote! { const #ident: #ty }
+            }
+        })
+        .collect();
+
+    // Rows are only hashed (to find the rows of a `Derived` layout to recompute) when every field
+    // implements `Hash`
+    let mut hash_generics = branded_generics.clone();
+    for ty in &field_types {
+        hash_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(for<'__b> #ty: core::hash::Hash));
+    }
+    let (_, _, hash_where_clause) = hash_generics.split_for_impl();
+
+    // Create the code that is used in both struct-of-arrays and array-of-structs
+    // Reordering a struct-of-arrays layout gathers every column, cloning the elements
+    let reorder_bounds = match layout {
+        Layout::StructOfArrays => quote! {
+            #(
+                for<'__b> #field_types: Clone,
+            )*
+        },
+        Layout::ArrayOfStructs => quote! {},
+    };
+
+    // Gathering each column of a struct-of-arrays layout to `order` on a task of `scope`
+    let par_column_bounds = quote! {
+        #(
+            for<'__b> #column_member_types: Send,
+        )*
+        #(
+            for<'__b> #field_types: Clone,
+        )*
+    };
+    let par_column_gathers = quote! {
+        #(
+            scope.spawn(|_| {
+                let column: #column_member_types = order
+                    .iter()
+                    .map(|id| id.0 as usize)
+                    .map(|index| #soa_member_cloned_reads)
+                    .collect();
+                self.#column_members = column;
+            });
+        )*
+    };
+
+    // With the `rayon` feature, the order of the nodes is sorted in parallel, then each column of
+    // a struct-of-arrays layout is gathered to it on its own task
+    let par_sorts = if !cfg!(feature = "rayon") {
+        quote! {}
+    } else {
+        match layout {
+            Layout::StructOfArrays => quote! {
+                #(
+                    /// Sort the nodes of the layout by this field, keeping the order of nodes with
+                    /// equal values. The order is sorted in parallel, then every column is moved to
+                    /// it on its own rayon task. Returns the map of the moved Ids.
+                    pub fn #par_sort_by_names(&mut self) -> #id_map_ty
+                    where
+                        for<'__b> #field_types: Ord,
+                        for<'__b> Self: Sync,
+                        #par_column_bounds
+                    {
+                        use ::soaaos::__private::rayon::slice::ParallelSliceMut;
+
+                        let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                        order.par_sort_by(|a, b| {
+                            let a = self.#getter_names(*a).expect("Id is in bounds");
+                            let b = self.#getter_names(*b).expect("Id is in bounds");
+                            core::borrow::Borrow::<#field_types>::borrow(&a).cmp(core::borrow::Borrow::<#field_types>::borrow(&b))
+                        });
+
+                        let order = &order;
+                        ::soaaos::__private::rayon::scope(|scope| {
+                            #par_column_gathers
+                        });
+                        #notify_reordered
+                        #id_map_ident::from_order(order)
+                    }
+                )*
+            },
+            Layout::ArrayOfStructs => quote! {
+                #(
+                    /// Sort the nodes of the layout by this field in parallel, keeping the order of
+                    /// nodes with equal values. Returns the map of the moved Ids.
+                    pub fn #par_sort_by_names(&mut self) -> #id_map_ty
+                    where
+                        for<'__b> #field_types: Ord,
+                        for<'__b> #struct_ident #impl_generics: Sync,
+                    {
+                        use ::soaaos::__private::rayon::slice::ParallelSliceMut;
+
+                        let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                        order.par_sort_by(|a, b| self.data[a.0 as usize].#field_names.cmp(&self.data[b.0 as usize].#field_names));
+                        self.reorder(&order)
+                    }
+                )*
+            },
+        }
+    };
+
+    // The maps of the Ids moved by reversing and rotating the nodes, once they are moved
+    let reversed_map = quote! {
+        let last = self.len() as u32 - 1;
+        #id_map_ident::from_fn(self.len(), |old| Some(#id_new(last - old.0)))
+    };
+    let rotated_left_map = quote! {
+        let len = self.len();
+        #id_map_ident::from_fn(len, |old| Some(#id_new(((old.0 as usize + len - mid) % len) as u32)))
+    };
+    let rotated_right_map = quote! {
+        let len = self.len();
+        #id_map_ident::from_fn(len, |old| Some(#id_new(((old.0 as usize + k) % len) as u32)))
+    };
+
+    // Panics unless `order` holds every id of the layout exactly once
+    let check_order = quote! {
+        let len = self.len();
+        let mut seen = vec![false; len];
+        assert!(
+            order.len() == len
+                && order.iter().all(|id| (id.0 as usize) < len && !core::mem::replace(&mut seen[id.0 as usize], true)),
+            "order must hold every id of the layout exactly once"
+        );
+    };
+
+    // With `derives(...)`, exactly the listed traits are derived on the layout. Otherwise `Debug`,
+    // `Clone`, `PartialEq`, `Eq` and `Hash` are implemented with bounds on the column types (see
+    // the `Serialize` impl), so a field type missing one of them (`f32` isn't `Eq` or `Hash`) only
+    // drops that impl. The row views are `Debug` under the same condition.
+    let (member_names, member_types) = match layout {
+        Layout::StructOfArrays => (
+            column_members.iter().map(|name| quote! { #name }).collect(),
+            column_member_types
+                .iter()
+                .map(|ty| quote! { #ty })
+                .collect(),
+        ),
+        Layout::ArrayOfStructs => (
+            vec![quote! { data }],
+            vec![quote! { Vec<#struct_ident #impl_generics> }],
+        ),
+    };
+    let mut all_member_names: Vec<TokenStream2> = member_names.clone();
+    if brand.is_some() {
+        all_member_names.push(quote! { _brand });
+    }
+    if layout_options.watch {
+        all_member_names.push(quote! { _subscribers });
+    }
+    // The stats and the row index are only bookkeeping, kept out of the `Debug` output of a layout
+    let debug_member_names = all_member_names.clone();
+    if layout_options.hashcons {
+        all_member_names.push(quote! { _hashcons });
+    }
+    if key_field.is_some() {
+        all_member_names.push(quote! { _key_index });
+    }
+    if layout_options.ring.is_some() {
+        all_member_names.push(quote! { _ring });
+    }
+    if layout_options.versioned {
+        all_member_names.push(quote! { _versions });
+    }
+    if layout_options.epochs {
+        all_member_names.push(quote! { _epochs });
+    }
+    if cfg!(feature = "stats") {
+        all_member_names.push(quote! { _stats });
+    }
+
+    let bounded_generics = |generics: &Generics, types: &[TokenStream2], bound: TokenStream2| {
+        let mut generics = generics.clone();
+        for ty in types {
+            generics
+                .make_where_clause()
+                .predicates
+                .push(parse_quote!(for<'__b> #ty: #bound));
+        }
+        generics
+    };
+
+    let layout_derives = match &layout_options.derives {
+        Some(derives) => quote! { #[derive(#(#derives),*)] },
+        None => quote! {},
+    };
+    let layout_trait_impls = if layout_options.derives.is_none() {
+        let debug_generics = bounded_generics(
+            &branded_generics,
+            &member_types,
+            quote! { core::fmt::Debug },
+        );
+        let clone_generics = bounded_generics(&branded_generics, &member_types, quote! { Clone });
+        let eq_generics = bounded_generics(&branded_generics, &member_types, quote! { PartialEq });
+        let full_eq_generics = bounded_generics(&branded_generics, &member_types, quote! { Eq });
+        let hash_generics = bounded_generics(
+            &branded_generics,
+            &member_types,
+            quote! { core::hash::Hash },
+        );
+        let (debug_impl_generics, _, debug_where_clause) = debug_generics.split_for_impl();
+        let (clone_impl_generics, _, clone_where_clause) = clone_generics.split_for_impl();
+        let (eq_impl_generics, _, eq_where_clause) = eq_generics.split_for_impl();
+        let (full_eq_impl_generics, _, full_eq_where_clause) = full_eq_generics.split_for_impl();
+        let (hash_impl_generics, _, hash_where_clause) = hash_generics.split_for_impl();
+
+        // Layouts are ordered by their rows, like a `Vec` of the struct, each row comparing its
+        // fields in order. Comparing the rows rather than the members keeps SoA and AoS layouts
+        // ordered the same way.
+        let row_types: Vec<TokenStream2> = field_types.iter().map(|ty| quote! { #ty }).collect();
+        let mut ord_generics = bounded_generics(&branded_generics, &row_types, quote! { Ord });
+        ord_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(for<'__b> #layout_ty: Eq));
+        let mut partial_ord_generics =
+            bounded_generics(&branded_generics, &row_types, quote! { PartialOrd });
+        partial_ord_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(for<'__b> #layout_ty: PartialEq));
+        let (ord_impl_generics, _, ord_where_clause) = ord_generics.split_for_impl();
+        let (partial_ord_impl_generics, _, partial_ord_where_clause) =
+            partial_ord_generics.split_for_impl();
+
+        quote! {
+            impl #debug_impl_generics core::fmt::Debug for #layout_ty #debug_where_clause {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    f.debug_struct(stringify!(#layout_struct_ident))
+                        #(
+                            .field(stringify!(#debug_member_names), &self.#debug_member_names)
+                        )*
+                        .finish()
+                }
+            }
+
+            impl #clone_impl_generics Clone for #layout_ty #clone_where_clause {
+                fn clone(&self) -> Self {
+                    Self {
+                        #(
+                            #all_member_names: Clone::clone(&self.#all_member_names),
+                        )*
+                    }
+                }
+            }
+
+            impl #eq_impl_generics PartialEq for #layout_ty #eq_where_clause {
+                fn eq(&self, other: &Self) -> bool {
+                    true #(&& self.#all_member_names == other.#all_member_names)*
+                }
+            }
+
+            impl #full_eq_impl_generics Eq for #layout_ty #full_eq_where_clause {}
+
+            impl #hash_impl_generics core::hash::Hash for #layout_ty #hash_where_clause {
+                fn hash<__H: core::hash::Hasher>(&self, state: &mut __H) {
+                    #(
+                        core::hash::Hash::hash(&self.#all_member_names, state);
+                    )*
+                }
+            }
+
+            impl #partial_ord_impl_generics PartialOrd for #layout_ty #partial_ord_where_clause {
+                fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+                    for (row, other_row) in self.iter().zip(other.iter()) {
+                        #(
+                            // Spelled out so the impls of other crates comparing the field
+                            // types with their own types can't make the operand type ambiguous
+                            match PartialOrd::partial_cmp(&row.#field_names, &other_row.#field_names)? {
+                                core::cmp::Ordering::Equal => {}
+                                ordering => return Some(ordering),
+                            }
+                        )*
+                    }
+                    Some(self.len().cmp(&other.len()))
+                }
+            }
+
+            impl #ord_impl_generics Ord for #layout_ty #ord_where_clause {
+                fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+                    for (row, other_row) in self.iter().zip(other.iter()) {
+                        #(
+                            match row.#field_names.cmp(&other_row.#field_names) {
+                                core::cmp::Ordering::Equal => {}
+                                ordering => return ordering,
+                            }
+                        )*
+                    }
+                    self.len().cmp(&other.len())
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let view_debug = layout_options
+        .derives
+        .as_ref()
+        .is_none_or(|derives| derives.iter().any(|path| path.is_ident("Debug")));
+    let view_trait_impls = if view_debug {
+        let ref_types: Vec<TokenStream2> = ref_types.iter().map(|ty| quote! { #ty }).collect();
+        let mut_types: Vec<TokenStream2> = mut_field_types
+            .iter()
+            .map(|ty| quote! { &#lifetime mut #ty })
+            .collect();
+        let ref_generics = bounded_generics(
+            &generics_with_lifetime,
+            &ref_types,
+            quote! { core::fmt::Debug },
+        );
+        let mut_generics = bounded_generics(
+            &generics_with_lifetime,
+            &mut_types,
+            quote! { core::fmt::Debug },
+        );
+        let (_, view_ty_generics, _) = generics_with_lifetime.split_for_impl();
+        let (ref_impl_generics, _, ref_where_clause) = ref_generics.split_for_impl();
+        let (mut_impl_generics, _, mut_where_clause) = mut_generics.split_for_impl();
+
+        quote! {
+            impl #ref_impl_generics core::fmt::Debug for #struct_ident_ref #view_ty_generics #ref_where_clause {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    f.debug_struct(stringify!(#struct_ident_ref))
+                        #(
+                            .field(stringify!(#field_names), &self.#field_names)
+                        )*
+                        .finish()
+                }
+            }
+
+            impl #mut_impl_generics core::fmt::Debug for #struct_ident_ref_mut #view_ty_generics #mut_where_clause {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    f.debug_struct(stringify!(#struct_ident_ref_mut))
+                        #(
+                            .field(stringify!(#mut_field_names), &self.#mut_field_names)
+                        )*
+                        .finish()
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The views display as a single line of `field=value` pairs, for logging rows in a loop
+    let display_format = |names: &[&Ident]| {
+        let format = names
+            .iter()
+            .map(|name| format!("{name}={{}}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        LitStr::new(&format, struct_ident.span())
+    };
+    let ref_display_format = display_format(&field_names);
+    let mut_display_format = display_format(&mut_field_names);
+    let ref_display_types: Vec<TokenStream2> = ref_types.iter().map(|ty| quote! { #ty }).collect();
+    let mut_display_types: Vec<TokenStream2> = mut_field_types
+        .iter()
+        .map(|ty| quote! { &#lifetime mut #ty })
+        .collect();
+    let ref_display_generics = bounded_generics(
+        &generics_with_lifetime,
+        &ref_display_types,
+        quote! { core::fmt::Display },
+    );
+    let mut_display_generics = bounded_generics(
+        &generics_with_lifetime,
+        &mut_display_types,
+        quote! { core::fmt::Display },
+    );
+    let (ref_display_impl_generics, _, ref_display_where_clause) =
+        ref_display_generics.split_for_impl();
+    let (mut_display_impl_generics, _, mut_display_where_clause) =
+        mut_display_generics.split_for_impl();
+    let (_, view_display_ty_generics, _) = generics_with_lifetime.split_for_impl();
+    let view_display = quote! {
+        impl #ref_display_impl_generics core::fmt::Display for #struct_ident_ref #view_display_ty_generics #ref_display_where_clause {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(f, #ref_display_format, #(self.#field_names),*)
+            }
+        }
+
+        impl #mut_display_impl_generics core::fmt::Display for #struct_ident_ref_mut #view_display_ty_generics #mut_display_where_clause {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                write!(f, #mut_display_format, #(self.#mut_field_names),*)
+            }
+        }
+    };
+
+    // The views convert back to owned nodes without going through the layout: cloning every field
+    // of a `NodeRef`, or swapping every field of a `NodeRefMut`, which only holds every field when
+    // none of them is frozen or written with `set_*` alone
+    let (view_impl_generics, view_ty_generics, _) = generics_with_lifetime.split_for_impl();
+    let owned_reads: Vec<_> = field_names
+        .iter()
+        .zip(&field_types)
+        .zip(&by_value)
+        .map(|((field_name, ty), by_value)| {
+            if *by_value {
+                quote! { <#ty as Clone>::clone(&self.#field_name) }
+            } else {
+                quote! { <#ty as Clone>::clone(self.#field_name) }
+            }
+        })
+        .collect();
+    let replace = if mut_fields.len() == fields.len() {
+        quote! {
+            impl #view_impl_generics #struct_ident_ref_mut #view_ty_generics #where_clause {
+                /// Overwrite every field of the node with the fields of `node`, returning the node
+                /// it replaced
+                pub fn replace(&mut self, node: #struct_ident #impl_generics) -> #struct_ident #impl_generics {
+                    #struct_ident {
+                        #(
+                            #field_names: core::mem::replace(self.#field_names, node.#field_names),
+                        )*
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let view_conversions = quote! {
+        impl #view_impl_generics #struct_ident_ref #view_ty_generics #where_clause {
+            /// Returns an owned copy of the node, cloning each field
+            pub fn to_owned(&self) -> #struct_ident #impl_generics
+            where
+                #(
+                    for<'__b> #field_types: Clone,
+                )*
+            {
+                #struct_ident {
+                    #(
+                        #field_names: #owned_reads,
+                    )*
+                }
+            }
+        }
+
+        #replace
+    };
+
+    let debug_stats = if cfg!(feature = "stats") {
+        quote! {
+            /// Returns the high-water mark of the layout, and the capacity, reallocations and
+            /// growth events of each column with a capacity
+            pub fn debug_stats(&self) -> ::soaaos::stats::DebugStats {
+                self._stats.stats(self.len(), &[#(#tracked_names),*], #tracked_capacities)
+            }
+
+            /// Returns the heap memory allocated by each column, named after the layout and the
+            /// field, to attribute the heap usage of a profile to the generated columns
+            pub fn allocation_report(&self) -> ::soaaos::stats::AllocationReport {
+                ::soaaos::stats::AllocationReport {
+                    layout: stringify!(#layout_struct_ident),
+                    columns: vec![
+                        #(
+                            ::soaaos::stats::ColumnAllocation {
+                                name: stringify!(#member_names),
+                                bytes: ::soaaos::stats::HeapSize::heap_bytes(&self.#member_names),
+                            },
+                        )*
+                    ],
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Bulk loading reserves room in the columns with a capacity (see `tracked_columns`), then adds
+    // each row with `add`
+    let bulk_load = quote! {
+        /// Add every row of `rows` as configured by `options`: sorted, deduplicated by key, with
+        /// room reserved up front and progress reported as rows are added. Returns the number of
+        /// rows added.
+        pub fn bulk_load(&mut self, rows: impl IntoIterator<Item = #struct_ident #impl_generics>, options: ::soaaos::bulk::BulkLoadOptions<'_, #struct_ident #impl_generics>) -> usize {
+            options.load(
+                self,
+                rows,
+                |layout, additional| {
+                    #(
+                        layout.#tracked_columns.reserve(additional);
+                    )*
+                },
+                |layout, row| {
+                    layout.add(row);
+                },
+            )
+        }
+    };
+
+    // Each `extract(Name = (field, ...))` generates the struct `Name` of only these fields, with
+    // a layout of the same kind, filled by `extract::<Name>()` with copies of their columns
+    if !layout_options.extracts.is_empty() && !generics.params.is_empty() {
+        return syn::Error::new_spanned(
+            &layout_options.extracts[0].0,
+            "`extract` isn't supported on generic structs",
+        )
+        .to_compile_error();
+    }
+    let mut projections = Vec::new();
+    for (projection_ident, projected) in &layout_options.extracts {
+        let mut projected_fields = Vec::new();
+        for name in projected {
+            match fields
+                .iter()
+                .position(|field| field.ident.as_ref() == Some(name))
+            {
+                Some(index) => projected_fields.push(index),
+                None => {
+                    return syn::Error::new_spanned(
+                        name,
+                        format!("no field `{name}` in `{struct_ident}`"),
+                    )
+                    .to_compile_error();
+                }
+            }
+        }
+
+        let vis = &input.vis;
+        let projection_layout = Ident::new(
+            &format!("{}sLayout", projection_ident),
+            projection_ident.span(),
+        );
+        let layout_name = match layout {
+            Layout::StructOfArrays => "soa",
+            Layout::ArrayOfStructs => "aos",
+        };
+        let names: Vec<_> = projected_fields.iter().map(|i| field_names[*i]).collect();
+        let types: Vec<_> = projected_fields.iter().map(|i| field_types[*i]).collect();
+        let field_vis: Vec<_> = projected_fields.iter().map(|i| &fields[*i].vis).collect();
+        let reads: Vec<_> = projected_fields
+            .iter()
+            .map(|i| {
+                let name = field_names[*i];
+                match layout {
+                    Layout::StructOfArrays if by_value[*i] => {
+                        quote! { source.#name.get(index).unwrap() }
+                    }
+                    Layout::StructOfArrays => {
+                        let column = field_columns[*i];
+                        let position = &field_positions[*i];
+                        quote! { source.#column[index]#position.clone() }
+                    }
+                    Layout::ArrayOfStructs => quote! { source.data[index].#name.clone() },
+                }
+            })
+            .collect();
+
+        projections.push(quote! {
+            #(#source_derives)*
+            #[::soaaos::layout(#layout_name)]
+            #vis struct #projection_ident {
+                #(
+                    #field_vis #names: #types,
+                )*
+            }
+
+            impl #layout_impl_generics ::soaaos::access::Projection<#layout_ty> for #projection_ident
+            where
+                #(
+                    for<'__b> #types: Clone,
+                )*
+            {
+                type Layout = #projection_layout;
+
+                fn extract(source: &#layout_ty) -> Self::Layout {
+                    let mut layout = #projection_layout::with_capacity(source.len());
+                    for index in 0..source.len() {
+                        layout.add(#projection_ident {
+                            #(
+                                #names: #reads,
+                            )*
+                        });
+                    }
+                    layout
+                }
+            }
+        });
+    }
+    let extract = if projections.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            /// Copy the columns of a projection declared with `extract(Name = (field, ...))`, for
+            /// every node in order, into a new layout of the projection
+            pub fn extract<__P: ::soaaos::access::Projection<Self>>(&self) -> __P::Layout {
+                __P::extract(self)
+            }
+        }
+    };
+
+    // Hash-consing looks the row up in the row index, first indexing the rows added since the last
+    // lookup
+    let add_unique = if layout_options.hashcons {
+        quote! {
+            /// Add the node unless a node equal in every field already exists, returning the Id of
+            /// that node instead.
+            ///
+            /// The row index only sees the mutations made through the methods of the layout, not
+            /// writes to its columns.
+            pub fn add_unique(&mut self, node: #struct_ident #impl_generics) -> #id_ty
+            where
+                #(
+                    for<'__b> #field_types: core::hash::Hash + Eq,
+                )*
+            {
+                use core::hash::{Hash, Hasher};
+
+                if self._hashcons.indexed() > self.len() {
+                    self._hashcons.invalidate();
+                }
+                while self._hashcons.indexed() < self.len() {
+                    let id = #id_new(self._hashcons.indexed() as u32);
+                    let hash = ::soaaos::derived::RowHash::row_hash(self, id);
+                    self._hashcons.push(hash);
+                }
+
+                let mut hasher = std::hash::DefaultHasher::new();
+                #(
+                    node.#field_names.hash(&mut hasher);
+                )*
+                let existing = self._hashcons.candidates(hasher.finish()).find(|&index| {
+                    let id = #id_new(index as u32);
+                    true #(
+                        && core::borrow::Borrow::<#field_types>::borrow(&self.#getter_names(id).expect("Id is in bounds")) == &node.#field_names
+                    )*
+                });
+
+                match existing {
+                    Some(index) => #id_new(index as u32),
+                    None => self.add(node),
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // The key of `#[soa(key)]` is looked up in the index of the keys, first indexing the nodes
+    // added since the last lookup. The nodes keep the order they were added in, until moved.
+    let keyed = if let Some(key) = key_field {
+        let key_type = field_types[key];
+        let key_getter = &getter_names[key];
+        quote! {
+            /// Returns the index of the first node whose key equals `key`, or `None` if no node
+            /// holds it.
+            ///
+            /// The index of the keys only sees the mutations made through the methods of the
+            /// layout, not writes to its columns.
+            pub fn get_index_of<__Q>(&self, key: &__Q) -> Option<usize>
+            where
+                __Q: core::hash::Hash + Eq + ?Sized,
+                for<'__b> #key_type: core::borrow::Borrow<__Q>,
+            {
+                use core::hash::{Hash, Hasher};
+
+                let hash = |key: &__Q| {
+                    let mut hasher = std::hash::DefaultHasher::new();
+                    key.hash(&mut hasher);
+                    hasher.finish()
+                };
+
+                let mut rows = self._key_index.lock();
+                if rows.indexed() > self.len() {
+                    rows.invalidate();
+                }
+                while rows.indexed() < self.len() {
+                    let id = #id_new(rows.indexed() as u32);
+                    let value = self.#key_getter(id).expect("Id is in bounds");
+                    let key = core::borrow::Borrow::<#key_type>::borrow(&value);
+                    rows.push(hash(core::borrow::Borrow::<__Q>::borrow(key)));
+                }
+
+                rows.candidates(hash(key)).find(|&index| {
+                    let value = self.#key_getter(#id_new(index as u32)).expect("Id is in bounds");
+                    let value = core::borrow::Borrow::<#key_type>::borrow(&value);
+                    core::borrow::Borrow::<__Q>::borrow(value) == key
+                })
+            }
+
+            /// Returns a view of the node at the given index, in the order the nodes were added,
+            /// or `None` if it is out of bounds
+            pub fn get_index(&self, index: usize) -> Option<#struct_ident_ref #generics_with_ellided_lifetime> {
+                let index = u32::try_from(index).ok()?;
+                self.get(#id_new(index)).ok()
+            }
+
+            /// Move the node at index `from` to index `to`, shifting the nodes in between by one
+            /// and keeping the order of every other node. Returns the map of the moved Ids.
+            ///
+            /// Panics if either index is out of bounds.
+            pub fn move_index(&mut self, from: usize, to: usize) -> #id_map_ty
+            where
+                #reorder_bounds
+            {
+                let len = self.len();
+                assert!(
+                    from < len && to < len,
+                    "move_index from {from} to {to} is out of bounds for a layout of {len} nodes"
+                );
+
+                let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                if from < to {
+                    order[from..=to].rotate_left(1);
+                } else {
+                    order[to..=from].rotate_right(1);
+                }
+                self.reorder(&order)
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Versions are read from the row versions of a `versioned` layout, counting the rows added
+    // since the last mutation
+    let versions = if layout_options.versioned {
+        quote! {
+            /// Returns the version of the node at the given index: the version of the layout when
+            /// the node was last added, written, or moved to this index.
+            pub fn version(&self, index: #id_ty) -> Result<u64, #error_ident> {
+                self._versions
+                    .get(index.0 as usize, self.len())
+                    .ok_or_else(|| #error_ident::NotFound)
+            }
+
+            /// Returns the current version of the layout, bumped by every mutation made through
+            /// its methods.
+            pub fn current_version(&self) -> u64 {
+                self._versions.current(self.len())
+            }
+
+            /// Returns the Ids of the nodes added, written or moved after the given version of
+            /// the layout, in ascending order.
+            pub fn changed_since(&self, version: u64) -> impl Iterator<Item = #id_ty> + '_ {
+                self._versions
+                    .changed_since(version, self.len())
+                    .map(|index| #id_new(index as u32))
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Epochs are read from the row epochs of an `epochs` layout, like versions
+    let epochs = if layout_options.epochs {
+        quote! {
+            /// Start the next epoch, such as the next frame, returning it. Nodes added or written
+            /// from now on are stamped with the new epoch.
+            pub fn advance_epoch(&mut self) -> u32 {
+                self._epochs.advance(self.len())
+            }
+
+            /// Returns the current epoch, starting at 0.
+            pub fn current_epoch(&self) -> u32 {
+                self._epochs.current()
+            }
+
+            /// Returns the epoch the node at the given index was last added, written, or moved to
+            /// this index in.
+            pub fn epoch(&self, index: #id_ty) -> Result<u32, #error_ident> {
+                self._epochs
+                    .get(index.0 as usize, self.len())
+                    .ok_or_else(|| #error_ident::NotFound)
+            }
+
+            /// Returns the Ids of the nodes added, written or moved in the given epoch or later,
+            /// in ascending order. `changed_since_epoch(layout.current_epoch())` returns the nodes
+            /// changed in the current epoch.
+            pub fn changed_since_epoch(&self, epoch: u32) -> impl Iterator<Item = #id_ty> + '_ {
+                self._epochs
+                    .changed_since(epoch, self.len())
+                    .map(|index| #id_new(index as u32))
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Builds a layout decoded from a FlatBuffer or a protobuf message, from a `Vec` of values per
+    // field in `column_vars` and the column of each field made from them in `into_fields`. Every
+    // `Vec` must hold as many nodes as the first, checked once all of them are read, failing with
+    // the `ColumnLength` variant of `error`. The columns are moved into a struct-of-arrays layout as
+    // is, and zipped into rows otherwise.
+    let build_from_columns =
+        |column_vars: &[Ident], into_fields: &[TokenStream2], error: TokenStream2| {
+            let build = match layout {
+                Layout::StructOfArrays => quote! {
+                    Ok(Self::from_columns(#columns_ident {
+                        #(
+                            #field_names: #into_fields,
+                        )*
+                    }))
+                },
+                Layout::ArrayOfStructs => quote! {
+                    #(
+                        let mut #column_vars = #into_fields.into_iter();
+                    )*
+                    let mut layout = Self::with_capacity(len);
+                    for _ in 0..len {
+                        layout.add(#struct_ident {
+                            #(
+                                #field_names: #column_vars.next().unwrap(),
+                            )*
+                        });
+                    }
+                    Ok(layout)
+                },
+            };
+
+            quote! {
+                let len = [#(#column_vars.len()),*][0];
+                #(
+                    if #column_vars.len() != len {
+                        return Err(#error::ColumnLength {
+                            field: #field_name_strs,
+                            len: #column_vars.len(),
+                            expected: len,
+                        });
+                    }
+                )*
+
+                #build
+            }
+        };
+
+    // With `flatbuffers`, the layout is converted from and to the root table of its FlatBuffers
+    // schema, holding a vector per field. Ids of this layout are stored as a `uint`.
+    let flatbuffers = if layout_options.flatbuffers {
+        let mut schema = format!(
+            "// The FlatBuffers schema of {layout_struct_ident}, with a vector per field\n\ntable {layout_struct_ident} {{\n"
+        );
+        let mut pushes = Vec::new();
+        let mut reads = Vec::new();
+        for (index, (field_name, ty)) in field_names.iter().zip(&field_types).enumerate() {
+            let getter_name = &getter_names[index];
+            let values = quote! {
+                (0..self.len()).map(|index| self.#getter_name(#id_new(index as u32)).unwrap())
+            };
+            let (element, push, read) = if is_id_type(ty) {
+                (
+                    "uint",
+                    quote! {
+                        builder.push_scalars::<u32>(#index, #values.map(|id| id.0));
+                    },
+                    quote! {
+                        table.scalars::<u32>(#index).map(|ids| ids.into_iter().map(#id_new).collect::<Vec<_>>())
+                    },
+                )
+            } else {
+                match flatbuffers_type(ty) {
+                    Some("string") => (
+                        "string",
+                        quote! { builder.push_strings(#index, #values); },
+                        quote! { table.strings(#index) },
+                    ),
+                    Some(element) => (
+                        element,
+                        quote! {
+                            builder.push_scalars::<#ty>(#index, #values.map(|val| *core::borrow::Borrow::<#ty>::borrow(&val)));
+                        },
+                        quote! { table.scalars::<#ty>(#index) },
+                    ),
+                    None => {
+                        return syn::Error::new_spanned(
+                            ty,
+                            "`flatbuffers` only supports fields of primitive types, `String` and the Id of the layout",
+                        )
+                        .to_compile_error();
+                    }
+                }
+            };
+            schema += &format!("    {field_name}:[{element}];\n");
+            pushes.push(push);
+            reads.push(read);
+        }
+        schema += &format!("}}\n\nroot_type {layout_struct_ident};\n");
+
+        let field_count = field_names.len();
+        let column_vars: Vec<Ident> = field_names
+            .iter()
+            .map(|ident| Ident::new(&format!("{}_column", ident), ident.span()))
+            .collect();
+
+        let column_values: Vec<TokenStream2> =
+            column_vars.iter().map(|var| quote! { #var }).collect();
+        let build_layout = build_from_columns(
+            &column_vars,
+            &column_values,
+            quote! { ::soaaos::flatbuffers::Error },
+        );
+
+        // With the `rayon` feature, each vector is read on its own task, since the table gives
+        // the position of every vector up front
+        let par_from_flatbuffer = if cfg!(feature = "rayon") {
+            quote! {
+                /// Decode a layout from a FlatBuffer of `FLATBUFFERS_SCHEMA` like
+                /// `from_flatbuffer()`, reading the vector of each field on its own rayon task.
+                /// The vectors are checked to hold the same number of nodes once all of them are
+                /// read.
+                pub fn par_from_flatbuffer(bytes: &[u8]) -> Result<Self, ::soaaos::flatbuffers::Error> {
+                    let table = &::soaaos::flatbuffers::Table::new(bytes)?;
+                    #(
+                        let mut #column_vars = None;
+                    )*
+                    ::soaaos::__private::rayon::scope(|scope| {
+                        #(
+                            let #column_vars = &mut #column_vars;
+                            scope.spawn(move |_| {
+                                *#column_vars = Some(#reads);
+                            });
+                        )*
+                    });
+                    #(
+                        let #column_vars = #column_vars.expect("Every task ran")?;
+                    )*
+
+                    #build_layout
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        quote! {
+            /// The FlatBuffers schema of the buffers written by `to_flatbuffer()`: a root table with
+            /// a vector of the values of each field
+            pub const FLATBUFFERS_SCHEMA: &'static str = #schema;
+
+            /// Encode the layout as a FlatBuffer of `FLATBUFFERS_SCHEMA`, to be read without this
+            /// crate by any FlatBuffers implementation.
+            ///
+            /// Panics if the buffer would exceed the 2 GiB limit of FlatBuffers.
+            pub fn to_flatbuffer(&self) -> Vec<u8> {
+                let mut builder = ::soaaos::flatbuffers::Builder::new(#field_count);
+                #(#pushes)*
+                builder.finish()
+            }
+
+            /// Decode a layout from a FlatBuffer of `FLATBUFFERS_SCHEMA`. An absent vector is read
+            /// as empty, and every vector must hold the same number of nodes.
+            pub fn from_flatbuffer(bytes: &[u8]) -> Result<Self, ::soaaos::flatbuffers::Error> {
+                let table = ::soaaos::flatbuffers::Table::new(bytes)?;
+                #(
+                    let #column_vars = #reads?;
+                )*
+
+                #build_layout
+            }
+
+            #par_from_flatbuffer
+        }
+    } else {
+        quote! {}
+    };
+
+    // With the `prost` feature, layouts whose fields all have a protobuf type are converted from
+    // and to a message with a repeated field per field. Ids of this layout are stored as a `uint32`.
+    let proto_fields: Option<Vec<&str>> = field_types
+        .iter()
+        .map(|ty| {
+            if is_id_type(ty) {
+                Some("uint32")
+            } else {
+                proto_type(ty)
+            }
+        })
+        .collect();
+    let proto = match proto_fields {
+        Some(proto_fields) if cfg!(feature = "prost") => {
+            let column_vars: Vec<Ident> = field_names
+                .iter()
+                .map(|ident| Ident::new(&format!("{}_column", ident), ident.span()))
+                .collect();
+            let mut schema = format!(
+                "// The protobuf schema of {layout_struct_ident}, with a repeated field per field\n\nsyntax = \"proto3\";\n\nmessage {layout_struct_ident} {{\n"
+            );
+            let mut pushes = Vec::new();
+            let mut reads = Vec::new();
+            let mut numbers = Vec::new();
+            let mut column_reads = Vec::new();
+            let mut column_types = Vec::new();
+            let mut into_fields = Vec::new();
+            for (index, (field_name, ty)) in field_names.iter().zip(&field_types).enumerate() {
+                let number = index as u32 + 1;
+                let getter_name = &getter_names[index];
+                let column_var = &column_vars[index];
+                let values = quote! {
+                    (0..self.len()).map(|index| self.#getter_name(#id_new(index as u32)).unwrap())
+                };
+                let (push, read, column_type, into_field) = if is_id_type(ty) {
+                    (
+                        quote! { encoder.push::<u32>(#number, #values.map(|id| id.0)); },
+                        quote! { value.push(&mut #column_var)? },
+                        quote! { u32 },
+                        quote! { #column_var.into_iter().map(#id_new).collect::<Vec<_>>() },
+                    )
+                } else if proto_fields[index] == "string" {
+                    (
+                        quote! { encoder.push::<String>(#number, #values); },
+                        quote! { value.push(&mut #column_var)? },
+                        quote! { String },
+                        quote! { #column_var },
+                    )
+                } else {
+                    (
+                        quote! {
+                            encoder.push::<#ty>(#number, #values);
+                        },
+                        quote! { value.push(&mut #column_var)? },
+                        quote! { #ty },
+                        quote! { #column_var },
+                    )
+                };
+                schema += &format!(
+                    "    repeated {} {field_name} = {number};\n",
+                    proto_fields[index]
+                );
+                pushes.push(push);
+                reads.push(quote! { #number => #read, });
+                numbers.push(number);
+                column_reads.push(read);
+                column_types.push(column_type);
+                into_fields.push(into_field);
+            }
+            schema += "}\n";
+
+            let build_layout = build_from_columns(
+                &column_vars,
+                &into_fields,
+                quote! { ::soaaos::prost::Error },
+            );
+
+            // With the `rayon` feature, the values of every field are found in a single pass over
+            // the message, then the values of each field are decoded on their own task
+            let par_from_proto = if cfg!(feature = "rayon") {
+                let value_vars: Vec<Ident> = field_names
+                    .iter()
+                    .map(|ident| Ident::new(&format!("{}_values", ident), ident.span()))
+                    .collect();
+                let decoded_vars: Vec<Ident> = field_names
+                    .iter()
+                    .map(|ident| Ident::new(&format!("{}_decoded", ident), ident.span()))
+                    .collect();
+
+                quote! {
+                    /// Decode a layout from a protobuf message of `PROTO_SCHEMA` like
+                    /// `from_proto()`, decoding the values of each field on its own rayon task.
+                    /// The repeated fields are checked to hold the same number of nodes once all
+                    /// of them are decoded.
+                    pub fn par_from_proto(bytes: &[u8]) -> Result<Self, ::soaaos::prost::Error> {
+                        #(
+                            let mut #value_vars = Vec::new();
+                        )*
+
+                        let mut decoder = ::soaaos::prost::Decoder::new(bytes);
+                        while let Some((number, value)) = decoder.next_field()? {
+                            match number {
+                                #(
+                                    #numbers => #value_vars.push(value),
+                                )*
+                                _ => {}
+                            }
+                        }
+
+                        #(
+                            let mut #decoded_vars = None;
+                        )*
+                        ::soaaos::__private::rayon::scope(|scope| {
+                            #(
+                                let #decoded_vars = &mut #decoded_vars;
+                                scope.spawn(move |_| {
+                                    let decode = || -> Result<Vec<#column_types>, ::soaaos::prost::Error> {
+                                        let mut #column_vars = Vec::new();
+                                        for value in #value_vars {
+                                            #column_reads;
+                                        }
+                                        Ok(#column_vars)
+                                    };
+                                    *#decoded_vars = Some(decode());
+                                });
+                            )*
+                        });
+                        #(
+                            let #column_vars = #decoded_vars.expect("Every task ran")?;
+                        )*
+
+                        #build_layout
+                    }
+                }
+            } else {
+                quote! {}
+            };
+
+            quote! {
+                /// The protobuf schema of the messages written by `to_proto()`: a message with a
+                /// repeated field of the values of each field
+                pub const PROTO_SCHEMA: &'static str = #schema;
+
+                /// Encode the layout as a protobuf message of `PROTO_SCHEMA` with prost, to be
+                /// decoded by any protobuf implementation
+                pub fn to_proto(&self) -> Vec<u8> {
+                    let mut encoder = ::soaaos::prost::Encoder::new();
+                    #(#pushes)*
+                    encoder.finish()
+                }
+
+                /// Decode a layout from a protobuf message of `PROTO_SCHEMA`. Unknown fields are
+                /// skipped, and every repeated field must hold the same number of nodes.
+                pub fn from_proto(bytes: &[u8]) -> Result<Self, ::soaaos::prost::Error> {
+                    #(
+                        let mut #column_vars: Vec<#column_types> = Vec::new();
+                    )*
+
+                    let mut decoder = ::soaaos::prost::Decoder::new(bytes);
+                    while let Some((number, value)) = decoder.next_field()? {
+                        match number {
+                            #(#reads)*
+                            _ => {}
+                        }
+                    }
+
+                    #build_layout
+                }
+
+                #par_from_proto
+            }
+        }
+        _ => quote! {},
+    };
+
+    // With the `sqlite` feature, the layout is dumped to and loaded from a table with a column per
+    // field, in the order of the nodes
+    let sqlite = if cfg!(feature = "sqlite") {
+        let params = 1..=field_names.len();
+        let columns = 0..field_names.len();
+        let placeholders = vec!["?"; field_names.len()].join(", ");
+
+        quote! {
+            /// Create the table `table` with a column per field, and insert every node into it in a
+            /// single transaction, rolled back if any insert fails
+            pub fn to_sqlite(&self, conn: &::soaaos::sqlite::Connection, table: &str) -> Result<(), ::soaaos::sqlite::Error>
+            where
+                #(
+                    for<'__b> #field_types: ::soaaos::sqlite::Column,
+                )*
+            {
+                use ::soaaos::sqlite::{Column, Param, quote_identifier};
+
+                let table = quote_identifier(table);
+                let columns = [#(quote_identifier(#field_name_strs)),*].join(", ");
+                let types = [
+                    #(
+                        format!("{} {}", quote_identifier(#field_name_strs), <#field_types as Column>::SQL_TYPE),
+                    )*
+                ];
+
+                // Dropping the transaction without committing it rolls it back
+                let transaction = conn.unchecked_transaction()?;
+                transaction.execute_batch(&format!("CREATE TABLE {table} ({})", types.join(", ")))?;
+                {
+                    let mut insert = transaction.prepare(&format!("INSERT INTO {table} ({columns}) VALUES ({})", #placeholders))?;
+                    for index in 0..self.len() {
+                        let id = #id_new(index as u32);
+                        #(
+                            insert.raw_bind_parameter(#params, Param(core::borrow::Borrow::<#field_types>::borrow(&self.#getter_names(id).unwrap())))?;
+                        )*
+                        insert.raw_execute()?;
+                    }
+                }
+                transaction.commit()
+            }
+
+            /// Load a layout from the columns named after the fields of the table `table`, in the
+            /// order its rows were inserted
+            pub fn from_sqlite(conn: &::soaaos::sqlite::Connection, table: &str) -> Result<Self, ::soaaos::sqlite::Error>
+            where
+                #(
+                    for<'__b> #field_types: ::soaaos::sqlite::Column,
+                )*
+            {
+                use ::soaaos::sqlite::quote_identifier;
+
+                let columns = [#(quote_identifier(#field_name_strs)),*].join(", ");
+                let mut select = conn.prepare(&format!("SELECT {columns} FROM {} ORDER BY rowid", quote_identifier(table)))?;
+                let mut rows = select.raw_query();
+
+                let mut layout = Self::new();
+                while let Some(row) = rows.next()? {
+                    layout.add(#struct_ident {
+                        #(
+                            #field_names: ::soaaos::sqlite::get(row, #columns)?,
+                        )*
+                    });
+                }
+                Ok(layout)
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Rebuilding a layout from the collections it replaces, returning where each row moved. The
+    // returned map is the only way from a key to an Id: rows are numbered densely, so the slot of a
+    // key doesn't name its row once anything was removed from the collection.
+    let slotmap_interop = if cfg!(feature = "slotmap") {
+        quote! {
+            /// Create a layout holding the values of a `SlotMap`, in slot order, also returning the
+            /// Id of each key in the new layout
+            pub fn from_slotmap<__K: ::soaaos::__private::slotmap::Key>(
+                map: ::soaaos::__private::slotmap::SlotMap<__K, #struct_ident #impl_generics>,
+            ) -> (Self, ::soaaos::__private::slotmap::SecondaryMap<__K, #id_ty>) {
+                let mut layout = Self::with_capacity(map.len());
+                let mut ids = ::soaaos::__private::slotmap::SecondaryMap::with_capacity(map.len());
+                for (key, value) in map {
+                    ids.insert(key, layout.add(value));
+                }
+                (layout, ids)
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let arena_interop = if cfg!(feature = "generational_arena") {
+        quote! {
+            /// Create a layout holding the values of an `Arena`, in slot order, also returning the
+            /// Id of each index in the new layout
+            pub fn from_arena(
+                mut arena: ::soaaos::__private::generational_arena::Arena<#struct_ident #impl_generics>,
+            ) -> (Self, std::collections::HashMap<::soaaos::__private::generational_arena::Index, #id_ty>) {
+                let mut layout = Self::with_capacity(arena.len());
+                let mut ids = std::collections::HashMap::with_capacity(arena.len());
+                for (index, value) in arena.drain() {
+                    ids.insert(index, layout.add(value));
+                }
+                (layout, ids)
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // `static_layout!` builds the read-only layout of literal rows, borrowing a slice of each field
+    // (or of the rows) from a `static`. The macro doesn't know the form of the layout, so it gives
+    // the literals in both forms and picks the constructor with `__IS_SOA`: only the constructor
+    // of the form is evaluated, and the other one is unreachable.
+    let static_layout_ident = new_ident!("Static{}sLayout");
+    let static_columns_ident = new_ident!("Static{}sColumns");
+    let (static_impl_generics, static_ty_generics, _) = generics_with_lifetime.split_for_impl();
+    let static_doc = format!(
+        "A read-only `{layout_struct_ident}` borrowing its {} from slices, built from literal \
+         rows in a `static` by `soaaos::static_layout!`",
+        if layout == Layout::StructOfArrays {
+            "columns"
+        } else {
+            "nodes"
+        }
+    );
+    let static_form = if layout == Layout::StructOfArrays {
+        quote! {
+            #[doc = #static_doc]
+            #[derive(Clone, Copy)]
+            pub struct #static_layout_ident #generics_with_lifetime #where_clause {
+                #(
+                    pub #field_names: &#lifetime [#field_types],
+                )*
+            }
+
+            impl #static_impl_generics #static_layout_ident #static_ty_generics #where_clause {
+                #[doc(hidden)]
+                pub const __IS_SOA: bool = true;
+
+                #[doc(hidden)]
+                pub const fn __from_columns(columns: #static_columns_ident #static_ty_generics) -> Self {
+                    Self {
+                        #(
+                            #field_names: columns.#field_names,
+                        )*
+                    }
+                }
+
+                #[doc(hidden)]
+                pub const fn __from_rows(_rows: &#lifetime [#struct_ident #impl_generics]) -> Self {
+                    panic!("a struct-of-arrays static layout is built from its columns")
+                }
+
+                /// Get the number of nodes in the layout
+                pub const fn len(&self) -> usize {
+                    self.#first_field.len()
+                }
+
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #getter_names(&self, index: #id_ty) -> Result<&#lifetime #field_types, #error_ident> {
+                        self.#field_names
+                            .get(index.0 as usize)
+                            .ok_or(#error_ident::#error_names)
+                    }
+
+                    /// Returns an iterator over the values of this field
+                    pub fn #field_names(&self) -> impl Iterator<Item = &#lifetime #field_types> {
+                        self.#field_names.iter()
+                    }
+                )*
+
+                /// Copy every node into a new layout
+                pub fn to_layout(&self) -> #layout_ty
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    let mut layout = #layout_struct_ident::with_capacity(self.len());
+                    for index in 0..self.len() {
+                        layout.add(#struct_ident {
+                            #(
+                                #field_names: self.#field_names[index].clone(),
+                            )*
+                        });
+                    }
+                    layout
+                }
+            }
+        }
+    } else {
+        quote! {
+            #[doc = #static_doc]
+            #[derive(Clone, Copy)]
+            pub struct #static_layout_ident #generics_with_lifetime #where_clause {
+                pub data: &#lifetime [#struct_ident #impl_generics],
+            }
+
+            impl #static_impl_generics #static_layout_ident #static_ty_generics #where_clause {
+                #[doc(hidden)]
+                pub const __IS_SOA: bool = false;
+
+                #[doc(hidden)]
+                pub const fn __from_columns(_columns: #static_columns_ident #static_ty_generics) -> Self {
+                    panic!("an array-of-structs static layout is built from its rows")
+                }
+
+                #[doc(hidden)]
+                pub const fn __from_rows(rows: &#lifetime [#struct_ident #impl_generics]) -> Self {
+                    Self { data: rows }
+                }
+
+                /// Get the number of nodes in the layout
+                pub const fn len(&self) -> usize {
+                    self.data.len()
+                }
+
+                /// Returns the node at the given index.
+                pub fn get(&self, index: #id_ty) -> Result<&#lifetime #struct_ident #impl_generics, #error_ident> {
+                    self.data
+                        .get(index.0 as usize)
+                        .ok_or(#error_ident::NotFound)
+                }
+
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #getter_names(&self, index: #id_ty) -> Result<&#lifetime #field_types, #error_ident> {
+                        self.data
+                            .get(index.0 as usize)
+                            .map(|item| &item.#field_names)
+                            .ok_or(#error_ident::#error_names)
+                    }
+
+                    /// Returns an iterator over the values of this field
+                    pub fn #field_names(&self) -> impl Iterator<Item = &#lifetime #field_types> {
+                        self.data.iter().map(|item| &item.#field_names)
+                    }
+                )*
+
+                /// Returns an iterator over every node, in Id order.
+                pub fn iter(&self) -> core::slice::Iter<#lifetime, #struct_ident #impl_generics> {
+                    self.data.iter()
+                }
+
+                /// Copy every node into a new layout
+                pub fn to_layout(&self) -> #layout_ty
+                where
+                    for<'__b> #struct_ident #impl_generics: Clone,
+                {
+                    let mut layout = #layout_struct_ident::with_capacity(self.len());
+                    for item in self.data {
+                        layout.add(item.clone());
+                    }
+                    layout
+                }
+            }
+        }
+    };
+    let static_layout = if brand.is_some() {
+        quote! {}
+    } else {
+        quote! {
+            #static_form
+
+            impl #static_impl_generics #static_layout_ident #static_ty_generics #where_clause {
+                /// Returns `true` if the layout is empty
+                pub const fn is_empty(&self) -> bool {
+                    self.len() == 0
+                }
+
+                /// Returns an iterator over the Ids of every node
+                pub fn iter_ids(&self) -> impl Iterator<Item = #id_ty> {
+                    (0..self.len() as u32).map(#id_new)
+                }
+            }
+
+            #[doc(hidden)]
+            pub struct #static_columns_ident #generics_with_lifetime #where_clause {
+                #(
+                    pub #field_names: &#lifetime [#field_types],
+                )*
+            }
+        }
+    };
+
+    // With the `fuzz` feature, the layout is checked against a `Vec` of its rows by
+    // `soaaos::fuzz`, through the row operations of `FuzzTarget`
+    let (fuzz_target, fuzz) = if cfg!(feature = "fuzz") {
+        let mut fuzz_generics = branded_generics.clone();
+        let fuzz_where_clause = fuzz_generics.make_where_clause();
+        fuzz_where_clause
+            .predicates
+            .push(parse_quote!(for<'__b> #struct_ident #impl_generics: Clone));
+        for ty in &field_types {
+            fuzz_where_clause
+                .predicates
+                .push(parse_quote!(for<'__b> #ty: Clone + PartialEq + core::fmt::Debug));
+        }
+        let (_, _, fuzz_where_clause) = fuzz_generics.split_for_impl();
+
+        let fuzz_target = quote! {
+            impl #layout_impl_generics ::soaaos::fuzz::FuzzTarget<#struct_ident #impl_generics> for #layout_ty #fuzz_where_clause {
+                fn empty() -> Self {
+                    Self::new()
+                }
+
+                fn cloned_row(&self, id: #id_ty) -> Option<#struct_ident #impl_generics> {
+                    if id.0 as usize >= self.len() {
+                        return None;
+                    }
+
+                    self.gather(&[id]).iter_cloned().next()
+                }
+
+                fn cloned_rows(&self) -> Vec<#struct_ident #impl_generics> {
+                    self.to_rows()
+                }
+
+                fn remove_row(&mut self, id: #id_ty) -> Option<#struct_ident #impl_generics> {
+                    self.remove(id).ok().map(|(row, _)| row)
+                }
+
+                fn swap_remove_row(&mut self, id: #id_ty) -> Option<#struct_ident #impl_generics> {
+                    self.swap_remove(id).ok().map(|(row, _)| row)
+                }
+
+                fn reorder_rows(&mut self, order: &[#id_ty]) {
+                    self.reorder(order);
+                }
+
+                fn reverse_rows(&mut self) {
+                    self.reverse();
+                }
+
+                fn differs(&self, other: &Self) -> bool {
+                    self.diff(other).is_some()
+                }
+            }
+        };
+
+        let fuzz = quote! {
+            /// Apply the sequence of operations decoded from the fuzzer's `data` to a new layout
+            /// and to a `Vec` of its rows, panicking at the first operation after which they
+            /// disagree. The struct derives `arbitrary::Arbitrary` to be decoded.
+            pub fn fuzz(data: &[u8])
+            where
+                for<'__b> Self: ::soaaos::fuzz::FuzzTarget<#struct_ident #impl_generics>,
+                for<'__b> #struct_ident #impl_generics: ::soaaos::__private::arbitrary::Arbitrary<'__b> + Clone + PartialEq + core::fmt::Debug,
+            {
+                ::soaaos::fuzz::run::<Self, #struct_ident #impl_generics>(data);
+            }
+        };
+
+        (fuzz_target, fuzz)
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    // A struct deriving `Default` (rather than implementing it by hand) defaults each field on
+    // its own, so its default rows can be built a column at a time
+    let derives_default = input.attrs.iter().any(|attr| {
+        attr.path().is_ident("derive")
+            && attr
+                .parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)
+                .is_ok_and(|derives| derives.iter().any(|path| path.is_ident("Default")))
+    });
+    let default_rows = if derives_default {
+        let fill = match layout {
+            Layout::StructOfArrays => {
+                let defaults = member_values(&vec![quote! { Default::default() }; fields.len()]);
+                quote! {
+                    #(
+                        for _ in 0..n {
+                            layout.#column_members.push(#defaults);
+                        }
+                    )*
+                }
+            }
+            Layout::ArrayOfStructs => quote! {
+                layout.data.resize_with(n, Default::default);
+            },
+        };
+        quote! {
+            /// Create a layout of `n` default nodes, filling each column with the default of its
+            /// field in turn
+            pub fn default_rows(n: usize) -> Self
+            where
+                for<'__b> #struct_ident #impl_generics: Default,
+                #(
+                    for<'__b> #field_types: Default,
+                )*
+            {
+                #ring_default_rows
+                let mut layout = Self::with_capacity(n);
+                #fill
+                layout
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let both = quote! {
+        // Keep the original struct definition.
+        #input
+
+        #(#projections)*
+
+        #(#group_structs)*
+
+        #(#lazy_defaults)*
+
+        #serde
+
+        #id
+
+        #stable_id
+
+        pub struct #struct_ident_ref #generics_with_lifetime #where_clause {
+            #(
+                pub #field_names: #ref_types,
+            )*
+        }
+
+        pub struct #struct_ident_ref_mut #generics_with_lifetime #where_clause {
+            #(
+                pub #mut_field_names: &#lifetime mut #mut_field_types,
+            )*
+        }
+
+        #view_trait_impls
+
+        #view_display
+
+        #view_conversions
+
+        #layout_trait_impls
+
+        #[derive(Debug)]
+        #[allow(non_camel_case_types)]
+        pub enum #error_ident {
+            /// No node has the given Id
+            NotFound,
+
+            #(
+                #error_names,
+            )*
+
+            #(
+                #missing_names,
+            )*
+
+            InvalidDiff,
+        }
+
+        impl core::fmt::Display for #error_ident {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    #error_ident::NotFound => write!(f, "Not Found"),
+
+                    #(
+                        #error_ident::#error_names => write!(f, "Not Found: {}", stringify!(#error_names)),
+                    )*
+
+                    #(
+                        #error_ident::#missing_names => write!(f, "Missing: {}", stringify!(#field_names)),
+                    )*
+
+                    #error_ident::InvalidDiff => write!(f, "Invalid Diff"),
+                }
+            }
+        }
+
+        impl core::error::Error for #error_ident {
+            fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+                match self {
+                    _ => None,
+                }
+            }
+        }
+
+        impl #layout_impl_generics #layout_ty #where_clause{
+            /// A hash of the field names and types of the struct, used to detect layouts built from
+            /// different definitions of the struct
+            ///
+            /// The types are hashed as they are written in the struct, not as they resolve: a
+            /// type alias or an imported type changed to another type (e.g. `type Addr = u32;`
+            /// becoming `u64`) keeps the same hash, and a generic field hashes the name of its
+            /// parameter whatever type it is given. Spelling the same type differently (`u32`
+            /// through an alias, or a path like `std::string::String`) changes the hash.
+            pub const SCHEMA_HASH: u64 = #schema_hash;
+
+            /// Returns the diff (by field) between two layouts
+            pub fn diff(&self, other: &Self) -> Option<String>
+            where
+                #(
+                    for<'__b> #field_types: PartialEq + core::fmt::Debug,
+                )*
+            {
+                use std::fmt::Write;
+
+                let mut out = String::new();
+
+                #(
+                    let this_iter = self.#field_names();
+                    let other_iter = other.#field_names();
+
+                    for (i, (o1, o2)) in this_iter.zip(other_iter).enumerate() {
+                        if o1 != o2 {
+                            write!(out, "\n{} {i}: {o1:?} vs {o2:?}", stringify!(#field_names)).unwrap();
+                        }
+                    }
+                )*
+
+                if !out.is_empty() {
+                    return Some(out);
+                }
+
+                None
+            }
+
+            /// Measure the throughput of scanning each column, and of scanning every row, on the
+            /// current machine. Each scan is repeated until it took at least 1ms.
+            pub fn profile_scan(&self) -> ::soaaos::profile::ScanProfile {
+                let columns = vec![
+                    #(
+                        ::soaaos::profile::ColumnScan::measure(
+                            #field_name_strs,
+                            self.len() * core::mem::size_of::<#field_types>(),
+                            || {
+                                for value in self.#field_names() {
+                                    core::hint::black_box(value);
+                                }
+                            },
+                        ),
+                    )*
+                ];
+
+                let rows = ::soaaos::profile::ColumnScan::measure(
+                    "rows",
+                    self.len() * core::mem::size_of::<#struct_ident #impl_generics>(),
+                    || {
+                        for row in self.iter() {
+                            core::hint::black_box(row);
+                        }
+                    },
+                );
+
+                ::soaaos::profile::ScanProfile { columns, rows }
+            }
+
+            #diff_pretty
+
+            #diff_json_patch
+
+            #json_chunks
+
+            /// Returns an iterator over the view of every node, in Id order.
+            ///
+            /// `remove` shifts every later node down instead of leaving a hole to reuse, so the
+            /// order is deterministic, and only differs from the insertion order after the layout
+            /// was reordered (`sort_by`, `reorder`, `reverse`, ...). A `ring` layout reuses the
+            /// slot of the oldest node once it is full, so `iter()` walks the slots in order, and
+            /// `iter_ids_by_age()` gives the Ids in insertion order.
+            pub fn iter(&self) -> #layout_iter_ident #layout_ty_generics {
+                #layout_iter_ident { index: #id_ident::null(), layout: self }
+            }
+
+            pub fn iter_enumerated(&self) -> impl Iterator<Item = (#id_ty, #struct_ident_ref #generics_with_ellided_lifetime)> {
+                self
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (#id_new(index as u32), item))
+            }
+
+            /// Copy the fields of the nodes at the given indexes, in order, into a new contiguous
+            /// `Vec` per field. The indexes may repeat.
+            ///
+            /// Panics if any of the indexes are out of bounds.
+            pub fn select(&self, indexes: &[#id_ty]) -> #selection_ident #impl_generics
+            where
+                #(
+                    for<'__b> #field_types: Clone,
+                )*
+            {
+                let mut selection = #selection_ident::new();
+                self.select_into(indexes, &mut selection);
+                selection
+            }
+
+            /// Copy the fields of the nodes at the given indexes, in order, into the `Vec`s of
+            /// `selection`, replacing the nodes it held but reusing its allocations.
+            ///
+            /// Panics if any of the indexes are out of bounds.
+            pub fn select_into(&self, indexes: &[#id_ty], selection: &mut #selection_ident #impl_generics)
+            where
+                #(
+                    for<'__b> #field_types: Clone,
+                )*
+            {
+                selection.clear();
+                #(
+                    selection.#field_names.reserve(indexes.len());
+                    selection.#field_names.extend(indexes.iter().map(|&index| {
+                        let value = self.#getter_names(index).expect("Id out of bounds");
+                        core::borrow::Borrow::<#field_types>::borrow(&value).clone()
+                    }));
+                )*
+            }
+
+            /// Returns the views of the nodes at the given indexes, in order, or the error of
+            /// `get` if any of them is out of bounds. The indexes may repeat.
+            pub fn get_many<const N: usize>(&self, indexes: [#id_ty; N]) -> Result<[#struct_ident_ref #generics_with_ellided_lifetime; N], #error_ident> {
+                let len = self.len();
+                if indexes.iter().any(|index| index.0 as usize >= len) {
+                    return Err(#error_ident::NotFound);
+                }
+
+                Ok(core::array::from_fn(|i| self.get(indexes[i]).expect("Index was checked")))
+            }
+
+            /// Sort the nodes of the layout by the key extracted from each node, keeping the order of
+            /// nodes with equal keys. The layout is reordered once, after sorting, returning the map
+            /// of the moved Ids like every sort.
+            ///
+            /// Sorting by several fields is sorting by a tuple of them, wrapping the fields sorted in
+            /// descending order in `core::cmp::Reverse`: `|node| (*node.op, Reverse(*node.arg1))`.
+            pub fn sort_by_key<__K: Ord>(&mut self, mut f: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> __K) -> #id_map_ty
+            where
+                #reorder_bounds
+            {
+                let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                order.sort_by_key(|id| f(self.get(*id).expect("Id is in bounds")));
+                self.reorder(&order)
+            }
+
+            /// Sort the nodes of the layout with the given comparison, keeping the order of nodes
+            /// that compare equal. The layout is reordered once, after sorting.
+            pub fn sort_by(&mut self, mut compare: impl FnMut(&#struct_ident_ref #generics_with_ellided_lifetime, &#struct_ident_ref #generics_with_ellided_lifetime) -> core::cmp::Ordering) -> #id_map_ty
+            where
+                #reorder_bounds
+            {
+                let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                order.sort_by(|a, b| {
+                    compare(
+                        &self.get(*a).expect("Id is in bounds"),
+                        &self.get(*b).expect("Id is in bounds"),
+                    )
+                });
+                self.reorder(&order)
+            }
+
+            /// Sort the nodes of the layout by the key extracted from each node, like
+            /// [`sort_by_key`](Self::sort_by_key) but without keeping the order of nodes with equal
+            /// keys.
+            pub fn sort_unstable_by_key<__K: Ord>(&mut self, mut f: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> __K) -> #id_map_ty
+            where
+                #reorder_bounds
+            {
+                let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                order.sort_unstable_by_key(|id| f(self.get(*id).expect("Id is in bounds")));
+                self.reorder(&order)
+            }
+
+            /// Sort the nodes of the layout with the given comparison, like
+            /// [`sort_by`](Self::sort_by) but without keeping the order of nodes that compare
+            /// equal.
+            pub fn sort_unstable_by(&mut self, mut compare: impl FnMut(&#struct_ident_ref #generics_with_ellided_lifetime, &#struct_ident_ref #generics_with_ellided_lifetime) -> core::cmp::Ordering) -> #id_map_ty
+            where
+                #reorder_bounds
+            {
+                let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                order.sort_unstable_by(|a, b| {
+                    compare(
+                        &self.get(*a).expect("Id is in bounds"),
+                        &self.get(*b).expect("Id is in bounds"),
+                    )
+                });
+                self.reorder(&order)
+            }
+
+            /// Sort the nodes of the layout by the key extracted from each node, calling `f` only
+            /// once per node, keeping the order of nodes with equal keys. Faster than
+            /// [`sort_by_key`](Self::sort_by_key) for expensive keys.
+            pub fn sort_by_cached_key<__K: Ord>(&mut self, mut f: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> __K) -> #id_map_ty
+            where
+                #reorder_bounds
+            {
+                let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                order.sort_by_cached_key(|id| f(self.get(*id).expect("Id is in bounds")));
+                self.reorder(&order)
+            }
+
+            // Generate a stable and an unstable sort by each field.
+            #(
+                /// Sort the nodes of the layout by this field, keeping the order of nodes with
+                /// equal values.
+                pub fn #sort_by_names(&mut self) -> #id_map_ty
+                where
+                    for<'__b> #field_types: Ord,
+                    #reorder_bounds
+                {
+                    let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                    order.sort_by(|a, b| {
+                        let a = self.#getter_names(*a).expect("Id is in bounds");
+                        let b = self.#getter_names(*b).expect("Id is in bounds");
+                        core::borrow::Borrow::<#field_types>::borrow(&a).cmp(core::borrow::Borrow::<#field_types>::borrow(&b))
+                    });
+                    self.reorder(&order)
+                }
+
+                /// Sort the nodes of the layout by this field, without keeping the order of nodes
+                /// with equal values.
+                pub fn #sort_unstable_by_names(&mut self) -> #id_map_ty
+                where
+                    for<'__b> #field_types: Ord,
+                    #reorder_bounds
+                {
+                    let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                    order.sort_unstable_by(|a, b| {
+                        let a = self.#getter_names(*a).expect("Id is in bounds");
+                        let b = self.#getter_names(*b).expect("Id is in bounds");
+                        core::borrow::Borrow::<#field_types>::borrow(&a).cmp(core::borrow::Borrow::<#field_types>::borrow(&b))
+                    });
+                    self.reorder(&order)
+                }
+            )*
+
+            #par_sorts
+
+            // Generate the lookup of the node holding the smallest and largest value of each field.
+            #(
+                /// Returns the Id of the first node holding the smallest value of this field, or
+                /// `None` if the layout is empty
+                pub fn #id_of_min_names(&self) -> Option<#id_ty>
+                where
+                    for<'__b> #field_types: Ord,
+                {
+                    self.#field_names()
+                        .enumerate()
+                        .min_by(|(_, a), (_, b)| {
+                            core::borrow::Borrow::<#field_types>::borrow(a).cmp(core::borrow::Borrow::<#field_types>::borrow(b))
+                        })
+                        .map(|(index, _)| #id_new(index as u32))
+                }
+
+                /// Returns the Id of the first node holding the largest value of this field, or
+                /// `None` if the layout is empty
+                pub fn #id_of_max_names(&self) -> Option<#id_ty>
+                where
+                    for<'__b> #field_types: Ord,
+                {
+                    self.#field_names()
+                        .enumerate()
+                        .max_by(|(a_index, a), (b_index, b)| {
+                            core::borrow::Borrow::<#field_types>::borrow(a)
+                                .cmp(core::borrow::Borrow::<#field_types>::borrow(b))
+                                .then(b_index.cmp(a_index))
+                        })
+                        .map(|(index, _)| #id_new(index as u32))
+                }
+            )*
+
+            // Generate the scan of a single field, stopping at the first `Break`.
+            #(
+                /// Scan this field of every node in order, without building the views of the
+                /// nodes. Yields the Id of each node with the value `f` returns in
+                /// `ControlFlow::Continue(Some(_))`, skips the nodes `f` returns
+                /// `Continue(None)` for, and stops at the first `ControlFlow::Break`.
+                pub fn #filter_map_names<__T>(
+                    &self,
+                    mut f: impl FnMut(#id_ty, #read_types) -> core::ops::ControlFlow<(), Option<__T>>,
+                ) -> impl Iterator<Item = (#id_ty, __T)> {
+                    self.#getter_enumerated_names()
+                        .map_while(move |(id, val)| match f(id, val) {
+                            core::ops::ControlFlow::Continue(out) => Some(out.map(|out| (id, out))),
+                            core::ops::ControlFlow::Break(()) => None,
+                        })
+                        .flatten()
+                }
+            )*
+
+            #link_methods
+
+            #apply_id_map
+
+            #to_dot
+
+            /// Returns the Id of the first node equal to `node` in every field, comparing the row
+            /// hashes before the fields
+            pub fn find_row(&self, node: &#struct_ident #impl_generics) -> Option<#id_ty>
+            where
+                #(
+                    for<'__b> #field_types: core::hash::Hash + Eq,
+                )*
+            {
+                use core::hash::{Hash, Hasher};
+
+                let mut hasher = std::hash::DefaultHasher::new();
+                #(
+                    node.#field_names.hash(&mut hasher);
+                )*
+                let hash = hasher.finish();
+
+                self.iter_ids().find(|&id| {
+                    ::soaaos::derived::RowHash::row_hash(self, id) == hash
+                        #(
+                            && core::borrow::Borrow::<#field_types>::borrow(&self.#getter_names(id).expect("Id is in bounds")) == &node.#field_names
+                        )*
+                })
+            }
+
+            /// Returns the stable Id of the node at the given index: the hash of its contents,
+            /// which `resolve()` finds again after the dense Ids of the layout change.
+            pub fn stable_id(&self, index: #id_ty) -> Result<#stable_id_ident, #error_ident>
+            where
+                #(
+                    for<'__b> #field_types: core::hash::Hash,
+                )*
+            {
+                if index.0 as usize >= self.len() {
+                    return Err(#error_ident::NotFound);
+                }
+
+                Ok(#stable_id_ident(::soaaos::derived::RowHash::row_hash(self, index)))
+            }
+
+            /// Returns the current Id of the first node whose contents hash to the stable Id, or
+            /// `None` if the node was written or removed since the stable Id was taken.
+            pub fn resolve(&self, stable: #stable_id_ident) -> Option<#id_ty>
+            where
+                #(
+                    for<'__b> #field_types: core::hash::Hash,
+                )*
+            {
+                self.iter_ids()
+                    .find(|&id| ::soaaos::derived::RowHash::row_hash(self, id) == stable.0)
+            }
+
+            /// Remove every node equal in every field to an earlier node, keeping the first of
+            /// each set of duplicates. The duplicates are found in one pass, then the layout is
+            /// compacted once with `retain()`.
+            ///
+            /// Returns the map of the new Id of each node, where removed duplicates map to the
+            /// node they duplicated instead of `None`.
+            pub fn dedup_rows(&mut self) -> #id_map_ty
+            where
+                #(
+                    for<'__b> #field_types: core::hash::Hash + Eq + Clone,
+                )*
+            {
+                let equal = |layout: &Self, a: #id_ty, b: #id_ty| {
+                    true #(
+                        && layout.#getter_names(a).expect("Id is in bounds") == layout.#getter_names(b).expect("Id is in bounds")
+                    )*
+                };
+
+                let mut kept: std::collections::HashMap<u64, Vec<#id_ty>> = std::collections::HashMap::new();
+                let mut map = #id_map_ident::identity(self.len());
+                let mut keep = Vec::with_capacity(self.len());
+                let mut kept_count = 0;
+                for id in self.iter_ids() {
+                    let candidates = kept.entry(::soaaos::derived::RowHash::row_hash(self, id)).or_default();
+                    match candidates.iter().find(|&&other| equal(self, other, id)) {
+                        Some(&other) => {
+                            map.insert(id, map.remap(other));
+                            keep.push(false);
+                        }
+                        None => {
+                            candidates.push(id);
+                            map.insert(id, Some(#id_new(kept_count)));
+                            keep.push(true);
+                            kept_count += 1;
+                        }
+                    }
+                }
+
+                let mut keep = keep.into_iter();
+                self.retain(|_| keep.next().expect("One flag per node"));
+                map
+            }
+
+            // Generate a hash join of another layout on each field. The rows of the other layout
+            // are hashed by key, then each node of this layout looks up its value, taking the
+            // matching rows one at a time by their position in the list of its key.
+            #(
+                /// Returns the pairs of the view of a node of this layout and the view of a row of
+                /// `other` whose `key` is equal to this field of the node, as a hash join. Pairs
+                /// are in the order of the nodes of this layout, then of the rows of `other`.
+                pub fn #join_by_names<'__o, __L, __K>(&self, other: &'__o __L, key: impl Fn(&__L::Ref<'__o>) -> __K) -> impl Iterator<Item = (#struct_ident_ref #generics_with_ellided_lifetime, __L::Ref<'__o>)>
+                where
+                    __L: ::soaaos::RowAccess,
+                    __K: core::hash::Hash + Eq + core::borrow::Borrow<#field_types>,
+                    for<'__b> #field_types: core::hash::Hash + Eq,
+                {
+                    let mut matches: std::collections::HashMap<__K, Vec<usize>> = std::collections::HashMap::new();
+                    for (index, row) in other.rows().enumerate() {
+                        matches.entry(key(&row)).or_default().push(index);
+                    }
+
+                    let mut probe = 0;
+                    let mut next = 0;
+                    core::iter::from_fn(move || {
+                        while probe < self.len() {
+                            let id = #id_new(probe as u32);
+                            let val = self.#getter_names(id).ok()?;
+                            let matched = matches
+                                .get(core::borrow::Borrow::<#field_types>::borrow(&val))
+                                .and_then(|indexes| indexes.get(next));
+                            if let Some(&index) = matched {
+                                next += 1;
+                                let row = other.row(<__L as ::soaaos::derived::Table>::id(index))?;
+                                return Some((self.get(id).ok()?, row));
+                            }
+
+                            probe += 1;
+                            next = 0;
+                        }
+                        None
+                    })
+                }
+            )*
+
+            // Generate the distribution summaries of each field.
+            #(
+                /// Returns the number of nodes holding each distinct value of this field
+                pub fn #value_counts_names(&self) -> std::collections::HashMap<#field_types, usize>
+                where
+                    for<'__b> #field_types: core::hash::Hash + Eq + Clone,
+                {
+                    let mut counts = std::collections::HashMap::new();
+                    for val in self.#field_names() {
+                        *counts.entry(core::borrow::Borrow::<#field_types>::borrow(&val).clone()).or_insert(0) += 1;
+                    }
+                    counts
+                }
+
+                /// Returns the number of nodes in each of `bins` equal-width buckets spanning the
+                /// values of this numeric field. NaNs aren't counted.
+                ///
+                /// Panics if `bins` is 0.
+                pub fn #histogram_names(&self, bins: usize) -> ::soaaos::summary::Histogram
+                where
+                    for<'__b> #field_types: ::soaaos::summary::Numeric,
+                {
+                    ::soaaos::summary::Histogram::new(bins, || {
+                        self.#field_names().map(|val| ::soaaos::summary::Numeric::to_f64(*core::borrow::Borrow::<#field_types>::borrow(&val)))
+                    })
+                }
+            )*
+
+            /// Returns an iterator over each pair of consecutive nodes, as `(node, next node)`
+            pub fn iter_pairs(&self) -> impl Iterator<Item = (#struct_ident_ref #generics_with_ellided_lifetime, #struct_ident_ref #generics_with_ellided_lifetime)> {
+                self.iter().zip(self.iter().skip(1))
+            }
+
+            /// Returns an iterator over every window of `n` consecutive nodes, overlapping like
+            /// `slice::windows`. Layouts with fewer than `n` nodes have no windows.
+            ///
+            /// Panics if `n` is 0.
+            pub fn windows(&self, n: usize) -> impl Iterator<Item = Vec<#struct_ident_ref #generics_with_ellided_lifetime>> {
+                assert!(n != 0, "window size must be non-zero");
+                (0..(self.len() + 1).saturating_sub(n)).map(move |start| {
+                    (start..start + n)
+                        .map(|index| self.get(#id_new(index as u32)).expect("Window is in bounds"))
+                        .collect()
+                })
+            }
+
+            /// Build a layout from an iterator of fallible nodes, stopping at the first error.
+            ///
+            /// The layout is pre-allocated using the lower bound of the iterator's size hint.
+            pub fn try_from_iter<E>(iter: impl IntoIterator<Item = Result<#struct_ident #impl_generics, E>>) -> Result<Self, E> {
+                let iter = iter.into_iter();
+                let mut layout = Self::with_capacity(iter.size_hint().0);
+
+                for item in iter {
+                    layout.add(item?);
+                }
+
+                Ok(layout)
+            }
+
+            #futures
+
+            /// Returns a reference to the field with the given name of the node at the given index.
+            ///
+            /// Returns `None` if there is no field with that name, the field is stored by value, or the
+            /// index is out of bounds.
+            pub fn get_field_dyn(&self, name: &str, index: #id_ty) -> Option<&dyn core::any::Any>
+            where
+                #(
+                    for<'__b> #field_types: 'static,
+                )*
+            {
+                match name {
+                    #(
+                        #field_name_strs => #dyn_reads,
+                    )*
+                    _ => None,
+                }
+            }
+
+            /// Returns a type-erased handle for each column in the layout, in field order
+            pub fn columns_dyn(&self) -> impl Iterator<Item = #column_dyn_ident #layout_generics_with_ellided_lifetime> {
+                [
+                    #(
+                        #column_dyn_ident {
+                            name: #field_name_strs,
+                            type_name: core::any::type_name::<#field_types>(),
+                            layout: self,
+                        },
+                    )*
+                ]
+                .into_iter()
+            }
+
+            /// Add a node from only the fields without a `#[soa(default)]`, using the default for
+            /// every other field. Returns the index of the newly inserted element.
+            #[allow(clippy::too_many_arguments)]
+            pub fn add_partial(&mut self, #(#required_names: #required_types),*) -> #id_ty {
+                self.add(#struct_ident {
+                    #(
+                        #field_names: #partial_values,
+                    )*
+                })
+            }
+
+            /// Returns a cursor starting at the first node in the layout
+            pub fn cursor(&mut self) -> #layout_cursor_ident #layout_generics_with_ellided_lifetime {
+                #layout_cursor_ident { index: #id_ident::null(), layout: self }
+            }
+
+            /// Returns an iterator over the id of every node in the layout, in order, without
+            /// reading any column. The iterator doesn't borrow the layout, so nodes can be written
+            /// while iterating.
+            pub fn iter_ids(&self) -> core::iter::Map<core::ops::Range<u32>, fn(u32) -> #id_ty> {
+                (0..self.len() as u32).map(#id_new)
+            }
+
+            /// Reset the layout for reuse, like an arena between iterations or frames: every node is
+            /// removed and the allocations are kept for the next nodes, as with
+            /// `clear_keep_capacity()`. Ids are handed out again from the first index, so an Id
+            /// kept from before the reset refers to whichever new node takes its index.
+            pub fn reset(&mut self) {
+                self.clear_keep_capacity();
+            }
+
+            /// Returns `true` if the id refers to a node in the layout
+            pub fn is_valid(&self, id: #id_ty) -> bool {
+                (id.0 as usize) < self.len()
+            }
+
+            // Generate a cascading removal for each field referencing another layout.
+            #(#cascades)*
+
+            #(
+                /// Overwrite this field of the node at the given index only if it currently holds
+                /// `expected`, returning `Ok` with the previous value if it was written, or `Err`
+                /// with the current value if it wasn't.
+                pub fn #set_compare_and_set_names(&mut self, index: #id_ty, expected: &#set_field_types, value: #set_field_types) -> Result<Result<#set_field_types, #set_field_types>, #error_ident>
+                where
+                    for<'__b> #set_field_types: PartialEq + Clone,
+                {
+                    let current = self.#set_getter_names(index)?;
+                    let current: #set_field_types = core::borrow::Borrow::<#set_field_types>::borrow(&current).clone();
+                    if current != *expected {
+                        return Ok(Err(current));
+                    }
+
+                    self.#set_setter_names(index, value)?;
+                    Ok(Ok(current))
+                }
+            )*
+
+            /// Start a query over the nodes of the layout, narrowed down with per-field predicates
+            pub fn query(&self) -> #query_ident #layout_generics_with_ellided_lifetime {
+                #query_ident { layout: self, predicates: Vec::new(), #query_candidates_init }
+            }
+
+            /// Returns the ids of `k` distinct nodes chosen uniformly at random, in random order, or
+            /// of every node if there are fewer than `k`.
+            pub fn sample(&self, rng: &mut impl ::soaaos::__private::rand::Rng, k: usize) -> Vec<#id_ty> {
+                let len = self.len();
+                ::soaaos::__private::rand::seq::index::sample(rng, len, k.min(len))
+                    .into_iter()
+                    .map(|index| #id_new(index as u32))
+                    .collect()
+            }
+
+            /// Returns the id of a node chosen uniformly at random, or `None` if the layout is
+            /// empty.
+            pub fn choose(&self, rng: &mut impl ::soaaos::__private::rand::Rng) -> Option<#id_ty> {
+                let len = self.len();
+                (len > 0).then(|| #id_new(rng.random_range(0..len) as u32))
+            }
+
+            /// Draw `k` Ids of nodes at random, with replacement, each node in proportion to the
+            /// weight `weight` returns for it. Negative and NaN weights count as 0.
+            ///
+            /// Returns no Ids if no weight is positive, or if the weights sum to infinity.
+            pub fn sample_weighted(
+                &self,
+                rng: &mut impl ::soaaos::__private::rand::Rng,
+                k: usize,
+                weight: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> f64,
+            ) -> Vec<#id_ty> {
+                match ::soaaos::sample::AliasTable::new(self.iter().map(weight)) {
+                    Some(table) => (0..k).map(|_| #id_new(table.sample(rng) as u32)).collect(),
+                    None => Vec::new(),
+                }
+            }
+
+            // Generate the weighted sampling by each numeric field.
+            #(
+                /// Draw `k` Ids of nodes at random, with replacement, each node in proportion to
+                /// the value of this numeric field. Negative and NaN values count as 0.
+                ///
+                /// Returns no Ids if no value is positive, or if the values sum to infinity.
+                pub fn #sample_weighted_by_names(&self, rng: &mut impl ::soaaos::__private::rand::Rng, k: usize) -> Vec<#id_ty>
+                where
+                    for<'__b> #field_types: ::soaaos::summary::Numeric,
+                {
+                    let weights = self.#field_names().map(|val| ::soaaos::summary::Numeric::to_f64(*core::borrow::Borrow::<#field_types>::borrow(&val)));
+                    match ::soaaos::sample::AliasTable::new(weights) {
+                        Some(table) => (0..k).map(|_| #id_new(table.sample(rng) as u32)).collect(),
+                        None => Vec::new(),
+                    }
+                }
+            )*
+
+            #subscribe
+
+            #debug_stats
+
+            #default_rows
+
+            #bulk_load
+
+            #extract
+
+            #add_unique
+
+            #keyed
+
+            #ring
+
+            #versions
+
+            #epochs
+
+            #flatbuffers
+
+            #proto
+
+            #sqlite
+
+            #slotmap_interop
+
+            #arena_interop
+
+            #fuzz
+
+            /// Buffer the adds, sets and removes made through the transaction given to `f`, then
+            /// apply them together to a copy of the layout. The copy replaces the layout only if
+            /// every operation used a node that existed at that point, no two nodes share the value
+            /// of a `#[soa(unique)]` field, and every `#[soa(references)]` field holding an Id of
+            /// this layout references an existing node. Otherwise the layout is left unchanged.
+            pub fn transaction<__R>(&mut self, f: impl FnOnce(&mut #transaction_ident #layout_generics_with_ellided_lifetime) -> __R) -> Result<__R, #transaction_error_ident>
+            where
+                for<'__b> Self: Clone,
+                #(
+                    for<'__b> #unique_types: core::hash::Hash + Eq,
+                )*
+            {
+                let mut transaction = #transaction_ident {
+                    len: self.len(),
+                    error: None,
+                    ops: Vec::new(),
+                    _layout: core::marker::PhantomData,
+                };
+                let result = f(&mut transaction);
+
+                if let Some(error) = transaction.error {
+                    return Err(error);
+                }
+
+                let mut staged = self.clone();
+                for op in transaction.ops {
+                    op(&mut staged);
+                }
+
+                #(#transaction_checks)*
+
+                #transaction_commit
+                Ok(result)
+            }
+        }
+
+        /// An error that prevented a transaction from being committed
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        pub enum #transaction_error_ident {
+            /// The operation at index `op` of the transaction used the node at `row`, which didn't
+            /// exist at that point of the transaction
+            NotFound { op: usize, row: usize },
+
+            /// The `#[soa(unique)]` field `field` of the node at `row` holds the same value as an
+            /// earlier node
+            Duplicate { row: usize, field: &'static str },
+
+            /// The `#[soa(references)]` field `field` of the node at `row` references a node past
+            /// the end of the layout
+            DanglingReference { row: usize, field: &'static str },
+        }
+
+        impl core::fmt::Display for #transaction_error_ident {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    #transaction_error_ident::NotFound { op, row } => write!(f, "Operation {op} uses node {row}, which doesn't exist"),
+                    #transaction_error_ident::Duplicate { row, field } => write!(f, "Duplicate {field} in node {row}"),
+                    #transaction_error_ident::DanglingReference { row, field } => write!(f, "Dangling reference in {field} of node {row}"),
+                }
+            }
+        }
+
+        impl core::error::Error for #transaction_error_ident {}
+
+        /// The operations of a `transaction()`, applied once the transaction is committed.
+        ///
+        /// Ids given to the transaction refer to the nodes as they are at that point of the
+        /// transaction: after every earlier add and remove.
+        pub struct #transaction_ident #layout_generics_with_lifetime #where_clause {
+            len: usize,
+            error: Option<#transaction_error_ident>,
+            ops: Vec<Box<dyn FnOnce(&mut #layout_ty) + 'a>>,
+            _layout: core::marker::PhantomData<&'a #layout_ty>,
+        }
+
+        impl #layout_generics_with_lifetime #transaction_ident #layout_generics_with_lifetime #where_clause {
+            /// Record an error for the operation using the given node if it doesn't exist
+            fn check(&mut self, index: #id_ty) {
+                if self.error.is_none() && index.0 as usize >= self.len {
+                    self.error = Some(#transaction_error_ident::NotFound { op: self.ops.len(), row: index.0 as usize });
+                }
+            }
+
+            /// Returns the number of nodes the layout will have after the operations so far
+            pub fn len(&self) -> usize {
+                self.len
+            }
+
+            /// Returns `true` if the layout will be empty after the operations so far
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
+            /// Add a node, returning the index it will have once committed
+            pub fn add(&mut self, item: #struct_ident #impl_generics) -> #id_ty {
+                let id = #id_new(self.len as u32);
+                self.len += 1;
+                self.ops.push(Box::new(move |layout| {
+                    layout.add(item);
+                }));
+                id
+            }
+
+            /// Remove a node, shifting every later node down by one
+            pub fn remove(&mut self, index: #id_ty) {
+                self.check(index);
+                self.len = self.len.saturating_sub(1);
+                self.ops.push(Box::new(move |layout| {
+                    let _ = layout.remove(index);
+                }));
+            }
+
+            #(
+                /// Overwrite this field of the node at the given index
+                pub fn #set_setter_names(&mut self, index: #id_ty, value: #set_field_types) {
+                    self.check(index);
+                    self.ops.push(Box::new(move |layout| {
+                        let _ = layout.#set_setter_names(index, value);
+                    }));
+                }
+            )*
+        }
+
+        /// A query over the nodes of a layout, matching the nodes that satisfy every predicate.
+        ///
+        /// Before scanning, each predicate is tried on a sample of the nodes. The most selective
+        /// predicate scans its whole column into a list of candidate Ids, and the other
+        /// predicates, from the most to the least selective, each narrow the list down with a
+        /// single pass over their column.
+        pub struct #query_ident #layout_generics_with_lifetime #where_clause {
+            layout: &'a #layout_ty,
+            /// Each predicate returns the matching Ids among the given candidates, in order, or
+            /// among every node without candidates
+            predicates: Vec<Box<dyn Fn(Option<Vec<#id_ty>>) -> Vec<#id_ty> + 'a>>,
+            #query_candidates_field
+        }
+
+        impl #layout_generics_with_lifetime #query_ident #layout_generics_with_lifetime #where_clause {
+            /// Add a predicate on the values of a column, read in order with `column`
+            fn column<I: Iterator>(
+                mut self,
+                column: impl Fn(&'a #layout_ty) -> I + 'a,
+                test: impl Fn(I::Item) -> bool + 'a,
+            ) -> Self {
+                let layout = self.layout;
+                self.predicates.push(Box::new(move |candidates| match candidates {
+                    Some(mut ids) => {
+                        // The candidates are in order, so the column is only walked once
+                        let mut values = column(layout);
+                        let mut next = 0;
+                        ids.retain(|id| {
+                            let index = id.0 as usize;
+                            let val = values.nth(index - next).expect("Candidate is in the layout");
+                            next = index + 1;
+                            test(val)
+                        });
+                        ids
+                    }
+                    None => column(layout)
+                        .enumerate()
+                        .filter_map(|(index, val)| test(val).then(|| #id_new(index as u32)))
+                        .collect(),
+                }));
+                self
+            }
+
+            #(
+                /// Only match nodes where this field is equal to `val`
+                pub fn #eq_names(self, val: #field_types) -> Self
+                where
+                    for<'__b> #field_types: PartialEq + 'a,
+                {
+                    #eq_bodies
+                }
+
+                /// Only match nodes where this field is not equal to `val`
+                pub fn #ne_names(self, val: #field_types) -> Self
+                where
+                    for<'__b> #field_types: PartialEq + 'a,
+                {
+                    self.column(
+                        |layout| layout.#field_names(),
+                        move |field| core::borrow::Borrow::<#field_types>::borrow(&field) != &val,
+                    )
+                }
+
+                /// Only match nodes where this field is less than `val`
+                pub fn #lt_names(self, val: #field_types) -> Self
+                where
+                    for<'__b> #field_types: PartialOrd + 'a,
+                {
+                    self.column(
+                        |layout| layout.#field_names(),
+                        move |field| core::borrow::Borrow::<#field_types>::borrow(&field) < &val,
+                    )
+                }
+
+                /// Only match nodes where this field is less than or equal to `val`
+                pub fn #le_names(self, val: #field_types) -> Self
+                where
+                    for<'__b> #field_types: PartialOrd + 'a,
+                {
+                    self.column(
+                        |layout| layout.#field_names(),
+                        move |field| core::borrow::Borrow::<#field_types>::borrow(&field) <= &val,
+                    )
+                }
+
+                /// Only match nodes where this field is greater than `val`
+                pub fn #gt_names(self, val: #field_types) -> Self
+                where
+                    for<'__b> #field_types: PartialOrd + 'a,
+                {
+                    self.column(
+                        |layout| layout.#field_names(),
+                        move |field| core::borrow::Borrow::<#field_types>::borrow(&field) > &val,
+                    )
+                }
+
+                /// Only match nodes where this field is greater than or equal to `val`
+                pub fn #ge_names(self, val: #field_types) -> Self
+                where
+                    for<'__b> #field_types: PartialOrd + 'a,
+                {
+                    self.column(
+                        |layout| layout.#field_names(),
+                        move |field| core::borrow::Borrow::<#field_types>::borrow(&field) >= &val,
+                    )
+                }
+            )*
+
+            /// Only match nodes for which `f` returns `true`
+            pub fn filter(mut self, f: impl Fn(#struct_ident_ref #generics_with_lifetime) -> bool + 'a) -> Self {
+                let layout = self.layout;
+                self.predicates.push(Box::new(move |candidates| match candidates {
+                    Some(mut ids) => {
+                        ids.retain(|id| f(layout.get(*id).expect("Candidate is in the layout")));
+                        ids
+                    }
+                    None => layout
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, node)| f(node).then(|| #id_new(index as u32)))
+                        .collect(),
+                }));
+                self
+            }
+
+            /// Returns the ids of every matching node, in order
+            pub fn ids(&self) -> Vec<#id_ty> {
+                // The number of nodes used to estimate the selectivity of each predicate
+                const SAMPLE: usize = 64;
+
+                let len = self.layout.len();
+                let samples = SAMPLE.min(len);
+                let sample: Vec<#id_ty> = (0..samples)
+                    .map(|i| #id_new((i * len / samples) as u32))
+                    .collect();
+
+                let mut predicates: Vec<_> = self
+                    .predicates
+                    .iter()
+                    .map(|predicate| (predicate(Some(sample.clone())).len(), predicate))
+                    .collect();
+                predicates.sort_by_key(|(matches, _)| *matches);
+
+                #query_candidates
+                for (_, predicate) in predicates {
+                    candidates = Some(predicate(candidates));
+                }
+                candidates.unwrap_or_else(|| (0..len).map(|index| #id_new(index as u32)).collect())
+            }
+
+            /// Returns a view of every matching node, in order
+            pub fn iter(self) -> impl Iterator<Item = #struct_ident_ref #generics_with_lifetime> {
+                let layout = self.layout;
+                self.ids().into_iter().map(move |id| layout.get(id).unwrap())
+            }
+
+            /// Returns the number of matching nodes
+            pub fn count(&self) -> usize {
+                self.ids().len()
+            }
+        }
+
+        impl #layout_impl_generics ::soaaos::derived::Table for #layout_ty #where_clause {
+            type Id = #id_ty;
+
+            fn len(&self) -> usize {
+                self.len()
+            }
+
+            fn id(index: usize) -> #id_ty {
+                #id_new(index as u32)
+            }
+
+            fn truncate_rows(&mut self, len: usize) {
+                while self.len() > len {
+                    self.remove(#id_new((self.len() - 1) as u32)).unwrap();
+                }
+            }
+        }
+
+        impl #layout_impl_generics ::soaaos::RowAccess for #layout_ty #where_clause {
+            type Ref<'a> = #struct_ident_ref #row_ty_generics where Self: 'a;
+            type Mut<'a> = #struct_ident_ref_mut #row_ty_generics where Self: 'a;
+
+            fn row(&self, id: #id_ty) -> Option<#struct_ident_ref #generics_with_ellided_lifetime> {
+                self.get(id).ok()
+            }
+
+            fn row_mut(&mut self, id: #id_ty) -> Option<#struct_ident_ref_mut #generics_with_ellided_lifetime> {
+                self.get_mut(id).ok()
+            }
+
+            fn rows(&self) -> impl Iterator<Item = #struct_ident_ref #generics_with_ellided_lifetime> {
+                self.iter()
+            }
+        }
+
+        impl #layout_impl_generics ::soaaos::derived::TableRows<#struct_ident #impl_generics> for #layout_ty #where_clause {
+            fn push_row(&mut self, row: #struct_ident #impl_generics) {
+                self.add(row);
+            }
+
+            // `#[soa(frozen)]` fields keep the value the row was added with
+            fn set_row(&mut self, id: #id_ty, row: #struct_ident #impl_generics) {
+                #(
+                    self.#set_setter_names(id, row.#set_field_names).expect("Id out of bounds");
+                )*
+            }
+        }
+
+        #scope_method
+
+        #id_map
+
+        /// The layout appended to from many threads through per-thread shards, merged into the
+        /// layout in batches
+        pub type #sharded_ident <#(#alias_params),*> = ::soaaos::sharded::Sharded<#layout_ty, #struct_ident #impl_generics>;
+
+        /// The layout shared by reference, read and written through the `soaaos::ghost::GhostToken`
+        /// of the brand `'__id`
+        pub type #ghost_ident <'__id, #(#alias_params),*> = ::soaaos::ghost::GhostLayout<'__id, #layout_ty>;
+
+        #fuzz_target
+
+        #static_layout
+
+        impl #layout_impl_generics ::soaaos::derived::RowHash for #layout_ty #hash_where_clause {
+            fn row_hash(&self, id: #id_ty) -> u64 {
+                use core::hash::{Hash, Hasher};
+
+                let mut hasher = std::hash::DefaultHasher::new();
+                #(
+                    self.#getter_names(id).expect("Id out of bounds").hash(&mut hasher);
+                )*
+                hasher.finish()
+            }
+        }
+
+        /// Builds a node one field at a time. Every field without a `#[soa(default)]` must be set
+        /// before building.
+        pub struct #builder_ident #impl_generics #where_clause {
+            #(
+                #field_names: Option<#field_types>,
+            )*
+        }
+
+        impl #impl_generics Default for #builder_ident #impl_generics #where_clause {
+            fn default() -> Self {
+                Self {
+                    #(
+                        #field_names: None,
+                    )*
+                }
+            }
+        }
+
+        impl #impl_generics #builder_ident #impl_generics #where_clause {
+            /// Create a builder with no fields set
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            #(
+                /// Set the value of this field
+                pub fn #field_names(mut self, val: #field_types) -> Self {
+                    self.#field_names = Some(val);
+                    self
+                }
+            )*
+
+            /// Build the node, returning an error for the first required field that was not set
+            pub fn build(self) -> Result<#struct_ident #impl_generics, #error_ident> {
+                Ok(#struct_ident {
+                    #(
+                        #field_names: #builder_values,
+                    )*
+                })
+            }
+
+            /// Build the node and add it to the given layout, returning its id
+            pub fn build_into #brand_generics (self, layout: &mut #layout_ty) -> Result<#id_ty, #error_ident> {
+                Ok(layout.add(self.build()?))
+            }
+        }
+
+        /// The fields of the nodes picked by `select()`, gathered into a contiguous `Vec` per field
+        /// in the order of their Ids, so a kernel over dense columns runs on them as is.
+        ///
+        /// Filled again by `select_into()`, which keeps the capacity of every `Vec`.
+        pub struct #selection_ident #impl_generics #where_clause {
+            #(
+                pub #field_names: Vec<#field_types>,
+            )*
+        }
+
+        impl #impl_generics Default for #selection_ident #impl_generics #where_clause {
+            fn default() -> Self {
+                Self {
+                    #(
+                        #field_names: Vec::new(),
+                    )*
+                }
+            }
+        }
+
+        impl #impl_generics #selection_ident #impl_generics #where_clause {
+            /// Create an empty selection, without allocating
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Get the number of nodes in the selection
+            pub fn len(&self) -> usize {
+                self.#first_field.len()
+            }
+
+            /// Returns `true` if the selection is empty
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Remove every node, keeping the capacity of every `Vec`
+            pub fn clear(&mut self) {
+                #(
+                    self.#field_names.clear();
+                )*
+            }
+        }
+
+        /// A type-erased handle to a single column of the layout
+        pub struct #column_dyn_ident #layout_generics_with_lifetime #where_clause {
+            name: &'static str,
+            type_name: &'static str,
+            layout: &'a #layout_ty,
+        }
+
+        impl #layout_generics_with_lifetime #column_dyn_ident #layout_generics_with_lifetime #where_clause {
+            /// Returns the name of the field stored in this column
+            pub fn name(&self) -> &'static str {
+                self.name
+            }
+
+            /// Returns the type name of the field stored in this column
+            pub fn type_name(&self) -> &'static str {
+                self.type_name
+            }
+
+            /// Get the number of elements in the column
+            pub fn len(&self) -> usize {
+                self.layout.len()
+            }
+
+            /// Returns `true` if the column is empty
+            pub fn is_empty(&self) -> bool {
+                self.layout.is_empty()
+            }
+
+            /// Returns a reference to the field value of the node at the given index
+            pub fn get(&self, index: #id_ty) -> Option<&'a dyn core::any::Any>
+            where
+                #(
+                    for<'__b> #field_types: 'static,
+                )*
+            {
+                self.layout.get_field_dyn(self.name, index)
+            }
+        }
+
+        /// A cursor over the layout tracking the id of the current node
+        pub struct #layout_cursor_ident #layout_generics_with_lifetime #where_clause {
+            index: #id_ty,
+            layout: &'a mut #layout_ty,
+        }
+
+        impl #layout_generics_with_lifetime #layout_cursor_ident #layout_generics_with_lifetime #where_clause {
+            /// Returns the id of the current node
+            pub fn id(&self) -> #id_ty {
+                self.index
+            }
+
+            /// Returns `true` if the cursor is past the last node in the layout
+            pub fn is_done(&self) -> bool {
+                self.index.0 as usize >= self.layout.len()
+            }
+
+            /// Returns a view of the current node, or `None` if the cursor is past the end
+            pub fn peek(&self) -> Option<#struct_ident_ref #generics_with_ellided_lifetime> {
+                self.layout.get(self.index).ok()
+            }
+
+            /// Returns a mutable view of the current node, or `None` if the cursor is past the end
+            pub fn peek_mut(&mut self) -> Option<#struct_ident_ref_mut #generics_with_ellided_lifetime> {
+                self.layout.get_mut(self.index).ok()
+            }
+
+            /// Move the cursor to the next node. Returns `false` if the cursor is now past the end.
+            pub fn advance(&mut self) -> bool {
+                if !self.is_done() {
+                    self.index = #id_new(self.index.0 + 1);
+                }
+
+                !self.is_done()
+            }
+
+            /// Move the cursor to the given node
+            pub fn seek(&mut self, id: #id_ty) {
+                self.index = id;
+            }
+        }
+
+        pub struct #layout_iter_ident #layout_generics_with_lifetime #where_clause {
+            index: #id_ty,
+            layout: &'a #layout_ty,
+        }
+
+
+        // Iterate through all elements in the layout, returning a struct of refs to the internal fields
+        impl #layout_generics_with_lifetime Iterator for #layout_iter_ident #layout_generics_with_lifetime #where_clause {
+            type Item = #struct_ident_ref #generics_with_lifetime;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let result = #struct_ident_ref {
+                    #(
+                        #field_names: self.layout.#getter_names(self.index).ok()?,
+                    )*
+                };
+
+                self.index = #id_new(self.index.0 + 1);
+
+                Some(result)
+            }
+        }
+    };
+
+    // Layouts where every column is segmented never move existing fields when adding nodes
+    let stable_addresses = if field_options
+        .iter()
+        .all(|options| options.storage == options::Storage::Segmented)
+    {
+        quote! {
+            unsafe impl #layout_impl_generics ::soaaos::column::StableAddresses for #layout_ty #where_clause {}
+        }
+    } else {
+        quote! {}
+    };
+
+    // Generate different implementations based on the chosen layout.
+    if layout == Layout::StructOfArrays {
+        let output = quote! {
+            #both
+
+            /// Layout version using struct-of-arrays layout.
+            #layout_derives
+            pub struct #layout_struct_ident #layout_def_generics #where_clause {
+                #(
+                    pub #column_members: #column_member_types,
+                )*
+                #hidden_fields
+            }
+
+            #stable_addresses
+
+            /// A `#[repr(C)]` description of the layout for non-Rust consumers, pointing to each
+            /// column stored in a contiguous slice. Only valid until the layout is modified.
+            #[repr(C)]
+            #[derive(Debug, Clone, Copy)]
+            pub struct #descriptor_ident #generics #where_clause {
+                /// The number of nodes in the layout
+                pub len: usize,
+                #(
+                    pub #vec_field_names: *const #vec_field_types,
+                )*
+                _row: core::marker::PhantomData<fn() -> #struct_ident #impl_generics>,
+            }
+
+            impl #layout_impl_generics #layout_ty #where_clause {
+                /// The C header defining the Id type and the descriptor returned by `descriptor()`
+                pub const C_HEADER: &'static str = #c_header;
+
+                /// Describe the layout with pointers to each column stored in a contiguous slice
+                pub fn descriptor(&self) -> #descriptor_ident #impl_generics {
+                    #descriptor_ident {
+                        len: self.len(),
+                        #(
+                            #vec_field_names: self.#vec_field_names.as_ptr(),
+                        )*
+                        _row: core::marker::PhantomData,
+                    }
+                }
+            }
+
+            /// A block of `N` consecutive nodes returned by `chunks()`, with an array of each field
+            /// stored in a `Vec`
+            pub struct #chunk_ident #chunk_generics #where_clause {
+                #(
+                    pub #vec_field_names: &#lifetime [#vec_field_types; __N],
+                )*
+                _row: core::marker::PhantomData<&#lifetime #struct_ident #impl_generics>,
+            }
+
+            #rest_views
+
+            /// Every column of a layout as a `Vec`, moved out by `into_columns()` and moved back
+            /// in by `from_columns()`
+            pub struct #columns_ident #generics #where_clause {
+                #(
+                    pub #field_names: Vec<#field_types>,
+                )*
+            }
+
+            impl #layout_impl_generics #layout_ty #where_clause {
+                /// Create a new layout struct with all internal vectors initialized.
+                pub #const_new fn new() -> Self {
+                    // println!("Using struct-of-arrays for {}", stringify!(#struct_ident));
+
+                    Self {
+                        #(
+                            #column_members: <#column_member_types>::new(),
+                        )*
+                        #hidden_init
+                    }
+                }
+
+
+                /// Create a new layout struct with all internal vectors initialized.
+                pub fn with_capacity(size: usize) -> Self {
+                    // println!("Using struct-of-arrays for {}", stringify!(#struct_ident));
+
+                    Self {
+                        #(
+                            #column_members: <#column_member_types>::with_capacity(size),
+                        )*
+                        #hidden_init
+                    }
+                }
+
+                /// Get the number of elements in the layout
+                pub fn len(&self) -> usize {
+                    self.#first_member.len()
+                }
+
+                /// Returns `true` if the layout is empty
+                pub fn is_empty(&self) -> bool {
+                    self.len() == 0
+                }
+
+                /// Add an instance of the original struct.
+                /// Each field value is pushed into its corresponding vector.
+                /// Returns the index of the newly inserted element.
+                pub fn add(&mut self, item: #struct_ident #impl_generics) -> #id_ty
+                #ring_add_bounds
+                {
+                    #ring_add
+                    let id = #id_new(self.#first_member.len() as u32);
+
+                    #(
+                        self.#column_members.push(#item_members);
+                    )*
+
+                    #notify_added
+                    #record_stats
+                    id
+                }
+
+                /// Add a node with every field defaulted, then fill it in place through the given
+                /// closure. Avoids building the node and moving it through `add` for large fields.
+                /// Returns the index of the newly inserted element.
+                pub fn push_with(&mut self, f: impl FnOnce(#struct_ident_ref_mut #generics_with_ellided_lifetime)) -> #id_ty
+                where
+                    #(
+                        for<'__b> #undefaulted_types: Default,
+                    )*
+                    #(
+                        for<'__b> #ring_cloned_types: Clone,
+                    )*
+                {
+                    #ring_push_with
+                    let id = #id_new(self.#first_member.len() as u32);
+
+                    #(
+                        self.#column_members.push(#default_members);
+                    )*
+                    #notify_added
+                    #record_stats
+
+                    f(self.get_mut(id).expect("Node was just added"));
+
+                    id
+                }
+
+                #(
+                    pub fn #field_names(&self) -> impl Iterator<Item = #read_types> {
+                        self.#field_columns.iter()#field_projections
+                    }
+                )*
+
+                // Generate a run iterator for each run-length encoded field.
+                #(
+                    /// Returns an iterator over each run of equal values of this field, with the
+                    /// length of the run.
+                    pub fn #runs_names(&self) -> impl Iterator<Item = (&#rle_field_types, usize)> {
+                        self.#rle_field_names.runs()
+                    }
+                )*
+
+                // Generate a bitmap lookup for each field with a bitmap index.
+                #(
+                    /// Returns the ids of the nodes where this field is equal to `val`.
+                    pub fn #bitmap_names(&self, val: &#bitmap_field_types) -> ::soaaos::__private::roaring::RoaringBitmap {
+                        self.#bitmap_field_names.bitmap(val).cloned().unwrap_or_default()
+                    }
+                )*
+
+                // Generate a range lookup for each field with a B-tree index.
+                #(
+                    /// Returns the ids of the nodes where this field is within `range`, ordered by
+                    /// the value of the field (and then by id).
+                    pub fn #range_by_names(&self, range: impl core::ops::RangeBounds<#btree_field_types>) -> impl Iterator<Item = #id_ty> + '_ {
+                        self.#btree_field_names
+                            .range(range)
+                            .map(|index| #id_new(index as u32))
+                    }
+                )*
+
+                // Generate the membership checks of each field with a bloom filter.
+                #(
+                    /// Returns `false` if no node holds `val` in this field, checking only the
+                    /// bloom filter. `true` may be a false positive.
+                    pub fn #maybe_contains_names(&self, val: &#bloom_field_types) -> bool {
+                        self.#bloom_field_names.maybe_contains(val)
+                    }
+
+                    /// Returns `true` if a node holds `val` in this field, only scanning the column
+                    /// if the bloom filter can't rule the value out.
+                    pub fn #contains_names(&self, val: &#bloom_field_types) -> bool
+                    where
+                        for<'__b> #bloom_field_types: PartialEq,
+                    {
+                        self.#bloom_field_names.maybe_contains(val)
+                            && self.#bloom_field_names.iter().any(|item| item == val)
+                    }
+                )*
+
+                // Generate a text search for each field with a search index.
+                #(
+                    /// Returns the ids of the nodes where this field contains every term of
+                    /// `query`, as split by `soaaos::column::tokenize`.
+                    pub fn #search_names(&self, query: &str) -> Vec<#id_ty> {
+                        self.#search_field_names
+                            .search(query)
+                            .into_iter()
+                            .map(|index| #id_new(index as u32))
+                            .collect()
+                    }
+                )*
+
+                // Generate the array chunks of each field stored in a `Vec`.
+                #(
+                    /// Returns this field split into arrays of `N` elements, and the remaining
+                    /// elements that don't fill an array.
+                    pub fn #chunks_names<const __N: usize>(&self) -> (&[[#vec_field_types; __N]], &[#vec_field_types]) {
+                        self.#vec_field_names.as_chunks::<__N>()
+                    }
+                )*
+
+                // Generate the range getters of each field stored in a slice.
+                #(
+                    /// Returns the field values of the nodes in the given range of Ids, checking
+                    /// the range once.
+                    pub fn #range_getter_names(&self, range: core::ops::Range<#id_ty>) -> Result<&[#vec_field_types], #error_ident> {
+                        self.#vec_field_names
+                            .get(range.start.0 as usize..range.end.0 as usize)
+                            .ok_or(#error_ident::#vec_error_names)
+                    }
+                )*
+                #(
+                    /// Returns the field values of the nodes in the given range of Ids mutably,
+                    /// checking the range once.
+                    pub fn #range_mut_getter_names(&mut self, range: core::ops::Range<#id_ty>) -> Result<&mut [#mut_vec_field_types], #error_ident> {
+                        let range = range.start.0 as usize..range.end.0 as usize;
+                        if range.start > range.end || range.end > self.len() {
+                            return Err(#error_ident::#mut_vec_error_names);
+                        }
+
+                        #range_borrowed
+                        Ok(&mut self.#mut_vec_field_names[range])
+                    }
+                )*
+
+                // Generate the reverse lookup of a reference into each field stored in a slice.
+                #(
+                    /// Returns the Id of the node holding `value`, when `value` is a reference into
+                    /// this column (from a getter, an iterator or a chunk of it), or `None` for any
+                    /// other reference. Found from the address alone, without comparing values.
+                    pub fn #id_of_names(&self, value: &#vec_field_types) -> Option<#id_ty> {
+                        let size = core::mem::size_of::<#vec_field_types>();
+                        let start = self.#vec_field_names.as_ptr() as usize;
+                        let offset = (value as *const #vec_field_types as usize).wrapping_sub(start);
+                        if size == 0 || offset % size != 0 || offset / size >= self.#vec_field_names.len() {
+                            return None;
+                        }
+
+                        Some(#id_new((offset / size) as u32))
+                    }
+                )*
+
+                // Generate the memory advice of each page aligned field.
+                #(
+                    /// Hint the OS that the column of this field is about to be scanned in order,
+                    /// so its pages are read ahead. Ignored on platforms without `posix_madvise`.
+                    pub fn #advise_sequential_names(&self) -> std::io::Result<()> {
+                        self.#page_aligned_field_names.advise(::soaaos::column::Advice::Sequential)
+                    }
+
+                    /// Hint the OS that the column of this field won't be read soon, so its pages
+                    /// are reclaimed first. The values are kept. Ignored on platforms without
+                    /// `posix_madvise`.
+                    pub fn #advise_dontneed_names(&self) -> std::io::Result<()> {
+                        self.#page_aligned_field_names.advise(::soaaos::column::Advice::DontNeed)
+                    }
+                )*
+
+                /// Returns an iterator over each block of `N` consecutive nodes, with an array of
+                /// each field stored in a `Vec`, so loops over a block have a length known at
+                /// compile time. The nodes that don't fill a block are returned by
+                /// `chunks_remainder()`.
+                pub fn chunks<const __N: usize>(&self) -> impl Iterator<Item = #chunk_ident #chunk_ellided_generics> {
+                    #(
+                        let #vec_field_names = self.#vec_field_names.as_chunks::<__N>().0;
+                    )*
+
+                    (0..self.len() / __N).map(move |chunk| #chunk_ident {
+                        #(
+                            #vec_field_names: &#vec_field_names[chunk],
+                        )*
+                        _row: core::marker::PhantomData,
+                    })
+                }
+
+                #simd_chunks
+
+                /// Returns the nodes after the last full block of `chunks()`, for a scalar loop
+                pub fn chunks_remainder<const __N: usize>(&self) -> impl Iterator<Item = (#id_ty, #struct_ident_ref #generics_with_ellided_lifetime)> {
+                    self.iter_enumerated().skip(self.len() / __N * __N)
+                }
+
+                // Generate the shared accessors for each atomic field.
+                #(
+                    /// Returns the atomic holding this field at the given index, for any atomic
+                    /// operation through `&self`.
+                    pub fn #get_atomic_names(&self, index: #id_ty) -> Result<&<#atomic_field_types as ::soaaos::column::AtomicInt>::Atomic, #error_ident> {
+                        self.#atomic_field_names
+                            .get_atomic(index.0 as usize)
+                            .ok_or_else(|| #error_ident::#atomic_error_names)
+                    }
+
+                    /// Add `val` to this field at the given index with `Relaxed` ordering, returning
+                    /// the previous value.
+                    pub fn #fetch_add_names(&self, index: #id_ty, val: #atomic_field_types) -> Result<#atomic_field_types, #error_ident> {
+                        self.#atomic_field_names
+                            .fetch_add(index.0 as usize, val, core::sync::atomic::Ordering::Relaxed)
+                            .ok_or_else(|| #error_ident::#atomic_error_names)
+                    }
+
+                    /// Returns this field at the given index, loaded with `Acquire` ordering.
+                    pub fn #load_acquire_names(&self, index: #id_ty) -> Result<#atomic_field_types, #error_ident> {
+                        self.#atomic_field_names
+                            .load(index.0 as usize, core::sync::atomic::Ordering::Acquire)
+                            .ok_or_else(|| #error_ident::#atomic_error_names)
+                    }
+
+                    /// Store `val` in this field at the given index with `Release` ordering.
+                    pub fn #store_release_names(&self, index: #id_ty, val: #atomic_field_types) -> Result<(), #error_ident> {
+                        self.#atomic_field_names
+                            .store(index.0 as usize, val, core::sync::atomic::Ordering::Release)
+                            .ok_or_else(|| #error_ident::#atomic_error_names)
+                    }
+                )*
+
+                /// Returns a reference to the field value at the given index.
+                // Generate an individual getter for each field.
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #getter_names(&self, index: #id_ty) -> Result<#read_types, #error_ident> {
+                        self
+                        .#field_columns
+                        .get(index.0 as usize)
+                        #field_projections
+                        .ok_or_else(|| #error_ident::#error_names)
+                    }
+                )*
+
+                // Generate an individual getter for each field.
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #getter_enumerated_names(&self) -> impl Iterator<Item = (#id_ty, #read_types)>{
+                        self
+                        .#field_columns
+                        .iter()
+                        #field_projections
+                        .enumerate()
+                        .map(|(index, item)| (#id_new(index as u32), item))
+                    }
+                )*
+
+                // Generate an mut individual getter for each field.
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #mut_getter_names(&mut self, index: #id_ty) -> Result<&mut #mut_field_types, #error_ident> {
+                        #notify_borrowed
+                        self
+                        .#mut_field_columns
+                        .get_mut(index.0 as usize)
+                        #mut_field_projections
+                        .ok_or_else(|| #error_ident::#mut_error_names)
+                    }
+                )*
+
+                // Generate a setter for each field.
+                #(
+                    /// Overwrite the field value at the given index.
+                    pub fn #set_setter_names(#set_setter_receivers, index: #id_ty, value: #set_field_types) -> Result<(), #error_ident> {
+                        let index = index.0 as usize;
+                        if index >= self.len() {
+                            return Err(#error_ident::#set_error_names);
+                        }
+
+                        #set_soa_writes
+                        #set_changed_at
+                        Ok(())
+                    }
+                )*
+
+                /// Returns a view of the node at the given index.
+                pub fn get(&self, index: #id_ty) -> Result<#struct_ident_ref #generics_with_ellided_lifetime, #error_ident> {
+                    if index.0 as usize >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    Ok(#struct_ident_ref {
+                        #(
+                            #field_names: self.#getter_names(index)?,
+                        )*
+                    })
+                }
+
+                /// Returns a mutable view of the node at the given index.
+                pub fn get_mut(&mut self, index: #id_ty) -> Result<#struct_ident_ref_mut #generics_with_ellided_lifetime, #error_ident> {
+                    if index.0 as usize >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    #notify_borrowed_all
+                    #(
+                        let #mut_member_locals = self
+                            .#mut_member_names
+                            .get_mut(index.0 as usize)
+                            .ok_or_else(|| #error_ident::#mut_member_errors)?;
+                    )*
+                    Ok(#struct_ident_ref_mut {
+                        #(
+                            #mut_field_names: #mut_field_borrows,
+                        )*
+                    })
+                }
+
+                /// Hand a mutable view of the node at the given index to `f`, returning its result.
+                /// The index is checked once, instead of once per field by `get_*_mut`.
+                pub fn update<__R>(&mut self, index: #id_ty, f: impl FnOnce(#struct_ident_ref_mut #generics_with_ellided_lifetime) -> __R) -> Result<__R, #error_ident> {
+                    if index.0 as usize >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    #notify_borrowed_all
+                    let index = index.0 as usize;
+                    #(
+                        let #mut_member_locals = &mut self.#mut_member_names[index];
+                    )*
+                    Ok(f(#struct_ident_ref_mut {
+                        #(
+                            #mut_field_names: #mut_field_borrows,
+                        )*
+                    }))
+                }
+
+                /// Remove and return the node at the given index, shifting every later node down by
+                /// one. Ids of the later nodes then refer to the node after them. Also returns the
+                /// map of the moved Ids.
+                pub fn remove(&mut self, index: #id_ty) -> Result<(#struct_ident #impl_generics, #id_map_ty), #error_ident> {
+                    let index = index.0 as usize;
+                    if index >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    let map = #id_map_ident::from_fn(self.len(), |old| match (old.0 as usize).cmp(&index) {
+                        core::cmp::Ordering::Less => Some(old),
+                        core::cmp::Ordering::Equal => None,
+                        core::cmp::Ordering::Greater => Some(#id_new(old.0 - 1)),
+                    });
+
+                    #(
+                        let #member_locals = self.#column_members.remove(index);
+                    )*
+                    let node = #struct_ident {
+                        #(
+                            #field_names: #fields_of_locals,
+                        )*
+                    };
+                    #notify_removed
+                    #ring_removed
+                    Ok((node, map))
+                }
+
+                /// Remove and return the node at the given index by moving the last node into its
+                /// place, without shifting the other nodes. Also returns the map of the moved Id.
+                pub fn swap_remove(&mut self, index: #id_ty) -> Result<(#struct_ident #impl_generics, #id_map_ty), #error_ident>
+                where
+                    #(
+                        for<'__b> #swap_cloned_types: Clone,
+                    )*
+                {
+                    let index = index.0 as usize;
+                    if index >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    let last = self.len() - 1;
+                    let mut map = #id_map_ident::identity(self.len());
+                    map.insert(#id_new(index as u32), None);
+                    map.insert(#id_new(last as u32), Some(#id_new(index as u32)).filter(|_| index != last));
+
+                    #(
+                        let #member_locals = {
+                            let moved = self.#column_members.remove(last);
+                            if index == last {
+                                moved
+                            } else {
+                                #soa_swap_takes
+                            }
+                        };
+                    )*
+                    let node = #struct_ident {
+                        #(
+                            #field_names: #fields_of_locals,
+                        )*
+                    };
+                    #notify_swap_removed
+                    #invalidate
+                    #ring_reset
+                    Ok((node, map))
+                }
+
+                /// Keep only the nodes for which `f` returns `true`, in order, returning the map of
+                /// the moved and removed Ids. Every column is gathered once.
+                pub fn retain(&mut self, mut f: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> bool) -> #id_map_ty
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    let keep: Vec<bool> = self.iter().map(&mut f).collect();
+                    let mut map = #id_map_ident::identity(self.len());
+                    let mut kept = Vec::new();
+                    for (index, keep) in keep.iter().enumerate() {
+                        let old = #id_new(index as u32);
+                        if *keep {
+                            map.insert(old, Some(#id_new(kept.len() as u32)));
+                            kept.push(old);
+                        } else {
+                            map.insert(old, None);
+                        }
+                    }
+
+                    if !map.is_identity() {
+                        let retained = self.gather(&kept);
+                        #(
+                            self.#column_members = retained.#column_members;
+                        )*
+                        #notify_retained
+                        #invalidate
+                        #ring_retained
+                    }
+                    map
+                }
+
+                /// Remove every node, keeping the allocation of every column for the next nodes.
+                pub fn clear_keep_capacity(&mut self) {
+                    #(
+                        self.#column_members.clear();
+                    )*
+                    #notify_cleared
+                }
+
+                /// Reverse the order of the elements in the layout, in place, returning the map of
+                /// the moved Ids.
+                pub fn reverse(&mut self) -> #id_map_ty {
+                    #(
+                        self.#column_members.reverse();
+                    )*
+                    #notify_reordered
+                    #reversed_map
+                }
+
+                /// Rotate the elements in the layout `mid` places to the left, returning the map of
+                /// the moved Ids.
+                ///
+                /// Panics if `mid` is greater than the length of the layout.
+                pub fn rotate_left(&mut self, mid: usize) -> #id_map_ty {
+                    #(
+                        self.#column_members.rotate_left(mid);
+                    )*
+                    #notify_reordered
+                    #rotated_left_map
+                }
+
+                /// Rotate the elements in the layout `k` places to the right, returning the map of
+                /// the moved Ids.
+                ///
+                /// Panics if `k` is greater than the length of the layout.
+                pub fn rotate_right(&mut self, k: usize) -> #id_map_ty {
+                    #(
+                        self.#column_members.rotate_right(k);
+                    )*
+                    #notify_reordered
+                    #rotated_right_map
+                }
+
+                /// Create a new layout containing only the nodes at the given ids, in the given order.
+                ///
+                /// Panics if any of the ids are out of bounds.
+                pub fn gather(&self, ids: &[#id_ty]) -> Self
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    #ring_gather
+                    Self {
+                        #(
+                            #column_members: ids
+                                .iter()
+                                .map(|id| id.0 as usize)
+                                .map(|index| #soa_member_cloned_reads)
+                                .collect(),
+                        )*
+                        #hidden_init
+                    }
+                }
+
+                #with_mut_methods
+
+                /// Move every column out of the layout into a `Vec`. The columns stored in a `Vec`
+                /// are moved as is, the elements of the others are cloned.
+                pub fn into_columns(mut self) -> #columns_ident #impl_generics
+                where
+                    #(
+                        for<'__b> #cloned_column_types: Clone,
+                    )*
+                {
+                    #into_columns_len
+                    #columns_ident {
+                        #(
+                            #field_names: #into_column_vecs,
+                        )*
+                    }
+                }
+
+                /// Create a layout from a `Vec` of every column, the inverse of `into_columns()`. The
+                /// columns stored in a `Vec` are moved in as is.
+                ///
+                /// Panics if the columns have different lengths.
+                pub fn from_columns(columns: #columns_ident #impl_generics) -> Self
+                where
+                    #(
+                        for<'__b> #column_types: FromIterator<#field_types>,
+                    )*
+                {
+                    let len = columns.#first_field.len();
+                    assert!(
+                        [#(columns.#field_names.len()),*].iter().all(|column_len| *column_len == len),
+                        "columns of different lengths"
+                    );
+                    #ring_from_columns
+
+                    Self {
+                        #(
+                            #column_members: #members_from_columns,
+                        )*
+                        #hidden_init
+                    }
+                }
+
+                /// Copy the nodes at the given ids of `other` to the end of the layout, in the given
+                /// order, returning their new ids. Each column is copied in one pass.
+                ///
+                /// Panics if any of the ids are out of bounds of `other`, without adding any node.
+                pub fn extend_from_layout(&mut self, other: &Self, ids: &[#id_ty]) -> Vec<#id_ty>
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    assert!(
+                        ids.iter().all(|id| (id.0 as usize) < other.len()),
+                        "Id out of bounds"
+                    );
+
+                    #ring_extend
+                    let start = self.len();
+                    #(
+                        for index in ids.iter().map(|id| id.0 as usize) {
+                            self.#column_members.push(#soa_other_member_cloned_reads);
+                        }
+                    )*
+
+                    let new_ids: Vec<#id_ty> = (start..self.len())
+                        .map(|index| #id_new(index as u32))
+                        .collect();
+                    #notify_added_each
+                    #record_stats
+                    new_ids
+                }
+
+                /// Move the nodes of the layout to the given order, so the node at `order[i]` is
+                /// then at index `i`, returning the map of the moved Ids. Every column is gathered
+                /// once.
+                ///
+                /// Panics unless `order` holds every id of the layout exactly once.
+                pub fn reorder(&mut self, order: &[#id_ty]) -> #id_map_ty
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    #check_order
+
+                    let reordered = self.gather(order);
+                    #(
+                        self.#column_members = reordered.#column_members;
+                    )*
+                    #notify_reordered
+                    #id_map_ident::from_order(order)
+                }
+
+                /// Shuffle the nodes of the layout uniformly at random, moving every field of a node
+                /// together, returning the map of the moved Ids.
+                pub fn shuffle(&mut self, rng: &mut impl ::soaaos::__private::rand::Rng) -> #id_map_ty
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                    ::soaaos::__private::rand::seq::SliceRandom::shuffle(order.as_mut_slice(), rng);
+                    self.reorder(&order)
+                }
+
+                // Generate a bulk setter for each field.
+                #(
+                    /// Write each value to the field of the node at its paired id.
+                    ///
+                    /// All ids are checked before anything is written, so an out of bounds id
+                    /// leaves the layout untouched.
+                    pub fn #set_scatter_names(&mut self, writes: &[(#id_ty, #set_field_types)]) -> Result<(), #error_ident>
+                    where
+                        for<'__b> #set_field_types: Clone,
+                    {
+                        let len = self.len();
+                        if writes.iter().any(|(id, _)| id.0 as usize >= len) {
+                            return Err(#error_ident::#set_error_names);
+                        }
+
+                        for (id, val) in writes {
+                            let index = id.0 as usize;
+                            let value = val.clone();
+                            #set_soa_writes
+                            #set_changed_at
+                        }
+
+                        Ok(())
+                    }
+                )*
+
+                /// Returns an iterator of owned nodes, cloning each field of every node.
+                pub fn iter_cloned(&self) -> impl Iterator<Item = #struct_ident #impl_generics> + '_
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    (0..self.len()).map(move |index| #struct_ident {
+                        #(
+                            #field_names: #soa_cloned_reads,
+                        )*
+                    })
+                }
+
+                /// Returns a `Vec` of owned nodes, cloning each field of every node.
+                pub fn to_rows(&self) -> Vec<#struct_ident #impl_generics>
+                where
+                    #(
+                        for<'__b> #field_types: Clone,
+                    )*
+                {
+                    self.iter_cloned().collect()
+                }
+            }
+
+        };
+
+        output
+    } else if layout == Layout::ArrayOfStructs {
+        let output = quote! {
+            #both
+
+            /// Layout version using array-of-structs layout.
+            #layout_derives
+            pub struct #layout_struct_ident #layout_def_generics #where_clause {
+                pub data: Vec<#struct_ident #impl_generics>,
+                #hidden_fields
+            }
+
+            /// A `#[repr(C)]` description of the layout for non-Rust consumers, pointing to the
+            /// rows with the offset of each field in a row. Only valid until the layout is modified.
+            #[repr(C)]
+            #[derive(Debug, Clone, Copy)]
+            pub struct #descriptor_ident #generics #where_clause {
+                /// The number of nodes in the layout
+                pub len: usize,
+
+                /// The number of bytes from one row to the next
+                pub stride: usize,
+
+                /// The first row
+                pub data: *const core::ffi::c_void,
+                #(
+                    pub #offset_names: usize,
+                )*
+                _row: core::marker::PhantomData<fn() -> #struct_ident #impl_generics>,
+            }
+
+            impl #layout_impl_generics #layout_ty #where_clause {
+                /// The C header defining the Id type and the descriptor returned by `descriptor()`
+                pub const C_HEADER: &'static str = #c_header;
+
+                /// Describe the layout with a pointer to the rows and the offset of each field
+                pub fn descriptor(&self) -> #descriptor_ident #impl_generics {
+                    #descriptor_ident {
+                        len: self.len(),
+                        stride: core::mem::size_of::<#struct_ident #impl_generics>(),
+                        data: self.data.as_ptr().cast(),
+                        #(
+                            #offset_names: core::mem::offset_of!(#struct_ident #impl_generics, #field_names),
+                        )*
+                        _row: core::marker::PhantomData,
+                    }
+                }
+            }
+
+            impl #layout_impl_generics #layout_ty #where_clause {
+                /// Create a new layout struct with an empty data vector.
+                pub #const_new fn new() -> Self {
+                    // println!("Using array-of-structs for {}", stringify!(#struct_ident));
+
+                    Self {
+                        data: Vec::new(),
+                        #hidden_init
+                    }
+                }
+
+                /// Create a new layout struct with a pre-allocated vector
+                pub fn with_capacity(size: usize) -> Self {
+                    // println!("Using array-of-structs for {}", stringify!(#struct_ident));
+
+                    Self {
+                        data: Vec::with_capacity(size),
+                        #hidden_init
+                    }
+                }
+
+                pub fn len(&self) -> usize {
+                    self.data.len()
+                }
+
+                pub fn is_empty(&self) -> bool {
+                    self.len() == 0
+                }
+
+                /// Add an instance of the original struct.
+                /// The entire struct is pushed into the internal vector.
+                /// Returns the index of the newly inserted element.
+                pub fn add(&mut self, item: #struct_ident #impl_generics) -> #id_ty {
+                    #ring_add
+                    let id = #id_new(self.data.len() as u32);
+                    self.data.push(item);
+                    #notify_added
+                    #record_stats
+                    id
+                }
+
+                /// Add a node with every field defaulted, then fill it in place through the given
+                /// closure. Returns the index of the newly inserted element.
+                pub fn push_with(&mut self, f: impl FnOnce(#struct_ident_ref_mut #generics_with_ellided_lifetime)) -> #id_ty
+                where
+                    #(
+                        for<'__b> #undefaulted_types: Default,
+                    )*
+                {
+                    let id = self.add(#struct_ident {
+                        #(
+                            #field_names: #default_values,
+                        )*
+                    });
+
+                    f(self.get_mut(id).expect("Node was just added"));
+
+                    id
+                }
+
+                #(
+                    pub fn #field_names(&self) -> impl Iterator<Item = #read_types> {
+                        self.data.iter().map(|item| #aos_reads)
+                    }
+                )*
+
+                // Generate a run iterator for each run-length encoded field.
+                #(
+                    /// Returns an iterator over each run of equal values of this field, with the
+                    /// length of the run.
+                    pub fn #runs_names(&self) -> impl Iterator<Item = (&#rle_field_types, usize)> {
+                        ::soaaos::__private::runs(self.data.iter().map(|item| &item.#rle_field_names))
+                    }
+                )*
+
+                // Generate a bitmap lookup for each field with a bitmap index, built by a scan.
+                #(
+                    /// Returns the ids of the nodes where this field is equal to `val`.
+                    pub fn #bitmap_names(&self, val: &#bitmap_field_types) -> ::soaaos::__private::roaring::RoaringBitmap
+                    where
+                        for<'__b> #bitmap_field_types: PartialEq,
+                    {
+                        self.data
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, item)| item.#bitmap_field_names == *val)
+                            .map(|(index, _)| index as u32)
+                            .collect()
+                    }
+                )*
+
+                // Generate a range lookup for each field with a B-tree index, sorted after a scan.
+                #(
+                    /// Returns the ids of the nodes where this field is within `range`, ordered by
+                    /// the value of the field (and then by id).
+                    pub fn #range_by_names(&self, range: impl core::ops::RangeBounds<#btree_field_types>) -> impl Iterator<Item = #id_ty> + '_
+                    where
+                        for<'__b> #btree_field_types: Ord,
+                    {
+                        let mut matching: Vec<_> = self
+                            .data
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, item)| range.contains(&item.#btree_field_names))
+                            .map(|(index, item)| (&item.#btree_field_names, index))
+                            .collect();
+                        matching.sort();
+                        matching.into_iter().map(|(_, index)| #id_new(index as u32))
+                    }
+                )*
+
+                // Generate the membership checks of each field with a bloom filter, both by a scan.
+                #(
+                    /// Returns `false` if no node holds `val` in this field. Array-of-structs
+                    /// layouts don't keep a bloom filter, so this is the same as the exact check.
+                    pub fn #maybe_contains_names(&self, val: &#bloom_field_types) -> bool
+                    where
+                        for<'__b> #bloom_field_types: PartialEq,
+                    {
+                        self.#contains_names(val)
+                    }
+
+                    /// Returns `true` if a node holds `val` in this field.
+                    pub fn #contains_names(&self, val: &#bloom_field_types) -> bool
+                    where
+                        for<'__b> #bloom_field_types: PartialEq,
+                    {
+                        self.data.iter().any(|item| item.#bloom_field_names == *val)
+                    }
+                )*
+
+                // Generate a text search for each field with a search index, tokenizing every
+                // node.
+                #(
+                    /// Returns the ids of the nodes where this field contains every term of
+                    /// `query`, as split by `soaaos::column::tokenize`.
+                    pub fn #search_names(&self, query: &str) -> Vec<#id_ty>
+                    where
+                        for<'__b> #search_field_types: AsRef<str>,
+                    {
+                        let terms: Vec<String> = ::soaaos::column::tokenize(query).collect();
+                        if terms.is_empty() {
+                            return Vec::new();
+                        }
+
+                        self.data
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, item)| {
+                                let found: Vec<String> =
+                                    ::soaaos::column::tokenize(item.#search_field_names.as_ref()).collect();
+                                terms.iter().all(|term| found.contains(term))
+                            })
+                            .map(|(index, _)| #id_new(index as u32))
+                            .collect()
+                    }
+                )*
+
+                // Generate an individual getter for each field.
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #getter_names(&self, index: #id_ty) -> Result<#read_types, #error_ident> {
+                        self
+                        .data
+                        .get(index.0 as usize)
+                        .map(|item| #aos_reads)
+                        .ok_or_else(|| #error_ident::#error_names)
+                    }
+                )*
+
+                // Generate an individual getter for each field.
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #getter_enumerated_names(&self) -> impl Iterator<Item = (#id_ty, #read_types)>{
+                        self
+                        .data
+                        .iter()
+                        .enumerate()
+                        .map(|(index, item)| (#id_new(index as u32), #aos_reads))
+                    }
+                )*
+
+                // Generate an individual mut getter for each field.
+                #(
+                    /// Returns a reference to the field value at the given index.
+                    pub fn #mut_getter_names(&mut self, index: #id_ty) -> Result<&mut #mut_field_types, #error_ident> {
+                        #notify_borrowed
+                        self
+                        .data
+                        .get_mut(index.0 as usize)
+                        .map(|item| &mut item.#mut_field_names)
+                        .ok_or_else(|| #error_ident::#mut_error_names)
+                    }
+                )*
+
+                // Generate a setter for each field.
+                #(
+                    /// Overwrite the field value at the given index.
+                    pub fn #set_setter_names(&mut self, index: #id_ty, value: #set_field_types) -> Result<(), #error_ident> {
+                        self
+                        .data
+                        .get_mut(index.0 as usize)
+                        .ok_or_else(|| #error_ident::#set_error_names)?
+                        .#set_field_names = value;
+
+                        #set_changed
+                        Ok(())
+                    }
+                )*
+
+                /// Returns a view of the node at the given index.
+                pub fn get(&self, index: #id_ty) -> Result<#struct_ident_ref #generics_with_ellided_lifetime, #error_ident> {
+                    let item = self
+                        .data
+                        .get(index.0 as usize)
+                        .ok_or_else(|| #error_ident::NotFound)?;
+
+                    Ok(#struct_ident_ref {
+                        #(
+                            #field_names: #aos_reads,
+                        )*
+                    })
+                }
+
+                /// Returns a mutable view of the node at the given index.
+                pub fn get_mut(&mut self, index: #id_ty) -> Result<#struct_ident_ref_mut #generics_with_ellided_lifetime, #error_ident> {
+                    #notify_borrowed_all
+                    let item = self
+                        .data
+                        .get_mut(index.0 as usize)
+                        .ok_or_else(|| #error_ident::NotFound)?;
+
+                    Ok(#struct_ident_ref_mut {
+                        #(
+                            #mut_field_names: &mut item.#mut_field_names,
+                        )*
+                    })
+                }
+
+                /// Hand a mutable view of the node at the given index to `f`, returning its result.
+                /// The index is checked once, instead of once per field by `get_*_mut`.
+                pub fn update<__R>(&mut self, index: #id_ty, f: impl FnOnce(#struct_ident_ref_mut #generics_with_ellided_lifetime) -> __R) -> Result<__R, #error_ident> {
+                    self.get_mut(index).map(f)
+                }
+
+                /// Remove and return the node at the given index, shifting every later node down by
+                /// one. Ids of the later nodes then refer to the node after them. Also returns the
+                /// map of the moved Ids.
+                pub fn remove(&mut self, index: #id_ty) -> Result<(#struct_ident #impl_generics, #id_map_ty), #error_ident> {
+                    let index = index.0 as usize;
+                    if index >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    let map = #id_map_ident::from_fn(self.len(), |old| match (old.0 as usize).cmp(&index) {
+                        core::cmp::Ordering::Less => Some(old),
+                        core::cmp::Ordering::Equal => None,
+                        core::cmp::Ordering::Greater => Some(#id_new(old.0 - 1)),
+                    });
+
+                    let node = self.data.remove(index);
+                    #notify_removed
+                    #ring_removed
+                    Ok((node, map))
+                }
+
+                /// Remove and return the node at the given index by moving the last node into its
+                /// place, without shifting the other nodes. Also returns the map of the moved Id.
+                pub fn swap_remove(&mut self, index: #id_ty) -> Result<(#struct_ident #impl_generics, #id_map_ty), #error_ident> {
+                    let index = index.0 as usize;
+                    if index >= self.len() {
+                        return Err(#error_ident::NotFound);
+                    }
+
+                    let last = self.len() - 1;
+                    let mut map = #id_map_ident::identity(self.len());
+                    map.insert(#id_new(index as u32), None);
+                    map.insert(#id_new(last as u32), Some(#id_new(index as u32)).filter(|_| index != last));
+
+                    let node = self.data.swap_remove(index);
+                    #notify_swap_removed
+                    #invalidate
+                    #ring_reset
+                    Ok((node, map))
+                }
+
+                /// Keep only the nodes for which `f` returns `true`, in order, returning the map of
+                /// the moved and removed Ids.
+                pub fn retain(&mut self, mut f: impl FnMut(#struct_ident_ref #generics_with_ellided_lifetime) -> bool) -> #id_map_ty {
+                    let keep: Vec<bool> = self.iter().map(&mut f).collect();
+                    let mut map = #id_map_ident::identity(self.len());
+                    let mut kept = 0;
+                    for (index, keep) in keep.iter().enumerate() {
+                        let old = #id_new(index as u32);
+                        if *keep {
+                            map.insert(old, Some(#id_new(kept as u32)));
+                            kept += 1;
+                        } else {
+                            map.insert(old, None);
+                        }
+                    }
+
+                    if !map.is_identity() {
+                        let mut keep_iter = keep.iter();
+                        self.data.retain(|_| *keep_iter.next().expect("One flag per node"));
+                        #notify_retained
+                        #invalidate
+                        #ring_retained
+                    }
+                    map
+                }
+
+                /// Remove every node, keeping the allocation of the rows for the next nodes.
+                pub fn clear_keep_capacity(&mut self) {
+                    self.data.clear();
+                    #notify_cleared
+                }
+
+                /// Reverse the order of the elements in the layout, in place, returning the map of
+                /// the moved Ids.
+                pub fn reverse(&mut self) -> #id_map_ty {
+                    self.data.reverse();
+                    #notify_reordered
+                    #reversed_map
+                }
+
+                /// Rotate the elements in the layout `mid` places to the left, returning the map of
+                /// the moved Ids.
+                ///
+                /// Panics if `mid` is greater than the length of the layout.
+                pub fn rotate_left(&mut self, mid: usize) -> #id_map_ty {
+                    self.data.rotate_left(mid);
+                    #notify_reordered
+                    #rotated_left_map
+                }
+
+                /// Rotate the elements in the layout `k` places to the right, returning the map of
+                /// the moved Ids.
+                ///
+                /// Panics if `k` is greater than the length of the layout.
+                pub fn rotate_right(&mut self, k: usize) -> #id_map_ty {
+                    self.data.rotate_right(k);
+                    #notify_reordered
+                    #rotated_right_map
+                }
+
+                /// Create a new layout containing only the nodes at the given ids, in the given order.
+                ///
+                /// Panics if any of the ids are out of bounds.
+                pub fn gather(&self, ids: &[#id_ty]) -> Self
+                where
+                    for<'__b> #struct_ident #impl_generics: Clone,
+                {
+                    #ring_gather
+                    Self {
+                        data: ids.iter().map(|id| self.data[id.0 as usize].clone()).collect(),
+                        #hidden_init
+                    }
+                }
+
+                /// Copy the nodes at the given ids of `other` to the end of the layout, in the given
+                /// order, returning their new ids.
+                ///
+                /// Panics if any of the ids are out of bounds of `other`, without adding any node.
+                pub fn extend_from_layout(&mut self, other: &Self, ids: &[#id_ty]) -> Vec<#id_ty>
+                where
+                    for<'__b> #struct_ident #impl_generics: Clone,
+                {
+                    assert!(
+                        ids.iter().all(|id| (id.0 as usize) < other.len()),
+                        "Id out of bounds"
+                    );
+
+                    #ring_extend
+                    let start = self.data.len();
+                    self.data.extend(ids.iter().map(|id| other.data[id.0 as usize].clone()));
+
+                    let new_ids: Vec<#id_ty> = (start..self.data.len())
+                        .map(|index| #id_new(index as u32))
+                        .collect();
+                    #notify_added_each
+                    #record_stats
+                    new_ids
+                }
+
+                /// Move the nodes of the layout to the given order, so the node at `order[i]` is
+                /// then at index `i`, returning the map of the moved Ids.
+                ///
+                /// Panics unless `order` holds every id of the layout exactly once.
+                pub fn reorder(&mut self, order: &[#id_ty]) -> #id_map_ty {
+                    #check_order
+
+                    let mut data: Vec<_> = core::mem::take(&mut self.data).into_iter().map(Some).collect();
+                    self.data = order
+                        .iter()
+                        .map(|id| data[id.0 as usize].take().expect("Order was checked"))
+                        .collect();
+                    #notify_reordered
+                    #id_map_ident::from_order(order)
+                }
+
+                /// Shuffle the nodes of the layout uniformly at random, returning the map of the
+                /// moved Ids.
+                pub fn shuffle(&mut self, rng: &mut impl ::soaaos::__private::rand::Rng) -> #id_map_ty {
+                    let mut order: Vec<#id_ty> = self.iter_ids().collect();
+                    ::soaaos::__private::rand::seq::SliceRandom::shuffle(order.as_mut_slice(), rng);
+                    self.reorder(&order)
+                }
+
+                // Generate a bulk setter for each field.
+                #(
+                    /// Write each value to the field of the node at its paired id.
+                    ///
+                    /// All ids are checked before anything is written, so an out of bounds id
+                    /// leaves the layout untouched.
+                    pub fn #set_scatter_names(&mut self, writes: &[(#id_ty, #set_field_types)]) -> Result<(), #error_ident>
+                    where
+                        for<'__b> #set_field_types: Clone,
+                    {
+                        let len = self.data.len();
+                        if writes.iter().any(|(id, _)| id.0 as usize >= len) {
+                            return Err(#error_ident::#set_error_names);
+                        }
+
+                        for (id, val) in writes {
+                            let index = id.0 as usize;
+                            self.data[index].#set_field_names = val.clone();
+                            #set_changed_at
+                        }
+
+                        Ok(())
+                    }
+                )*
+
+                /// Returns an iterator of owned nodes, cloning every node.
+                pub fn iter_cloned(&self) -> impl Iterator<Item = #struct_ident #impl_generics> + '_
+                where
+                    for<'__b> #struct_ident #impl_generics: Clone,
+                {
+                    self.data.iter().cloned()
+                }
+
+                /// Returns a `Vec` of owned nodes, cloning every node.
+                pub fn to_rows(&self) -> Vec<#struct_ident #impl_generics>
+                where
+                    for<'__b> #struct_ident #impl_generics: Clone,
+                {
+                    self.data.clone()
+                }
+            }
+        };
+        output
+    } else {
+        syn::Error::new_spanned(
+            struct_ident,
+            "Invalid layout specified. Expected \"soa\" or \"aos\".",
+        )
+        .to_compile_error()
+    }
+}
@@ -0,0 +1,593 @@
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Data, DeriveInput, Expr, Fields, Ident, LitInt, LitStr, Path, Token, Type, parse_quote};
+
+/// The memory layout of the generated collection
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Layout {
+    StructOfArrays,
+    ArrayOfStructs,
+}
+
+/// The arguments of `#[layout(...)]`: the memory layout, then any comma separated options
+#[derive(Clone)]
+pub(crate) struct LayoutOptions {
+    /// The memory layout of the collection
+    pub(crate) layout: Layout,
+
+    /// `"persistent"`: a struct-of-arrays layout of persistent vectors, where adding or writing
+    /// a node returns a new layout sharing structure with the old one
+    pub(crate) persistent: bool,
+
+    /// `segmented`: store every column as a `SegmentedColumn`, so getters return references that
+    /// stay valid across `add`
+    pub(crate) segmented: bool,
+
+    /// `page_aligned`: store every column as a `PageAlignedColumn`, starting on a page boundary
+    /// and padded to whole pages
+    pub(crate) page_aligned: bool,
+
+    /// `id = Name`: use the Id type `Name` instead of generating one. On a module, `Name` is
+    /// generated once and shared by every layout in the module.
+    pub(crate) id: Option<Ident>,
+
+    /// `error = Name`: on a module, generate an error type `Name` wrapping the error of every
+    /// layout in the module
+    pub(crate) error: Option<Ident>,
+
+    /// `brand`: give the layout and its Id a marker type parameter, so Ids of one layout can't be
+    /// used with another layout of the same struct branded with a different marker
+    pub(crate) brand: bool,
+
+    /// `watch`: keep the subscribers of the layout, generating `subscribe()` to receive an event
+    /// for every mutation (requires the `crossbeam` feature)
+    pub(crate) watch: bool,
+
+    /// `zerocopy`: derive the `zerocopy` traits converting the struct from and to raw bytes
+    /// (requires the `zerocopy` feature)
+    pub(crate) zerocopy: bool,
+
+    /// `hashcons`: keep an index of the rows by the hash of their contents, generating
+    /// `add_unique()` returning the Id of an identical row instead of adding a duplicate
+    pub(crate) hashcons: bool,
+
+    /// `versioned`: keep the version of each row, bumped by every mutation, generating
+    /// `version()` and `changed_since()`
+    pub(crate) versioned: bool,
+
+    /// `epochs`: keep the epoch each row was last written in, generating `advance_epoch()` and
+    /// `changed_since_epoch()`
+    pub(crate) epochs: bool,
+
+    /// `ring = N`: hold at most `N` nodes, where adding a node to a full layout overwrites the
+    /// oldest node in its slot, handing it to the callback set with `on_evict()`
+    pub(crate) ring: Option<usize>,
+
+    /// `flatbuffers`: generate the FlatBuffers schema of the layout, with a vector per field, and
+    /// the conversions from and to a buffer of that schema
+    pub(crate) flatbuffers: bool,
+
+    /// `derives(Trait, ...)`: derive exactly these traits on the layout, instead of implementing
+    /// each of `Debug`, `Clone`, `PartialEq`, `Eq` and `Hash` that every column implements. The
+    /// row views are only `Debug` if it is listed.
+    pub(crate) derives: Option<Vec<Path>>,
+
+    /// `extract(Name = (field, ...))`: generate the struct `Name` of only these fields, with a
+    /// layout of its own that `extract::<Name>()` copies the columns into
+    pub(crate) extracts: Vec<(Ident, Vec<Ident>)>,
+}
+
+impl Parse for LayoutOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // The layout is given as a string (`"soa"`) or as a bare name (`soa`)
+        let (val, span) = if input.peek(LitStr) {
+            let text: LitStr = input.parse()?;
+            (text.value(), text.span())
+        } else {
+            let ident: Ident = input.parse()?;
+            (ident.to_string(), ident.span())
+        };
+
+        let (layout, persistent) = match val.as_str() {
+            "soa" | "struct-of-arrays" => (Layout::StructOfArrays, false),
+            "aos" | "array-of-structs" => (Layout::ArrayOfStructs, false),
+            "persistent" => (Layout::StructOfArrays, true),
+            _ => {
+                return Err(syn::Error::new(
+                    span,
+                    format!(
+                        "Unknown memory layout (expected 'struct-of-arrays', 'array-of-structs' or 'persistent'): {val}"
+                    ),
+                ));
+            }
+        };
+
+        let mut options = LayoutOptions {
+            layout,
+            persistent,
+            segmented: false,
+            page_aligned: false,
+            id: None,
+            error: None,
+            brand: false,
+            watch: false,
+            zerocopy: false,
+            hashcons: false,
+            versioned: false,
+            epochs: false,
+            ring: None,
+            flatbuffers: false,
+            derives: None,
+            extracts: Vec::new(),
+        };
+
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let option: Ident = input.parse()?;
+            if persistent && option != "id" {
+                return Err(syn::Error::new(
+                    option.span(),
+                    "`persistent` layouts only support the `id` option",
+                ));
+            }
+            if option == "segmented" {
+                if layout != Layout::StructOfArrays {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "`segmented` is only supported by struct-of-arrays layouts",
+                    ));
+                }
+                options.segmented = true;
+            } else if option == "page_aligned" {
+                if layout != Layout::StructOfArrays {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "`page_aligned` is only supported by struct-of-arrays layouts",
+                    ));
+                }
+                options.page_aligned = true;
+            } else if option == "id" {
+                input.parse::<Token![=]>()?;
+                options.id = Some(input.parse()?);
+            } else if option == "error" {
+                input.parse::<Token![=]>()?;
+                options.error = Some(input.parse()?);
+            } else if option == "brand" {
+                options.brand = true;
+            } else if option == "watch" {
+                if !cfg!(feature = "crossbeam") {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "`watch` requires the `crossbeam` feature of soaaos",
+                    ));
+                }
+                options.watch = true;
+            } else if option == "hashcons" {
+                options.hashcons = true;
+            } else if option == "versioned" {
+                options.versioned = true;
+            } else if option == "epochs" {
+                options.epochs = true;
+            } else if option == "ring" {
+                input.parse::<Token![=]>()?;
+                let capacity: LitInt = input.parse()?;
+                let capacity = capacity.base10_parse()?;
+                if capacity == 0 {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "`ring` layouts must hold at least one node",
+                    ));
+                }
+                options.ring = Some(capacity);
+            } else if option == "flatbuffers" {
+                options.flatbuffers = true;
+            } else if option == "zerocopy" {
+                if !cfg!(feature = "zerocopy") {
+                    return Err(syn::Error::new(
+                        option.span(),
+                        "`zerocopy` requires the `zerocopy` feature of soaaos",
+                    ));
+                }
+                options.zerocopy = true;
+            } else if option == "derives" {
+                let content;
+                syn::parenthesized!(content in input);
+                let derives = Punctuated::<Path, Token![,]>::parse_terminated(&content)?;
+                options.derives = Some(derives.into_iter().collect());
+            } else if option == "extract" {
+                let content;
+                syn::parenthesized!(content in input);
+                let name: Ident = content.parse()?;
+                content.parse::<Token![=]>()?;
+                let fields;
+                syn::parenthesized!(fields in content);
+                let fields = Punctuated::<Ident, Token![,]>::parse_terminated(&fields)?;
+                options.extracts.push((name, fields.into_iter().collect()));
+            } else {
+                return Err(syn::Error::new(option.span(), "unknown #[layout] option"));
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// How the column of a field is stored in a struct-of-arrays layout
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Storage {
+    /// A plain `Vec<T>`
+    #[default]
+    Vec,
+
+    /// `#[soa(lazy)]`: only allocated once a non-default value is written
+    Lazy,
+
+    /// `#[soa(sparse)]`: only the non-default values are stored, by index
+    Sparse,
+
+    /// `#[soa(rle)]`: runs of equal values stored once with their length
+    Rle,
+
+    /// `#[soa(delta)]`: integers stored as varint deltas from the previous element
+    Delta,
+
+    /// `#[soa(zstd)]`: blocks of elements held zstd compressed (requires the `zstd` feature)
+    Zstd,
+
+    /// `#[soa(segmented)]`: fixed-size segments that are never reallocated
+    Segmented,
+
+    /// `#[soa(spill)]`: full segments written to a temporary file and paged in on access
+    Spill,
+
+    /// `#[soa(bitmap_index)]`: a roaring bitmap of the indexes holding each distinct value
+    /// (requires the `roaring` feature)
+    Bitmap,
+
+    /// `#[soa(btree_index)]`: a B-tree of the indexes holding each distinct value, for range
+    /// lookups
+    BTree,
+
+    /// `#[soa(bloom)]`: a bloom filter over the values, rejecting most missing values
+    Bloom,
+
+    /// `#[soa(search_index)]`: an inverted index of the terms of each text value (requires the
+    /// `search` feature)
+    Search,
+
+    /// `#[soa(atomic)]`: integers stored as atomics, updated through `&self`. Only supported by
+    /// struct-of-arrays layouts.
+    Atomic,
+
+    /// `#[soa(cell)]`: `Copy` values stored in cells, written through `&self`. Only supported by
+    /// struct-of-arrays layouts.
+    Cell,
+
+    /// `#[soa(page_aligned)]`: allocated on a page boundary, in whole pages
+    PageAligned,
+
+    /// `#[soa(group = "...")]`: stored with the other fields of the group, in a single `Vec` of a
+    /// generated tuple struct. Only used by struct-of-arrays layouts, whose rows aren't already
+    /// stored together.
+    Group,
+}
+
+impl Storage {
+    /// The name of the option selecting this storage
+    fn name(self) -> &'static str {
+        match self {
+            Storage::Vec => "vec",
+            Storage::Lazy => "lazy",
+            Storage::Sparse => "sparse",
+            Storage::Rle => "rle",
+            Storage::Delta => "delta",
+            Storage::Zstd => "zstd",
+            Storage::Segmented => "segmented",
+            Storage::Spill => "spill",
+            Storage::Bitmap => "bitmap_index",
+            Storage::BTree => "btree_index",
+            Storage::Bloom => "bloom",
+            Storage::Search => "search_index",
+            Storage::Atomic => "atomic",
+            Storage::Cell => "cell",
+            Storage::PageAligned => "page_aligned",
+            Storage::Group => "group",
+        }
+    }
+
+    /// Whether elements are only available by value, since the column doesn't keep them decoded
+    /// in memory
+    pub(crate) fn by_value(self) -> bool {
+        matches!(
+            self,
+            Storage::Delta | Storage::Zstd | Storage::Spill | Storage::Atomic | Storage::Cell
+        )
+    }
+
+    /// Whether elements can only be written with `set_*`, since the column can't hand out `&mut`
+    /// to a single element
+    pub(crate) fn set_only(self) -> bool {
+        matches!(
+            self,
+            Storage::Rle
+                | Storage::Delta
+                | Storage::Zstd
+                | Storage::Spill
+                | Storage::Bitmap
+                | Storage::BTree
+                | Storage::Bloom
+                | Storage::Search
+                | Storage::Atomic
+                | Storage::Cell
+        )
+    }
+
+    /// Whether an empty column is created by a `const fn`, rather than allocating an index or
+    /// calling `Default`
+    pub(crate) fn const_new(self) -> bool {
+        !matches!(
+            self,
+            Storage::Lazy | Storage::Sparse | Storage::Delta | Storage::Bitmap | Storage::Search
+        )
+    }
+
+    /// The type of the column storing a field of the given type
+    pub(crate) fn column_type(self, ty: &Type) -> Type {
+        match self {
+            Storage::Vec => parse_quote!(Vec<#ty>),
+            Storage::Lazy => parse_quote!(::soaaos::column::LazyColumn<#ty>),
+            Storage::Sparse => parse_quote!(::soaaos::column::SparseColumn<#ty>),
+            Storage::Rle => parse_quote!(::soaaos::column::RleColumn<#ty>),
+            Storage::Delta => parse_quote!(::soaaos::column::DeltaColumn<#ty>),
+            Storage::Zstd => parse_quote!(::soaaos::column::ZstdColumn<#ty>),
+            Storage::Segmented => parse_quote!(::soaaos::column::SegmentedColumn<#ty>),
+            Storage::Spill => parse_quote!(::soaaos::column::SpillColumn<#ty>),
+            Storage::Bitmap => parse_quote!(::soaaos::column::BitmapColumn<#ty>),
+            Storage::BTree => parse_quote!(::soaaos::column::BTreeColumn<#ty>),
+            Storage::Bloom => parse_quote!(::soaaos::column::BloomColumn<#ty>),
+            Storage::Search => parse_quote!(::soaaos::column::SearchColumn<#ty>),
+            Storage::Atomic => parse_quote!(::soaaos::column::AtomicColumn<#ty>),
+            Storage::Cell => parse_quote!(::soaaos::column::CellColumn<#ty>),
+            Storage::PageAligned => parse_quote!(::soaaos::column::PageAlignedColumn<#ty>),
+            // The column of the group holds the field
+            Storage::Group => parse_quote!(Vec<#ty>),
+        }
+    }
+}
+
+/// The options given to a single field with `#[soa(...)]`
+#[derive(Default)]
+pub(crate) struct FieldOptions {
+    /// The value used for this field when it isn't given (`#[soa(default)]` or
+    /// `#[soa(default = expr)]`)
+    pub(crate) default: Option<Expr>,
+
+    /// How the column of this field is stored. Only used by struct-of-arrays layouts.
+    pub(crate) storage: Storage,
+
+    /// `#[soa(references)]`: the field holds the Id (or `Option` of the Id) of a node in another
+    /// layout, generating a `remove_cascade_*` helper for when that node is removed
+    pub(crate) references: bool,
+
+    /// `#[soa(unique)]`: no two nodes may hold the same value, checked when a `transaction()` is
+    /// committed
+    pub(crate) unique: bool,
+
+    /// `#[soa(key)]`: the nodes are looked up by the value of this field, through an index kept
+    /// by the layout, generating `get_index_of`, `get_index` and `move_index`
+    pub(crate) key: bool,
+
+    /// `#[soa(frozen)]`: the value is written once, when the node is added. No `get_*_mut`,
+    /// `set_*` or other writer is generated for the field.
+    pub(crate) frozen: bool,
+
+    /// `#[soa(group = "...")]`: the name of the group of fields stored together, which is also
+    /// the name of the column holding them
+    pub(crate) group: Option<Ident>,
+}
+
+/// Parse and remove the `#[soa(...)]` attributes from each named field of the struct, since the
+/// struct itself is re-emitted and `soa` is not a real attribute.
+///
+/// Returns the options for each field, in field order. With `#[layout(..., segmented)]` (or
+/// `page_aligned`), every field without a storage option is segmented (or page aligned).
+pub(crate) fn take_field_options(
+    input: &mut DeriveInput,
+    layout_options: &LayoutOptions,
+) -> syn::Result<Vec<FieldOptions>> {
+    let Data::Struct(data) = &mut input.data else {
+        return Ok(Vec::new());
+    };
+
+    let Fields::Named(fields) = &mut data.fields else {
+        return Ok(Vec::new());
+    };
+
+    let mut result = Vec::new();
+
+    for field in fields.named.iter_mut() {
+        let mut options = FieldOptions::default();
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("soa"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("default") {
+                    let default = if meta.input.peek(syn::Token![=]) {
+                        meta.value()?.parse()?
+                    } else {
+                        parse_quote!(Default::default())
+                    };
+
+                    options.default = Some(default);
+                    return Ok(());
+                }
+
+                if meta.path.is_ident("references") {
+                    options.references = true;
+                    return Ok(());
+                }
+
+                if meta.path.is_ident("unique") {
+                    options.unique = true;
+                    return Ok(());
+                }
+
+                if meta.path.is_ident("key") {
+                    options.key = true;
+                    return Ok(());
+                }
+
+                if meta.path.is_ident("frozen") {
+                    options.frozen = true;
+                    return Ok(());
+                }
+
+                let storage = if meta.path.is_ident("lazy") {
+                    Storage::Lazy
+                } else if meta.path.is_ident("sparse") {
+                    Storage::Sparse
+                } else if meta.path.is_ident("rle") {
+                    Storage::Rle
+                } else if meta.path.is_ident("delta") {
+                    Storage::Delta
+                } else if meta.path.is_ident("zstd") {
+                    if !cfg!(feature = "zstd") {
+                        return Err(
+                            meta.error("#[soa(zstd)] requires the `zstd` feature of soaaos")
+                        );
+                    }
+                    Storage::Zstd
+                } else if meta.path.is_ident("segmented") {
+                    Storage::Segmented
+                } else if meta.path.is_ident("spill") {
+                    Storage::Spill
+                } else if meta.path.is_ident("bitmap_index") {
+                    if !cfg!(feature = "roaring") {
+                        return Err(meta.error(
+                            "#[soa(bitmap_index)] requires the `roaring` feature of soaaos",
+                        ));
+                    }
+                    Storage::Bitmap
+                } else if meta.path.is_ident("btree_index") {
+                    Storage::BTree
+                } else if meta.path.is_ident("bloom") {
+                    Storage::Bloom
+                } else if meta.path.is_ident("search_index") {
+                    if !cfg!(feature = "search") {
+                        return Err(meta.error(
+                            "#[soa(search_index)] requires the `search` feature of soaaos",
+                        ));
+                    }
+                    Storage::Search
+                } else if meta.path.is_ident("atomic") {
+                    if layout_options.layout != Layout::StructOfArrays {
+                        return Err(meta.error(
+                            "#[soa(atomic)] is only supported by struct-of-arrays layouts",
+                        ));
+                    }
+                    Storage::Atomic
+                } else if meta.path.is_ident("cell") {
+                    if layout_options.layout != Layout::StructOfArrays {
+                        return Err(meta.error(
+                            "#[soa(cell)] is only supported by struct-of-arrays layouts",
+                        ));
+                    }
+                    if layout_options.watch
+                        || layout_options.hashcons
+                        || layout_options.versioned
+                        || layout_options.epochs
+                    {
+                        return Err(meta.error(
+                            "#[soa(cell)] can't be written through `&self` in `watch`, `hashcons`, `versioned` or `epochs` layouts",
+                        ));
+                    }
+                    Storage::Cell
+                } else if meta.path.is_ident("page_aligned") {
+                    Storage::PageAligned
+                } else if meta.path.is_ident("group") {
+                    let group: LitStr = meta.value()?.parse()?;
+                    options.group = Some(group.parse()?);
+                    Storage::Group
+                } else {
+                    return Err(meta.error("unknown #[soa] field option"));
+                };
+
+                if options.storage != Storage::Vec {
+                    return Err(meta.error(format!(
+                        "conflicting #[soa] storage: `{}` and `{}`",
+                        options.storage.name(),
+                        storage.name()
+                    )));
+                }
+
+                options.storage = storage;
+                Ok(())
+            })?;
+        }
+
+        // Atomic and cell columns are written through `&self`, and references are rewritten when
+        // the referenced node is removed
+        if options.frozen
+            && (matches!(options.storage, Storage::Atomic | Storage::Cell) || options.references)
+        {
+            return Err(syn::Error::new_spanned(
+                &field.ident,
+                "#[soa(frozen)] can't be combined with `atomic`, `cell` or `references`",
+            ));
+        }
+
+        // The key index only sees writes made through `&mut self`, and a layout has a single index
+        if options.key {
+            if matches!(options.storage, Storage::Atomic | Storage::Cell) {
+                return Err(syn::Error::new_spanned(
+                    &field.ident,
+                    "#[soa(key)] can't be combined with `atomic` or `cell`",
+                ));
+            }
+            if result.iter().any(|options: &FieldOptions| options.key) {
+                return Err(syn::Error::new_spanned(
+                    &field.ident,
+                    "only one field can be the #[soa(key)] of a layout",
+                ));
+            }
+        }
+
+        // Layout-wide storage options apply to every field without a storage option
+        let layout_storage = if layout_options.segmented {
+            Some(Storage::Segmented)
+        } else if layout_options.page_aligned {
+            Some(Storage::PageAligned)
+        } else {
+            None
+        };
+
+        if let Some(layout_storage) = layout_storage {
+            match options.storage {
+                Storage::Vec => options.storage = layout_storage,
+                storage if storage == layout_storage => {}
+                storage => {
+                    return Err(syn::Error::new_spanned(
+                        &field.ident,
+                        format!(
+                            "conflicting #[soa] storage: `{}` in a `{}` layout",
+                            storage.name(),
+                            layout_storage.name()
+                        ),
+                    ));
+                }
+            }
+        }
+
+        field.attrs.retain(|attr| !attr.path().is_ident("soa"));
+        result.push(options);
+    }
+
+    Ok(result)
+}
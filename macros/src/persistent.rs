@@ -0,0 +1,353 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, GenericParam, Ident, Lifetime, LifetimeParam};
+
+use crate::options::{self, LayoutOptions};
+
+/// Generate the `#[layout("persistent")]` layout of a single struct: a struct-of-arrays layout
+/// whose columns are `PersistentVec`s, where `add` and `set_*` return a new layout sharing every
+/// unchanged part of its columns with the old one instead of writing in place
+pub(crate) fn expand_persistent(
+    layout_options: &LayoutOptions,
+    mut input: DeriveInput,
+) -> TokenStream2 {
+    // Field options choose how a column is stored or checked, which persistent columns don't
+    // support
+    let field_options = match options::take_field_options(&mut input, layout_options) {
+        Ok(field_options) => field_options,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    let struct_ident = input.ident.clone();
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields_named) => fields_named.named.iter().collect::<Vec<_>>(),
+            _ => {
+                return syn::Error::new_spanned(
+                    struct_ident,
+                    "Only structs with named fields are supported for #[layout]",
+                )
+                .to_compile_error();
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(
+                struct_ident,
+                "#[layout] can only be applied to structs",
+            )
+            .to_compile_error();
+        }
+    };
+    if fields.is_empty() {
+        return syn::Error::new_spanned(struct_ident, "No fields found for this memory layout")
+            .to_compile_error();
+    }
+
+    for (field, options) in fields.iter().zip(&field_options) {
+        if options.storage != options::Storage::Vec
+            || options.references
+            || options.unique
+            || options.key
+            || options.frozen
+        {
+            return syn::Error::new_spanned(
+                field,
+                "#[soa] field options other than `default` aren't supported by persistent layouts",
+            )
+            .to_compile_error();
+        }
+    }
+
+    let generics = input.generics.clone();
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let new_ident = |format: &str| {
+        Ident::new(
+            &format.replace("{}", &struct_ident.to_string()),
+            struct_ident.span(),
+        )
+    };
+    let layout_struct_ident = new_ident("{}sLayout");
+    let struct_ident_ref = new_ident("{}Ref");
+    let error_ident = new_ident("{}sError");
+    let id_ident = new_ident("{}Id");
+
+    // The Id type, or a re-export of the shared Id type given with `id = ...`
+    let id = match &layout_options.id {
+        Some(shared) if *shared == id_ident => quote! {},
+        Some(shared) => quote! {
+            #[allow(unused_imports)]
+            pub use #shared as #id_ident;
+        },
+        None => crate::id_tokens(&id_ident, None),
+    };
+
+    let field_names: Vec<_> = fields
+        .iter()
+        .map(|f| f.ident.as_ref().expect("Expected named field"))
+        .collect();
+    let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let first_field = field_names[0];
+
+    let field_names_with = |format: &str| -> Vec<Ident> {
+        field_names
+            .iter()
+            .map(|ident| Ident::new(&format.replace("{}", &ident.to_string()), ident.span()))
+            .collect()
+    };
+    let getter_names = field_names_with("get_{}");
+    let setter_names = field_names_with("set_{}");
+    let error_names = field_names_with("NotFound_{}");
+    let first_error_name = &error_names[0];
+
+    // The views of a node borrow the layout for 'a
+    let mut generics_with_lifetime = generics.clone();
+    let lifetime = Lifetime::new("'a", struct_ident.span());
+    generics_with_lifetime.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(lifetime.clone())),
+    );
+    let mut generics_with_ellided_lifetime = generics.clone();
+    generics_with_ellided_lifetime.params.insert(
+        0,
+        GenericParam::Lifetime(LifetimeParam::new(Lifetime::new("'_", struct_ident.span()))),
+    );
+    let (_, generics_with_ellided_lifetime, _) = generics_with_ellided_lifetime.split_for_impl();
+    let layout_ty = quote! { #layout_struct_ident #ty_generics };
+
+    // The predicates of the struct, extended by the bounds of each trait implementation
+    let predicates: Vec<_> = where_clause
+        .map(|where_clause| where_clause.predicates.iter().collect())
+        .unwrap_or_default();
+
+    quote! {
+        // Keep the original struct definition.
+        #input
+
+        #id
+
+        pub struct #struct_ident_ref #generics_with_lifetime #where_clause {
+            #(
+                pub #field_names: &#lifetime #field_types,
+            )*
+        }
+
+        #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+        #[allow(non_camel_case_types)]
+        pub enum #error_ident {
+            #(
+                #error_names,
+            )*
+        }
+
+        impl core::fmt::Display for #error_ident {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                match self {
+                    #(
+                        #error_ident::#error_names => write!(f, "Not Found: {}", stringify!(#error_names)),
+                    )*
+                }
+            }
+        }
+
+        impl core::error::Error for #error_ident {}
+
+        /// Layout version using persistent columns: adding or writing a node returns a new layout,
+        /// sharing every unchanged part of its columns with this one.
+        pub struct #layout_struct_ident #generics #where_clause {
+            #(
+                pub #field_names: ::soaaos::persistent::PersistentVec<#field_types>,
+            )*
+        }
+
+        impl #impl_generics #layout_ty #where_clause {
+            /// Create an empty layout
+            pub fn new() -> Self {
+                Self {
+                    #(
+                        #field_names: ::soaaos::persistent::PersistentVec::new(),
+                    )*
+                }
+            }
+
+            /// Get the number of nodes in the layout
+            pub fn len(&self) -> usize {
+                self.#first_field.len()
+            }
+
+            /// Returns `true` if the layout is empty
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Returns a new layout with the node added, along with the Id of the node. This
+            /// layout is left unchanged.
+            #[must_use]
+            pub fn add(&self, item: #struct_ident #ty_generics) -> (Self, #id_ident)
+            where
+                #(
+                    for<'__b> #field_types: Clone,
+                )*
+            {
+                let id = #id_ident(self.len() as u32);
+                let layout = Self {
+                    #(
+                        #field_names: self.#field_names.push(item.#field_names),
+                    )*
+                };
+                (layout, id)
+            }
+
+            #(
+                /// Returns a reference to the field value at the given index.
+                pub fn #getter_names(&self, index: #id_ident) -> Result<&#field_types, #error_ident> {
+                    self.#field_names
+                        .get(index.0 as usize)
+                        .ok_or(#error_ident::#error_names)
+                }
+
+                /// Returns a new layout with the field value at the given index overwritten. This
+                /// layout is left unchanged.
+                pub fn #setter_names(&self, index: #id_ident, value: #field_types) -> Result<Self, #error_ident>
+                where
+                    for<'__b> #field_types: Clone,
+                {
+                    let #field_names = self.#field_names
+                        .set(index.0 as usize, value)
+                        .ok_or(#error_ident::#error_names)?;
+                    Ok(Self {
+                        #field_names,
+                        ..self.clone()
+                    })
+                }
+
+                /// Returns an iterator over the values of this field
+                pub fn #field_names(&self) -> impl Iterator<Item = &#field_types> {
+                    self.#field_names.iter()
+                }
+            )*
+
+            /// Returns a view of the node at the given index.
+            pub fn get(&self, index: #id_ident) -> Result<#struct_ident_ref #generics_with_ellided_lifetime, #error_ident> {
+                if index.0 as usize >= self.len() {
+                    return Err(#error_ident::#first_error_name);
+                }
+
+                Ok(#struct_ident_ref {
+                    #(
+                        #field_names: self.#field_names.get(index.0 as usize).unwrap(),
+                    )*
+                })
+            }
+
+            /// Returns an iterator over the Ids of every node
+            pub fn iter_ids(&self) -> impl Iterator<Item = #id_ident> {
+                (0..self.len() as u32).map(#id_ident)
+            }
+
+            /// Returns an iterator over a view of every node
+            pub fn iter(&self) -> impl Iterator<Item = #struct_ident_ref #generics_with_ellided_lifetime> {
+                self.iter_ids().map(|id| self.get(id).unwrap())
+            }
+
+            /// Returns `true` if both layouts share every column, so they are equal without
+            /// comparing their nodes
+            pub fn ptr_eq(&self, other: &Self) -> bool {
+                true #(
+                    && self.#field_names.ptr_eq(&other.#field_names)
+                )*
+            }
+        }
+
+        impl #impl_generics Default for #layout_ty #where_clause {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        // Cloning only shares the columns of the layout
+        impl #impl_generics Clone for #layout_ty #where_clause {
+            fn clone(&self) -> Self {
+                Self {
+                    #(
+                        #field_names: self.#field_names.clone(),
+                    )*
+                }
+            }
+        }
+
+        impl #impl_generics core::fmt::Debug for #layout_ty
+        where
+            #(
+                #predicates,
+            )*
+            #(
+                for<'__b> #field_types: core::fmt::Debug,
+            )*
+        {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_struct(stringify!(#layout_struct_ident))
+                    #(
+                        .field(stringify!(#field_names), &self.#field_names)
+                    )*
+                    .finish()
+            }
+        }
+
+        impl #impl_generics PartialEq for #layout_ty
+        where
+            #(
+                #predicates,
+            )*
+            #(
+                for<'__b> #field_types: PartialEq,
+            )*
+        {
+            fn eq(&self, other: &Self) -> bool {
+                true #(
+                    && self.#field_names == other.#field_names
+                )*
+            }
+        }
+
+        impl #impl_generics Eq for #layout_ty
+        where
+            #(
+                #predicates,
+            )*
+            #(
+                for<'__b> #field_types: Eq,
+            )*
+        {}
+
+        impl #impl_generics core::hash::Hash for #layout_ty
+        where
+            #(
+                #predicates,
+            )*
+            #(
+                for<'__b> #field_types: core::hash::Hash,
+            )*
+        {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                #(
+                    self.#field_names.hash(state);
+                )*
+            }
+        }
+
+        impl #impl_generics FromIterator<#struct_ident #ty_generics> for #layout_ty
+        where
+            #(
+                #predicates,
+            )*
+            #(
+                for<'__b> #field_types: Clone,
+            )*
+        {
+            fn from_iter<I: IntoIterator<Item = #struct_ident #ty_generics>>(iter: I) -> Self {
+                iter.into_iter().fold(Self::new(), |layout, item| layout.add(item).0)
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Generic access to the rows of any generated layout
+//!
+//! [`RowAccess`] is implemented by every layout with its `NodeRef`/`NodeRefMut` views as the
+//! associated [`Ref`](RowAccess::Ref) and [`Mut`](RowAccess::Mut) types, so an algorithm over
+//! rows (a topological sort, a graph walk, a validation pass) can be written once over any layout
+//! instead of being generated for each struct.
+
+use crate::derived::Table;
+
+/// Implemented by every generated layout, to read and write its rows through the views of the
+/// layout without knowing the struct
+pub trait RowAccess: Table {
+    /// The view of a single row, `NodeRef<'a>` for a layout of `Node`
+    type Ref<'a>
+    where
+        Self: 'a;
+
+    /// The mutable view of a single row, `NodeRefMut<'a>` for a layout of `Node`
+    type Mut<'a>
+    where
+        Self: 'a;
+
+    /// Returns the view of the row with the given Id, or `None` if it is out of bounds
+    fn row(&self, id: Self::Id) -> Option<Self::Ref<'_>>;
+
+    /// Returns the mutable view of the row with the given Id, or `None` if it is out of bounds
+    fn row_mut(&mut self, id: Self::Id) -> Option<Self::Mut<'_>>;
+
+    /// Returns an iterator over the view of every row, in order
+    fn rows(&self) -> impl Iterator<Item = Self::Ref<'_>>;
+}
+
+/// Implemented by the projections generated with `#[layout(..., extract(Name = (field, ...)))]`:
+/// a struct of only some of the fields of the layout `Source`, with a layout of its own
+pub trait Projection<Source> {
+    /// The layout of the projection
+    type Layout;
+
+    /// Copy the projected fields of every node of `source`, in order, into a new layout
+    fn extract(source: &Source) -> Self::Layout;
+}
@@ -0,0 +1,18 @@
+//! The brand of the layouts created by the generated `scope()` of `brand` layouts
+//!
+//! A layout created by `NodesLayout::scope(|layout| ...)` is branded with a [`Scope`] over a
+//! lifetime that is only known inside the closure. The lifetime is invariant, so it can't be
+//! shortened or lengthened to match another one: the Ids of a scoped layout can only be used with
+//! that layout, and neither the layout nor its Ids can leave the closure.
+//!
+//! Every call to `scope()` gets its own lifetime, but a generic function naming the lifetime of
+//! its caller's scope can still create a second layout of the same brand with `new()`. The brand
+//! keeps Ids from crossing between scopes by mistake, it doesn't stop code going out of its way to
+//! do so.
+
+use core::marker::PhantomData;
+
+/// The brand of a scoped layout, unique to the call to `scope()` that created it
+///
+/// Never constructed, only used as the brand type parameter of a layout and its Id.
+pub struct Scope<'id>(PhantomData<fn(&'id ()) -> &'id ()>);
@@ -0,0 +1,174 @@
+//! The options of the generated `bulk_load()`, the ingestion path for loading many rows at once
+
+use core::cmp::Ordering;
+use core::hash::Hash;
+use std::collections::HashSet;
+
+/// A comparison of two rows, for sorting them before they are added
+type Compare<'a, T> = Box<dyn FnMut(&T, &T) -> Ordering + 'a>;
+
+/// Returns `true` for the first row of each key
+type FirstOfKey<'a, T> = Box<dyn FnMut(&T) -> bool + 'a>;
+
+/// Called with the number of rows added so far
+type Progress<'a> = Box<dyn FnMut(usize) + 'a>;
+
+/// How the rows given to `bulk_load()` are added to a layout
+///
+/// By default, every row is added in order, with room reserved up front when the iterator of rows
+/// knows its exact size:
+///
+/// ```rust
+/// use soaaos::bulk::BulkLoadOptions;
+///
+/// #[soaaos::layout("soa")]
+/// #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// struct Reading {
+///     sensor: u32,
+///     time: u64,
+/// }
+///
+/// let rows = [(2, 10), (1, 30), (2, 10), (1, 20)].map(|(sensor, time)| Reading { sensor, time });
+///
+/// let mut readings = ReadingsLayout::new();
+/// let mut reports = Vec::new();
+/// let options = BulkLoadOptions::new()
+///     .dedup_by_key(|row: &Reading| (row.sensor, row.time))
+///     .sort_by_key(|row: &Reading| row.time)
+///     .progress(2, |loaded| reports.push(loaded));
+///
+/// assert_eq!(readings.bulk_load(rows, options), 3);
+/// assert_eq!(readings.time().collect::<Vec<_>>(), vec![&10, &20, &30]);
+/// assert_eq!(reports, vec![2, 3]);
+/// ```
+pub struct BulkLoadOptions<'a, T> {
+    /// The comparison the rows are sorted by, keeping the order of equal rows
+    sort: Option<Compare<'a, T>>,
+
+    /// Returns `true` for the first row of each key, skipping the later ones
+    first_of_key: Option<FirstOfKey<'a, T>>,
+
+    /// The number of rows to reserve room for, instead of the exact size of the iterator
+    capacity: Option<usize>,
+
+    /// Called with the number of rows added so far, after every given number of rows
+    progress: Option<(usize, Progress<'a>)>,
+}
+
+impl<'a, T> BulkLoadOptions<'a, T> {
+    /// Add every row in order
+    pub fn new() -> Self {
+        Self {
+            sort: None,
+            first_of_key: None,
+            capacity: None,
+            progress: None,
+        }
+    }
+
+    /// Sort the rows with the given comparison before adding them, keeping the order of equal
+    /// rows. Every row is buffered until the last one is read.
+    #[must_use]
+    pub fn sort_by(mut self, compare: impl FnMut(&T, &T) -> Ordering + 'a) -> Self {
+        self.sort = Some(Box::new(compare));
+        self
+    }
+
+    /// Sort the rows by the given key before adding them, keeping the order of rows with equal
+    /// keys. Every row is buffered until the last one is read.
+    #[must_use]
+    pub fn sort_by_key<K: Ord>(self, mut key: impl FnMut(&T) -> K + 'a) -> Self {
+        self.sort_by(move |a, b| key(a).cmp(&key(b)))
+    }
+
+    /// Only add the first row of each key, in the order the rows are given, skipping every later
+    /// row with the same key
+    #[must_use]
+    pub fn dedup_by_key<K: Hash + Eq + 'a>(mut self, mut key: impl FnMut(&T) -> K + 'a) -> Self {
+        let mut seen = HashSet::new();
+        self.first_of_key = Some(Box::new(move |row| seen.insert(key(row))));
+        self
+    }
+
+    /// Reserve room for `rows` rows up front, for iterators that don't know their exact size
+    #[must_use]
+    pub fn capacity(mut self, rows: usize) -> Self {
+        self.capacity = Some(rows);
+        self
+    }
+
+    /// Call `callback` with the number of rows added so far after every `every` rows, and once
+    /// all rows are added.
+    ///
+    /// Panics if `every` is 0.
+    #[must_use]
+    pub fn progress(mut self, every: usize, callback: impl FnMut(usize) + 'a) -> Self {
+        assert!(every > 0, "progress must be reported every 1 or more rows");
+        self.progress = Some((every, Box::new(callback)));
+        self
+    }
+
+    /// Add the rows to `layout` as configured, reserving room with `reserve` and adding each row
+    /// with `add`. Returns the number of rows added.
+    ///
+    /// Used by the generated `bulk_load()`.
+    pub fn load<L>(
+        mut self,
+        layout: &mut L,
+        rows: impl IntoIterator<Item = T>,
+        mut reserve: impl FnMut(&mut L, usize),
+        mut add: impl FnMut(&mut L, T),
+    ) -> usize {
+        let rows = rows.into_iter();
+        let exact_size = match rows.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(lower),
+            _ => None,
+        };
+
+        let mut first_of_key = self.first_of_key.take();
+        let rows = rows.filter(move |row| first_of_key.as_mut().is_none_or(|first| first(row)));
+
+        let mut loaded = 0;
+        let mut add_row = |layout: &mut L, row: T| {
+            add(layout, row);
+            loaded += 1;
+            if let Some((every, callback)) = &mut self.progress
+                && loaded % *every == 0
+            {
+                callback(loaded);
+            }
+        };
+
+        match self.sort.take() {
+            Some(mut compare) => {
+                let mut sorted: Vec<T> = rows.collect();
+                sorted.sort_by(|a, b| compare(a, b));
+                reserve(layout, self.capacity.unwrap_or(sorted.len()));
+                for row in sorted {
+                    add_row(layout, row);
+                }
+            }
+            None => {
+                if let Some(capacity) = self.capacity.or(exact_size) {
+                    reserve(layout, capacity);
+                }
+                for row in rows {
+                    add_row(layout, row);
+                }
+            }
+        }
+
+        if let Some((every, callback)) = &mut self.progress
+            && loaded % *every != 0
+        {
+            callback(loaded);
+        }
+        loaded
+    }
+}
+
+impl<T> Default for BulkLoadOptions<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
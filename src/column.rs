@@ -0,0 +1,47 @@
+//! Alternative column storage used by `#[soa(...)]` field options
+//!
+//! Every column mirrors the parts of the `Vec` API used by the generated layouts (`len`, `push`,
+//! `get`, `get_mut`, `iter`, indexing, `clear`, `reverse`, `rotate_left`, `rotate_right`) so the
+//! generated code is the same regardless of how a field is stored.
+//!
+//! Columns that can't hand out `&mut` to a single element, such as [`RleColumn`], write elements
+//! with `set` instead of `get_mut`. Columns that don't keep their elements decoded in memory, such
+//! as [`DeltaColumn`], also return elements by value from `get` and `iter`.
+
+mod aligned;
+mod atomic;
+#[cfg(feature = "roaring")]
+mod bitmap;
+mod bloom;
+mod btree;
+mod cell;
+mod delta;
+mod encode;
+mod lazy;
+mod rle;
+#[cfg(feature = "search")]
+mod search;
+mod segmented;
+mod sparse;
+mod spill;
+#[cfg(feature = "zstd")]
+mod zstd;
+
+pub use aligned::{Advice, PAGE_SIZE, PageAlignedColumn};
+pub use atomic::{AtomicColumn, AtomicInt};
+#[cfg(feature = "roaring")]
+pub use bitmap::BitmapColumn;
+pub use bloom::BloomColumn;
+pub use btree::BTreeColumn;
+pub use cell::CellColumn;
+pub use delta::{Delta, DeltaColumn};
+pub use encode::ColdEncode;
+pub use lazy::{DefaultValue, LazyColumn, LazyDefault};
+pub use rle::RleColumn;
+#[cfg(feature = "search")]
+pub use search::{SearchColumn, tokenize};
+pub use segmented::{SegmentedColumn, StableAddresses};
+pub use sparse::SparseColumn;
+pub use spill::SpillColumn;
+#[cfg(feature = "zstd")]
+pub use zstd::ZstdColumn;
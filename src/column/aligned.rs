@@ -0,0 +1,346 @@
+use core::hash::{Hash, Hasher};
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+use std::alloc::{self, Layout};
+use std::io;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The alignment, and the granularity of the allocation, of a [`PageAlignedColumn`]
+pub const PAGE_SIZE: usize = 4096;
+
+/// How the pages of a [`PageAlignedColumn`] are about to be accessed, hinted to the OS by
+/// [`advise`](PageAlignedColumn::advise)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Advice {
+    /// No particular access pattern, undoing an earlier hint
+    Normal,
+
+    /// The pages are about to be read in order, so the OS can read ahead more aggressively and
+    /// drop pages soon after they are read
+    Sequential,
+
+    /// The pages are about to be read in no particular order, so reading ahead is wasted
+    Random,
+
+    /// The pages are about to be read, so the OS can start reading them in
+    WillNeed,
+
+    /// The pages won't be read soon, so the OS can reclaim them first. The elements are kept.
+    DontNeed,
+}
+
+/// A column allocated on page boundaries, in whole pages (`#[soa(page_aligned)]`)
+///
+/// The elements start at a [`PAGE_SIZE`] aligned address, and the allocation is padded to a
+/// whole number of pages, so the column can be handed to `io_uring`, mmap-based IPC or DMA
+/// engines through [`as_ptr`](Self::as_ptr) and [`allocation_len`](Self::allocation_len)
+/// without copying it into an aligned buffer first. The padding is zeroed when allocated.
+///
+/// Dereferences to a slice of the elements, so it is read and written like a `Vec`.
+pub struct PageAlignedColumn<T> {
+    /// The start of the allocation, or dangling if nothing is allocated
+    ptr: NonNull<T>,
+
+    /// The number of elements in the column
+    len: usize,
+
+    /// The number of elements that fit in the allocation
+    cap: usize,
+}
+
+// The column owns its elements like a `Vec`
+unsafe impl<T: Send> Send for PageAlignedColumn<T> {}
+unsafe impl<T: Sync> Sync for PageAlignedColumn<T> {}
+
+impl<T> PageAlignedColumn<T> {
+    /// Create an empty column, without allocating
+    pub const fn new() -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+            len: 0,
+            cap: if size_of::<T>() == 0 { usize::MAX } else { 0 },
+        }
+    }
+
+    /// Create an empty column with room for at least `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        let mut column = Self::new();
+        column.reserve(size);
+        column
+    }
+
+    /// The allocation layout holding at least `cap` elements, in whole pages
+    fn layout(cap: usize) -> Layout {
+        let bytes = cap
+            .checked_mul(size_of::<T>())
+            .and_then(|bytes| bytes.checked_next_multiple_of(PAGE_SIZE))
+            .expect("PageAlignedColumn capacity overflow");
+        Layout::from_size_align(bytes, PAGE_SIZE.max(align_of::<T>()))
+            .expect("PageAlignedColumn capacity overflow")
+    }
+
+    /// Make room for at least `additional` more elements
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self
+            .len
+            .checked_add(additional)
+            .expect("PageAlignedColumn capacity overflow");
+        if needed <= self.cap {
+            return;
+        }
+
+        let new_layout = Self::layout(needed.max(self.cap * 2));
+        let new_cap = new_layout.size() / size_of::<T>();
+
+        // SAFETY: `new_layout` has a non-zero size since `needed > cap >= 0` and `T` isn't a ZST
+        // (ZSTs have an unbounded capacity). An existing allocation was made with the layout of
+        // `self.cap`, and reallocating keeps its alignment.
+        let ptr = unsafe {
+            if self.cap == 0 {
+                alloc::alloc_zeroed(new_layout)
+            } else {
+                let old_layout = Self::layout(self.cap);
+                let ptr = alloc::realloc(self.ptr.as_ptr().cast(), old_layout, new_layout.size());
+                if !ptr.is_null() {
+                    ptr.add(old_layout.size())
+                        .write_bytes(0, new_layout.size() - old_layout.size());
+                }
+                ptr
+            }
+        };
+
+        self.ptr =
+            NonNull::new(ptr.cast()).unwrap_or_else(|| alloc::handle_alloc_error(new_layout));
+        self.cap = new_cap;
+    }
+
+    /// Returns the number of elements that fit in the allocation
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    /// Returns the page aligned pointer to the first element
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_ptr()
+    }
+
+    /// Returns the page aligned mutable pointer to the first element
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    /// Returns the number of bytes of the allocation, a multiple of [`PAGE_SIZE`], including the
+    /// padding after the last element
+    pub fn allocation_len(&self) -> usize {
+        if size_of::<T>() == 0 || self.cap == 0 {
+            0
+        } else {
+            Self::layout(self.cap).size()
+        }
+    }
+
+    /// Hint the OS about how the pages of the allocation are about to be accessed, with
+    /// `posix_madvise`. A hint never changes the elements, and is ignored on platforms without
+    /// `posix_madvise` and for a column that hasn't allocated.
+    ///
+    /// Only the OS pages entirely inside the allocation are advised, when the OS page is larger
+    /// than [`PAGE_SIZE`].
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        let len = self.allocation_len();
+        if len == 0 {
+            return Ok(());
+        }
+
+        advise(self.ptr.as_ptr().cast(), len, advice)
+    }
+
+    /// Append a value to the column, growing the allocation by whole pages if it is full
+    pub fn push(&mut self, val: T) {
+        if self.len == self.cap {
+            self.reserve(1);
+        }
+
+        // SAFETY: `len < cap`, so the slot is inside the allocation and not initialized yet
+        unsafe { self.ptr.as_ptr().add(self.len).write(val) };
+        self.len += 1;
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+
+        // SAFETY: `index < len`, so the element is initialized. The later elements are moved
+        // down over it before the length is reduced, keeping the first `len` slots initialized.
+        unsafe {
+            let ptr = self.ptr.as_ptr().add(index);
+            let val = ptr.read();
+            core::ptr::copy(ptr.add(1), ptr, self.len - index - 1);
+            self.len -= 1;
+            val
+        }
+    }
+
+    /// Drop every element after the first `len` elements, keeping the allocation
+    pub fn truncate(&mut self, len: usize) {
+        while self.len > len {
+            self.len -= 1;
+
+            // SAFETY: the element was initialized, and is no longer part of the column
+            unsafe { self.ptr.as_ptr().add(self.len).drop_in_place() };
+        }
+    }
+
+    /// Drop every element, keeping the allocation
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+/// Pass the advice for the whole OS pages of the `len` bytes at `ptr` to `posix_madvise`
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+))]
+fn advise(ptr: *mut u8, len: usize, advice: Advice) -> io::Result<()> {
+    // SAFETY: `sysconf` has no preconditions
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    let page = usize::try_from(page)
+        .ok()
+        .filter(|page| page.is_power_of_two())
+        .unwrap_or(PAGE_SIZE);
+
+    let start = (ptr as usize).next_multiple_of(page) - ptr as usize;
+    let end = (ptr as usize + len) / page * page - ptr as usize;
+    if start >= end {
+        return Ok(());
+    }
+
+    let advice = match advice {
+        Advice::Normal => libc::POSIX_MADV_NORMAL,
+        Advice::Sequential => libc::POSIX_MADV_SEQUENTIAL,
+        Advice::Random => libc::POSIX_MADV_RANDOM,
+        Advice::WillNeed => libc::POSIX_MADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_MADV_DONTNEED,
+    };
+
+    // SAFETY: the range is inside the allocation, and `posix_madvise` only hints at the access
+    // pattern: unlike `MADV_DONTNEED` on Linux, `POSIX_MADV_DONTNEED` never discards the pages
+    let err = unsafe { libc::posix_madvise(ptr.add(start).cast(), end - start, advice) };
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(err))
+    }
+}
+
+/// Hints are ignored on platforms without `posix_madvise`
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+fn advise(_ptr: *mut u8, _len: usize, _advice: Advice) -> io::Result<()> {
+    Ok(())
+}
+
+impl<T> Drop for PageAlignedColumn<T> {
+    fn drop(&mut self) {
+        self.truncate(0);
+
+        if size_of::<T>() != 0 && self.cap != 0 {
+            // SAFETY: the allocation was made with the layout of `self.cap`
+            unsafe { alloc::dealloc(self.ptr.as_ptr().cast(), Self::layout(self.cap)) };
+        }
+    }
+}
+
+impl<T> Deref for PageAlignedColumn<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: the first `len` slots are initialized, and the pointer is non-null and aligned
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> DerefMut for PageAlignedColumn<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: as above, and the column is borrowed mutably
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Default for PageAlignedColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<Vec<T>> for PageAlignedColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<T> for PageAlignedColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut column = Self::with_capacity(iter.size_hint().0);
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T: Clone> Clone for PageAlignedColumn<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for PageAlignedColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for PageAlignedColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Eq> Eq for PageAlignedColumn<T> {}
+
+impl<T: Hash> Hash for PageAlignedColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for PageAlignedColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for PageAlignedColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.allocation_len()
+    }
+}
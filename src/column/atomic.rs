@@ -0,0 +1,239 @@
+use core::hash::{Hash, Hasher};
+use core::sync::atomic::{
+    AtomicI8, AtomicI16, AtomicI32, AtomicI64, AtomicIsize, AtomicU8, AtomicU16, AtomicU32,
+    AtomicU64, AtomicUsize, Ordering,
+};
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// An integer with a matching atomic type, which can be stored in an [`AtomicColumn`]
+/// (`#[soa(atomic)]`)
+pub trait AtomicInt: Copy {
+    /// The atomic type holding this integer (e.g. `AtomicU64` for `u64`)
+    type Atomic: Send + Sync;
+
+    /// Create an atomic holding `val`
+    fn new_atomic(val: Self) -> Self::Atomic;
+
+    /// Load the value of the atomic
+    fn load(atomic: &Self::Atomic, order: Ordering) -> Self;
+
+    /// Store `val` in the atomic
+    fn store(atomic: &Self::Atomic, val: Self, order: Ordering);
+
+    /// Add `val` to the atomic, wrapping on overflow, returning the previous value
+    fn fetch_add(atomic: &Self::Atomic, val: Self, order: Ordering) -> Self;
+}
+
+macro_rules! impl_atomic_int {
+    ($($ty:ty => $atomic:ty),* $(,)?) => {
+        $(
+            impl AtomicInt for $ty {
+                type Atomic = $atomic;
+
+                fn new_atomic(val: Self) -> Self::Atomic {
+                    <$atomic>::new(val)
+                }
+
+                fn load(atomic: &Self::Atomic, order: Ordering) -> Self {
+                    atomic.load(order)
+                }
+
+                fn store(atomic: &Self::Atomic, val: Self, order: Ordering) {
+                    atomic.store(val, order)
+                }
+
+                fn fetch_add(atomic: &Self::Atomic, val: Self, order: Ordering) -> Self {
+                    atomic.fetch_add(val, order)
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_int! {
+    u8 => AtomicU8, u16 => AtomicU16, u32 => AtomicU32, u64 => AtomicU64, usize => AtomicUsize,
+    i8 => AtomicI8, i16 => AtomicI16, i32 => AtomicI32, i64 => AtomicI64, isize => AtomicIsize,
+}
+
+/// A column of atomic integers, for counters bumped from many threads through a shared layout
+/// (`#[soa(atomic)]`)
+///
+/// Elements are returned by value, loaded with `Relaxed` ordering, and written with
+/// [`set`](Self::set) through `&mut`. Through `&self`, elements are read and updated with
+/// [`load`](Self::load), [`store`](Self::store) and [`fetch_add`](Self::fetch_add), or any other
+/// operation of the atomic returned by [`get_atomic`](Self::get_atomic).
+pub struct AtomicColumn<T: AtomicInt> {
+    values: Vec<T::Atomic>,
+}
+
+impl<T: AtomicInt> AtomicColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    /// Create an empty column with room for `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(size),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Append a value to the column
+    pub fn push(&mut self, val: T) {
+        self.values.push(T::new_atomic(val));
+    }
+
+    /// Remove every element, keeping the allocation
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Returns the element at the given index, loaded with `Relaxed` ordering
+    pub fn get(&self, index: usize) -> Option<T> {
+        self.load(index, Ordering::Relaxed)
+    }
+
+    /// Returns the atomic holding the element at the given index
+    pub fn get_atomic(&self, index: usize) -> Option<&T::Atomic> {
+        self.values.get(index)
+    }
+
+    /// Returns the element at the given index, loaded with the given ordering
+    pub fn load(&self, index: usize, order: Ordering) -> Option<T> {
+        self.values.get(index).map(|atomic| T::load(atomic, order))
+    }
+
+    /// Store `val` at the given index with the given ordering. Returns `None` if the index is out
+    /// of bounds.
+    pub fn store(&self, index: usize, val: T, order: Ordering) -> Option<()> {
+        self.values
+            .get(index)
+            .map(|atomic| T::store(atomic, val, order))
+    }
+
+    /// Add `val` to the element at the given index with the given ordering, returning the
+    /// previous value
+    pub fn fetch_add(&self, index: usize, val: T, order: Ordering) -> Option<T> {
+        self.values
+            .get(index)
+            .map(|atomic| T::fetch_add(atomic, val, order))
+    }
+
+    /// Returns an iterator over every element, loaded with `Relaxed` ordering
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.values
+            .iter()
+            .map(|atomic| T::load(atomic, Ordering::Relaxed))
+    }
+
+    /// Overwrite the element at the given index.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.len(), "index out of bounds");
+        self.values[index] = T::new_atomic(val);
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of bounds");
+        T::load(&self.values.remove(index), Ordering::Relaxed)
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.values.rotate_left(mid);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        self.values.rotate_right(k);
+    }
+}
+
+impl<T: AtomicInt> Default for AtomicColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: AtomicInt> From<Vec<T>> for AtomicColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: AtomicInt> FromIterator<T> for AtomicColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            values: iter.into_iter().map(T::new_atomic).collect(),
+        }
+    }
+}
+
+// A clone is a snapshot of every element, loaded with `Relaxed` ordering
+impl<T: AtomicInt> Clone for AtomicColumn<T> {
+    fn clone(&self) -> Self {
+        self.iter().collect()
+    }
+}
+
+impl<T: AtomicInt + core::fmt::Debug> core::fmt::Debug for AtomicColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: AtomicInt + PartialEq> PartialEq for AtomicColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: AtomicInt + Eq> Eq for AtomicColumn<T> {}
+
+impl<T: AtomicInt + Hash> Hash for AtomicColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: AtomicInt + serde::Serialize> serde::Serialize for AtomicColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T: AtomicInt> HeapSize for AtomicColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes()
+    }
+}
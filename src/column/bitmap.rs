@@ -0,0 +1,215 @@
+use core::hash::{Hash, Hasher};
+use core::ops::Index;
+use std::collections::HashMap;
+
+use roaring::RoaringBitmap;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// A column keeping a roaring bitmap of the indexes holding each distinct value
+/// (`#[soa(bitmap_index)]`)
+///
+/// Meant for low-cardinality fields, where queries combining several equality predicates can
+/// intersect the bitmaps of [`bitmap`](Self::bitmap) instead of scanning every element. Since every
+/// write must also move the index between bitmaps, elements are written with [`set`](Self::set)
+/// rather than `&mut`.
+#[derive(Clone)]
+pub struct BitmapColumn<T> {
+    /// The elements of the column
+    values: Vec<T>,
+
+    /// The indexes holding each distinct value
+    bitmaps: HashMap<T, RoaringBitmap>,
+}
+
+impl<T> BitmapColumn<T> {
+    /// Create an empty column
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            bitmaps: HashMap::new(),
+        }
+    }
+
+    /// Create an empty column with room for `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(size),
+            bitmaps: HashMap::new(),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the number of distinct values in the column
+    pub fn cardinality(&self) -> usize {
+        self.bitmaps.len()
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+
+    /// Remove every element, keeping the allocations
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.bitmaps.clear();
+    }
+}
+
+impl<T: Eq + Hash + Clone> BitmapColumn<T> {
+    /// Returns the indexes holding the given value, or `None` if no element has the value
+    pub fn bitmap(&self, val: &T) -> Option<&RoaringBitmap> {
+        self.bitmaps.get(val)
+    }
+
+    /// Append a value to the column
+    pub fn push(&mut self, val: T) {
+        let index = u32::try_from(self.values.len()).expect("BitmapColumn index overflows u32");
+        self.bitmaps.entry(val.clone()).or_default().insert(index);
+        self.values.push(val);
+    }
+
+    /// Overwrite the element at the given index, moving the index to the bitmap of the new value.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.values.len(), "index out of bounds");
+
+        let old = core::mem::replace(&mut self.values[index], val.clone());
+        if old == val {
+            return;
+        }
+
+        if let Some(bitmap) = self.bitmaps.get_mut(&old) {
+            bitmap.remove(index as u32);
+            if bitmap.is_empty() {
+                self.bitmaps.remove(&old);
+            }
+        }
+        self.bitmaps.entry(val).or_default().insert(index as u32);
+    }
+
+    /// Rebuild every bitmap from the elements, after the elements were moved
+    fn reindex(&mut self) {
+        *self = core::mem::take(&mut self.values).into();
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let val = self.values.remove(index);
+        self.reindex();
+        val
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+        self.reindex();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.values.rotate_left(mid);
+        self.reindex();
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        self.values.rotate_right(k);
+        self.reindex();
+    }
+}
+
+impl<T> Default for BitmapColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Eq + Hash + Clone> From<Vec<T>> for BitmapColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Eq + Hash + Clone> FromIterator<T> for BitmapColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut column = Self::with_capacity(iter.size_hint().0);
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T> Index<usize> for BitmapColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.values[index]
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for BitmapColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// The bitmaps are derived from the elements, so columns are compared by their elements
+impl<T: PartialEq> PartialEq for BitmapColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl<T: Eq> Eq for BitmapColumn<T> {}
+
+impl<T: Hash> Hash for BitmapColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.values.hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for BitmapColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for BitmapColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        // The containers of a bitmap take about as much memory as its serialized form
+        self.values.heap_bytes()
+            + self.bitmaps.heap_bytes()
+            + self
+                .bitmaps
+                .values()
+                .map(RoaringBitmap::serialized_size)
+                .sum::<usize>()
+    }
+}
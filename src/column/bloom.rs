@@ -0,0 +1,220 @@
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::ops::Index;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::BuildHasherDefault;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The number of filter bits kept per element, giving a false positive rate of about 1%
+const BITS_PER_ELEMENT: usize = 10;
+
+/// The number of bits set for each value
+const PROBES: u64 = 7;
+
+/// A column with a bloom filter over its values (`#[soa(bloom)]`)
+///
+/// [`maybe_contains`](Self::maybe_contains) rejects most values missing from the column in
+/// constant time, before falling back to a scan. Overwritten and removed values are kept in the
+/// filter, only making it less selective, until [`rebuild`](Self::rebuild) is called. Since every
+/// write must also update the filter, elements are written with [`set`](Self::set) rather than
+/// `&mut`.
+#[derive(Clone)]
+pub struct BloomColumn<T> {
+    /// The elements of the column
+    values: Vec<T>,
+
+    /// The bits of the filter, grown (and rebuilt) as elements are added
+    bits: Vec<u64>,
+}
+
+impl<T> BloomColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            bits: Vec::new(),
+        }
+    }
+
+    /// Create an empty column with room for `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(size),
+            bits: Vec::new(),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+
+    /// Remove every element, keeping the allocations. The filter is emptied.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.bits.fill(0);
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.values.rotate_left(mid);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        self.values.rotate_right(k);
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down. The
+    /// value stays in the filter.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        self.values.remove(index)
+    }
+}
+
+impl<T: Hash> BloomColumn<T> {
+    /// The bits of the filter for the given value, for a filter of `len` bits: double hashing of
+    /// a single 64-bit hash
+    fn probes(val: &T, len: usize) -> impl Iterator<Item = usize> {
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(val);
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        (0..PROBES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len as u64) as usize)
+    }
+
+    /// Set the bits of the given value in the filter
+    fn insert(bits: &mut [u64], val: &T) {
+        for bit in Self::probes(val, bits.len() * 64) {
+            bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns `false` if the value is definitely not in the column. `true` may be a false
+    /// positive.
+    pub fn maybe_contains(&self, val: &T) -> bool {
+        let len = self.bits.len() * 64;
+        len != 0 && Self::probes(val, len).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Rebuild the filter from the current elements, dropping the overwritten and removed values
+    pub fn rebuild(&mut self) {
+        let words = (self.values.len() * 2 * BITS_PER_ELEMENT)
+            .div_ceil(64)
+            .max(1);
+        self.bits = vec![0; words];
+        for val in &self.values {
+            Self::insert(&mut self.bits, val);
+        }
+    }
+
+    /// Append a value to the column, growing the filter once it holds too many elements
+    pub fn push(&mut self, val: T) {
+        if (self.values.len() + 1) * BITS_PER_ELEMENT > self.bits.len() * 64 {
+            self.values.push(val);
+            self.rebuild();
+        } else {
+            Self::insert(&mut self.bits, &val);
+            self.values.push(val);
+        }
+    }
+
+    /// Overwrite the element at the given index, adding the new value to the filter.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.values.len(), "index out of bounds");
+        Self::insert(&mut self.bits, &val);
+        self.values[index] = val;
+    }
+}
+
+impl<T> Default for BloomColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Hash> From<Vec<T>> for BloomColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        let mut column = Self {
+            values,
+            bits: Vec::new(),
+        };
+        column.rebuild();
+        column
+    }
+}
+
+impl<T: Hash> FromIterator<T> for BloomColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl<T> Index<usize> for BloomColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.values[index]
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for BloomColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// The filter is derived from the elements, so columns are compared by their elements
+impl<T: PartialEq> PartialEq for BloomColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl<T: Eq> Eq for BloomColumn<T> {}
+
+impl<T: Hash> Hash for BloomColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.values.hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for BloomColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for BloomColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes() + self.bits.heap_bytes()
+    }
+}
@@ -0,0 +1,217 @@
+use core::hash::{Hash, Hasher};
+use core::ops::{Index, RangeBounds};
+use std::collections::BTreeMap;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// A column keeping a sorted index from each distinct value to the indexes holding it
+/// (`#[soa(btree_index)]`)
+///
+/// Meant for range lookups (time or address ranges) over a layout that isn't sorted by the field:
+/// [`range`](Self::range) walks the index instead of scanning every element. Since every write
+/// must also move the index between values, elements are written with [`set`](Self::set) rather
+/// than `&mut`.
+#[derive(Clone)]
+pub struct BTreeColumn<T> {
+    /// The elements of the column
+    values: Vec<T>,
+
+    /// The indexes holding each distinct value, in ascending order
+    index: BTreeMap<T, Vec<u32>>,
+}
+
+impl<T> BTreeColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            index: BTreeMap::new(),
+        }
+    }
+
+    /// Create an empty column with room for `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(size),
+            index: BTreeMap::new(),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the number of distinct values in the column
+    pub fn cardinality(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+
+    /// Remove every element, keeping the allocations
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.index.clear();
+    }
+}
+
+impl<T: Ord + Clone> BTreeColumn<T> {
+    /// Returns the indexes of the elements within the given range, ordered by value and then by
+    /// index
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> impl Iterator<Item = usize> + '_ {
+        self.index
+            .range(range)
+            .flat_map(|(_, indexes)| indexes.iter().map(|index| *index as usize))
+    }
+
+    /// Append a value to the column
+    pub fn push(&mut self, val: T) {
+        let index = u32::try_from(self.values.len()).expect("BTreeColumn index overflows u32");
+        self.index.entry(val.clone()).or_default().push(index);
+        self.values.push(val);
+    }
+
+    /// Overwrite the element at the given index, moving the index to the entry of the new value.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.values.len(), "index out of bounds");
+
+        let old = core::mem::replace(&mut self.values[index], val.clone());
+        if old == val {
+            return;
+        }
+
+        let index = index as u32;
+        if let Some(indexes) = self.index.get_mut(&old) {
+            if let Ok(pos) = indexes.binary_search(&index) {
+                indexes.remove(pos);
+            }
+            if indexes.is_empty() {
+                self.index.remove(&old);
+            }
+        }
+
+        let indexes = self.index.entry(val).or_default();
+        let pos = indexes.binary_search(&index).unwrap_or_else(|pos| pos);
+        indexes.insert(pos, index);
+    }
+
+    /// Rebuild the index from the elements, after the elements were moved
+    fn reindex(&mut self) {
+        *self = core::mem::take(&mut self.values).into();
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let val = self.values.remove(index);
+        self.reindex();
+        val
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+        self.reindex();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.values.rotate_left(mid);
+        self.reindex();
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        self.values.rotate_right(k);
+        self.reindex();
+    }
+}
+
+impl<T> Default for BTreeColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> From<Vec<T>> for BTreeColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<T> for BTreeColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut column = Self::with_capacity(iter.size_hint().0);
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T> Index<usize> for BTreeColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.values[index]
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for BTreeColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// The index is derived from the elements, so columns are compared by their elements
+impl<T: PartialEq> PartialEq for BTreeColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl<T: Eq> Eq for BTreeColumn<T> {}
+
+impl<T: Hash> Hash for BTreeColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.values.hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for BTreeColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for BTreeColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes()
+            + self.index.len() * size_of::<(T, Vec<u32>)>()
+            + self.index.values().map(Vec::heap_bytes).sum::<usize>()
+    }
+}
@@ -0,0 +1,160 @@
+use core::cell::Cell;
+use core::hash::{Hash, Hasher};
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// A column of cells, for `Copy` fields written through a shared layout on a single thread
+/// (`#[soa(cell)]`)
+///
+/// Elements are returned by value, and written with [`set`](Self::set) through `&self`. The
+/// column is `Send` but not `Sync`, so a layout holding it can't be shared between threads.
+pub struct CellColumn<T: Copy> {
+    values: Vec<Cell<T>>,
+}
+
+impl<T: Copy> CellColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    /// Create an empty column with room for `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(size),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Append a value to the column
+    pub fn push(&mut self, val: T) {
+        self.values.push(Cell::new(val));
+    }
+
+    /// Remove every element, keeping the allocation
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Returns the element at the given index
+    pub fn get(&self, index: usize) -> Option<T> {
+        self.values.get(index).map(Cell::get)
+    }
+
+    /// Returns the cell holding the element at the given index
+    pub fn get_cell(&self, index: usize) -> Option<&Cell<T>> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over every element
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.values.iter().map(Cell::get)
+    }
+
+    /// Overwrite the element at the given index.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&self, index: usize, val: T) {
+        assert!(index < self.len(), "index out of bounds");
+        self.values[index].set(val);
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of bounds");
+        self.values.remove(index).into_inner()
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.values.rotate_left(mid);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        self.values.rotate_right(k);
+    }
+}
+
+impl<T: Copy> Default for CellColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy> From<Vec<T>> for CellColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Copy> FromIterator<T> for CellColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            values: iter.into_iter().map(Cell::new).collect(),
+        }
+    }
+}
+
+impl<T: Copy> Clone for CellColumn<T> {
+    fn clone(&self) -> Self {
+        self.iter().collect()
+    }
+}
+
+impl<T: Copy + core::fmt::Debug> core::fmt::Debug for CellColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Copy + PartialEq> PartialEq for CellColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Copy + Eq> Eq for CellColumn<T> {}
+
+impl<T: Copy + Hash> Hash for CellColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Copy + serde::Serialize> serde::Serialize for CellColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T: Copy> HeapSize for CellColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes()
+    }
+}
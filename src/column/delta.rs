@@ -0,0 +1,317 @@
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The number of elements encoded relative to each stored base value. Random access decodes at
+/// most this many deltas.
+const BLOCK: usize = 64;
+
+/// An integer that can be stored as the difference from the previous element (`#[soa(delta)]`)
+pub trait Delta: Copy + Default + PartialEq {
+    /// The zigzag-encoded difference from `prev` to `self`
+    fn delta(self, prev: Self) -> u64;
+
+    /// The value `delta` (as returned by [`Delta::delta`]) away from `prev`
+    fn apply(prev: Self, delta: u64) -> Self;
+}
+
+macro_rules! impl_delta {
+    ($($ty:ty => $signed:ty),* $(,)?) => {
+        $(
+            impl Delta for $ty {
+                fn delta(self, prev: Self) -> u64 {
+                    let delta = self.wrapping_sub(prev) as $signed as i64;
+                    ((delta << 1) ^ (delta >> 63)) as u64
+                }
+
+                fn apply(prev: Self, delta: u64) -> Self {
+                    let delta = ((delta >> 1) as i64) ^ -((delta & 1) as i64);
+                    prev.wrapping_add(delta as $ty)
+                }
+            }
+        )*
+    };
+}
+
+impl_delta! {
+    u8 => i8, u16 => i16, u32 => i32, u64 => i64, usize => isize,
+    i8 => i8, i16 => i16, i32 => i32, i64 => i64, isize => isize,
+}
+
+/// Append `val` to `bytes` as a LEB128 varint
+fn write_varint(bytes: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        bytes.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    bytes.push(val as u8);
+}
+
+/// Read a LEB128 varint from `bytes` starting at `pos`, advancing `pos` past it
+fn read_varint(bytes: &[u8], pos: &mut usize) -> u64 {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        val |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+/// A column of integers stored as varint deltas from the previous element (`#[soa(delta)]`)
+///
+/// Monotonic columns such as addresses or timestamps usually take one or two bytes per element.
+/// Every [`BLOCK`] elements the full value is stored, so a random `get` decodes at most one block,
+/// while a full scan with [`iter`](Self::iter) or [`decode_into`](Self::decode_into) decodes each
+/// delta once. Elements are returned by value since they don't exist decoded in memory.
+///
+/// The encoding of a given sequence of elements is always the same, so the derived comparisons
+/// and hash match those of the elements.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct DeltaColumn<T> {
+    /// The number of elements in the column
+    len: usize,
+
+    /// The first element of each block
+    bases: Vec<T>,
+
+    /// The offset into `bytes` of the deltas of each block
+    offsets: Vec<usize>,
+
+    /// The varint deltas of every element that isn't the first of its block
+    bytes: Vec<u8>,
+
+    /// The last element in the column, which the next pushed element is encoded against
+    last: T,
+}
+
+impl<T: Delta> DeltaColumn<T> {
+    /// Create an empty column
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            bases: Vec::new(),
+            offsets: Vec::new(),
+            bytes: Vec::new(),
+            last: T::default(),
+        }
+    }
+
+    /// Create an empty column with room for `size` elements of one byte deltas
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            len: 0,
+            bases: Vec::with_capacity(size.div_ceil(BLOCK)),
+            offsets: Vec::with_capacity(size.div_ceil(BLOCK)),
+            bytes: Vec::with_capacity(size),
+            last: T::default(),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes used to store the deltas
+    pub fn encoded_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Remove every element, keeping the allocations
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.bases.clear();
+        self.offsets.clear();
+        self.bytes.clear();
+        self.last = T::default();
+    }
+
+    /// Append a value to the column
+    pub fn push(&mut self, val: T) {
+        if self.len.is_multiple_of(BLOCK) {
+            self.bases.push(val);
+            self.offsets.push(self.bytes.len());
+        } else {
+            write_varint(&mut self.bytes, val.delta(self.last));
+        }
+
+        self.last = val;
+        self.len += 1;
+    }
+
+    /// Returns the element at the given index
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len {
+            return None;
+        }
+
+        let block = index / BLOCK;
+        let mut pos = self.offsets[block];
+        let mut val = self.bases[block];
+        for _ in 0..index % BLOCK {
+            val = T::apply(val, read_varint(&self.bytes, &mut pos));
+        }
+
+        Some(val)
+    }
+
+    /// Returns an iterator decoding every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        let mut index = 0;
+        let mut pos = 0;
+        let mut val = T::default();
+
+        core::iter::from_fn(move || {
+            if index >= self.len {
+                return None;
+            }
+
+            val = if index.is_multiple_of(BLOCK) {
+                self.bases[index / BLOCK]
+            } else {
+                T::apply(val, read_varint(&self.bytes, &mut pos))
+            };
+            index += 1;
+
+            Some(val)
+        })
+    }
+
+    /// Decode every element into `scratch`, replacing its contents, and return them as a slice.
+    ///
+    /// Reusing the same scratch buffer across scans avoids allocating a decoded copy each time.
+    pub fn decode_into<'a>(&self, scratch: &'a mut Vec<T>) -> &'a [T] {
+        scratch.clear();
+        scratch.extend(self.iter());
+        scratch
+    }
+
+    /// Overwrite the element at the given index, re-encoding its block.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.len, "index out of bounds");
+
+        let block = index / BLOCK;
+        let start = block * BLOCK;
+        let end = (start + BLOCK).min(self.len);
+        let mut values: Vec<T> = (start..end).map(|i| self.get(i).unwrap()).collect();
+        values[index - start] = val;
+
+        let mut encoded = Vec::new();
+        for pair in values.windows(2) {
+            write_varint(&mut encoded, pair[1].delta(pair[0]));
+        }
+
+        let byte_start = self.offsets[block];
+        let byte_end = self
+            .offsets
+            .get(block + 1)
+            .copied()
+            .unwrap_or(self.bytes.len());
+        let removed = byte_end - byte_start;
+        self.bytes
+            .splice(byte_start..byte_end, encoded.iter().copied());
+        for offset in &mut self.offsets[block + 1..] {
+            *offset = *offset + encoded.len() - removed;
+        }
+
+        self.bases[block] = values[0];
+        if index == self.len - 1 {
+            self.last = val;
+        }
+    }
+
+    /// Re-encode the column from the given elements, in order
+    fn reencode(&mut self, values: Vec<T>) {
+        *self = values.into();
+    }
+
+    /// Remove and return the element at the given index, re-encoding the column.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+
+        let mut values: Vec<T> = self.iter().collect();
+        let val = values.remove(index);
+        self.reencode(values);
+        val
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        let mut values: Vec<T> = self.iter().collect();
+        values.reverse();
+        self.reencode(values);
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        let mut values: Vec<T> = self.iter().collect();
+        values.rotate_left(mid);
+        self.reencode(values);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        let mut values: Vec<T> = self.iter().collect();
+        values.rotate_right(k);
+        self.reencode(values);
+    }
+}
+
+impl<T: Delta> Default for DeltaColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Delta> From<Vec<T>> for DeltaColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Delta> FromIterator<T> for DeltaColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut column = Self::with_capacity(iter.size_hint().0);
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T: Delta + core::fmt::Debug> core::fmt::Debug for DeltaColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Delta + serde::Serialize> serde::Serialize for DeltaColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for DeltaColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.bases.heap_bytes() + self.offsets.heap_bytes() + self.bytes.heap_bytes()
+    }
+}
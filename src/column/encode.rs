@@ -0,0 +1,96 @@
+/// A value that can be written to and read back from bytes, for columns that don't keep their
+/// elements in memory (such as `ZstdColumn` and [`SpillColumn`](super::SpillColumn))
+pub trait ColdEncode: Sized {
+    /// Append the encoding of `self` to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Decode a value from the start of `bytes`, advancing `bytes` past it
+    fn decode(bytes: &mut &[u8]) -> Self;
+}
+
+macro_rules! impl_cold_encode {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl ColdEncode for $ty {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(bytes: &mut &[u8]) -> Self {
+                    let (head, rest) = bytes.split_at(size_of::<$ty>());
+                    *bytes = rest;
+                    <$ty>::from_le_bytes(head.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_cold_encode!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+impl ColdEncode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u8::from(*self).encode(out);
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Self {
+        u8::decode(bytes) != 0
+    }
+}
+
+impl<T: ColdEncode> ColdEncode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for val in self {
+            val.encode(out);
+        }
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Self {
+        let len = usize::decode(bytes);
+        (0..len).map(|_| T::decode(bytes)).collect()
+    }
+}
+
+impl ColdEncode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Self {
+        let len = usize::decode(bytes);
+        let (head, rest) = bytes.split_at(len);
+        *bytes = rest;
+        String::from_utf8(head.to_vec()).expect("Encoded String holds invalid UTF-8")
+    }
+}
+
+impl<T: ColdEncode> ColdEncode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.is_some().encode(out);
+        if let Some(val) = self {
+            val.encode(out);
+        }
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Self {
+        bool::decode(bytes).then(|| T::decode(bytes))
+    }
+}
+
+/// Encode every given element, one after the other
+pub(crate) fn encode_all<T: ColdEncode>(values: &[T]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for val in values {
+        val.encode(&mut out);
+    }
+    out
+}
+
+/// Decode `len` elements encoded with [`encode_all`]
+pub(crate) fn decode_all<T: ColdEncode>(mut bytes: &[u8], len: usize) -> Vec<T> {
+    (0..len).map(|_| T::decode(&mut bytes)).collect()
+}
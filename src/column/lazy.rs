@@ -0,0 +1,264 @@
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The default value of the elements of a [`LazyColumn`]
+///
+/// The layouts implement it for a generated type per `#[soa(lazy, default = expr)]` field, so the
+/// column of the field is unallocated while every element is `expr`.
+pub trait LazyDefault<T> {
+    /// The value of every element while the column is not materialized
+    fn value() -> T;
+}
+
+/// The [`LazyDefault`] of a column with a default of `T::default()`
+pub struct DefaultValue;
+
+impl<T: Default> LazyDefault<T> for DefaultValue {
+    fn value() -> T {
+        T::default()
+    }
+}
+
+/// A column that is only allocated once a non-default value is written to it (`#[soa(lazy)]`)
+///
+/// Until then, every element is the value of `D` and only the length is tracked.
+pub struct LazyColumn<T, D = DefaultValue> {
+    /// The number of elements in the column
+    len: usize,
+
+    /// The elements of the column. Empty until the column is materialized.
+    values: Vec<T>,
+
+    /// The value of every element while the column is not materialized
+    default: T,
+
+    _default: PhantomData<fn() -> D>,
+}
+
+impl<T, D: LazyDefault<T>> LazyColumn<T, D> {
+    /// Create an empty column
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            values: Vec::new(),
+            default: D::value(),
+            _default: PhantomData,
+        }
+    }
+
+    /// Create an empty column. The capacity is ignored since nothing is allocated until the column
+    /// is materialized.
+    pub fn with_capacity(_size: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl<T, D> LazyColumn<T, D> {
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the column has been allocated
+    pub fn is_materialized(&self) -> bool {
+        !self.values.is_empty()
+    }
+
+    /// Remove every element, keeping the allocation. The column is no longer materialized, so
+    /// default values are again pushed without writing them.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.len = 0;
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some(self.values.get(index).unwrap_or(&self.default))
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len).map(|index| self.values.get(index).unwrap_or(&self.default))
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        assert!(mid <= self.len);
+        if self.is_materialized() {
+            self.values.rotate_left(mid);
+        }
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        assert!(k <= self.len);
+        if self.is_materialized() {
+            self.values.rotate_right(k);
+        }
+    }
+}
+
+impl<T: PartialEq + Clone, D> LazyColumn<T, D> {
+    /// Allocate the column, filling it with the default value
+    fn materialize(&mut self) {
+        if self.values.is_empty() && self.len > 0 {
+            self.values = vec![self.default.clone(); self.len];
+        }
+    }
+
+    /// Append a value to the column. Default values don't allocate the column.
+    pub fn push(&mut self, val: T) {
+        if !self.is_materialized() && val == self.default {
+            self.len += 1;
+            return;
+        }
+
+        self.materialize();
+        self.values.push(val);
+        self.len += 1;
+    }
+
+    /// Returns a mutable reference to the element at the given index, allocating the column if
+    /// needed
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+
+        self.materialize();
+        self.values.get_mut(index)
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+
+        self.len -= 1;
+        if self.is_materialized() {
+            self.values.remove(index)
+        } else {
+            self.default.clone()
+        }
+    }
+}
+
+impl<T, D: LazyDefault<T>> Default for LazyColumn<T, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq, D: LazyDefault<T>> From<Vec<T>> for LazyColumn<T, D> {
+    fn from(mut values: Vec<T>) -> Self {
+        let default = D::value();
+        let len = values.len();
+
+        // Stay unallocated if nothing was ever written
+        if values.iter().all(|val| *val == default) {
+            values = Vec::new();
+        }
+
+        Self {
+            len,
+            values,
+            default,
+            _default: PhantomData,
+        }
+    }
+}
+
+impl<T: PartialEq + Clone, D: LazyDefault<T>> FromIterator<T> for LazyColumn<T, D> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut column = Self::new();
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T, D> Index<usize> for LazyColumn<T, D> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T: PartialEq + Clone, D> IndexMut<usize> for LazyColumn<T, D> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T: Clone, D> Clone for LazyColumn<T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            len: self.len,
+            values: self.values.clone(),
+            default: self.default.clone(),
+            _default: PhantomData,
+        }
+    }
+}
+
+impl<T: core::fmt::Debug, D> core::fmt::Debug for LazyColumn<T, D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Columns are compared by their elements, not by whether they have been materialized
+impl<T: PartialEq, D> PartialEq for LazyColumn<T, D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, D> Eq for LazyColumn<T, D> {}
+
+impl<T: Hash, D> Hash for LazyColumn<T, D> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, D> serde::Serialize for LazyColumn<T, D> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T, D> HeapSize for LazyColumn<T, D> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes()
+    }
+}
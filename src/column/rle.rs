@@ -0,0 +1,269 @@
+use core::ops::Index;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// A column storing each run of equal elements once, with where the run ends (`#[soa(rle)]`)
+///
+/// Low-cardinality fields such as flags or opcode classes usually take a handful of runs rather
+/// than one element per row, and passes can handle a whole run at once through
+/// [`runs`](Self::runs). Reading an element is a binary search over the runs. Since many rows share
+/// a single stored element, elements are written with [`set`](Self::set) rather than `&mut`.
+///
+/// Adjacent runs are always merged, so the derived comparisons and hash match those of the
+/// elements.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RleColumn<T> {
+    /// The value of each run and the index one past its last element
+    runs: Vec<(T, usize)>,
+}
+
+impl<T> RleColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self { runs: Vec::new() }
+    }
+
+    /// Create an empty column. The capacity is ignored since the number of runs isn't known.
+    pub fn with_capacity(_size: usize) -> Self {
+        Self::new()
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.runs.last().map_or(0, |(_, end)| *end)
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Returns the number of runs stored
+    pub fn run_count(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Returns the index of the run containing the element at the given index
+    fn run_of(&self, index: usize) -> usize {
+        self.runs.partition_point(|(_, end)| *end <= index)
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.runs.get(self.run_of(index)).map(|(val, _)| val)
+    }
+
+    /// Returns an iterator over each run of equal elements, with the length of the run
+    pub fn runs(&self) -> impl Iterator<Item = (&T, usize)> {
+        let mut start = 0;
+        self.runs.iter().map(move |(val, end)| {
+            let len = end - start;
+            start = *end;
+            (val, len)
+        })
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.runs()
+            .flat_map(|(val, len)| core::iter::repeat_n(val, len))
+    }
+
+    /// Remove every element, keeping the allocation of the runs
+    pub fn clear(&mut self) {
+        self.runs.clear();
+    }
+}
+
+impl<T: PartialEq + Clone> RleColumn<T> {
+    /// Append a value to the column, extending the last run if it has the same value
+    pub fn push(&mut self, val: T) {
+        match self.runs.last_mut() {
+            Some((last, end)) if *last == val => *end += 1,
+            _ => {
+                let end = self.len() + 1;
+                self.runs.push((val, end));
+            }
+        }
+    }
+
+    /// Overwrite the element at the given index, splitting or merging runs as needed.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.len(), "index out of bounds");
+
+        let run = self.run_of(index);
+        if self.runs[run].0 == val {
+            return;
+        }
+
+        let start = if run == 0 { 0 } else { self.runs[run - 1].1 };
+        let (old, end) = self.runs[run].clone();
+
+        let mut pieces = Vec::with_capacity(3);
+        if index > start {
+            pieces.push((old.clone(), index));
+        }
+        pieces.push((val, index + 1));
+        if index + 1 < end {
+            pieces.push((old, end));
+        }
+
+        let first = run.saturating_sub(1);
+        let last = run + pieces.len();
+        self.runs.splice(run..=run, pieces);
+        self.merge(first, last);
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of bounds");
+
+        let run = self.run_of(index);
+        let val = self.runs[run].0.clone();
+        for (_, end) in &mut self.runs[run..] {
+            *end -= 1;
+        }
+
+        // Drop the run if it is now empty, which may leave two equal runs next to each other
+        let start = if run == 0 { 0 } else { self.runs[run - 1].1 };
+        if self.runs[run].1 == start {
+            self.runs.remove(run);
+            if run > 0 {
+                self.merge(run - 1, run);
+            }
+        }
+
+        val
+    }
+
+    /// Merge adjacent runs with equal values between the runs at `first` and `last`, inclusive
+    fn merge(&mut self, first: usize, last: usize) {
+        let last = last.min(self.runs.len() - 1);
+        for index in (first..last).rev() {
+            if self.runs[index].0 == self.runs[index + 1].0 {
+                let (_, end) = self.runs.remove(index + 1);
+                self.runs[index].1 = end;
+            }
+        }
+    }
+
+    /// Rebuild the column from runs given as a value and length
+    fn from_lengths(runs: impl IntoIterator<Item = (T, usize)>) -> Self {
+        let mut column = Self::new();
+        for (val, len) in runs {
+            match column.runs.last_mut() {
+                Some((last, end)) if *last == val => *end += len,
+                _ => {
+                    let end = column.len() + len;
+                    column.runs.push((val, end));
+                }
+            }
+        }
+        column
+    }
+
+    /// Returns each run as a value and length, with a run boundary at `mid`
+    fn lengths_split_at(&self, mid: usize) -> Vec<(T, usize)> {
+        let mut lengths = Vec::with_capacity(self.runs.len() + 1);
+        let mut start = 0;
+        for (val, end) in &self.runs {
+            if start < mid && mid < *end {
+                lengths.push((val.clone(), mid - start));
+                lengths.push((val.clone(), end - mid));
+            } else {
+                lengths.push((val.clone(), end - start));
+            }
+            start = *end;
+        }
+        lengths
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        let mut lengths = self.lengths_split_at(0);
+        lengths.reverse();
+        *self = Self::from_lengths(lengths);
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        assert!(mid <= self.len());
+
+        let mut lengths = self.lengths_split_at(mid);
+        let mut start = 0;
+        let split = lengths
+            .iter()
+            .position(|(_, len)| {
+                let at = start;
+                start += len;
+                at == mid
+            })
+            .unwrap_or(lengths.len());
+        lengths.rotate_left(split);
+        *self = Self::from_lengths(lengths);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        assert!(k <= self.len());
+        self.rotate_left(self.len() - k);
+    }
+}
+
+impl<T> Default for RleColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq + Clone> From<Vec<T>> for RleColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: PartialEq + Clone> FromIterator<T> for RleColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut column = Self::new();
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T> Index<usize> for RleColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for RleColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for RleColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for RleColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.runs.heap_bytes()
+    }
+}
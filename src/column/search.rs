@@ -0,0 +1,254 @@
+use core::hash::{Hash, Hasher};
+use core::ops::Index;
+use std::collections::HashMap;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// Split `text` into the lowercase terms matched by [`SearchColumn::search`]: the runs of
+/// alphanumeric characters
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// A column of text with an inverted index from each term to the indexes of the elements
+/// containing it (`#[soa(search_index)]`)
+///
+/// Elements are split into terms with [`tokenize`], so searches are case insensitive and match
+/// whole words. Since every write must also update the index, elements are written with
+/// [`set`](Self::set) rather than `&mut`.
+#[derive(Clone)]
+pub struct SearchColumn<T> {
+    /// The elements of the column
+    values: Vec<T>,
+
+    /// The indexes of the elements containing each term, in ascending order
+    terms: HashMap<String, Vec<u32>>,
+}
+
+impl<T> SearchColumn<T> {
+    /// Create an empty column
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            terms: HashMap::new(),
+        }
+    }
+
+    /// Create an empty column with room for `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            values: Vec::with_capacity(size),
+            terms: HashMap::new(),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the number of distinct terms in the column
+    pub fn term_count(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.values.get(index)
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.iter()
+    }
+
+    /// Remove every element, keeping the allocations
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.terms.clear();
+    }
+
+    /// Returns the indexes of the elements containing every term of `query`, in ascending order.
+    /// A query without any term matches nothing.
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        let mut postings = Vec::new();
+        for term in tokenize(query) {
+            match self.terms.get(&term) {
+                Some(indexes) => postings.push(indexes),
+                None => return Vec::new(),
+            }
+        }
+
+        // Intersect starting from the rarest term
+        postings.sort_by_key(|indexes| indexes.len());
+        let Some((first, rest)) = postings.split_first() else {
+            return Vec::new();
+        };
+
+        first
+            .iter()
+            .filter(|index| {
+                rest.iter()
+                    .all(|indexes| indexes.binary_search(index).is_ok())
+            })
+            .map(|index| *index as usize)
+            .collect()
+    }
+}
+
+impl<T: AsRef<str>> SearchColumn<T> {
+    /// Append a value to the column
+    pub fn push(&mut self, val: T) {
+        let index = u32::try_from(self.values.len()).expect("SearchColumn index overflows u32");
+        for term in tokenize(val.as_ref()) {
+            let indexes = self.terms.entry(term).or_default();
+            if indexes.last() != Some(&index) {
+                indexes.push(index);
+            }
+        }
+        self.values.push(val);
+    }
+
+    /// Overwrite the element at the given index, moving the index to the terms of the new value.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.values.len(), "index out of bounds");
+
+        let old = core::mem::replace(&mut self.values[index], val);
+        let index = index as u32;
+
+        for term in tokenize(old.as_ref()) {
+            if let Some(indexes) = self.terms.get_mut(&term) {
+                if let Ok(pos) = indexes.binary_search(&index) {
+                    indexes.remove(pos);
+                }
+                if indexes.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+
+        for term in tokenize(self.values[index as usize].as_ref()) {
+            let indexes = self.terms.entry(term).or_default();
+            if let Err(pos) = indexes.binary_search(&index) {
+                indexes.insert(pos, index);
+            }
+        }
+    }
+
+    /// Rebuild the index from the elements, after the elements were moved
+    fn reindex(&mut self) {
+        *self = core::mem::take(&mut self.values).into();
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let val = self.values.remove(index);
+        self.reindex();
+        val
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.values.reverse();
+        self.reindex();
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        self.values.rotate_left(mid);
+        self.reindex();
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        self.values.rotate_right(k);
+        self.reindex();
+    }
+}
+
+impl<T> Default for SearchColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: AsRef<str>> From<Vec<T>> for SearchColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: AsRef<str>> FromIterator<T> for SearchColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut column = Self::with_capacity(iter.size_hint().0);
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T> Index<usize> for SearchColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.values[index]
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for SearchColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// The index is derived from the elements, so columns are compared by their elements
+impl<T: PartialEq> PartialEq for SearchColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl<T: Eq> Eq for SearchColumn<T> {}
+
+impl<T: Hash> Hash for SearchColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.values.hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for SearchColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for SearchColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes()
+            + self.terms.heap_bytes()
+            + self
+                .terms
+                .iter()
+                .map(|(term, indexes)| term.capacity() + indexes.heap_bytes())
+                .sum::<usize>()
+    }
+}
@@ -0,0 +1,245 @@
+use core::hash::{Hash, Hasher};
+use core::ops::{Index, IndexMut};
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The number of elements in each segment
+const SEGMENT: usize = 1024;
+
+/// A column backed by fixed-size segments that are never reallocated (`#[soa(segmented)]`)
+///
+/// Pushing an element never moves the elements already in the column, so their addresses stay the
+/// same for as long as they are in the column. Reordering the column (`reverse`, `rotate_*`)
+/// moves values between existing slots without moving the slots themselves.
+pub struct SegmentedColumn<T> {
+    /// The segments, each allocated with room for [`SEGMENT`] elements. Only the last segment may
+    /// be partially filled.
+    segments: Vec<Vec<T>>,
+}
+
+impl<T> SegmentedColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self {
+            segments: Vec::new(),
+        }
+    }
+
+    /// Create an empty column with room for the segments holding `size` elements
+    pub fn with_capacity(size: usize) -> Self {
+        Self {
+            segments: Vec::with_capacity(size.div_ceil(SEGMENT)),
+        }
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.segments
+            .last()
+            .map_or(0, |last| (self.segments.len() - 1) * SEGMENT + last.len())
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Remove every element. The segments are freed, since each is allocated whole.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    /// Append a value to the column, allocating a new segment if the last one is full
+    pub fn push(&mut self, val: T) {
+        match self.segments.last_mut() {
+            Some(last) if last.len() < SEGMENT => last.push(val),
+            _ => {
+                let mut segment = Vec::with_capacity(SEGMENT);
+                segment.push(val);
+                self.segments.push(segment);
+            }
+        }
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.segments.get(index / SEGMENT)?.get(index % SEGMENT)
+    }
+
+    /// Returns a mutable reference to the element at the given index
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.segments
+            .get_mut(index / SEGMENT)?
+            .get_mut(index % SEGMENT)
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.segments.iter().flatten()
+    }
+
+    /// Returns an iterator over mutable references to every element in the column
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.segments.iter_mut().flatten()
+    }
+
+    /// Swap the elements at the given indexes
+    pub fn swap(&mut self, a: usize, b: usize) {
+        let (a, b) = (a.min(b), a.max(b));
+        let (seg_a, seg_b) = (a / SEGMENT, b / SEGMENT);
+
+        if seg_a == seg_b {
+            self.segments[seg_a].swap(a % SEGMENT, b % SEGMENT);
+        } else {
+            let (head, tail) = self.segments.split_at_mut(seg_b);
+            core::mem::swap(&mut head[seg_a][a % SEGMENT], &mut tail[0][b % SEGMENT]);
+        }
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "index out of bounds");
+
+        // Move the element to the end, keeping the order of the elements after it
+        for i in index..len - 1 {
+            self.swap(i, i + 1);
+        }
+
+        let last = self.segments.last_mut().unwrap();
+        let val = last.pop().unwrap();
+        if last.is_empty() {
+            self.segments.pop();
+        }
+        val
+    }
+
+    /// Reverse the order of the elements in the given range of the column
+    fn reverse_range(&mut self, start: usize, end: usize) {
+        let (mut a, mut b) = (start, end);
+        while a + 1 < b {
+            b -= 1;
+            self.swap(a, b);
+            a += 1;
+        }
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        self.reverse_range(0, self.len());
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        let len = self.len();
+        assert!(mid <= len);
+
+        self.reverse_range(0, mid);
+        self.reverse_range(mid, len);
+        self.reverse_range(0, len);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        let len = self.len();
+        assert!(k <= len);
+        self.rotate_left(len - k);
+    }
+}
+
+impl<T> Default for SegmentedColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<Vec<T>> for SegmentedColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<T> for SegmentedColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut column = Self::with_capacity(iter.size_hint().0);
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T> Index<usize> for SegmentedColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for SegmentedColumn<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+// Cloned through `collect` so that every segment of the clone also has room for a full segment
+impl<T: Clone> Clone for SegmentedColumn<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for SegmentedColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for SegmentedColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.segments == other.segments
+    }
+}
+
+impl<T: Eq> Eq for SegmentedColumn<T> {}
+
+impl<T: Hash> Hash for SegmentedColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for SegmentedColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Implemented by the layouts whose columns are all [`SegmentedColumn`]s
+/// (`#[layout("soa", segmented)]`)
+///
+/// # Safety
+///
+/// Adding a node (`add`, `push_with`, ...) must never move the fields of the nodes already in the
+/// layout. A raw pointer taken from a reference returned by a getter stays valid across later
+/// `add` calls, until the layout is reordered, cleared or dropped.
+pub unsafe trait StableAddresses {}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for SegmentedColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.segments.heap_bytes() + self.segments.iter().map(Vec::heap_bytes).sum::<usize>()
+    }
+}
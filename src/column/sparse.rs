@@ -0,0 +1,245 @@
+use core::hash::{Hash, Hasher};
+use core::ops::{Index, IndexMut};
+use std::collections::HashMap;
+
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// A column only storing the elements that differ from the default value (`#[soa(sparse)]`)
+///
+/// Every element without an entry is `T::default()`, so fields set on only a few rows take
+/// memory proportional to the number of set rows rather than the length of the layout.
+pub struct SparseColumn<T> {
+    /// The number of elements in the column
+    len: usize,
+
+    /// The elements that have been set, by index
+    values: HashMap<usize, T>,
+
+    /// The value of every element without an entry
+    default: T,
+}
+
+impl<T: Default> SparseColumn<T> {
+    /// Create an empty column
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            values: HashMap::new(),
+            default: T::default(),
+        }
+    }
+
+    /// Create an empty column. The capacity is ignored since only set elements are stored.
+    pub fn with_capacity(_size: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SparseColumn<T> {
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements with an entry
+    pub fn populated(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Remove every element, keeping the allocation of the entries
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the elements with an entry, in no particular order
+    pub fn entries(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.values.iter().map(|(index, val)| (*index, val))
+    }
+
+    /// Returns a reference to the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some(self.values.get(&index).unwrap_or(&self.default))
+    }
+
+    /// Returns an iterator over every element in the column
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len).map(|index| self.values.get(&index).unwrap_or(&self.default))
+    }
+
+    /// Move every entry to the index given by `f`
+    fn remap(&mut self, f: impl Fn(usize) -> usize) {
+        self.values = self
+            .values
+            .drain()
+            .map(|(index, val)| (f(index), val))
+            .collect();
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        let len = self.len;
+        self.remap(|index| len - 1 - index);
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        assert!(mid <= self.len);
+        let len = self.len;
+        self.remap(|index| (index + len - mid) % len);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        assert!(k <= self.len);
+        let len = self.len;
+        self.remap(|index| (index + k) % len);
+    }
+}
+
+impl<T: PartialEq + Clone> SparseColumn<T> {
+    /// Append a value to the column. Default values don't take an entry.
+    pub fn push(&mut self, val: T) {
+        if val != self.default {
+            self.values.insert(self.len, val);
+        }
+
+        self.len += 1;
+    }
+
+    /// Returns a mutable reference to the element at the given index, adding an entry if needed
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some(
+            self.values
+                .entry(index)
+                .or_insert_with(|| self.default.clone()),
+        )
+    }
+
+    /// Remove and return the element at the given index, shifting every later element down.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "index out of bounds");
+
+        let val = self
+            .values
+            .remove(&index)
+            .unwrap_or_else(|| self.default.clone());
+        self.len -= 1;
+        self.remap(|i| if i > index { i - 1 } else { i });
+        val
+    }
+}
+
+impl<T: Default> Default for SparseColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Default + PartialEq> From<Vec<T>> for SparseColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        let default = T::default();
+        let len = values.len();
+
+        Self {
+            len,
+            values: values
+                .into_iter()
+                .enumerate()
+                .filter(|(_, val)| *val != default)
+                .collect(),
+            default,
+        }
+    }
+}
+
+impl<T: Default + PartialEq + Clone> FromIterator<T> for SparseColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut column = Self::new();
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+impl<T> Index<usize> for SparseColumn<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T: PartialEq + Clone> IndexMut<usize> for SparseColumn<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T: Clone> Clone for SparseColumn<T> {
+    fn clone(&self) -> Self {
+        Self {
+            len: self.len,
+            values: self.values.clone(),
+            default: self.default.clone(),
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for SparseColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Columns are compared by their elements, not by which elements have an entry
+impl<T: PartialEq> PartialEq for SparseColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for SparseColumn<T> {}
+
+impl<T: Hash> Hash for SparseColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for SparseColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for SparseColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        self.values.heap_bytes()
+    }
+}
@@ -0,0 +1,416 @@
+use core::hash::{Hash, Hasher};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::ColdEncode;
+use super::encode::{decode_all, encode_all};
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The number of elements spilled to disk together. Reading any element pages in its segment.
+const SEGMENT: usize = 4096;
+
+/// Used to give each spill file of the process a unique name
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary file holding spilled segments, removed when dropped
+struct SpillFile {
+    file: File,
+    path: PathBuf,
+
+    /// The offset one past the last segment written
+    end: u64,
+
+    /// The offset and length of each unused range before `end`, sorted by offset and never
+    /// adjacent, left by segments that shrank or outgrew their place
+    free: Vec<(u64, u64)>,
+}
+
+impl SpillFile {
+    /// Create a new spill file in `$SOAAOS_SPILL_DIR`, or the system temporary directory
+    fn create() -> Self {
+        let dir = std::env::var_os("SOAAOS_SPILL_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let path = dir.join(format!(
+            "soaaos-spill-{}-{}",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .unwrap_or_else(|err| panic!("Failed to create spill file {}: {err}", path.display()));
+
+        Self {
+            file,
+            path,
+            end: 0,
+            free: Vec::new(),
+        }
+    }
+
+    /// Write the given bytes in the first unused range that fits them, or at the end of the
+    /// file, returning their offset
+    fn write(&mut self, bytes: &[u8]) -> u64 {
+        let len = bytes.len() as u64;
+        let offset = match self.free.iter().position(|(_, free)| *free >= len) {
+            Some(index) => {
+                let (offset, free) = &mut self.free[index];
+                let start = *offset;
+                *offset += len;
+                *free -= len;
+                if *free == 0 {
+                    self.free.remove(index);
+                }
+                start
+            }
+            None => {
+                self.end += len;
+                self.end - len
+            }
+        };
+        self.write_at(offset, bytes);
+        offset
+    }
+
+    /// Overwrite the bytes at the given offset
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) {
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(bytes))
+            .expect("Failed to write SpillColumn segment");
+    }
+
+    /// Mark the `len` bytes at the given offset as unused, merging them with the unused ranges
+    /// around them, or with the end of the file
+    fn release(&mut self, offset: u64, len: u64) {
+        let index = self.free.partition_point(|(free, _)| *free < offset);
+        self.free.insert(index, (offset, len));
+
+        if let Some(&(next, next_len)) = self.free.get(index + 1)
+            && offset + len == next
+        {
+            self.free[index].1 += next_len;
+            self.free.remove(index + 1);
+        }
+        if index > 0 && self.free[index - 1].0 + self.free[index - 1].1 == offset {
+            self.free[index - 1].1 += self.free[index].1;
+            self.free.remove(index);
+        }
+        if let Some(&(last, last_len)) = self.free.last()
+            && last + last_len == self.end
+        {
+            self.end = last;
+            self.free.pop();
+        }
+    }
+
+    /// Read `len` bytes at the given offset
+    fn read(&mut self, offset: u64, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(&mut bytes))
+            .expect("Failed to read SpillColumn segment");
+        bytes
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The parts of a [`SpillColumn`] used when reading through `&self`
+struct Paging<T> {
+    /// The spill file, created when the first segment is spilled
+    file: Option<SpillFile>,
+
+    /// The most recently paged in segment and its index
+    cache: Option<(usize, Vec<T>)>,
+}
+
+/// A column writing every full segment to a temporary file, for datasets larger than memory
+/// (`#[soa(spill)]`)
+///
+/// Only the last, partial segment and the most recently read segment are held in memory. Reading
+/// an element in another segment pages that segment back in from disk. Elements are returned by
+/// value and written with [`set`](Self::set), which writes the segment out again.
+///
+/// The spill file is created in `$SOAAOS_SPILL_DIR`, or the system temporary directory, and is
+/// removed when the column is dropped. IO errors while spilling or paging panic, since getters
+/// can't return them.
+pub struct SpillColumn<T> {
+    /// The offset and encoded length of each spilled segment in the spill file
+    spilled: Vec<(u64, usize)>,
+
+    /// The elements after the last spilled segment
+    tail: Vec<T>,
+
+    /// The spill file and paged in segment, locked so that reads through `&self` can page
+    paging: Mutex<Paging<T>>,
+}
+
+impl<T> SpillColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self {
+            spilled: Vec::new(),
+            tail: Vec::new(),
+            paging: Mutex::new(Paging {
+                file: None,
+                cache: None,
+            }),
+        }
+    }
+
+    /// Create an empty column. The capacity is ignored since at most one segment is kept in memory.
+    pub fn with_capacity(_size: usize) -> Self {
+        Self::new()
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.spilled.len() * SEGMENT + self.tail.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of segments written to disk
+    pub fn spilled_segments(&self) -> usize {
+        self.spilled.len()
+    }
+
+    /// Remove every element, keeping the spill file to write the next segments over
+    pub fn clear(&mut self) {
+        self.spilled.clear();
+        self.tail.clear();
+
+        let paging = self.paging.get_mut().unwrap();
+        paging.cache = None;
+        if let Some(file) = &mut paging.file {
+            file.end = 0;
+            file.free.clear();
+        }
+    }
+
+    /// Returns the number of bytes of the spill file holding segments, including the unused
+    /// ranges left by segments that shrank or outgrew their place
+    pub fn spilled_bytes(&self) -> u64 {
+        let paging = self.paging.lock().unwrap();
+        paging.file.as_ref().map_or(0, |file| file.end)
+    }
+}
+
+impl<T: ColdEncode + Clone> SpillColumn<T> {
+    /// Append a value to the column, spilling the last segment once it is full
+    pub fn push(&mut self, val: T) {
+        self.tail.push(val);
+
+        if self.tail.len() == SEGMENT {
+            let paging = self.paging.get_mut().unwrap();
+            let file = paging.file.get_or_insert_with(SpillFile::create);
+            let bytes = encode_all(&self.tail);
+            self.spilled.push((file.write(&bytes), bytes.len()));
+            self.tail.clear();
+        }
+    }
+
+    /// Call `f` with the elements of the given spilled segment, paging it in if needed
+    fn with_segment<R>(&self, segment: usize, f: impl FnOnce(&[T]) -> R) -> R {
+        let mut paging = self.paging.lock().unwrap();
+        let paging = &mut *paging;
+
+        if !matches!(&paging.cache, Some((cached, _)) if *cached == segment) {
+            let (offset, len) = self.spilled[segment];
+            let file = paging
+                .file
+                .as_mut()
+                .expect("Spilled segment without a spill file");
+            paging.cache = Some((segment, decode_all(&file.read(offset, len), SEGMENT)));
+        }
+
+        f(&paging.cache.as_ref().unwrap().1)
+    }
+
+    /// Returns the element at the given index, paging in its segment if needed
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let segment = index / SEGMENT;
+        if segment == self.spilled.len() {
+            return Some(self.tail[index % SEGMENT].clone());
+        }
+
+        Some(self.with_segment(segment, |values| values[index % SEGMENT].clone()))
+    }
+
+    /// Returns an iterator over every element, paging in one segment at a time
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.spilled.len())
+            .flat_map(|segment| self.with_segment(segment, <[T]>::to_vec))
+            .chain(self.tail.iter().cloned())
+    }
+
+    /// Overwrite the element at the given index. A spilled segment is written out again in its
+    /// place, or moved to an unused range of the spill file if it grew past it.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.len(), "index out of bounds");
+
+        let segment = index / SEGMENT;
+        if segment == self.spilled.len() {
+            self.tail[index % SEGMENT] = val;
+            return;
+        }
+
+        self.with_segment(segment, |_| ());
+        let paging = self.paging.get_mut().unwrap();
+        let (_, values) = paging.cache.as_mut().unwrap();
+        values[index % SEGMENT] = val;
+
+        let bytes = encode_all(values);
+        let file = paging.file.as_mut().unwrap();
+        let (offset, len) = &mut self.spilled[segment];
+        if bytes.len() <= *len {
+            // The unused end of a segment that shrank is freed, so it is never read
+            file.write_at(*offset, &bytes);
+            file.release(*offset + bytes.len() as u64, (*len - bytes.len()) as u64);
+        } else {
+            file.release(*offset, *len as u64);
+            *offset = file.write(&bytes);
+        }
+        *len = bytes.len();
+    }
+
+    /// Rebuild the column in a new spill file from the elements at the given indexes, in order.
+    /// Elements are streamed through one segment at a time, as long as the indexes run through
+    /// each segment in turn.
+    fn respill(&mut self, indexes: impl Iterator<Item = usize>) {
+        let column: Self = indexes
+            .map(|index| self.get(index).expect("index out of bounds"))
+            .collect();
+        *self = column;
+    }
+
+    /// Remove and return the element at the given index, rebuilding the column in a new spill
+    /// file.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let val = self.get(index).expect("index out of bounds");
+        self.respill((0..index).chain(index + 1..self.len()));
+        val
+    }
+
+    /// Reverse the order of the elements in the column
+    pub fn reverse(&mut self) {
+        self.respill((0..self.len()).rev());
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    ///
+    /// Panics if `mid` is greater than the length of the column.
+    pub fn rotate_left(&mut self, mid: usize) {
+        assert!(mid <= self.len(), "mid out of bounds");
+        self.respill((mid..self.len()).chain(0..mid));
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    ///
+    /// Panics if `k` is greater than the length of the column.
+    pub fn rotate_right(&mut self, k: usize) {
+        assert!(k <= self.len(), "k out of bounds");
+        self.rotate_left(self.len() - k);
+    }
+}
+
+impl<T> Default for SpillColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ColdEncode + Clone> From<Vec<T>> for SpillColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: ColdEncode + Clone> FromIterator<T> for SpillColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut column = Self::new();
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+// A clone spills to its own file
+impl<T: ColdEncode + Clone> Clone for SpillColumn<T> {
+    fn clone(&self) -> Self {
+        self.iter().collect()
+    }
+}
+
+impl<T: ColdEncode + Clone + core::fmt::Debug> core::fmt::Debug for SpillColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Columns are compared by their elements, not by their spill files
+impl<T: ColdEncode + Clone + PartialEq> PartialEq for SpillColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: ColdEncode + Clone + Eq> Eq for SpillColumn<T> {}
+
+impl<T: ColdEncode + Clone + Hash> Hash for SpillColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ColdEncode + Clone + serde::Serialize> serde::Serialize for SpillColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for SpillColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        // The spilled segments are on disk, only the paged in segment is in memory
+        let paging = self.paging.lock().unwrap();
+        let cache = paging
+            .cache
+            .as_ref()
+            .map_or(0, |(_, values)| values.heap_bytes());
+        self.spilled.heap_bytes() + self.tail.heap_bytes() + cache
+    }
+}
@@ -0,0 +1,268 @@
+use core::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use super::ColdEncode;
+use super::encode::{decode_all, encode_all};
+#[cfg(feature = "stats")]
+use crate::stats::HeapSize;
+
+/// The number of elements compressed together. Reading any element decompresses its whole block.
+const BLOCK: usize = 1024;
+
+/// The zstd compression level used for each block
+const LEVEL: i32 = 3;
+
+/// Compress the given elements into a single block
+fn compress<T: ColdEncode>(values: &[T]) -> Vec<u8> {
+    zstd::bulk::compress(&encode_all(values), LEVEL).expect("Failed to compress ZstdColumn block")
+}
+
+/// Decompress every element of a block
+fn decompress<T: ColdEncode>(block: &[u8], len: usize) -> Vec<T> {
+    let raw = zstd::stream::decode_all(block).expect("Failed to decompress ZstdColumn block");
+    decode_all(&raw, len)
+}
+
+/// A column held zstd compressed in memory, for heavy fields that are rarely read (`#[soa(zstd)]`)
+///
+/// Elements are compressed in blocks of [`BLOCK`] elements once a block is full, while the last,
+/// partial block is kept uncompressed so adding nodes is cheap. Reading an element decompresses its
+/// block, which is cached until another block is read, so reads close together only decompress
+/// once. Elements are returned by value and written with [`set`](Self::set), which recompresses
+/// the block.
+pub struct ZstdColumn<T> {
+    /// The compressed full blocks
+    blocks: Vec<Vec<u8>>,
+
+    /// The elements after the last full block, uncompressed
+    tail: Vec<T>,
+
+    /// The most recently decompressed block and its index
+    cache: Mutex<Option<(usize, Vec<T>)>>,
+}
+
+impl<T> ZstdColumn<T> {
+    /// Create an empty column
+    pub const fn new() -> Self {
+        Self {
+            blocks: Vec::new(),
+            tail: Vec::new(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Create an empty column. The capacity is ignored since the compressed size isn't known.
+    pub fn with_capacity(_size: usize) -> Self {
+        Self::new()
+    }
+
+    /// Get the number of elements in the column
+    pub fn len(&self) -> usize {
+        self.blocks.len() * BLOCK + self.tail.len()
+    }
+
+    /// Returns `true` if the column is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of bytes used by the compressed blocks
+    pub fn compressed_len(&self) -> usize {
+        self.blocks.iter().map(Vec::len).sum()
+    }
+
+    /// Remove every element, keeping the allocation of the uncompressed tail. The compressed
+    /// blocks are freed.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.tail.clear();
+        *self.cache.get_mut().unwrap() = None;
+    }
+}
+
+impl<T: ColdEncode + Clone> ZstdColumn<T> {
+    /// Append a value to the column, compressing the last block once it is full
+    pub fn push(&mut self, val: T) {
+        self.tail.push(val);
+
+        if self.tail.len() == BLOCK {
+            self.blocks.push(compress(&self.tail));
+            self.tail.clear();
+        }
+    }
+
+    /// Call `f` with the decompressed elements of the given full block, using the cache
+    fn with_block<R>(&self, block: usize, f: impl FnOnce(&[T]) -> R) -> R {
+        let mut cache = self.cache.lock().unwrap();
+        match &*cache {
+            Some((cached, values)) if *cached == block => f(values),
+            _ => {
+                let values = decompress(&self.blocks[block], BLOCK);
+                let result = f(&values);
+                *cache = Some((block, values));
+                result
+            }
+        }
+    }
+
+    /// Returns the element at the given index, decompressing its block if needed
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let block = index / BLOCK;
+        if block == self.blocks.len() {
+            return Some(self.tail[index % BLOCK].clone());
+        }
+
+        Some(self.with_block(block, |values| values[index % BLOCK].clone()))
+    }
+
+    /// Returns an iterator over every element, decompressing one block at a time
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.blocks
+            .iter()
+            .flat_map(|block| decompress::<T>(block, BLOCK))
+            .chain(self.tail.iter().cloned())
+    }
+
+    /// Overwrite the element at the given index, recompressing its block.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn set(&mut self, index: usize, val: T) {
+        assert!(index < self.len(), "index out of bounds");
+
+        let block = index / BLOCK;
+        if block == self.blocks.len() {
+            self.tail[index % BLOCK] = val;
+            return;
+        }
+
+        let cache = self.cache.get_mut().unwrap();
+        let mut values = match cache.take() {
+            Some((cached, values)) if cached == block => values,
+            _ => decompress(&self.blocks[block], BLOCK),
+        };
+        values[index % BLOCK] = val;
+        self.blocks[block] = compress(&values);
+        *cache = Some((block, values));
+    }
+
+    /// Re-compress the column from the given elements, in order
+    fn recompress(&mut self, values: Vec<T>) {
+        *self = values.into_iter().collect();
+    }
+
+    /// Remove and return the element at the given index, recompressing the column.
+    ///
+    /// Panics if the index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of bounds");
+
+        let mut values: Vec<T> = self.iter().collect();
+        let val = values.remove(index);
+        self.recompress(values);
+        val
+    }
+
+    /// Reverse the order of the elements in the column, in place
+    pub fn reverse(&mut self) {
+        let mut values: Vec<T> = self.iter().collect();
+        values.reverse();
+        self.recompress(values);
+    }
+
+    /// Rotate the elements in the column `mid` places to the left
+    pub fn rotate_left(&mut self, mid: usize) {
+        let mut values: Vec<T> = self.iter().collect();
+        values.rotate_left(mid);
+        self.recompress(values);
+    }
+
+    /// Rotate the elements in the column `k` places to the right
+    pub fn rotate_right(&mut self, k: usize) {
+        let mut values: Vec<T> = self.iter().collect();
+        values.rotate_right(k);
+        self.recompress(values);
+    }
+}
+
+impl<T> Default for ZstdColumn<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ColdEncode + Clone> From<Vec<T>> for ZstdColumn<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: ColdEncode + Clone> FromIterator<T> for ZstdColumn<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut column = Self::new();
+        for val in iter {
+            column.push(val);
+        }
+        column
+    }
+}
+
+// The cache isn't cloned, so a clone starts with nothing decompressed
+impl<T: Clone> Clone for ZstdColumn<T> {
+    fn clone(&self) -> Self {
+        Self {
+            blocks: self.blocks.clone(),
+            tail: self.tail.clone(),
+            cache: Mutex::new(None),
+        }
+    }
+}
+
+impl<T: ColdEncode + Clone + core::fmt::Debug> core::fmt::Debug for ZstdColumn<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Columns are compared by their elements, not by their compressed bytes
+impl<T: ColdEncode + Clone + PartialEq> PartialEq for ZstdColumn<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: ColdEncode + Clone + Eq> Eq for ZstdColumn<T> {}
+
+impl<T: ColdEncode + Clone + Hash> Hash for ZstdColumn<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: ColdEncode + Clone + serde::Serialize> serde::Serialize for ZstdColumn<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<T> HeapSize for ZstdColumn<T> {
+    fn heap_bytes(&self) -> usize {
+        let cache = self.cache.lock().unwrap();
+        let cache = cache.as_ref().map_or(0, |(_, values)| values.heap_bytes());
+        self.blocks.heap_bytes()
+            + self.blocks.iter().map(Vec::heap_bytes).sum::<usize>()
+            + self.tail.heap_bytes()
+            + cache
+    }
+}
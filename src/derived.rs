@@ -0,0 +1,136 @@
+//! Layouts derived from another layout, kept up to date incrementally
+//!
+//! A [`Derived`] layout holds one row computed from each row of a source layout by a mapping
+//! function, like a cache of lowered IR or computed bounding boxes. [`Derived::refresh`] only
+//! recomputes the rows whose source row changed since the last refresh, detected by comparing a
+//! hash of each source row with the hash seen when the row was last computed.
+
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+/// Implemented by every generated layout, to walk its rows without knowing the struct
+pub trait Table {
+    /// The Id type of the layout
+    type Id: Copy;
+
+    /// Get the number of rows in the layout
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the layout is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The Id of the row at the given index
+    fn id(index: usize) -> Self::Id;
+
+    /// Remove every row after the first `len` rows
+    fn truncate_rows(&mut self, len: usize);
+}
+
+/// Implemented by every generated layout storing rows of type `Row`
+///
+/// The row type is a parameter rather than an associated type so that layouts of private
+/// structs can implement it.
+pub trait TableRows<Row>: Table {
+    /// Add a row to the end of the layout
+    fn push_row(&mut self, row: Row);
+
+    /// Overwrite every field of the row with the given Id.
+    ///
+    /// Panics if the Id is out of bounds.
+    fn set_row(&mut self, id: Self::Id, row: Row);
+}
+
+/// Implemented by the generated layouts whose fields all implement `Hash`
+pub trait RowHash: Table {
+    /// A hash of every field of the row with the given Id.
+    ///
+    /// Panics if the Id is out of bounds.
+    fn row_hash(&self, id: Self::Id) -> u64;
+}
+
+/// A layout `D` with one row computed from each row of a source layout `S` by the function `F`
+///
+/// Dereferences to the derived layout, which is only updated by [`refresh`](Self::refresh).
+pub struct Derived<S, D, F> {
+    /// The derived rows
+    layout: D,
+
+    /// The hash of each source row when its derived row was last computed
+    hashes: Vec<u64>,
+
+    /// Computes the derived row of a source row
+    f: F,
+
+    _source: PhantomData<fn(&S)>,
+}
+
+impl<S, D: Table, F> Derived<S, D, F> {
+    /// Create a derived layout filled by `f`, starting from the given (usually empty) layout.
+    /// Nothing is computed until the first [`refresh`](Self::refresh).
+    pub fn new(mut layout: D, f: F) -> Self {
+        layout.truncate_rows(0);
+
+        Self {
+            layout,
+            hashes: Vec::new(),
+            f,
+            _source: PhantomData,
+        }
+    }
+
+    /// Recompute the rows whose source row was added or changed since the last refresh, and drop
+    /// the rows past the end of the source. Returns the number of rows recomputed.
+    pub fn refresh<R>(&mut self, source: &S) -> usize
+    where
+        S: RowHash,
+        D: TableRows<R>,
+        F: FnMut(&S, S::Id) -> R,
+    {
+        let mut recomputed = 0;
+
+        for index in 0..source.len() {
+            let hash = source.row_hash(S::id(index));
+
+            match self.hashes.get(index) {
+                Some(old) if *old == hash => continue,
+                Some(_) => {
+                    let row = (self.f)(source, S::id(index));
+                    self.layout.set_row(D::id(index), row);
+                    self.hashes[index] = hash;
+                }
+                None => {
+                    let row = (self.f)(source, S::id(index));
+                    self.layout.push_row(row);
+                    self.hashes.push(hash);
+                }
+            }
+
+            recomputed += 1;
+        }
+
+        self.layout.truncate_rows(source.len());
+        self.hashes.truncate(source.len());
+
+        recomputed
+    }
+
+    /// Returns the derived layout
+    pub fn layout(&self) -> &D {
+        &self.layout
+    }
+
+    /// Returns the derived layout, dropping the change tracking
+    pub fn into_layout(self) -> D {
+        self.layout
+    }
+}
+
+impl<S, D, F> Deref for Derived<S, D, F> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        &self.layout
+    }
+}
@@ -0,0 +1,295 @@
+//! The FlatBuffers encoding of `flatbuffers` layouts, used by the generated `to_flatbuffer()` and
+//! `from_flatbuffer()`
+//!
+//! A layout is encoded as the root table of the generated `FLATBUFFERS_SCHEMA`, with a vector per
+//! field. Only the vectors of scalars and of strings needed by layouts are supported, so no
+//! FlatBuffers runtime is required.
+
+use std::fmt;
+
+/// A scalar that can be an element of a FlatBuffers vector, stored little-endian
+pub trait Scalar: Copy {
+    /// The size of the scalar in bytes, which is also its alignment
+    const SIZE: usize;
+
+    /// Append the little-endian bytes of `self` to `out`
+    fn write(self, out: &mut Vec<u8>);
+
+    /// Read the scalar from exactly `SIZE` little-endian bytes
+    fn read(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Scalar for $ty {
+                const SIZE: usize = size_of::<$ty>();
+
+                fn write(self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read(bytes: &[u8]) -> Self {
+                    <$ty>::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Scalar for bool {
+    const SIZE: usize = 1;
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.push(u8::from(self));
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+/// An error reading a layout from a FlatBuffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// An offset or a length points past the end of the buffer
+    OutOfBounds,
+
+    /// A string isn't valid UTF-8
+    InvalidUtf8,
+
+    /// The vector of a field doesn't hold one element per node
+    ColumnLength {
+        /// The name of the field
+        field: &'static str,
+
+        /// The number of elements in the vector of the field
+        len: usize,
+
+        /// The number of nodes, taken from the vector of the first field
+        expected: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfBounds => write!(f, "offset or length past the end of the FlatBuffer"),
+            Error::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            Error::ColumnLength {
+                field,
+                len,
+                expected,
+            } => write!(
+                f,
+                "column `{field}` has {len} nodes but expected {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Converts a position in the buffer to a FlatBuffers offset
+fn offset(pos: usize) -> [u8; 4] {
+    u32::try_from(pos)
+        .ok()
+        .filter(|pos| *pos <= i32::MAX as u32)
+        .expect("FlatBuffers are limited to 2 GiB")
+        .to_le_bytes()
+}
+
+/// Writes a FlatBuffer holding a single root table of vectors
+///
+/// The buffer is written front to back: the vtable and the table of offsets first, then each
+/// vector as it is pushed, so every offset points forward as FlatBuffers requires.
+pub struct Builder {
+    /// The buffer written so far
+    buf: Vec<u8>,
+
+    /// The position of the offset to the vector of the first field in the table
+    slots: usize,
+}
+
+impl Builder {
+    /// Start a buffer whose root table has a vector for each of `fields` fields
+    pub fn new(fields: usize) -> Self {
+        // The root offset, then the vtable of two sizes and the position of each field
+        let vtable = 4;
+        let vtable_len = 4 + 2 * fields;
+        let table_len = 4 + 4 * fields;
+        let table = (vtable + vtable_len).next_multiple_of(4);
+
+        let mut buf = Vec::with_capacity(table + table_len);
+        buf.extend_from_slice(&offset(table));
+        buf.extend_from_slice(&(vtable_len as u16).to_le_bytes());
+        buf.extend_from_slice(&(table_len as u16).to_le_bytes());
+        for field in 0..fields {
+            buf.extend_from_slice(&((4 + 4 * field) as u16).to_le_bytes());
+        }
+        buf.resize(table, 0);
+
+        // The table starts with the distance back to its vtable
+        buf.extend_from_slice(&offset(table - vtable));
+        buf.resize(table + table_len, 0);
+
+        Self {
+            buf,
+            slots: table + 4,
+        }
+    }
+
+    /// Write the length of the vector of `field`, pointing the field to it, and return the
+    /// position of its first element, aligned to `align`
+    fn start_vector(&mut self, field: usize, len: usize, align: usize) -> usize {
+        let elements = (self.buf.len() + 4).next_multiple_of(align.max(4));
+        self.buf.resize(elements - 4, 0);
+
+        let slot = self.slots + 4 * field;
+        let distance = offset(elements - 4 - slot);
+        self.buf[slot..slot + 4].copy_from_slice(&distance);
+        self.buf.extend_from_slice(&offset(len));
+        elements
+    }
+
+    /// Write the vector of scalars of `field`
+    pub fn push_scalars<T: Scalar>(
+        &mut self,
+        field: usize,
+        values: impl ExactSizeIterator<Item = T>,
+    ) {
+        self.start_vector(field, values.len(), T::SIZE);
+        for value in values {
+            value.write(&mut self.buf);
+        }
+    }
+
+    /// Write the vector of strings of `field`: a vector of offsets, then each string with its
+    /// length and a null terminator
+    pub fn push_strings(
+        &mut self,
+        field: usize,
+        values: impl ExactSizeIterator<Item = impl AsRef<str>>,
+    ) {
+        let elements = self.start_vector(field, values.len(), 4);
+        self.buf.resize(elements + 4 * values.len(), 0);
+
+        for (index, value) in values.enumerate() {
+            let value = value.as_ref();
+            let string = self.buf.len().next_multiple_of(4);
+            self.buf.resize(string, 0);
+
+            let element = elements + 4 * index;
+            let distance = offset(string - element);
+            self.buf[element..element + 4].copy_from_slice(&distance);
+            self.buf.extend_from_slice(&offset(value.len()));
+            self.buf.extend_from_slice(value.as_bytes());
+            self.buf.push(0);
+        }
+    }
+
+    /// Returns the finished buffer
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads the vectors of the root table of a FlatBuffer, checking every offset against the buffer
+pub struct Table<'a> {
+    /// The whole buffer
+    bytes: &'a [u8],
+
+    /// The position of the root table
+    table: usize,
+
+    /// The position of the vtable of the root table
+    vtable: usize,
+
+    /// The size of the vtable in bytes
+    vtable_len: usize,
+}
+
+impl<'a> Table<'a> {
+    /// Find the root table of the buffer
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let table = read_u32(bytes, 0)? as usize;
+        let distance = i64::from(i32::from_le_bytes(read(bytes, table)?));
+        let vtable = usize::try_from(table as i64 - distance).map_err(|_| Error::OutOfBounds)?;
+        let vtable_len = usize::from(u16::from_le_bytes(read(bytes, vtable)?));
+
+        Ok(Self {
+            bytes,
+            table,
+            vtable,
+            vtable_len,
+        })
+    }
+
+    /// Returns the position of the first element of the vector of `field` and its length, or
+    /// `None` if the field is absent
+    fn vector(&self, field: usize) -> Result<Option<(usize, usize)>, Error> {
+        let entry = 4 + 2 * field;
+        if entry + 2 > self.vtable_len {
+            return Ok(None);
+        }
+
+        let slot = usize::from(u16::from_le_bytes(read(self.bytes, self.vtable + entry)?));
+        if slot == 0 {
+            return Ok(None);
+        }
+
+        let slot = self.table + slot;
+        let vector = slot + read_u32(self.bytes, slot)? as usize;
+        let len = read_u32(self.bytes, vector)? as usize;
+        Ok(Some((vector + 4, len)))
+    }
+
+    /// Read the vector of scalars of `field`, empty if the field is absent
+    pub fn scalars<T: Scalar>(&self, field: usize) -> Result<Vec<T>, Error> {
+        let Some((elements, len)) = self.vector(field)? else {
+            return Ok(Vec::new());
+        };
+
+        let size = len.checked_mul(T::SIZE).ok_or(Error::OutOfBounds)?;
+        Ok(slice(self.bytes, elements, size)?
+            .chunks_exact(T::SIZE)
+            .map(T::read)
+            .collect())
+    }
+
+    /// Read the vector of strings of `field`, empty if the field is absent
+    pub fn strings(&self, field: usize) -> Result<Vec<String>, Error> {
+        let Some((elements, len)) = self.vector(field)? else {
+            return Ok(Vec::new());
+        };
+
+        (0..len)
+            .map(|index| {
+                let element = elements + 4 * index;
+                let string = element + read_u32(self.bytes, element)? as usize;
+                let len = read_u32(self.bytes, string)? as usize;
+                let bytes = slice(self.bytes, string + 4, len)?;
+                String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidUtf8)
+            })
+            .collect()
+    }
+}
+
+/// Returns the `len` bytes of the buffer at `pos`
+fn slice(bytes: &[u8], pos: usize, len: usize) -> Result<&[u8], Error> {
+    pos.checked_add(len)
+        .and_then(|end| bytes.get(pos..end))
+        .ok_or(Error::OutOfBounds)
+}
+
+/// Returns the `N` bytes of the buffer at `pos`
+fn read<const N: usize>(bytes: &[u8], pos: usize) -> Result<[u8; N], Error> {
+    Ok(slice(bytes, pos, N)?.try_into().unwrap())
+}
+
+/// Reads the offset or length at `pos`
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32, Error> {
+    read(bytes, pos).map(u32::from_le_bytes)
+}
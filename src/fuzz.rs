@@ -0,0 +1,219 @@
+//! Fuzzing a layout against a `Vec` of its rows
+//!
+//! [`run`] decodes a sequence of [`Op`]s from the bytes given by a fuzzer, applies each of them to
+//! both a layout and a `Vec` of the same rows (the oracle), and panics at the first operation after
+//! which the two disagree. Every generated layout calls it from its `fuzz(data)`, so fuzzing a
+//! layout configuration (its `sparse`, `rle`, `segmented`, ... columns) for equivalence bugs is a
+//! single line in a fuzz target:
+//!
+//! ```rust
+//! #[soaaos::layout("soa")]
+//! #[derive(Debug, Clone, PartialEq, arbitrary::Arbitrary)]
+//! struct Node {
+//!     #[soa(sparse)]
+//!     name: Option<String>,
+//!     #[soa(rle)]
+//!     op: u8,
+//! }
+//!
+//! // libfuzzer_sys::fuzz_target!(|data: &[u8]| NodesLayout::fuzz(data));
+//! NodesLayout::fuzz(b"bytes from the fuzzer");
+//! ```
+//!
+//! The row struct derives `arbitrary::Arbitrary` to be decoded. Rows are compared with
+//! `PartialEq`, so every row must equal its own copy (no `NaN` floats).
+
+use core::fmt;
+
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::derived::TableRows;
+
+/// Implemented by every generated layout whose fields are `Clone`, `PartialEq` and `Debug`, for
+/// the operations [`check`] compares with the oracle
+pub trait FuzzTarget<Row>: TableRows<Row> + Sized {
+    /// Create an empty layout
+    fn empty() -> Self;
+
+    /// Returns an owned copy of the row with the given Id, or `None` if it is out of bounds
+    fn cloned_row(&self, id: Self::Id) -> Option<Row>;
+
+    /// Returns owned copies of every row, in order
+    fn cloned_rows(&self) -> Vec<Row>;
+
+    /// Remove the row with the given Id, shifting every later row down by one. Returns `None` if
+    /// the Id is out of bounds.
+    fn remove_row(&mut self, id: Self::Id) -> Option<Row>;
+
+    /// Remove the row with the given Id, moving the last row into its place. Returns `None` if
+    /// the Id is out of bounds.
+    fn swap_remove_row(&mut self, id: Self::Id) -> Option<Row>;
+
+    /// Move the rows to the given order, so the row at `order[i]` is then at index `i`
+    fn reorder_rows(&mut self, order: &[Self::Id]);
+
+    /// Reverse the order of the rows
+    fn reverse_rows(&mut self);
+
+    /// Returns `true` if the layouts differ in any field of the rows they both hold
+    fn differs(&self, other: &Self) -> bool;
+}
+
+/// An operation applied to both the layout and the oracle
+///
+/// Indexes are taken modulo one more than the number of rows, so that an operation is sometimes
+/// given the index just past the last row, which both must reject.
+#[derive(Debug, Clone, PartialEq, Arbitrary)]
+pub enum Op<Row> {
+    /// Add the row
+    Add(Row),
+
+    /// Get a copy of the row at the index
+    Get(usize),
+
+    /// Remove the row at the index, shifting every later row down
+    Remove(usize),
+
+    /// Remove the row at the index, moving the last row into its place
+    SwapRemove(usize),
+
+    /// Stably sort the rows by the key at the index of each row, rows without a key sorting as 0
+    Sort(Vec<u16>),
+
+    /// Reverse the order of the rows
+    Reverse,
+
+    /// Keep a copy of the rows, for the following `Diff`s
+    Snapshot,
+
+    /// Diff the layout with a layout of the rows of the last `Snapshot`
+    Diff,
+}
+
+/// The first operation after which the layout and the oracle disagreed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The index of the operation in the sequence
+    pub step: usize,
+
+    /// The operation, formatted with `Debug`
+    pub op: String,
+
+    /// What the oracle held or returned, formatted with `Debug`
+    pub expected: String,
+
+    /// What the layout held or returned, formatted with `Debug`
+    pub found: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {} ({}): expected {}, found {}",
+            self.step, self.op, self.expected, self.found
+        )
+    }
+}
+
+impl core::error::Error for Mismatch {}
+
+/// Apply the operations to an empty layout `L` and to an empty `Vec` of rows, comparing the
+/// results of every operation and then every row. Returns the first disagreement.
+pub fn check<L, Row>(ops: &[Op<Row>]) -> Result<(), Mismatch>
+where
+    L: FuzzTarget<Row>,
+    Row: Clone + PartialEq + fmt::Debug,
+{
+    let mut layout = L::empty();
+    let mut oracle: Vec<Row> = Vec::new();
+    let mut snapshot: Vec<Row> = Vec::new();
+
+    for (step, op) in ops.iter().enumerate() {
+        let mismatch = |expected: &dyn fmt::Debug, found: &dyn fmt::Debug| Mismatch {
+            step,
+            op: format!("{op:?}"),
+            expected: format!("{expected:?}"),
+            found: format!("{found:?}"),
+        };
+
+        match op {
+            Op::Add(row) => {
+                layout.push_row(row.clone());
+                oracle.push(row.clone());
+            }
+            Op::Get(index) => {
+                let index = index % (oracle.len() + 1);
+                let expected = oracle.get(index).cloned();
+                let found = layout.cloned_row(L::id(index));
+                if found != expected {
+                    return Err(mismatch(&expected, &found));
+                }
+            }
+            Op::Remove(index) => {
+                let index = index % (oracle.len() + 1);
+                let expected = (index < oracle.len()).then(|| oracle.remove(index));
+                let found = layout.remove_row(L::id(index));
+                if found != expected {
+                    return Err(mismatch(&expected, &found));
+                }
+            }
+            Op::SwapRemove(index) => {
+                let index = index % (oracle.len() + 1);
+                let expected = (index < oracle.len()).then(|| oracle.swap_remove(index));
+                let found = layout.swap_remove_row(L::id(index));
+                if found != expected {
+                    return Err(mismatch(&expected, &found));
+                }
+            }
+            Op::Sort(keys) => {
+                let mut order: Vec<usize> = (0..oracle.len()).collect();
+                order.sort_by_key(|&index| keys.get(index).copied().unwrap_or(0));
+                let ids: Vec<L::Id> = order.iter().map(|&index| L::id(index)).collect();
+                layout.reorder_rows(&ids);
+                oracle = order.iter().map(|&index| oracle[index].clone()).collect();
+            }
+            Op::Reverse => {
+                layout.reverse_rows();
+                oracle.reverse();
+            }
+            Op::Snapshot => snapshot = oracle.clone(),
+            Op::Diff => {
+                let mut other = L::empty();
+                for row in &snapshot {
+                    other.push_row(row.clone());
+                }
+                let expected = oracle.iter().zip(&snapshot).any(|(a, b)| a != b);
+                let found = layout.differs(&other);
+                if found != expected {
+                    return Err(mismatch(&expected, &found));
+                }
+            }
+        }
+
+        let rows = layout.cloned_rows();
+        if rows != oracle {
+            return Err(mismatch(&oracle, &rows));
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode a sequence of operations from `data` and [`check`] them against the layout `L`,
+/// panicking with the [`Mismatch`] if they disagree. Data that can't be decoded is ignored.
+///
+/// Used by the generated `fuzz(data)`.
+pub fn run<L, Row>(data: &[u8])
+where
+    L: FuzzTarget<Row>,
+    Row: for<'a> Arbitrary<'a> + Clone + PartialEq + fmt::Debug,
+{
+    let Ok(ops) = Vec::<Op<Row>>::arbitrary_take_rest(Unstructured::new(data)) else {
+        return;
+    };
+
+    if let Err(mismatch) = check::<L, Row>(&ops) {
+        panic!("layout disagrees with the oracle at {mismatch}");
+    }
+}
@@ -0,0 +1,113 @@
+//! Layouts mutated through shared references, coordinated by a single token
+//!
+//! A [`GhostLayout`] holds a layout that is only read through a shared [`GhostToken`] and only
+//! written through the token borrowed mutably, in the style of `GhostCell`. Every layout of a
+//! system of interlinked layouts can then be shared freely (held by several owners, or borrowed by
+//! many parts of a program at once), while the borrow checker still guarantees that no layout is
+//! written while it is read: writing any of them borrows the one token they share mutably.
+//!
+//! Tokens are created by [`GhostToken::scope`], branded with a lifetime unique to the call, so a
+//! token only unlocks the layouts created with its brand. Every layout has a `GhostNodesLayout`
+//! alias of its `GhostLayout`.
+//!
+//! ```rust
+//! use soaaos::ghost::GhostToken;
+//!
+//! #[soaaos::layout("soa")]
+//! struct Node {
+//!     parent: Option<NodeId>,
+//!     weight: u32,
+//! }
+//!
+//! GhostToken::scope(|mut token| {
+//!     let nodes = GhostNodesLayout::new(NodesLayout::new());
+//!     let (a, b) = (&nodes, &nodes);
+//!
+//!     let root = a.borrow_mut(&mut token).add(Node { parent: None, weight: 1 });
+//!     b.borrow_mut(&mut token).add(Node { parent: Some(root), weight: 2 });
+//!     assert_eq!(a.borrow(&token).weight().sum::<u32>(), 3);
+//! });
+//! ```
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+
+/// The single key to every [`GhostLayout`] of the brand `'id`
+///
+/// Reading a layout borrows the token, writing a layout borrows it mutably. The lifetime is
+/// invariant and only known inside [`GhostToken::scope`], so there is one token per brand.
+pub struct GhostToken<'id>(PhantomData<fn(&'id ()) -> &'id ()>);
+
+impl GhostToken<'_> {
+    /// Call `f` with a new token, branded with a lifetime unique to this call
+    pub fn scope<R>(f: impl for<'id> FnOnce(GhostToken<'id>) -> R) -> R {
+        f(GhostToken(PhantomData))
+    }
+}
+
+impl<'id> GhostToken<'id> {
+    /// Borrow two different layouts of the brand mutably at once, e.g. to remove a node from one
+    /// layout and the nodes referencing it from another.
+    ///
+    /// Panics if both are the same layout.
+    pub fn borrow_mut_pair<'a, A, B>(
+        &'a mut self,
+        a: &'a GhostLayout<'id, A>,
+        b: &'a GhostLayout<'id, B>,
+    ) -> (&'a mut A, &'a mut B) {
+        assert!(
+            a.layout.get().cast::<()>() != b.layout.get().cast::<()>(),
+            "borrow_mut_pair was given the same layout twice"
+        );
+
+        // SAFETY: the token is borrowed mutably for 'a, so no other borrow of a layout of the
+        // brand exists, and the two layouts don't overlap
+        unsafe { (&mut *a.layout.get(), &mut *b.layout.get()) }
+    }
+}
+
+/// A layout only read through a shared [`GhostToken`] of the brand `'id`, and only written
+/// through the token borrowed mutably
+pub struct GhostLayout<'id, L> {
+    /// The layout, only borrowed while the token is
+    layout: UnsafeCell<L>,
+
+    /// The brand of the token unlocking the layout
+    _brand: PhantomData<fn(&'id ()) -> &'id ()>,
+}
+
+// SAFETY: like a `RwLock`, a shared `GhostLayout` hands out `&L` to several threads, and `&mut L`
+// to the thread holding the token mutably
+unsafe impl<L: Send + Sync> Sync for GhostLayout<'_, L> {}
+
+impl<'id, L> GhostLayout<'id, L> {
+    /// Hand the layout over to the token of the brand `'id`
+    pub fn new(layout: L) -> Self {
+        Self {
+            layout: UnsafeCell::new(layout),
+            _brand: PhantomData,
+        }
+    }
+
+    /// Read the layout, for as long as the token is borrowed
+    pub fn borrow<'a>(&'a self, _token: &'a GhostToken<'id>) -> &'a L {
+        // SAFETY: the token is borrowed for 'a, so the layout isn't borrowed mutably
+        unsafe { &*self.layout.get() }
+    }
+
+    /// Write the layout, for as long as the token is borrowed mutably
+    pub fn borrow_mut<'a>(&'a self, _token: &'a mut GhostToken<'id>) -> &'a mut L {
+        // SAFETY: the token is borrowed mutably for 'a, so no other borrow of the layout exists
+        unsafe { &mut *self.layout.get() }
+    }
+
+    /// Write the layout without the token, since it is borrowed mutably
+    pub fn get_mut(&mut self) -> &mut L {
+        self.layout.get_mut()
+    }
+
+    /// Take the layout back
+    pub fn into_inner(self) -> L {
+        self.layout.into_inner()
+    }
+}
@@ -0,0 +1,111 @@
+//! The row index of `hashcons` layouts, used by the generated `add_unique()`, and of the `key`
+//! field of a layout, used by the generated `get_index_of()`
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The indexes of the rows of a layout by the hash of their contents
+///
+/// Rows are indexed lazily, when looking up a row, so adding nodes stays as cheap as without the
+/// index. Writing, removing or reordering nodes through the methods of the layout discards the
+/// index, which is then rebuilt by the next lookup. Like the subscribers of a `watch` layout, the
+/// index doesn't take part in comparing or hashing layouts.
+#[derive(Debug, Clone, Default)]
+pub struct RowIndex {
+    /// The indexes of the rows with each hash, in ascending order
+    rows: HashMap<u64, Vec<u32>>,
+
+    /// The number of rows indexed, from the start of the layout
+    indexed: usize,
+}
+
+impl RowIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of rows indexed, from the start of the layout
+    pub fn indexed(&self) -> usize {
+        self.indexed
+    }
+
+    /// Index the next row, with the given hash
+    pub fn push(&mut self, hash: u64) {
+        let index = u32::try_from(self.indexed).expect("RowIndex index overflows u32");
+        self.rows.entry(hash).or_default().push(index);
+        self.indexed += 1;
+    }
+
+    /// Returns the indexes of the rows with the given hash, in ascending order
+    pub fn candidates(&self, hash: u64) -> impl Iterator<Item = usize> + '_ {
+        self.rows
+            .get(&hash)
+            .into_iter()
+            .flatten()
+            .map(|index| *index as usize)
+    }
+
+    /// Discard every indexed row, after the rows were changed
+    pub fn invalidate(&mut self) {
+        self.rows.clear();
+        self.indexed = 0;
+    }
+}
+
+impl PartialEq for RowIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RowIndex {}
+
+impl core::hash::Hash for RowIndex {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+/// A [`RowIndex`] that can be brought up to date through a shared reference, so lookups such as
+/// `get_index_of()` take `&self`
+///
+/// The index is behind a `Mutex` rather than a `RefCell` so layouts stay `Sync`.
+#[derive(Debug, Default)]
+pub struct SharedRowIndex(Mutex<RowIndex>);
+
+impl SharedRowIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lock the index to look up or index rows
+    pub fn lock(&self) -> MutexGuard<'_, RowIndex> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Discard every indexed row, after the rows were changed
+    pub fn invalidate(&mut self) {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .invalidate();
+    }
+}
+
+impl Clone for SharedRowIndex {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.lock().clone()))
+    }
+}
+
+impl PartialEq for SharedRowIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SharedRowIndex {}
+
+impl core::hash::Hash for SharedRowIndex {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
@@ -1,3 +1,6 @@
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
+#![allow(unused_imports, dead_code)]
+
 use rand::distr::SampleString;
 use rand::prelude::SliceRandom;
 use rand::{Rng, RngCore};
@@ -0,0 +1,282 @@
+//! The columns of `#[layout("persistent")]` layouts
+//!
+//! A persistent layout is never written in place: adding or writing a node returns a new layout,
+//! sharing every column and every part of a column that didn't change with the old one. Keeping
+//! the old layout around is as cheap as cloning a handful of `Arc`s, which makes undo stacks and
+//! speculative search over many versions of a layout affordable.
+
+use core::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// The number of bits of an index used by each level of the tree
+const BITS: u32 = 5;
+
+/// The number of children of a branch, and of elements of a leaf
+const WIDTH: usize = 1 << BITS;
+
+/// The mask of the bits of an index used by a single level of the tree
+const MASK: usize = WIDTH - 1;
+
+/// A node of the tree of a [`PersistentVec`]
+enum Node<T> {
+    /// The children of a branch, from the left
+    Branch(Vec<Arc<Node<T>>>),
+
+    /// A full block of `WIDTH` elements
+    Leaf(Vec<T>),
+}
+
+/// A persistent vector: cloning, pushing and writing an element share every unchanged block of
+/// elements with the original vector
+///
+/// Elements are stored in a tree of blocks of 32 elements, where each branch holds up to 32
+/// children. The last, partial block is held aside as the tail, so pushing usually only copies
+/// the tail. Writing an element copies the path from the root to its block, so both take
+/// `O(log32 n)` time and memory.
+pub struct PersistentVec<T> {
+    /// The number of elements
+    len: usize,
+
+    /// The number of index bits below the root, `BITS` times the height of the tree
+    shift: u32,
+
+    /// The root of the tree of the full blocks, before the tail
+    root: Arc<Node<T>>,
+
+    /// The elements after the last full block
+    tail: Arc<Vec<T>>,
+}
+
+impl<T> PersistentVec<T> {
+    /// Create an empty vector
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            shift: BITS,
+            root: Arc::new(Node::Branch(Vec::new())),
+            tail: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Get the number of elements in the vector
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the vector is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The index of the first element of the tail
+    fn tail_offset(&self) -> usize {
+        if self.len < WIDTH {
+            0
+        } else {
+            ((self.len - 1) >> BITS) << BITS
+        }
+    }
+
+    /// Returns the element at the given index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+
+        let tail_offset = self.tail_offset();
+        if index >= tail_offset {
+            return self.tail.get(index - tail_offset);
+        }
+
+        let mut node = &*self.root;
+        let mut level = self.shift;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    node = &children[(index >> level) & MASK];
+                    level -= BITS;
+                }
+                Node::Leaf(values) => return values.get(index & MASK),
+            }
+        }
+    }
+
+    /// Returns an iterator over every element
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.len).map(|index| self.get(index).unwrap())
+    }
+
+    /// Returns `true` if both vectors share every block of elements, so they are equal without
+    /// comparing them
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && Arc::ptr_eq(&self.root, &other.root)
+            && Arc::ptr_eq(&self.tail, &other.tail)
+    }
+}
+
+impl<T: Clone> PersistentVec<T> {
+    /// Returns a new vector with `val` appended, sharing every full block with this one
+    #[must_use]
+    pub fn push(&self, val: T) -> Self {
+        let tail_offset = self.tail_offset();
+
+        // Room in the tail
+        if self.len - tail_offset < WIDTH {
+            let mut tail = Vec::with_capacity(WIDTH);
+            tail.extend_from_slice(&self.tail);
+            tail.push(val);
+            return Self {
+                len: self.len + 1,
+                shift: self.shift,
+                root: self.root.clone(),
+                tail: Arc::new(tail),
+            };
+        }
+
+        // The tail is full: move it into the tree, growing the tree when the root is full
+        let leaf = Arc::new(Node::Leaf(self.tail.to_vec()));
+        let (root, shift) = if (self.len >> BITS) > (1 << self.shift) {
+            let path = new_path(self.shift, leaf);
+            let root = Arc::new(Node::Branch(vec![self.root.clone(), path]));
+            (root, self.shift + BITS)
+        } else {
+            (
+                push_leaf(self.len, self.shift, &self.root, leaf),
+                self.shift,
+            )
+        };
+
+        let mut tail = Vec::with_capacity(WIDTH);
+        tail.push(val);
+        Self {
+            len: self.len + 1,
+            shift,
+            root,
+            tail: Arc::new(tail),
+        }
+    }
+
+    /// Returns a new vector with the element at the given index replaced by `val`, sharing every
+    /// other block with this one. Returns `None` if the index is out of bounds.
+    #[must_use]
+    pub fn set(&self, index: usize, val: T) -> Option<Self> {
+        if index >= self.len {
+            return None;
+        }
+
+        let tail_offset = self.tail_offset();
+        let mut new = Self {
+            len: self.len,
+            shift: self.shift,
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+        };
+        if index >= tail_offset {
+            Arc::make_mut(&mut new.tail)[index - tail_offset] = val;
+        } else {
+            new.root = set_leaf(self.shift, &self.root, index, val);
+        }
+        Some(new)
+    }
+}
+
+/// Returns a branch of single children down to `leaf`, `level` bits above it
+fn new_path<T>(level: u32, leaf: Arc<Node<T>>) -> Arc<Node<T>> {
+    if level == 0 {
+        leaf
+    } else {
+        Arc::new(Node::Branch(vec![new_path(level - BITS, leaf)]))
+    }
+}
+
+/// Returns a copy of `node`, `level` bits above the leaves, with `leaf` appended as the block of
+/// the last element of a vector of `len` elements
+fn push_leaf<T>(len: usize, level: u32, node: &Arc<Node<T>>, leaf: Arc<Node<T>>) -> Arc<Node<T>> {
+    let Node::Branch(children) = &**node else {
+        unreachable!("leaves are only found at level 0");
+    };
+
+    let mut children = children.clone();
+    let child = ((len - 1) >> level) & MASK;
+    if level == BITS {
+        children.push(leaf);
+    } else if let Some(existing) = children.get(child) {
+        children[child] = push_leaf(len, level - BITS, existing, leaf);
+    } else {
+        children.push(new_path(level - BITS, leaf));
+    }
+    Arc::new(Node::Branch(children))
+}
+
+/// Returns a copy of `node`, `level` bits above the leaves, with the element at `index` replaced
+/// by `val`
+fn set_leaf<T: Clone>(level: u32, node: &Arc<Node<T>>, index: usize, val: T) -> Arc<Node<T>> {
+    match &**node {
+        Node::Branch(children) => {
+            let mut children = children.clone();
+            let child = (index >> level) & MASK;
+            children[child] = set_leaf(level - BITS, &children[child], index, val);
+            Arc::new(Node::Branch(children))
+        }
+        Node::Leaf(values) => {
+            let mut values = values.clone();
+            values[index & MASK] = val;
+            Arc::new(Node::Leaf(values))
+        }
+    }
+}
+
+impl<T> Default for PersistentVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cloning only shares the blocks of the vector
+impl<T> Clone for PersistentVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            len: self.len,
+            shift: self.shift,
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+        }
+    }
+}
+
+impl<T: Clone> From<Vec<T>> for PersistentVec<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Clone> FromIterator<T> for PersistentVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |values, val| values.push(val))
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for PersistentVec<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for PersistentVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || (self.len == other.len && self.iter().eq(other.iter()))
+    }
+}
+
+impl<T: Eq> Eq for PersistentVec<T> {}
+
+impl<T: Hash> Hash for PersistentVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for val in self.iter() {
+            val.hash(state);
+        }
+    }
+}
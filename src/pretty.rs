@@ -0,0 +1,85 @@
+//! Rendering of the `diff_pretty()` of generated layouts
+
+use core::fmt::Write;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Render the rows of two layouts that differ as a unified diff with ANSI colors.
+///
+/// `changed(index)` returns whether the row at `index` differs between the layouts, and
+/// `rows(index)` returns the Debug of each field of the row in both layouts, or `None` for a
+/// layout without that row. Changed rows within `2 * context` rows of each other are grouped in a
+/// single hunk, along with up to `context` unchanged rows around them.
+///
+/// Returns `None` if no row differs.
+pub fn render_diff(
+    fields: &[&str],
+    len: usize,
+    changed: impl Fn(usize) -> bool,
+    rows: impl Fn(usize) -> (Option<Vec<String>>, Option<Vec<String>>),
+    context: usize,
+) -> Option<String> {
+    let changed: Vec<usize> = (0..len).filter(|&index| changed(index)).collect();
+    if changed.is_empty() {
+        return None;
+    }
+
+    // Group the changed rows into hunks of `start..end`, including their context
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &index in &changed {
+        let start = index.saturating_sub(context);
+        let end = (index + context + 1).min(len);
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    let mut changed = changed.into_iter().peekable();
+    for (start, end) in hunks {
+        writeln!(out, "{CYAN}@@ rows {start}..{end} @@{RESET}").unwrap();
+
+        for index in start..end {
+            let (this, other) = rows(index);
+
+            if changed.next_if_eq(&index).is_none() {
+                let values = this.unwrap_or_default();
+                let values: Vec<_> = fields
+                    .iter()
+                    .zip(&values)
+                    .map(|(field, value)| format!("{field}: {value}"))
+                    .collect();
+                writeln!(out, "{DIM}  row {index} {{ {} }}{RESET}", values.join(", ")).unwrap();
+                continue;
+            }
+
+            writeln!(out, "{BOLD}  row {index}{RESET}").unwrap();
+            for (field_index, field) in fields.iter().enumerate() {
+                let this = this.as_ref().map(|values| &values[field_index]);
+                let other = other.as_ref().map(|values| &values[field_index]);
+
+                match (this, other) {
+                    (Some(this), Some(other)) if this == other => {
+                        writeln!(out, "      {field}: {this}").unwrap();
+                    }
+                    (this, other) => {
+                        if let Some(this) = this {
+                            writeln!(out, "{RED}-     {field}: {this}{RESET}").unwrap();
+                        }
+                        if let Some(other) = other {
+                            writeln!(out, "{GREEN}+     {field}: {other}{RESET}").unwrap();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Some(out)
+}
@@ -0,0 +1,87 @@
+//! The report of the generated `profile_scan()`, measuring the scan throughput of each column
+
+use core::fmt;
+use std::time::{Duration, Instant};
+
+/// Scans are repeated until they took at least this long, so short columns are measured over
+/// enough passes to be meaningful
+const MIN_ELAPSED: Duration = Duration::from_millis(1);
+
+/// The throughput of scanning a single column, or every row
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnScan {
+    /// The name of the field, or `"rows"` for full rows
+    pub name: &'static str,
+
+    /// The number of bytes scanned over every pass: the number of elements times the size of
+    /// each element
+    pub bytes: usize,
+
+    /// The number of passes over the column
+    pub passes: usize,
+
+    /// The time taken by every pass
+    pub elapsed: Duration,
+}
+
+impl ColumnScan {
+    /// Measure `scan`, which scans `bytes` bytes, repeating it until the passes took at least
+    /// 1ms. Empty columns (`bytes == 0`) are only scanned once.
+    pub fn measure(name: &'static str, bytes: usize, mut scan: impl FnMut()) -> Self {
+        let start = Instant::now();
+        let mut passes = 0;
+        loop {
+            scan();
+            passes += 1;
+
+            if bytes == 0 || start.elapsed() >= MIN_ELAPSED {
+                break;
+            }
+        }
+
+        Self {
+            name,
+            bytes: bytes * passes,
+            passes,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// The number of bytes scanned per second
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl fmt::Display for ColumnScan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<20} {:>10.1} MB/s ({} passes in {:?})",
+            self.name,
+            self.bytes_per_sec() / 1_000_000.0,
+            self.passes,
+            self.elapsed
+        )
+    }
+}
+
+/// The throughput of scanning each column of a layout, and of scanning full rows, returned by
+/// the generated `profile_scan()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanProfile {
+    /// The scan of each column, in field order
+    pub columns: Vec<ColumnScan>,
+
+    /// The scan of every row, reading every field
+    pub rows: ColumnScan,
+}
+
+impl fmt::Display for ScanProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for column in &self.columns {
+            writeln!(f, "{column}")?;
+        }
+        write!(f, "{}", self.rows)
+    }
+}
@@ -0,0 +1,214 @@
+//! The protobuf encoding of layouts with prost, used by the generated `to_proto()` and
+//! `from_proto()`
+//!
+//! A layout is encoded as the columnar message of the generated `PROTO_SCHEMA`, with a repeated
+//! field per field of the struct, through the field codecs of [`prost::encoding`]. Repeated
+//! scalars are written packed, as proto3 does by default, and read either packed or not, so the
+//! messages are those of a prost `Message` generated from `PROTO_SCHEMA`.
+
+use std::borrow::Borrow;
+use std::fmt;
+
+use ::prost::DecodeError;
+use ::prost::encoding::{self, DecodeContext, WireType};
+
+/// A type that can be an element of a repeated protobuf field
+pub trait Scalar: Sized {
+    /// Append the repeated field `tag` holding `values` to `buf`. Nothing is written without
+    /// values, as proto3 omits empty repeated fields.
+    fn encode(tag: u32, values: impl Iterator<Item = impl Borrow<Self>>, buf: &mut Vec<u8>);
+
+    /// Append the elements of a value of the repeated field, read from the start of `buf`, to
+    /// `values`
+    fn merge(wire_type: WireType, values: &mut Vec<Self>, buf: &mut &[u8]) -> Result<(), Error>;
+}
+
+/// Types stored in a protobuf field of their own type, in the given `prost::encoding` module
+macro_rules! impl_scalar {
+    ($($ty:ty => $module:ident),* $(,)?) => {
+        $(
+            impl Scalar for $ty {
+                fn encode(tag: u32, values: impl Iterator<Item = impl Borrow<Self>>, buf: &mut Vec<u8>) {
+                    let values: Vec<$ty> = values.map(|val| *val.borrow()).collect();
+                    encoding::$module::encode_packed(tag, &values, buf);
+                }
+
+                fn merge(wire_type: WireType, values: &mut Vec<Self>, buf: &mut &[u8]) -> Result<(), Error> {
+                    Ok(encoding::$module::merge_repeated(wire_type, values, buf, DecodeContext::default())?)
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(
+    u32 => uint32,
+    u64 => uint64,
+    i32 => sint32,
+    i64 => sint64,
+    f32 => float,
+    f64 => double,
+    bool => bool,
+);
+
+/// Integers stored in the protobuf field of a wider type, failing to decode values that don't fit
+macro_rules! impl_narrow {
+    ($($ty:ty => $wide:ty, $module:ident),* $(,)?) => {
+        $(
+            impl Scalar for $ty {
+                fn encode(tag: u32, values: impl Iterator<Item = impl Borrow<Self>>, buf: &mut Vec<u8>) {
+                    let values: Vec<$wide> = values.map(|val| <$wide>::from(*val.borrow())).collect();
+                    encoding::$module::encode_packed(tag, &values, buf);
+                }
+
+                fn merge(wire_type: WireType, values: &mut Vec<Self>, buf: &mut &[u8]) -> Result<(), Error> {
+                    let mut wide = Vec::new();
+                    encoding::$module::merge_repeated(wire_type, &mut wide, buf, DecodeContext::default())?;
+                    for val in wide {
+                        values.push(<$ty>::try_from(val).map_err(|_| Error::Overflow)?);
+                    }
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_narrow!(
+    u8 => u32, uint32,
+    u16 => u32, uint32,
+    i8 => i32, sint32,
+    i16 => i32, sint32,
+);
+
+impl Scalar for String {
+    fn encode(tag: u32, values: impl Iterator<Item = impl Borrow<Self>>, buf: &mut Vec<u8>) {
+        for value in values {
+            encoding::string::encode(tag, value.borrow(), buf);
+        }
+    }
+
+    fn merge(wire_type: WireType, values: &mut Vec<Self>, buf: &mut &[u8]) -> Result<(), Error> {
+        Ok(encoding::string::merge_repeated(
+            wire_type,
+            values,
+            buf,
+            DecodeContext::default(),
+        )?)
+    }
+}
+
+/// An error reading a layout from a protobuf message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The message isn't valid protobuf, or a value doesn't match the type of its field
+    Decode(DecodeError),
+
+    /// A value doesn't fit in the type of its field
+    Overflow,
+
+    /// A repeated field doesn't hold one element per node
+    ColumnLength {
+        /// The name of the field
+        field: &'static str,
+
+        /// The number of elements of the field
+        len: usize,
+
+        /// The number of nodes, taken from the first field
+        expected: usize,
+    },
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::Decode(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Decode(err) => write!(f, "{err}"),
+            Error::Overflow => write!(f, "value overflows the type of its field"),
+            Error::ColumnLength {
+                field,
+                len,
+                expected,
+            } => write!(
+                f,
+                "column `{field}` has {len} nodes but expected {expected}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Writes a message of repeated fields
+#[derive(Default)]
+pub struct Encoder {
+    /// The message written so far
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    /// Start an empty message
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the repeated field `tag` holding `values`
+    pub fn push<T: Scalar>(&mut self, tag: u32, values: impl Iterator<Item = impl Borrow<T>>) {
+        T::encode(tag, values, &mut self.buf);
+    }
+
+    /// Returns the finished message
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// A value of a field read from a message
+pub struct Value<'a> {
+    /// The wire type of the value
+    wire_type: WireType,
+
+    /// The encoded value, following its key
+    bytes: &'a [u8],
+}
+
+impl Value<'_> {
+    /// Append the elements of the repeated field held by this value to `out`
+    pub fn push<T: Scalar>(self, out: &mut Vec<T>) -> Result<(), Error> {
+        let mut bytes = self.bytes;
+        T::merge(self.wire_type, out, &mut bytes)
+    }
+}
+
+/// Reads the fields of a message in order, as `(field number, value)` pairs
+pub struct Decoder<'a> {
+    /// The rest of the message
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Start reading a message
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Returns the next field number and value, or `None` at the end of the message
+    pub fn next_field(&mut self) -> Result<Option<(u32, Value<'a>)>, Error> {
+        if self.bytes.is_empty() {
+            return Ok(None);
+        }
+
+        let (tag, wire_type) = encoding::decode_key(&mut self.bytes)?;
+        let start = self.bytes;
+        encoding::skip_field(wire_type, tag, &mut self.bytes, DecodeContext::default())?;
+        let bytes = &start[..start.len() - self.bytes.len()];
+
+        Ok(Some((tag, Value { wire_type, bytes })))
+    }
+}
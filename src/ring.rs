@@ -0,0 +1,108 @@
+//! The eviction state of `ring` layouts: the slot of the oldest node, and the callback set with
+//! the generated `on_evict()`
+
+/// The slot of the oldest node of a full `ring` layout, and the callback handed every node
+/// evicted from it, oldest first
+///
+/// Once the layout is full, adding a node overwrites the oldest node in its slot and moves the
+/// head to the next slot, so the Ids of the other nodes don't change. The head is only past the
+/// first slot while the layout is full: removing nodes rotates the oldest node back to the first
+/// slot, and reordering nodes makes their new order the order of eviction.
+///
+/// Like the row index of a `hashcons` layout, the callback doesn't take part in comparing or
+/// hashing layouts. A clone of a layout keeps the head but starts without a callback, since a
+/// closure can't be cloned.
+pub struct Ring<T> {
+    /// The slot of the oldest node, overwritten by the next node added to the full layout
+    head: usize,
+
+    /// The callback, if one was set
+    callback: Option<Box<dyn FnMut(T) + Send + Sync>>,
+}
+
+impl<T> Ring<T> {
+    /// Create the state of an empty layout, dropping every evicted node
+    pub const fn new() -> Self {
+        Self {
+            head: 0,
+            callback: None,
+        }
+    }
+
+    /// Returns the slot of the oldest node, which is 0 unless the layout is full
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// Hand every later evicted node to `callback`, replacing the previous callback
+    pub fn set(&mut self, callback: impl FnMut(T) + Send + Sync + 'static) {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// Drop every later evicted node, removing the callback
+    pub fn clear(&mut self) {
+        self.callback = None;
+    }
+
+    /// The oldest node was overwritten in its slot by a new node: hand the evicted node to the
+    /// callback, or drop it if there is none, and move the head to the next slot of a layout
+    /// holding `capacity` nodes
+    pub fn overwritten(&mut self, capacity: usize, node: T) {
+        self.head = (self.head + 1) % capacity;
+        if let Some(callback) = &mut self.callback {
+            callback(node);
+        }
+    }
+
+    /// Nodes were removed from the layout, `before_head` of them from the slots before the oldest
+    /// node, leaving `len` nodes. Returns how far the layout must be rotated to the left to bring
+    /// the oldest node back to the first slot.
+    pub fn removed(&mut self, before_head: usize, len: usize) -> usize {
+        let head = self.head - before_head;
+        self.head = 0;
+        if head < len { head } else { 0 }
+    }
+
+    /// The nodes were reordered or cleared, making their order the order of eviction
+    pub fn reset(&mut self) {
+        self.head = 0;
+    }
+}
+
+impl<T> Default for Ring<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for Ring<T> {
+    fn clone(&self) -> Self {
+        Self {
+            head: self.head,
+            callback: None,
+        }
+    }
+}
+
+impl<T> core::fmt::Debug for Ring<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Ring")
+            .field("head", &self.head)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+// The head is left out, like the other bookkeeping of a layout: layouts holding the same rows in
+// the same slots are equal, matching the layout `Ord` comparing the rows only
+impl<T> PartialEq for Ring<T> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<T> Eq for Ring<T> {}
+
+impl<T> core::hash::Hash for Ring<T> {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
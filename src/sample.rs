@@ -0,0 +1,98 @@
+//! The alias tables of the generated `sample_weighted()`, drawing nodes in proportion to a weight
+
+use rand::Rng;
+
+/// Draws indexes in proportion to their weights in constant time, with Vose's alias method
+///
+/// Building the table takes a single pass over the weights, after which every draw takes one
+/// random index and one random float, regardless of how skewed the weights are.
+///
+/// ```rust
+/// use rand::SeedableRng;
+/// use soaaos::sample::AliasTable;
+///
+/// let table = AliasTable::new([1.0, 0.0, 3.0]).unwrap();
+/// let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+/// let draws: Vec<usize> = (0..1000).map(|_| table.sample(&mut rng)).collect();
+///
+/// assert!(!draws.contains(&1));
+/// assert!(draws.iter().filter(|&&index| index == 2).count() > 600);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AliasTable {
+    /// The probability of keeping each index once it is drawn, instead of taking its alias
+    prob: Vec<f64>,
+
+    /// The index taken instead of each index when it isn't kept
+    alias: Vec<u32>,
+}
+
+impl AliasTable {
+    /// Build the table of the given weights, where index `i` is drawn with the probability of
+    /// `weights[i]` over the sum of the weights. Negative and NaN weights count as 0.
+    ///
+    /// Returns `None` if no weight is positive, or if the weights sum to infinity.
+    pub fn new(weights: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut prob: Vec<f64> = weights
+            .into_iter()
+            .map(|weight| if weight > 0.0 { weight } else { 0.0 })
+            .collect();
+        let total: f64 = prob.iter().sum();
+        if !(total > 0.0 && total.is_finite()) {
+            return None;
+        }
+        let len = u32::try_from(prob.len()).expect("AliasTable index overflows u32");
+
+        // Scale the weights to an average of 1, then pair every index below 1 with an index
+        // above 1 that fills the rest of its bucket
+        let scale = f64::from(len) / total;
+        let mut small = Vec::new();
+        let mut large = Vec::new();
+        for (index, weight) in (0..len).zip(&mut prob) {
+            *weight *= scale;
+            if *weight < 1.0 {
+                small.push(index);
+            } else {
+                large.push(index);
+            }
+        }
+
+        let mut alias: Vec<u32> = (0..len).collect();
+        while let (Some(&less), Some(&more)) = (small.last(), large.last()) {
+            small.pop();
+            alias[less as usize] = more;
+            prob[more as usize] -= 1.0 - prob[less as usize];
+            if prob[more as usize] < 1.0 {
+                large.pop();
+                small.push(more);
+            }
+        }
+
+        // Whatever is left is 1 up to rounding errors
+        for index in small.into_iter().chain(large) {
+            prob[index as usize] = 1.0;
+        }
+
+        Some(Self { prob, alias })
+    }
+
+    /// Returns the number of indexes in the table
+    pub fn len(&self) -> usize {
+        self.prob.len()
+    }
+
+    /// Returns `true` if the table has no indexes, which a built table never does
+    pub fn is_empty(&self) -> bool {
+        self.prob.is_empty()
+    }
+
+    /// Draw an index in proportion to its weight
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        let index = rng.random_range(0..self.prob.len());
+        if rng.random::<f64>() < self.prob[index] {
+            index
+        } else {
+            self.alias[index] as usize
+        }
+    }
+}
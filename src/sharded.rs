@@ -0,0 +1,114 @@
+//! Layouts appended to from many threads through per-thread shards
+//!
+//! A [`Sharded`] layout hands out a [`Shard`] to each appending thread. Rows pushed to a shard are
+//! buffered in the shard, and only merged into the main layout in batches, under a short lock,
+//! by [`Shard::flush`]. This amortizes the contention of high-rate ingestion (events, traces)
+//! over many rows.
+
+use core::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::derived::TableRows;
+
+/// The number of rows buffered in a shard before it is flushed, unless set with
+/// [`Sharded::with_flush_len`]
+const DEFAULT_FLUSH_LEN: usize = 1024;
+
+/// A layout `L` of rows `Row` appended to through per-thread [`Shard`]s
+pub struct Sharded<L, Row> {
+    /// The layout every shard is merged into
+    main: Mutex<L>,
+
+    /// The number of rows buffered by a shard before it flushes itself
+    flush_len: usize,
+
+    _row: PhantomData<fn(Row)>,
+}
+
+impl<L: TableRows<Row>, Row> Sharded<L, Row> {
+    /// Append to the given layout, flushing shards every 1024 rows
+    pub fn new(layout: L) -> Self {
+        Self::with_flush_len(layout, DEFAULT_FLUSH_LEN)
+    }
+
+    /// Append to the given layout, flushing shards every `flush_len` rows
+    pub fn with_flush_len(layout: L, flush_len: usize) -> Self {
+        Self {
+            main: Mutex::new(layout),
+            flush_len: flush_len.max(1),
+            _row: PhantomData,
+        }
+    }
+
+    /// Create a shard buffering rows pushed by the current thread
+    pub fn shard(&self) -> Shard<'_, L, Row> {
+        Shard {
+            sharded: self,
+            rows: Vec::with_capacity(self.flush_len),
+        }
+    }
+
+    /// Lock the main layout, holding every row flushed so far
+    pub fn lock(&self) -> MutexGuard<'_, L> {
+        self.main.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the main layout. Rows still buffered in shards can't exist at this point, since
+    /// every shard borrows the sharded layout and is flushed when dropped.
+    pub fn into_inner(self) -> L {
+        self.main
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Rows buffered by one thread for a [`Sharded`] layout, flushed every `flush_len` rows and
+/// when dropped
+pub struct Shard<'a, L: TableRows<Row>, Row> {
+    sharded: &'a Sharded<L, Row>,
+
+    /// The rows pushed since the last flush
+    rows: Vec<Row>,
+}
+
+impl<L: TableRows<Row>, Row> Shard<'_, L, Row> {
+    /// Buffer a row, flushing the shard once it holds `flush_len` rows
+    pub fn push(&mut self, row: Row) {
+        self.rows.push(row);
+        if self.rows.len() >= self.sharded.flush_len {
+            self.flush();
+        }
+    }
+
+    /// Get the number of rows buffered since the last flush
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Returns `true` if no rows are buffered
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Append every buffered row to the main layout, in the order they were pushed, returning
+    /// their Ids in the main layout
+    pub fn flush(&mut self) -> Vec<L::Id> {
+        if self.rows.is_empty() {
+            return Vec::new();
+        }
+
+        let mut main = self.sharded.lock();
+        let start = main.len();
+        for row in self.rows.drain(..) {
+            main.push_row(row);
+        }
+
+        (start..main.len()).map(L::id).collect()
+    }
+}
+
+impl<L: TableRows<Row>, Row> Drop for Shard<'_, L, Row> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
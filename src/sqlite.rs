@@ -0,0 +1,114 @@
+//! The column types of the generated `to_sqlite()` and `from_sqlite()`, dumping a layout to a
+//! table with a column per field and loading it back through a [`rusqlite::Connection`]
+//!
+//! A field type is stored through its [`Column`] impl, which names the type of its column in
+//! `CREATE TABLE` and converts values with the [`rusqlite`] value types.
+
+pub use rusqlite::types::{FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+pub use rusqlite::{Connection, Error, Row, Statement, ToSql};
+
+/// Quote a table or column name for SQL, so any name can be used
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A field type stored in a column of its own
+pub trait Column: Sized {
+    /// The type of the column holding the values in `CREATE TABLE`
+    const SQL_TYPE: &'static str;
+
+    /// Convert the value to the SQLite value bound to its parameter
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error>;
+
+    /// Read a value from a column, failing if it holds a value of another type
+    fn from_sql(value: ValueRef<'_>) -> FromSqlResult<Self>;
+}
+
+/// Binds a field with the conversion of its [`Column`] impl
+pub struct Param<'a, T>(pub &'a T);
+
+impl<T: Column> ToSql for Param<'_, T> {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        self.0.to_sql()
+    }
+}
+
+/// A field read with the conversion of its [`Column`] impl
+struct Read<T>(T);
+
+impl<T: Column> rusqlite::types::FromSql for Read<T> {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        T::from_sql(value).map(Read)
+    }
+}
+
+/// Read the field at column `index` of `row`
+pub fn get<T: Column>(row: &Row<'_>, index: usize) -> Result<T, Error> {
+    row.get::<_, Read<T>>(index).map(|Read(val)| val)
+}
+
+/// Types stored with the conversions of `rusqlite`, in a column of the given type
+macro_rules! impl_column {
+    ($($ty:ty => $sql_type:literal),* $(,)?) => {
+        $(
+            impl Column for $ty {
+                const SQL_TYPE: &'static str = $sql_type;
+
+                fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+                    ToSql::to_sql(self)
+                }
+
+                fn from_sql(value: ValueRef<'_>) -> FromSqlResult<Self> {
+                    rusqlite::types::FromSql::column_result(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_column!(
+    bool => "INTEGER",
+    u8 => "INTEGER",
+    u16 => "INTEGER",
+    u32 => "INTEGER",
+    i8 => "INTEGER",
+    i16 => "INTEGER",
+    i32 => "INTEGER",
+    i64 => "INTEGER",
+    f32 => "REAL",
+    f64 => "REAL",
+    String => "TEXT",
+);
+
+/// SQLite integers are signed, so a `u64` is stored with the bits of an `i64`: values above
+/// `i64::MAX` read back unchanged, but are negative in SQL
+impl Column for u64 {
+    const SQL_TYPE: &'static str = "INTEGER";
+
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        Ok(ToSqlOutput::from(*self as i64))
+    }
+
+    fn from_sql(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_i64().map(|val| val as u64)
+    }
+}
+
+/// `None` is stored as `NULL`, in a column of the type of `T`
+impl<T: Column> Column for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>, Error> {
+        match self {
+            Some(val) => val.to_sql(),
+            None => Ok(ToSqlOutput::from(rusqlite::types::Null)),
+        }
+    }
+
+    fn from_sql(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Null => Ok(None),
+            value => T::from_sql(value).map(Some),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! The growth diagnostics of the generated `debug_stats()`, kept by every layout with the
+//! `stats` feature, and the heap usage of each column reported by `allocation_report()`
+
+use core::fmt;
+use std::collections::HashMap;
+
+/// A column allocation growing, from `from` to `to` elements, when the layout reached `len` nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrowthEvent {
+    /// The name of the column (the field name, or `"data"` for the rows of an array-of-structs
+    /// layout)
+    pub column: &'static str,
+
+    /// The number of nodes in the layout after the growth
+    pub len: usize,
+
+    /// The capacity before the growth, 0 for the first allocation
+    pub from: usize,
+
+    /// The capacity after the growth
+    pub to: usize,
+}
+
+/// The allocation of a single column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnStats {
+    /// The name of the column
+    pub name: &'static str,
+
+    /// The current capacity of the column, in elements
+    pub capacity: usize,
+
+    /// The number of times the allocation of the column moved to a larger one, not counting the
+    /// first allocation
+    pub reallocations: usize,
+}
+
+/// The shape of a layout over its lifetime, returned by the generated `debug_stats()`
+///
+/// Only the columns with a capacity (stored in a `Vec` or `PageAlignedColumn`) are tracked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugStats {
+    /// The current number of nodes
+    pub len: usize,
+
+    /// The largest number of nodes the layout held at once
+    pub high_water: usize,
+
+    /// Each tracked column, in field order
+    pub columns: Vec<ColumnStats>,
+
+    /// Every growth of a tracked column, in order
+    pub growth: Vec<GrowthEvent>,
+}
+
+impl DebugStats {
+    /// The number of reallocations of every column
+    pub fn reallocations(&self) -> usize {
+        self.columns.iter().map(|column| column.reallocations).sum()
+    }
+}
+
+impl fmt::Display for DebugStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes (high water {})", self.len, self.high_water)?;
+        for column in &self.columns {
+            write!(
+                f,
+                "\n{:<20} capacity {:>10} ({} reallocations)",
+                column.name, column.capacity, column.reallocations
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The growth of a layout, recorded by the generated code after each add
+///
+/// Like the subscribers of a `watch` layout, the tracker doesn't take part in comparing or
+/// hashing layouts.
+#[derive(Clone, Default)]
+pub struct StatsTracker {
+    /// The largest number of nodes seen
+    high_water: usize,
+
+    /// The capacity of each column when last recorded
+    capacities: Vec<usize>,
+
+    /// The number of reallocations of each column
+    reallocations: Vec<usize>,
+
+    /// Every growth of a column
+    growth: Vec<GrowthEvent>,
+}
+
+impl StatsTracker {
+    /// Create a tracker that hasn't recorded anything
+    pub const fn new() -> Self {
+        Self {
+            high_water: 0,
+            capacities: Vec::new(),
+            reallocations: Vec::new(),
+            growth: Vec::new(),
+        }
+    }
+
+    /// Record the current length of the layout and the capacity of each column, named by `names`
+    pub fn record(&mut self, len: usize, names: &[&'static str], capacities: &[usize]) {
+        self.high_water = self.high_water.max(len);
+        self.capacities.resize(capacities.len(), 0);
+        self.reallocations.resize(capacities.len(), 0);
+
+        for (column, &capacity) in capacities.iter().enumerate() {
+            let from = self.capacities[column];
+            if capacity == from {
+                continue;
+            }
+
+            if capacity > from {
+                if from != 0 {
+                    self.reallocations[column] += 1;
+                }
+                self.growth.push(GrowthEvent {
+                    column: names[column],
+                    len,
+                    from,
+                    to: capacity,
+                });
+            }
+            self.capacities[column] = capacity;
+        }
+    }
+
+    /// Returns the stats of the layout, with its current length and the current capacity of each
+    /// column, named by `names`
+    pub fn stats(&self, len: usize, names: &[&'static str], capacities: &[usize]) -> DebugStats {
+        DebugStats {
+            len,
+            high_water: self.high_water.max(len),
+            columns: names
+                .iter()
+                .zip(capacities)
+                .enumerate()
+                .map(|(column, (&name, &capacity))| ColumnStats {
+                    name,
+                    capacity,
+                    reallocations: self.reallocations.get(column).copied().unwrap_or(0),
+                })
+                .collect(),
+            growth: self.growth.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for StatsTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "high water {}", self.high_water)
+    }
+}
+
+impl PartialEq for StatsTracker {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for StatsTracker {}
+
+impl core::hash::Hash for StatsTracker {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+/// The heap memory owned by a column, used by the generated `allocation_report()`
+///
+/// Only the buffers of the column itself are counted, not the heap memory owned by its elements
+/// (such as the contents of `String` elements). Hash and tree maps are estimated from their
+/// capacity or length, since their allocations aren't exposed.
+pub trait HeapSize {
+    /// Returns the number of bytes allocated by the column
+    fn heap_bytes(&self) -> usize;
+}
+
+impl<T> HeapSize for Vec<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>()
+    }
+}
+
+impl<K, V, S> HeapSize for HashMap<K, V, S> {
+    fn heap_bytes(&self) -> usize {
+        // Each bucket also has a control byte
+        self.capacity() * (size_of::<(K, V)>() + 1)
+    }
+}
+
+/// The heap usage of a single column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnAllocation {
+    /// The name of the column (the field name, or `"data"` for the rows of an array-of-structs
+    /// layout)
+    pub name: &'static str,
+
+    /// The number of bytes allocated by the column, see [`HeapSize`]
+    pub bytes: usize,
+}
+
+/// The heap usage of a layout attributed to each of its columns, returned by the generated
+/// `allocation_report()`
+///
+/// Meant to be logged next to a heap profile (such as the ad hoc events of `dhat`), where the
+/// allocations of every column only show up as the same `Vec` growth.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationReport {
+    /// The name of the layout type
+    pub layout: &'static str,
+
+    /// Each column, in field order
+    pub columns: Vec<ColumnAllocation>,
+}
+
+impl AllocationReport {
+    /// The number of bytes allocated by every column
+    pub fn total(&self) -> usize {
+        self.columns.iter().map(|column| column.bytes).sum()
+    }
+}
+
+impl fmt::Display for AllocationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} bytes", self.layout, self.total())?;
+        for column in &self.columns {
+            write!(
+                f,
+                "\n{}.{:<20} {:>10} bytes",
+                self.layout, column.name, column.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
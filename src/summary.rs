@@ -0,0 +1,88 @@
+//! The distribution summaries of a column, returned by the generated `histogram_*(bins)`
+
+/// A number that can be bucketed into a [`Histogram`]
+pub trait Numeric: Copy {
+    /// The value as a float, possibly rounded for 64-bit integers
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_numeric {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Numeric for $ty {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_numeric! {
+    u8, u16, u32, u64, usize,
+    i8, i16, i32, i64, isize,
+    f32, f64,
+}
+
+/// The number of values in each of `bins` equal-width buckets spanning the values of a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// The smallest value, the start of the first bucket (0 for an empty column)
+    pub min: f64,
+
+    /// The largest value, the end of the last bucket (0 for an empty column)
+    pub max: f64,
+
+    /// The number of values in each bucket. Every bucket but the last excludes its end.
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Bucket the values returned by `values`, skipping NaNs. `values` is called twice, once to
+    /// find the bounds of the buckets and once to fill them.
+    ///
+    /// Panics if `bins` is 0.
+    pub fn new<I: Iterator<Item = f64>>(bins: usize, values: impl Fn() -> I) -> Self {
+        assert!(bins > 0, "a histogram needs at least one bin");
+
+        let (min, max) = values()
+            .filter(|val| !val.is_nan())
+            .fold(None, |bounds: Option<(f64, f64)>, val| match bounds {
+                Some((min, max)) => Some((min.min(val), max.max(val))),
+                None => Some((val, val)),
+            })
+            .unwrap_or((0.0, 0.0));
+
+        let mut counts = vec![0; bins];
+        let width = (max - min) / bins as f64;
+        for val in values().filter(|val| !val.is_nan()) {
+            let bin = if width > 0.0 {
+                (((val - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+
+        Self { min, max, counts }
+    }
+
+    /// The width of each bucket
+    pub fn width(&self) -> f64 {
+        (self.max - self.min) / self.counts.len() as f64
+    }
+
+    /// Returns the start and end of the bucket at the given index
+    pub fn bin_range(&self, index: usize) -> (f64, f64) {
+        let width = self.width();
+        (
+            self.min + width * index as f64,
+            self.min + width * (index + 1) as f64,
+        )
+    }
+
+    /// The number of values in every bucket
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+}
@@ -0,0 +1,228 @@
+//! The row versions of `versioned` layouts, used by the generated `version()`,
+//! `current_version()` and `changed_since()`, and the row epochs of `epochs` layouts, used by the
+//! generated `advance_epoch()`, `epoch()` and `changed_since_epoch()`
+
+/// The version of each row of a layout: the value of a layout-wide counter when the row was last
+/// added or written
+///
+/// Rows added since the last mutation are stamped lazily, by the next mutation, so adding nodes
+/// stays as cheap as without versions. Until then, they read as the version that mutation will
+/// give them. Removing a node also stamps every later node, as their Ids now point to other rows.
+/// Like the row index of a `hashcons` layout, the versions don't take part in comparing or
+/// hashing layouts.
+#[derive(Debug, Clone, Default)]
+pub struct RowVersions {
+    /// The version of each stamped row, from the start of the layout
+    versions: Vec<u64>,
+
+    /// The version given to the last stamped rows
+    clock: u64,
+}
+
+impl RowVersions {
+    /// Create the versions of an empty layout
+    pub const fn new() -> Self {
+        Self {
+            versions: Vec::new(),
+            clock: 0,
+        }
+    }
+
+    /// Returns the index of the first row of a layout of `len` rows that isn't stamped yet, if
+    /// any. Rows removed without being seen leave every row unknown.
+    fn unstamped(&self, len: usize) -> Option<usize> {
+        match self.versions.len().cmp(&len) {
+            core::cmp::Ordering::Less => Some(self.versions.len()),
+            core::cmp::Ordering::Equal => None,
+            core::cmp::Ordering::Greater => Some(0),
+        }
+    }
+
+    /// Stamp the rows that aren't stamped yet with a new version
+    fn stamp(&mut self, len: usize) {
+        if let Some(first) = self.unstamped(len) {
+            self.clock += 1;
+            self.versions.truncate(first);
+            self.versions.resize(len, self.clock);
+        }
+    }
+
+    /// Returns the current version of a layout of `len` rows: the version of its last mutation
+    pub fn current(&self, len: usize) -> u64 {
+        self.clock + u64::from(self.unstamped(len).is_some())
+    }
+
+    /// Returns the version of the row at `index` of a layout of `len` rows
+    pub fn get(&self, index: usize, len: usize) -> Option<u64> {
+        if index >= len {
+            return None;
+        }
+
+        match self.unstamped(len) {
+            Some(first) if index >= first => Some(self.clock + 1),
+            _ => Some(self.versions[index]),
+        }
+    }
+
+    /// Returns the indexes of the rows of a layout of `len` rows added or written after
+    /// `version`, in ascending order
+    pub fn changed_since(&self, version: u64, len: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..len).filter(move |&index| self.get(index, len).unwrap() > version)
+    }
+
+    /// Stamp the row at `index` of a layout of `len` rows with a new version, after writing it
+    pub fn changed(&mut self, index: usize, len: usize) {
+        self.stamp(len);
+        self.clock += 1;
+        self.versions[index] = self.clock;
+    }
+
+    /// Stamp every row of a layout of `len` rows with a new version, after moving or writing
+    /// every row
+    pub fn changed_all(&mut self, len: usize) {
+        self.clock += 1;
+        self.versions.clear();
+        self.versions.resize(len, self.clock);
+    }
+
+    /// Forget the row removed from `index`, stamping every later row with a new version
+    pub fn removed(&mut self, index: usize) {
+        if index < self.versions.len() {
+            self.versions.remove(index);
+            self.clock += 1;
+            let clock = self.clock;
+            self.versions[index..].fill(clock);
+        }
+    }
+
+    /// Forget every row, after the layout was cleared
+    pub fn clear(&mut self) {
+        self.versions.clear();
+    }
+}
+
+impl PartialEq for RowVersions {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RowVersions {}
+
+impl core::hash::Hash for RowVersions {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+/// The epoch each row of a layout was last added or written in, where the epoch is a counter
+/// advanced by the layout's `advance_epoch()`, such as once per frame
+///
+/// Rows added since the last mutation are stamped lazily like [`RowVersions`], at the latest when
+/// the epoch is advanced, so they keep the epoch they were added in. Removing a node also stamps
+/// every later node with the current epoch. The epochs don't take part in comparing or hashing
+/// layouts.
+#[derive(Debug, Clone, Default)]
+pub struct RowEpochs {
+    /// The epoch of each stamped row, from the start of the layout
+    epochs: Vec<u32>,
+
+    /// The current epoch, given to the rows written in it
+    current: u32,
+}
+
+impl RowEpochs {
+    /// Create the epochs of an empty layout, in epoch 0
+    pub const fn new() -> Self {
+        Self {
+            epochs: Vec::new(),
+            current: 0,
+        }
+    }
+
+    /// Returns the index of the first row of a layout of `len` rows that isn't stamped yet, if
+    /// any. Rows removed without being seen leave every row unknown.
+    fn unstamped(&self, len: usize) -> Option<usize> {
+        match self.epochs.len().cmp(&len) {
+            core::cmp::Ordering::Less => Some(self.epochs.len()),
+            core::cmp::Ordering::Equal => None,
+            core::cmp::Ordering::Greater => Some(0),
+        }
+    }
+
+    /// Stamp the rows that aren't stamped yet with the current epoch
+    fn stamp(&mut self, len: usize) {
+        if let Some(first) = self.unstamped(len) {
+            self.epochs.truncate(first);
+            self.epochs.resize(len, self.current);
+        }
+    }
+
+    /// Returns the current epoch
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Start the next epoch of a layout of `len` rows, returning it
+    pub fn advance(&mut self, len: usize) -> u32 {
+        self.stamp(len);
+        self.current = self.current.checked_add(1).expect("epoch overflows u32");
+        self.current
+    }
+
+    /// Returns the epoch of the row at `index` of a layout of `len` rows
+    pub fn get(&self, index: usize, len: usize) -> Option<u32> {
+        if index >= len {
+            return None;
+        }
+
+        match self.unstamped(len) {
+            Some(first) if index >= first => Some(self.current),
+            _ => Some(self.epochs[index]),
+        }
+    }
+
+    /// Returns the indexes of the rows of a layout of `len` rows added or written in `epoch` or
+    /// later, in ascending order
+    pub fn changed_since(&self, epoch: u32, len: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..len).filter(move |&index| self.get(index, len).unwrap() >= epoch)
+    }
+
+    /// Stamp the row at `index` of a layout of `len` rows with the current epoch, after writing
+    /// it
+    pub fn changed(&mut self, index: usize, len: usize) {
+        self.stamp(len);
+        self.epochs[index] = self.current;
+    }
+
+    /// Stamp every row of a layout of `len` rows with the current epoch, after moving or writing
+    /// every row
+    pub fn changed_all(&mut self, len: usize) {
+        self.epochs.clear();
+        self.epochs.resize(len, self.current);
+    }
+
+    /// Forget the row removed from `index`, stamping every later row with the current epoch
+    pub fn removed(&mut self, index: usize) {
+        if index < self.epochs.len() {
+            self.epochs.remove(index);
+            let current = self.current;
+            self.epochs[index..].fill(current);
+        }
+    }
+
+    /// Forget every row, after the layout was cleared
+    pub fn clear(&mut self) {
+        self.epochs.clear();
+    }
+}
+
+impl PartialEq for RowEpochs {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for RowEpochs {}
+
+impl core::hash::Hash for RowEpochs {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
@@ -0,0 +1,102 @@
+//! Mutation events of `#[layout(..., watch)]` layouts (requires the `crossbeam` feature)
+//!
+//! Every receiver returned by a layout's `subscribe()` gets an [`Event`] for each mutation made
+//! through the layout's methods, so other threads can react to changes without polling. Writes
+//! made directly to the public columns of a struct-of-arrays layout are not seen.
+
+pub use crossbeam_channel::Receiver;
+use crossbeam_channel::Sender;
+
+/// A mutation of a layout with the Id type `Id`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Event<Id> {
+    /// A node was added with the given Id
+    Added(Id),
+
+    /// The node with the given Id was removed, shifting every later node down by one
+    Removed(Id),
+
+    /// A field of the node with the given Id was written, or borrowed mutably
+    Changed {
+        /// The node written
+        id: Id,
+
+        /// The name of the field written
+        field: &'static str,
+    },
+
+    /// Every node was moved (`reverse`, `rotate_left`, `rotate_right`)
+    Reordered,
+
+    /// Every node was removed (`clear_keep_capacity`, `reset`)
+    Cleared,
+}
+
+/// The senders of the subscribers of a layout
+///
+/// Subscribers belong to a single layout: a clone of the layout starts without subscribers, and
+/// subscribers don't take part in comparing or hashing layouts.
+pub struct Subscribers<Id> {
+    senders: Vec<Sender<Event<Id>>>,
+}
+
+impl<Id: Copy> Subscribers<Id> {
+    /// Create an empty list of subscribers
+    pub const fn new() -> Self {
+        Self {
+            senders: Vec::new(),
+        }
+    }
+
+    /// Add a subscriber, returning the receiver of its events
+    pub fn subscribe(&mut self) -> Receiver<Event<Id>> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// Returns the number of subscribers still listening
+    pub fn len(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// Returns `true` if there are no subscribers
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    /// Send the event to every subscriber, dropping the subscribers whose receiver was dropped
+    pub fn notify(&mut self, event: Event<Id>) {
+        self.senders.retain(|sender| sender.send(event).is_ok());
+    }
+}
+
+impl<Id: Copy> Default for Subscribers<Id> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Id: Copy> Clone for Subscribers<Id> {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<Id> core::fmt::Debug for Subscribers<Id> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} subscribers", self.senders.len())
+    }
+}
+
+impl<Id> PartialEq for Subscribers<Id> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<Id> Eq for Subscribers<Id> {}
+
+impl<Id> core::hash::Hash for Subscribers<Id> {
+    fn hash<H: core::hash::Hasher>(&self, _state: &mut H) {}
+}
@@ -1,8 +1,9 @@
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
+
 // tests/layout_tests.rs
 
 // Import the macro from your crate (assume your crate is named `soaaos`).
 use soaaos::layout;
-use std::error::Error;
 
 //
 // Test for the Struct-of-Arrays (SOA) layout.
@@ -143,3 +144,213 @@ fn diff_method() {
     // Expect the diff output to mention the differing field (e.g., "field2").
     assert!(diff_str.contains("field2"));
 }
+
+//
+// Test gather and scatter on both layouts.
+//
+#[test]
+fn gather_and_scatter() {
+    let mut soa = SoaTestsLayout::new();
+    let mut aos = AosTestsLayout::new();
+    let mut soa_ids = Vec::new();
+    let mut aos_ids = Vec::new();
+    for i in 0..4 {
+        soa_ids.push(soa.add(SoaTest {
+            field1: i,
+            field2: format!("s{i}"),
+        }));
+        aos_ids.push(aos.add(AosTest {
+            value: i as i32,
+            text: format!("a{i}"),
+        }));
+    }
+
+    // Gather keeps only the requested rows, in the requested order.
+    let soa_subset = soa.gather(&[soa_ids[3], soa_ids[1]]);
+    assert_eq!(soa_subset.len(), 2);
+    assert_eq!(soa_subset.field1().copied().collect::<Vec<_>>(), vec![3, 1]);
+    assert_eq!(soa_subset.field2().collect::<Vec<_>>(), vec!["s3", "s1"]);
+
+    let aos_subset = aos.gather(&[aos_ids[0], aos_ids[2], aos_ids[0]]);
+    assert_eq!(
+        aos_subset.value().copied().collect::<Vec<_>>(),
+        vec![0, 2, 0]
+    );
+
+    // Scatter writes only the given field.
+    soa.scatter_field2(&[(soa_ids[0], "x".to_string()), (soa_ids[2], "y".to_string())])
+        .unwrap();
+    assert_eq!(soa.field2().collect::<Vec<_>>(), vec!["x", "s1", "y", "s3"]);
+    assert_eq!(soa.field1().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+
+    aos.scatter_value(&[(aos_ids[1], 10), (aos_ids[3], 30)])
+        .unwrap();
+    assert_eq!(aos.value().copied().collect::<Vec<_>>(), vec![0, 10, 2, 30]);
+
+    // An out of bounds id rejects the whole batch.
+    assert!(
+        soa.scatter_field1(&[(soa_ids[0], 7), (SoaTestId(9), 9)])
+            .is_err()
+    );
+    assert_eq!(soa.get_field1(soa_ids[0]).unwrap(), &0);
+    assert!(aos.scatter_value(&[(AosTestId(4), 1)]).is_err());
+}
+
+//
+// Test the cursor on both layouts.
+//
+#[test]
+fn cursor_walk_and_mutate() {
+    let mut soa = SoaTestsLayout::new();
+    let mut aos = AosTestsLayout::new();
+    for i in 0..3 {
+        soa.add(SoaTest {
+            field1: i,
+            field2: format!("s{i}"),
+        });
+        aos.add(AosTest {
+            value: i as i32,
+            text: format!("a{i}"),
+        });
+    }
+
+    let mut cursor = soa.cursor();
+    assert_eq!(cursor.id(), SoaTestId(0));
+    assert_eq!(cursor.peek().unwrap().field1, &0);
+    assert!(cursor.advance());
+    *cursor.peek_mut().unwrap().field1 = 100;
+    assert!(cursor.advance());
+    assert_eq!(cursor.peek().unwrap().field2, "s2");
+    assert!(!cursor.advance());
+    assert!(cursor.is_done());
+    assert!(cursor.peek().is_none());
+    cursor.seek(SoaTestId(0));
+    assert_eq!(cursor.peek().unwrap().field2, "s0");
+    assert_eq!(soa.field1().copied().collect::<Vec<_>>(), vec![0, 100, 2]);
+
+    let mut cursor = aos.cursor();
+    cursor.seek(AosTestId(2));
+    cursor.peek_mut().unwrap().text.push('!');
+    assert!(!cursor.advance());
+    assert_eq!(aos.get(AosTestId(2)).unwrap().text, "a2!");
+    assert!(aos.get(AosTestId(3)).is_err());
+}
+
+//
+// Test fallible construction from an iterator.
+//
+#[test]
+fn try_from_iter() {
+    let rows = ["1", "2", "3"].map(|text| {
+        text.parse::<u32>().map(|field1| SoaTest {
+            field1,
+            field2: text.to_string(),
+        })
+    });
+    let layout = SoaTestsLayout::try_from_iter(rows).unwrap();
+    assert_eq!(layout.len(), 3);
+    assert_eq!(layout.field1().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+    let rows = ["4", "five", "6"].map(|text| {
+        text.parse::<i32>().map(|value| AosTest {
+            value,
+            text: text.to_string(),
+        })
+    });
+    assert!(AosTestsLayout::try_from_iter(rows).is_err());
+}
+
+//
+// Test dynamic field access by name.
+//
+#[test]
+fn dynamic_field_access() {
+    let mut soa = SoaTestsLayout::new();
+    let id = soa.add(SoaTest {
+        field1: 7,
+        field2: "dyn".to_string(),
+    });
+
+    let field1 = soa.get_field_dyn("field1", id).unwrap();
+    assert_eq!(field1.downcast_ref::<u32>(), Some(&7));
+    assert!(soa.get_field_dyn("field3", id).is_none());
+    assert!(soa.get_field_dyn("field1", SoaTestId(1)).is_none());
+
+    let columns = soa.columns_dyn().collect::<Vec<_>>();
+    assert_eq!(
+        columns.iter().map(|c| c.name()).collect::<Vec<_>>(),
+        vec!["field1", "field2"]
+    );
+    assert_eq!(columns[1].type_name(), std::any::type_name::<String>());
+    assert_eq!(columns[1].len(), 1);
+    let field2 = columns[1].get(id).unwrap();
+    assert_eq!(field2.downcast_ref::<String>().unwrap(), "dyn");
+
+    let mut aos = AosTestsLayout::new();
+    let id = aos.add(AosTest {
+        value: -1,
+        text: "aos".to_string(),
+    });
+    assert_eq!(
+        aos.get_field_dyn("value", id)
+            .unwrap()
+            .downcast_ref::<i32>(),
+        Some(&-1)
+    );
+    assert_eq!(aos.columns_dyn().count(), 2);
+}
+
+//
+// Test the generated builder.
+//
+#[test]
+fn builder() {
+    let mut soa = SoaTestsLayout::new();
+    let id = SoaTestBuilder::new()
+        .field2("built".to_string())
+        .field1(5)
+        .build_into(&mut soa)
+        .unwrap();
+    assert_eq!(soa.get_field1(id).unwrap(), &5);
+    assert_eq!(soa.get_field2(id).unwrap(), "built");
+
+    // Every field must be provided
+    let err = SoaTestBuilder::new().field1(1).build().unwrap_err();
+    assert!(matches!(err, SoaTestsError::Missing_field2));
+    assert_eq!(err.to_string(), "Missing: field2");
+    assert_eq!(soa.len(), 1);
+
+    let mut aos = AosTestsLayout::new();
+    let node = AosTestBuilder::new()
+        .value(1)
+        .text("a".into())
+        .build()
+        .unwrap();
+    aos.add(node);
+    assert!(AosTestBuilder::new().build_into(&mut aos).is_err());
+    assert_eq!(aos.len(), 1);
+}
+
+#[test]
+fn inline_soa() {
+    let mut scores = soaaos::soa! {
+        name: String,
+        #[soa(default = 1)]
+        level: u8,
+        score: u32,
+    };
+
+    let alice = scores.add_partial("alice".to_string(), 10);
+    let bob = scores.add_partial("bob".to_string(), 7);
+    scores.set_level(bob, 3).unwrap();
+
+    assert_eq!(scores.len(), 2);
+    assert_eq!(scores.get_name(alice).unwrap(), "alice");
+    assert_eq!(scores.level().copied().collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(scores.score().sum::<u32>(), 17);
+
+    // Fields don't need to be `Eq` or `Hash`
+    let mut points = soaaos::soa! { x: f32, y: u8 };
+    let point = points.add_partial(0.5, 2);
+    assert_eq!(points.get_x(point).unwrap(), &0.5);
+}
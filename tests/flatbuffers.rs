@@ -0,0 +1,179 @@
+//! Round trips of layouts through the `flatbuffers` crate, reading and building the root table of
+//! their `FLATBUFFERS_SCHEMA`
+
+use flatbuffers::{
+    FlatBufferBuilder, Follow, ForwardsUOffset, Table, Vector, Verifiable, Verifier,
+};
+use soaaos::layout;
+
+#[layout(soa, flatbuffers)]
+#[derive(Debug, Clone, PartialEq)]
+struct Reading {
+    sensor: u8,
+    offset: i16,
+    time: u64,
+    value: f32,
+    total: f64,
+    valid: bool,
+    unit: String,
+    previous: ReadingId,
+}
+
+/// The root table of `ReadingsLayout::FLATBUFFERS_SCHEMA`, as `flatc --rust` generates it
+#[derive(Clone, Copy)]
+struct ReadingsTable<'a> {
+    table: Table<'a>,
+}
+
+impl<'a> ReadingsTable<'a> {
+    const SENSOR: u16 = 4;
+    const OFFSET: u16 = 6;
+    const TIME: u16 = 8;
+    const VALUE: u16 = 10;
+    const TOTAL: u16 = 12;
+    const VALID: u16 = 14;
+    const UNIT: u16 = 16;
+    const PREVIOUS: u16 = 18;
+
+    /// Returns the vector of the field at `slot`, if present
+    fn vector<T: Follow<'a> + 'a>(&self, slot: u16) -> Option<Vector<'a, T>> {
+        // SAFETY: the buffer was verified with the type of every field
+        unsafe { self.table.get::<ForwardsUOffset<Vector<'a, T>>>(slot, None) }
+    }
+}
+
+impl<'a> Follow<'a> for ReadingsTable<'a> {
+    type Inner = Self;
+
+    unsafe fn follow(buf: &'a [u8], loc: usize) -> Self {
+        Self {
+            // SAFETY: forwarded from the caller
+            table: unsafe { Table::new(buf, loc) },
+        }
+    }
+}
+
+impl Verifiable for ReadingsTable<'_> {
+    fn run_verifier(
+        verifier: &mut Verifier,
+        pos: usize,
+    ) -> Result<(), flatbuffers::InvalidFlatbuffer> {
+        verifier
+            .visit_table(pos)?
+            .visit_field::<ForwardsUOffset<Vector<u8>>>("sensor", Self::SENSOR, false)?
+            .visit_field::<ForwardsUOffset<Vector<i16>>>("offset", Self::OFFSET, false)?
+            .visit_field::<ForwardsUOffset<Vector<u64>>>("time", Self::TIME, false)?
+            .visit_field::<ForwardsUOffset<Vector<f32>>>("value", Self::VALUE, false)?
+            .visit_field::<ForwardsUOffset<Vector<f64>>>("total", Self::TOTAL, false)?
+            .visit_field::<ForwardsUOffset<Vector<bool>>>("valid", Self::VALID, false)?
+            .visit_field::<ForwardsUOffset<Vector<ForwardsUOffset<&str>>>>(
+                "unit",
+                Self::UNIT,
+                false,
+            )?
+            .visit_field::<ForwardsUOffset<Vector<u32>>>("previous", Self::PREVIOUS, false)?
+            .finish();
+        Ok(())
+    }
+}
+
+/// The reading at `index`, spanning the range of each type
+fn reading(index: u32) -> Reading {
+    Reading {
+        sensor: index as u8,
+        offset: -(index as i16) * 100,
+        time: u64::MAX - u64::from(index),
+        value: index as f32 / 3.0,
+        total: -f64::from(index) * 1e10,
+        valid: index.is_multiple_of(3),
+        unit: ["C", "", "hPa"][index as usize % 3].to_string(),
+        previous: ReadingId(index.saturating_sub(1)),
+    }
+}
+
+#[test]
+fn test_flatbuffers_crate_reads_layout() {
+    let mut readings = ReadingsLayout::new();
+    for index in 0..300 {
+        readings.add(reading(index));
+    }
+    let bytes = readings.to_flatbuffer();
+
+    let table = flatbuffers::root::<ReadingsTable>(&bytes).unwrap();
+    let sensor = table.vector::<u8>(ReadingsTable::SENSOR).unwrap();
+    let offset = table.vector::<i16>(ReadingsTable::OFFSET).unwrap();
+    let time = table.vector::<u64>(ReadingsTable::TIME).unwrap();
+    let value = table.vector::<f32>(ReadingsTable::VALUE).unwrap();
+    let total = table.vector::<f64>(ReadingsTable::TOTAL).unwrap();
+    let valid = table.vector::<bool>(ReadingsTable::VALID).unwrap();
+    let unit = table
+        .vector::<ForwardsUOffset<&str>>(ReadingsTable::UNIT)
+        .unwrap();
+    let previous = table.vector::<u32>(ReadingsTable::PREVIOUS).unwrap();
+
+    assert_eq!(sensor.len(), 300);
+    for index in 0..300 {
+        let expected = reading(index as u32);
+        assert_eq!(sensor.get(index), expected.sensor);
+        assert_eq!(offset.get(index), expected.offset);
+        assert_eq!(time.get(index), expected.time);
+        assert_eq!(value.get(index), expected.value);
+        assert_eq!(total.get(index), expected.total);
+        assert_eq!(valid.get(index), expected.valid);
+        assert_eq!(unit.get(index), expected.unit);
+        assert_eq!(previous.get(index), expected.previous.0);
+    }
+}
+
+#[test]
+fn test_flatbuffers_crate_builds_layout() {
+    let expected: Vec<Reading> = (0..300).map(reading).collect();
+
+    // Built back to front, so the vectors come before the table and the table points backwards to
+    // them, unlike the buffers of `to_flatbuffer()`
+    let mut builder = FlatBufferBuilder::new();
+    let units: Vec<_> = expected
+        .iter()
+        .map(|reading| builder.create_string(&reading.unit))
+        .collect();
+    let unit = builder.create_vector(&units);
+    let sensor = builder.create_vector(&expected.iter().map(|r| r.sensor).collect::<Vec<_>>());
+    let offset = builder.create_vector(&expected.iter().map(|r| r.offset).collect::<Vec<_>>());
+    let time = builder.create_vector(&expected.iter().map(|r| r.time).collect::<Vec<_>>());
+    let value = builder.create_vector(&expected.iter().map(|r| r.value).collect::<Vec<_>>());
+    let total = builder.create_vector(&expected.iter().map(|r| r.total).collect::<Vec<_>>());
+    let valid = builder.create_vector(&expected.iter().map(|r| r.valid).collect::<Vec<_>>());
+    let previous =
+        builder.create_vector(&expected.iter().map(|r| r.previous.0).collect::<Vec<_>>());
+
+    let start = builder.start_table();
+    builder.push_slot_always(ReadingsTable::SENSOR, sensor);
+    builder.push_slot_always(ReadingsTable::OFFSET, offset);
+    builder.push_slot_always(ReadingsTable::TIME, time);
+    builder.push_slot_always(ReadingsTable::VALUE, value);
+    builder.push_slot_always(ReadingsTable::TOTAL, total);
+    builder.push_slot_always(ReadingsTable::VALID, valid);
+    builder.push_slot_always(ReadingsTable::UNIT, unit);
+    builder.push_slot_always(ReadingsTable::PREVIOUS, previous);
+    let root = builder.end_table(start);
+    builder.finish_minimal(root);
+
+    let mut readings = ReadingsLayout::new();
+    for reading in expected {
+        readings.add(reading);
+    }
+    assert_eq!(
+        ReadingsLayout::from_flatbuffer(builder.finished_data()).unwrap(),
+        readings
+    );
+
+    // An empty table is an empty layout
+    let mut builder = FlatBufferBuilder::new();
+    let start = builder.start_table();
+    let root = builder.end_table(start);
+    builder.finish_minimal(root);
+    assert_eq!(
+        ReadingsLayout::from_flatbuffer(builder.finished_data()).unwrap(),
+        ReadingsLayout::new()
+    );
+}
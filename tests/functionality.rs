@@ -1,5 +1,7 @@
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
+#![allow(clippy::useless_conversion, clippy::clone_on_copy)]
+
 use soaaos::layout;
-use std::error::Error;
 
 #[test]
 fn test_soa() {
@@ -199,3 +201,5432 @@ fn test_soa_with_generics() {
     insta::assert_debug_snapshot!(nodes_soa);
     insta::assert_debug_snapshot!(nodes_aos);
 }
+
+#[test]
+fn test_reverse_and_rotate() {
+    #[layout("soa")]
+    #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u8,
+        arg1: u16,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        arg1: u16,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for i in 0..5 {
+        nodes_soa.add(NodeSoa {
+            op: i,
+            arg1: u16::from(i * 10),
+        });
+        nodes_aos.add(NodeAos {
+            op: i,
+            arg1: u16::from(i * 10),
+        });
+    }
+
+    nodes_soa.reverse();
+    nodes_aos.reverse();
+    assert_eq!(
+        nodes_soa.op().copied().collect::<Vec<_>>(),
+        vec![4, 3, 2, 1, 0]
+    );
+    assert_eq!(
+        nodes_soa.arg1().copied().collect::<Vec<_>>(),
+        vec![40, 30, 20, 10, 0]
+    );
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+
+    nodes_soa.rotate_left(2);
+    nodes_aos.rotate_left(2);
+    assert_eq!(
+        nodes_soa.op().copied().collect::<Vec<_>>(),
+        vec![2, 1, 0, 4, 3]
+    );
+    assert_eq!(
+        nodes_soa.arg1().collect::<Vec<_>>(),
+        nodes_aos.arg1().collect::<Vec<_>>()
+    );
+
+    nodes_soa.rotate_right(1);
+    nodes_aos.rotate_right(1);
+    assert_eq!(
+        nodes_soa.op().copied().collect::<Vec<_>>(),
+        vec![3, 2, 1, 0, 4]
+    );
+    assert_eq!(
+        nodes_soa.arg1().copied().collect::<Vec<_>>(),
+        vec![30, 20, 10, 0, 40]
+    );
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_iter_cloned_and_to_rows() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u8,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        name: String,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for i in 0..3 {
+        nodes_soa.add(NodeSoa {
+            op: i,
+            name: format!("node{i}"),
+        });
+        nodes_aos.add(NodeAos {
+            op: i,
+            name: format!("node{i}"),
+        });
+    }
+
+    let rows = nodes_soa.to_rows();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(
+        rows[1],
+        NodeSoa {
+            op: 1,
+            name: "node1".to_string()
+        }
+    );
+    assert_eq!(nodes_soa.iter_cloned().collect::<Vec<_>>(), rows);
+
+    let rows = nodes_aos.to_rows();
+    assert_eq!(
+        rows[2],
+        NodeAos {
+            op: 2,
+            name: "node2".to_string()
+        }
+    );
+    assert_eq!(nodes_aos.iter_cloned().collect::<Vec<_>>(), rows);
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn test_stream() {
+    use futures::StreamExt;
+
+    #[layout("soa")]
+    #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u8,
+        arg1: u16,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        arg1: u16,
+    }
+
+    futures::executor::block_on(async {
+        let nodes = (0..3).map(|i| NodeSoa {
+            op: i,
+            arg1: u16::from(i * 10),
+        });
+        let nodes_soa = NodeSoasLayout::from_stream(futures::stream::iter(nodes)).await;
+        assert_eq!(nodes_soa.len(), 3);
+
+        let ops = nodes_soa
+            .stream()
+            .map(|node| *node.op)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(ops, vec![0, 1, 2]);
+
+        let nodes = nodes_soa.stream().map(|node| NodeAos {
+            op: *node.op,
+            arg1: *node.arg1,
+        });
+        let nodes_aos = NodeAossLayout::from_stream(nodes).await;
+        assert_eq!(
+            nodes_soa.arg1().collect::<Vec<_>>(),
+            nodes_aos.arg1().collect::<Vec<_>>()
+        );
+    });
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_roundtrip_and_migration() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u8,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        name: String,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    for i in 0..3 {
+        nodes_soa.add(NodeSoa {
+            op: i,
+            name: format!("node{i}"),
+        });
+    }
+
+    // Both layouts share the same columnar format
+    let json = serde_json::to_string(&nodes_soa).unwrap();
+    insta::assert_snapshot!(json);
+    let nodes_aos: NodeAossLayout = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&nodes_aos).unwrap(), json);
+    assert_eq!(
+        serde_json::from_str::<NodeSoasLayout>(&json).unwrap(),
+        nodes_soa
+    );
+
+    // Data saved with an older definition: `name` used to be `label` and `op` didn't exist
+    let old = r#"{"label":["a","b"],"removed":[1,2]}"#;
+    assert!(serde_json::from_str::<NodeSoasLayout>(old).is_err());
+
+    struct FromV1;
+    impl NodeSoasMigration for FromV1 {
+        fn rename_field(name: &str) -> Option<&'static str> {
+            (name == "label").then_some("name")
+        }
+
+        fn default_op() -> Option<u8> {
+            Some(0xff)
+        }
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_str(old);
+    let migrated = NodeSoasLayout::deserialize_with::<FromV1, _>(&mut deserializer).unwrap();
+    assert_eq!(migrated.op().copied().collect::<Vec<_>>(), vec![0xff, 0xff]);
+    assert_eq!(migrated.name().collect::<Vec<_>>(), vec!["a", "b"]);
+
+    // Columns must agree on the number of nodes
+    let mismatched = r#"{"op":[1],"name":["a","b"]}"#;
+    assert!(serde_json::from_str::<NodeAossLayout>(mismatched).is_err());
+}
+
+#[test]
+fn test_schema_hash() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    #[allow(dead_code)]
+    struct NodeSoa {
+        op: u8,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    #[allow(dead_code)]
+    struct NodeAos {
+        op: u8,
+        name: String,
+    }
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeWide {
+        op: u16,
+        name: String,
+    }
+
+    // The hash only depends on the field names and types
+    assert_eq!(NodeSoasLayout::SCHEMA_HASH, NodeAossLayout::SCHEMA_HASH);
+    assert_ne!(NodeSoasLayout::SCHEMA_HASH, NodeWidesLayout::SCHEMA_HASH);
+
+    #[cfg(feature = "serde")]
+    {
+        let mut nodes = NodeWidesLayout::new();
+        nodes.add(NodeWide {
+            op: 1,
+            name: "wide".to_string(),
+        });
+        let json = serde_json::to_string(&nodes).unwrap();
+        assert!(json.contains(&NodeWidesLayout::SCHEMA_HASH.to_string()));
+
+        let err = serde_json::from_str::<NodeSoasLayout>(&json).unwrap_err();
+        assert!(err.to_string().contains("schema hash mismatch"));
+        assert!(serde_json::from_str::<NodeWidesLayout>(&json).is_ok());
+    }
+}
+
+#[test]
+fn test_push_with() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    #[allow(dead_code)]
+    struct NodeSoa {
+        op: u8,
+        name: String,
+        regs: [u64; 32],
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        name: String,
+        regs: [u64; 32],
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for i in 0..3 {
+        nodes_soa.push_with(|node| {
+            *node.op = i;
+            node.name.push_str("node");
+            node.regs[usize::from(i)] = u64::from(i) + 1;
+        });
+        nodes_aos.push_with(|node| {
+            *node.op = i;
+            node.name.push_str("node");
+            node.regs[usize::from(i)] = u64::from(i) + 1;
+        });
+    }
+
+    let id = NodeSoaId(2);
+    assert_eq!(nodes_soa.get_op(id).unwrap(), &2);
+    assert_eq!(nodes_soa.get_name(id).unwrap(), "node");
+    assert_eq!(nodes_soa.get_regs(id).unwrap()[2], 3);
+    assert_eq!(nodes_soa.get_regs(id).unwrap()[0], 0);
+    assert_eq!(
+        nodes_soa.regs().collect::<Vec<_>>(),
+        nodes_aos.regs().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_field_defaults() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u8,
+        #[soa(default = 0xff)]
+        arg1: u16,
+        #[soa(default)]
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        #[soa(default = 0xff)]
+        arg1: u16,
+        #[soa(default)]
+        name: String,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    nodes_soa.add_partial(1);
+    nodes_aos.add_partial(1);
+
+    // Only the fields without a default are required by the builder
+    NodeSoaBuilder::new()
+        .op(2)
+        .name("two".to_string())
+        .build_into(&mut nodes_soa)
+        .unwrap();
+    NodeAosBuilder::new()
+        .op(2)
+        .name("two".to_string())
+        .build_into(&mut nodes_aos)
+        .unwrap();
+    assert!(NodeSoaBuilder::new().arg1(1).build().is_err());
+
+    nodes_soa.push_with(|node| *node.op = 3);
+    nodes_aos.push_with(|node| *node.op = 3);
+
+    insta::assert_debug_snapshot!(nodes_soa);
+    assert_eq!(
+        nodes_soa
+            .to_rows()
+            .into_iter()
+            .map(|n| (n.op, n.arg1, n.name))
+            .collect::<Vec<_>>(),
+        nodes_aos
+            .to_rows()
+            .into_iter()
+            .map(|n| (n.op, n.arg1, n.name))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_lazy_column() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    #[allow(dead_code)]
+    struct NodeSoa {
+        op: u8,
+        #[soa(lazy, default)]
+        visited: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    #[allow(dead_code)]
+    struct NodeAos {
+        op: u8,
+        #[soa(lazy, default)]
+        visited: u32,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for i in 0..4 {
+        nodes_soa.add_partial(i);
+        nodes_aos.add_partial(i);
+    }
+
+    // Nothing is allocated until a value is written
+    assert!(!nodes_soa.visited.is_materialized());
+    assert_eq!(nodes_soa.get_visited(NodeSoaId(3)).unwrap(), &0);
+    assert!(nodes_soa.get_visited(NodeSoaId(4)).is_err());
+    nodes_soa.reverse();
+    nodes_soa.rotate_left(1);
+    assert!(!nodes_soa.visited.is_materialized());
+
+    *nodes_soa.get_visited_mut(NodeSoaId(1)).unwrap() += 1;
+    nodes_aos.reverse();
+    nodes_aos.rotate_left(1);
+    *nodes_aos.get_visited_mut(NodeAosId(1)).unwrap() += 1;
+    assert!(nodes_soa.visited.is_materialized());
+
+    assert_eq!(
+        nodes_soa.visited().collect::<Vec<_>>(),
+        nodes_aos.visited().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+    insta::assert_debug_snapshot!(nodes_soa);
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Weighted {
+        op: u8,
+        #[soa(lazy, default = 1)]
+        weight: u32,
+    }
+
+    // Rows holding the default of the field don't allocate the column
+    let mut weighted = WeightedsLayout::new();
+    weighted.add_partial(0);
+    weighted.add(Weighted { op: 1, weight: 1 });
+    assert!(!weighted.weight.is_materialized());
+    assert_eq!(weighted.get_weight(WeightedId(0)).unwrap(), &1);
+
+    weighted.add(Weighted { op: 2, weight: 0 });
+    assert!(weighted.weight.is_materialized());
+    assert_eq!(
+        weighted.weight().copied().collect::<Vec<_>>(),
+        vec![1, 1, 0]
+    );
+
+    let columns = weighted.into_columns();
+    assert!(
+        !WeightedsLayout::from_columns(WeightedsColumns {
+            op: columns.op,
+            weight: vec![1, 1, 1],
+        })
+        .weight
+        .is_materialized()
+    );
+}
+
+#[test]
+fn test_sparse_column() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u8,
+        #[soa(sparse, default)]
+        comment: Option<String>,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u8,
+        #[soa(sparse, default)]
+        comment: Option<String>,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for i in 0..100 {
+        let comment = (i % 40 == 0).then(|| format!("comment {i}"));
+        nodes_soa.add(NodeSoa {
+            op: i,
+            comment: comment.clone(),
+        });
+        nodes_aos.add(NodeAos { op: i, comment });
+    }
+
+    // Only the rows with a comment take an entry
+    assert_eq!(nodes_soa.comment.len(), 100);
+    assert_eq!(nodes_soa.comment.populated(), 3);
+    assert_eq!(nodes_soa.get_comment(NodeSoaId(1)).unwrap(), &None);
+    assert_eq!(
+        nodes_soa.get_comment(NodeSoaId(80)).unwrap().as_deref(),
+        Some("comment 80")
+    );
+
+    *nodes_soa.get_comment_mut(NodeSoaId(7)).unwrap() = Some("seven".to_string());
+    *nodes_aos.get_comment_mut(NodeAosId(7)).unwrap() = Some("seven".to_string());
+    assert_eq!(nodes_soa.comment.populated(), 4);
+
+    nodes_soa.reverse();
+    nodes_aos.reverse();
+    nodes_soa.rotate_right(3);
+    nodes_aos.rotate_right(3);
+
+    assert_eq!(
+        nodes_soa.comment().collect::<Vec<_>>(),
+        nodes_aos.comment().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+
+    let subset = nodes_soa.gather(&[NodeSoaId(0), NodeSoaId(5)]);
+    assert_eq!(subset.comment.populated(), 0);
+}
+
+#[test]
+fn test_delta_column() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        #[soa(delta)]
+        addr: u64,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        #[soa(delta)]
+        addr: u64,
+        name: String,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for i in 0..200_u64 {
+        let addr = 0x4000_0000 + i * 4;
+        nodes_soa.add(NodeSoa {
+            addr,
+            name: format!("node{i}"),
+        });
+        nodes_aos.add(NodeAos {
+            addr,
+            name: format!("node{i}"),
+        });
+    }
+
+    // One byte per delta, apart from the first element of each block
+    assert!(nodes_soa.addr.encoded_len() < 200);
+    assert_eq!(
+        nodes_soa.get_addr(NodeSoaId(130)).unwrap(),
+        0x4000_0000 + 130 * 4
+    );
+    assert_eq!(nodes_soa.get(NodeSoaId(3)).unwrap().addr, 0x4000_000c);
+
+    // Writes re-encode the block, including going backwards
+    nodes_soa.set_addr(NodeSoaId(70), 7).unwrap();
+    nodes_aos.set_addr(NodeAosId(70), 7).unwrap();
+    nodes_soa.set_addr(NodeSoaId(199), u64::MAX).unwrap();
+    nodes_aos.set_addr(NodeAosId(199), u64::MAX).unwrap();
+    assert!(nodes_soa.set_addr(NodeSoaId(200), 0).is_err());
+    *nodes_soa.get_mut(NodeSoaId(70)).unwrap().name = "seventy".to_string();
+    *nodes_aos.get_mut(NodeAosId(70)).unwrap().name = "seventy".to_string();
+    nodes_soa.add(NodeSoa {
+        addr: 1,
+        name: "last".to_string(),
+    });
+    nodes_aos.add(NodeAos {
+        addr: 1,
+        name: "last".to_string(),
+    });
+
+    nodes_soa.rotate_left(5);
+    nodes_aos.rotate_left(5);
+
+    assert_eq!(
+        nodes_soa.addr().collect::<Vec<_>>(),
+        nodes_aos.addr().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.iter().map(|node| node.addr).collect::<Vec<_>>(),
+        (0..nodes_soa.len())
+            .map(|i| nodes_soa.get_addr(NodeSoaId(i as u32)).unwrap())
+            .collect::<Vec<_>>()
+    );
+
+    let mut scratch = Vec::new();
+    let decoded = nodes_soa.addr.decode_into(&mut scratch);
+    assert_eq!(decoded, nodes_aos.addr().collect::<Vec<_>>());
+    assert!(nodes_soa.get_field_dyn("addr", NodeSoaId(0)).is_none());
+
+    let subset = nodes_soa.gather(&[NodeSoaId(65), NodeSoaId(0)]);
+    assert_eq!(subset.addr().collect::<Vec<_>>(), vec![7, 0x4000_0014]);
+}
+
+#[test]
+fn test_rle_column() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        #[soa(rle)]
+        class: u8,
+        op: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        #[soa(rle)]
+        class: u8,
+        op: u32,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for op in 0..100 {
+        let class = (op / 25) as u8;
+        nodes_soa.add(NodeSoa { class, op });
+        nodes_aos.add(NodeAos { class, op });
+    }
+
+    assert_eq!(nodes_soa.class.run_count(), 4);
+    assert_eq!(nodes_soa.get_class(NodeSoaId(30)).unwrap(), &1);
+    assert_eq!(
+        nodes_soa.class_runs().collect::<Vec<_>>(),
+        vec![(&0, 25), (&1, 25), (&2, 25), (&3, 25)]
+    );
+
+    // Splitting a run, then merging it back
+    nodes_soa.set_class(NodeSoaId(10), 7).unwrap();
+    nodes_aos.set_class(NodeAosId(10), 7).unwrap();
+    assert_eq!(nodes_soa.class.run_count(), 6);
+    nodes_soa.set_class(NodeSoaId(10), 0).unwrap();
+    nodes_aos.set_class(NodeAosId(10), 0).unwrap();
+    assert_eq!(nodes_soa.class.run_count(), 4);
+
+    // Joining two runs by overwriting the boundary
+    nodes_soa.set_class(NodeSoaId(50), 1).unwrap();
+    nodes_aos.set_class(NodeAosId(50), 1).unwrap();
+    nodes_soa.set_class(NodeSoaId(99), 9).unwrap();
+    nodes_aos.set_class(NodeAosId(99), 9).unwrap();
+    assert!(nodes_soa.set_class(NodeSoaId(100), 0).is_err());
+
+    nodes_soa.rotate_left(40);
+    nodes_aos.rotate_left(40);
+    nodes_soa.reverse();
+    nodes_aos.reverse();
+
+    assert_eq!(
+        nodes_soa.class_runs().collect::<Vec<_>>(),
+        nodes_aos.class_runs().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.class().collect::<Vec<_>>(),
+        nodes_aos.class().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+    assert_eq!(nodes_soa.class.run_count(), 6);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_column() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u32,
+        #[soa(zstd)]
+        disasm: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u32,
+        #[soa(zstd)]
+        disasm: String,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for op in 0..3000 {
+        let disasm = format!("mov rax, qword ptr [rbp - {}]", op % 16);
+        nodes_soa.add(NodeSoa {
+            op,
+            disasm: disasm.clone(),
+        });
+        nodes_aos.add(NodeAos { op, disasm });
+    }
+
+    // The two full blocks are compressed, the rest is kept as is
+    let raw_len: usize = nodes_aos.disasm().map(|disasm| disasm.len()).sum();
+    assert!(nodes_soa.disasm.compressed_len() < raw_len / 10);
+    assert_eq!(
+        nodes_soa.get_disasm(NodeSoaId(1500)).unwrap(),
+        "mov rax, qword ptr [rbp - 12]"
+    );
+    assert_eq!(
+        nodes_soa.get_disasm(NodeSoaId(2999)).unwrap(),
+        "mov rax, qword ptr [rbp - 7]"
+    );
+
+    nodes_soa
+        .set_disasm(NodeSoaId(10), "nop".to_string())
+        .unwrap();
+    nodes_aos
+        .set_disasm(NodeAosId(10), "nop".to_string())
+        .unwrap();
+    nodes_soa
+        .set_disasm(NodeSoaId(2990), "ret".to_string())
+        .unwrap();
+    nodes_aos
+        .set_disasm(NodeAosId(2990), "ret".to_string())
+        .unwrap();
+    assert_eq!(nodes_soa.get(NodeSoaId(10)).unwrap().disasm, "nop");
+
+    nodes_soa.rotate_right(100);
+    nodes_aos.rotate_right(100);
+
+    assert_eq!(
+        nodes_soa.disasm().collect::<Vec<_>>(),
+        nodes_aos.disasm().collect::<Vec<_>>()
+    );
+    assert_eq!(nodes_soa.clone(), nodes_soa);
+    assert_eq!(
+        nodes_soa
+            .gather(&[NodeSoaId(110)])
+            .disasm()
+            .collect::<Vec<_>>(),
+        vec!["nop".to_string()]
+    );
+}
+
+#[test]
+fn test_segmented_layout() {
+    #[layout("soa", segmented)]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u32,
+        name: String,
+    }
+
+    #[layout(aos)]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u32,
+        name: String,
+    }
+
+    fn assert_stable<L: soaaos::column::StableAddresses>(_: &L) {}
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    nodes_soa.add(NodeSoa {
+        op: 0,
+        name: "node0".to_string(),
+    });
+    assert_stable(&nodes_soa);
+
+    // The first field never moves while the layout grows across many segments
+    let first: *const u32 = nodes_soa.get_op(NodeSoaId(0)).unwrap();
+    for op in 1..5000 {
+        nodes_soa.add(NodeSoa {
+            op,
+            name: format!("node{op}"),
+        });
+    }
+    assert!(core::ptr::eq(
+        first,
+        nodes_soa.get_op(NodeSoaId(0)).unwrap()
+    ));
+
+    for op in 0..5000 {
+        nodes_aos.add(NodeAos {
+            op,
+            name: format!("node{op}"),
+        });
+    }
+
+    *nodes_soa.get_name_mut(NodeSoaId(1030)).unwrap() = "changed".to_string();
+    *nodes_aos.get_name_mut(NodeAosId(1030)).unwrap() = "changed".to_string();
+    nodes_soa.rotate_left(1500);
+    nodes_aos.rotate_left(1500);
+    nodes_soa.reverse();
+    nodes_aos.reverse();
+
+    assert_eq!(
+        nodes_soa.op().collect::<Vec<_>>(),
+        nodes_aos.op().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        nodes_soa.name().collect::<Vec<_>>(),
+        nodes_aos.name().collect::<Vec<_>>()
+    );
+    assert_eq!(nodes_soa.clone(), nodes_soa);
+}
+
+#[test]
+fn test_spill_column() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeSoa {
+        op: u32,
+        #[soa(spill)]
+        bytes: Vec<u8>,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct NodeAos {
+        op: u32,
+        #[soa(spill)]
+        bytes: Vec<u8>,
+    }
+
+    let mut nodes_soa = NodeSoasLayout::new();
+    let mut nodes_aos = NodeAossLayout::new();
+    for op in 0..10_000_u32 {
+        let bytes = op.to_le_bytes().to_vec();
+        nodes_soa.add(NodeSoa {
+            op,
+            bytes: bytes.clone(),
+        });
+        nodes_aos.add(NodeAos { op, bytes });
+    }
+
+    assert_eq!(nodes_soa.bytes.spilled_segments(), 2);
+    assert_eq!(
+        nodes_soa.get_bytes(NodeSoaId(5000)).unwrap(),
+        5000_u32.to_le_bytes().to_vec()
+    );
+
+    // Writing a spilled segment, then reading another one and coming back
+    nodes_soa.set_bytes(NodeSoaId(10), vec![1, 2, 3]).unwrap();
+    nodes_aos.set_bytes(NodeAosId(10), vec![1, 2, 3]).unwrap();
+    assert_eq!(nodes_soa.get_bytes(NodeSoaId(4100)).unwrap().len(), 4);
+    assert_eq!(nodes_soa.get(NodeSoaId(10)).unwrap().bytes, vec![1, 2, 3]);
+
+    // Segments are rewritten in their place, or in the room left by other segments, so writing
+    // over and over doesn't grow the spill file
+    let spilled = nodes_soa.bytes.spilled_bytes();
+    for round in 0..100_u8 {
+        let bytes = vec![round; usize::from(round % 4) + 2];
+        nodes_soa.set_bytes(NodeSoaId(10), bytes.clone()).unwrap();
+        nodes_soa.set_bytes(NodeSoaId(5000), bytes).unwrap();
+    }
+    assert!(nodes_soa.bytes.spilled_bytes() < spilled * 2);
+    assert_eq!(nodes_soa.get_bytes(NodeSoaId(10)).unwrap(), vec![99; 5]);
+    assert_eq!(nodes_soa.get_bytes(NodeSoaId(5000)).unwrap(), vec![99; 5]);
+    nodes_soa.set_bytes(NodeSoaId(5000), vec![1, 2, 3]).unwrap();
+    nodes_soa.set_bytes(NodeSoaId(10), vec![1, 2, 3]).unwrap();
+    nodes_aos.set_bytes(NodeAosId(5000), vec![1, 2, 3]).unwrap();
+
+    nodes_soa.rotate_left(3);
+    nodes_aos.rotate_left(3);
+
+    assert_eq!(
+        nodes_soa.bytes().collect::<Vec<_>>(),
+        nodes_aos.bytes().collect::<Vec<_>>()
+    );
+    assert_eq!(nodes_soa.clone(), nodes_soa);
+
+    nodes_soa.reverse();
+    nodes_aos.reverse();
+    nodes_soa.rotate_right(4097);
+    nodes_aos.rotate_right(4097);
+    assert_eq!(
+        nodes_soa.remove(NodeSoaId(4096)).unwrap().0.op,
+        nodes_aos.remove(NodeAosId(4096)).unwrap().0.op
+    );
+    assert_eq!(
+        nodes_soa.bytes().collect::<Vec<_>>(),
+        nodes_aos.bytes().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_unmet_field_bounds() {
+    // Implements none of the traits the layout methods may need of a field
+    #[derive(Debug)]
+    struct Handle(u32);
+
+    #[layout(soa)]
+    #[derive(Debug)]
+    struct Resource {
+        handle: Handle,
+        size: u64,
+    }
+
+    #[layout(aos)]
+    #[derive(Debug)]
+    struct Slot {
+        handle: Handle,
+    }
+
+    // The layouts compile, and only the methods needing more of `Handle` are missing
+    let mut resources = ResourcesLayout::new();
+    let id = resources.add(Resource {
+        handle: Handle(3),
+        size: 64,
+    });
+    assert_eq!(resources.get_handle(id).unwrap().0, 3);
+    assert_eq!(*resources.get_size(id).unwrap(), 64);
+
+    let mut slots = SlotsLayout::new();
+    let id = slots.add(Slot { handle: Handle(5) });
+    assert_eq!(slots.get_handle(id).unwrap().0, 5);
+    assert_eq!(slots.len(), 1);
+}
+
+#[test]
+fn test_layout_module() {
+    #[layout("soa", id = GraphId, error = GraphError)]
+    mod graph {
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct Block {
+            pub start: u64,
+            pub size: u32,
+        }
+
+        #[layout(aos)]
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub struct Edge {
+            pub from: GraphId,
+            pub to: GraphId,
+        }
+
+        /// Items other than structs are kept as is
+        pub fn entry() -> GraphId {
+            GraphId(0)
+        }
+    }
+
+    use graph::*;
+
+    fn edge_len(
+        blocks: &BlocksLayout,
+        edges: &EdgesLayout,
+        edge: GraphId,
+    ) -> Result<u32, GraphError> {
+        let to = *edges.get_to(edge)?;
+        Ok(*blocks.get_size(to)?)
+    }
+
+    let mut blocks = BlocksLayout::new();
+    let mut edges = EdgesLayout::new();
+    let entry: BlockId = blocks.add(Block {
+        start: 0x1000,
+        size: 16,
+    });
+    let exit = blocks.add(Block {
+        start: 0x1010,
+        size: 4,
+    });
+    assert_eq!(entry, graph::entry());
+
+    let edge: EdgeId = edges.add(Edge {
+        from: entry,
+        to: exit,
+    });
+    assert_eq!(edge_len(&blocks, &edges, edge).unwrap(), 4);
+
+    let err = edge_len(&blocks, &edges, GraphId(7)).unwrap_err();
+    assert!(matches!(err, GraphError::Edge(EdgesError::NotFound_to)));
+    assert_eq!(err.to_string(), "Edge: Not Found: NotFound_to");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_id_serde() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+    struct Node {
+        op: u8,
+    }
+
+    let mut nodes = NodesLayout::new();
+    nodes.add(Node { op: 1 });
+    let id = nodes.add(Node { op: 2 });
+
+    let saved = serde_json::to_string(&vec![id, NodeId(0)]).unwrap();
+    assert_eq!(saved, "[1,0]");
+
+    let loaded: Vec<NodeId> = serde_json::from_str(&saved).unwrap();
+    assert_eq!(nodes.get_op(loaded[0]).unwrap(), &2);
+
+    #[cfg(feature = "schemars")]
+    {
+        let schema = schemars::schema_for!(NodeId);
+        assert_eq!(schema.get("type").unwrap(), "integer");
+        assert_eq!(schema.get("format").unwrap(), "uint32");
+    }
+}
+
+#[test]
+fn test_branded_layout() {
+    #[layout("soa", brand)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Node<R>
+    where
+        R: std::fmt::Debug + PartialEq,
+    {
+        op: u8,
+        arg: R,
+    }
+
+    #[layout("aos", brand)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Edge {
+        to: u32,
+    }
+
+    // One brand per function body being analyzed
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum Caller {}
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum Callee {}
+
+    // Only Ids of the same brand can be used, so mixing up the tables doesn't compile
+    fn arg_of<B>(nodes: &NodesLayout<u32, B>, id: NodeId<B>) -> u32 {
+        *nodes.get_arg(id).unwrap()
+    }
+
+    let mut caller: NodesLayout<u32, Caller> = NodesLayout::new();
+    let mut callee: NodesLayout<u32, Callee> = NodesLayout::with_capacity(1);
+
+    let call = caller.add(Node { op: 1, arg: 10 });
+    let ret: NodeId<Callee> = NodeBuilder::new()
+        .op(2)
+        .arg(20)
+        .build_into(&mut callee)
+        .unwrap();
+    assert_eq!(arg_of(&caller, call), 10);
+    assert_eq!(arg_of(&callee, ret), 20);
+    assert_eq!(call, NodeId::new(0));
+    assert_eq!(format!("{call:?}"), "NodeId(0)");
+
+    caller.add(Node { op: 3, arg: 30 });
+    assert_eq!(
+        caller.iter().map(|node| *node.op).collect::<Vec<_>>(),
+        vec![1, 3]
+    );
+    let ids: Vec<NodeId<Caller>> = caller.iter_enumerated().map(|(id, _)| id).collect();
+    assert_eq!(
+        caller.gather(&ids[1..]).to_rows(),
+        vec![Node { op: 3, arg: 30 }]
+    );
+    assert_eq!(caller.clone(), caller);
+
+    let mut cursor = caller.cursor();
+    cursor.advance();
+    assert_eq!(cursor.id(), NodeId::<Caller>::new(1));
+
+    // An unbranded layout defaults the brand to `()`
+    let mut unbranded = NodesLayout::<u8>::new();
+    let id: NodeId = unbranded.add(Node { op: 0, arg: 5 });
+    assert_eq!(unbranded.get_arg(id).unwrap(), &5);
+
+    let mut edges: EdgesLayout<Caller> = EdgesLayout::new();
+    let edge = edges.add(Edge { to: call.0 });
+    assert_eq!(edges.get_to(edge).unwrap(), &0);
+    assert!(edges.get(EdgeId::new(1)).is_err());
+}
+
+#[test]
+fn test_branded_scope() {
+    #[layout("soa", brand)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Node {
+        op: u8,
+        arg: u32,
+    }
+
+    // Ids of a scoped layout are only usable with it, even from a nested scope
+    let sum = NodesLayout::scope(|mut outer| {
+        let first = outer.add(Node { op: 1, arg: 10 });
+        let second = outer.add(Node { op: 2, arg: 20 });
+
+        let inner_arg = NodesLayout::scope(|mut inner| {
+            let id = inner.add(Node { op: 3, arg: 30 });
+            assert_eq!(id.0, first.0);
+            *inner.get_arg(id).unwrap()
+        });
+
+        outer.get_arg(first).unwrap() + outer.get_arg(second).unwrap() + inner_arg
+    });
+    assert_eq!(sum, 60);
+}
+
+#[test]
+fn test_ghost_layouts() {
+    use soaaos::ghost::GhostToken;
+    use std::rc::Rc;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Func {
+        name: String,
+        calls: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Call {
+        caller: FuncId,
+        callee: FuncId,
+    }
+
+    GhostToken::scope(|mut token| {
+        // Both passes hold every layout, and take turns writing them through the token
+        let funcs = Rc::new(GhostFuncsLayout::new(FuncsLayout::new()));
+        let calls = Rc::new(GhostCallsLayout::new(CallsLayout::new()));
+        let pass = (funcs.clone(), calls.clone());
+
+        let main = funcs.borrow_mut(&mut token).add(Func {
+            name: "main".to_string(),
+            calls: 0,
+        });
+        let helper = pass.0.borrow_mut(&mut token).add(Func {
+            name: "helper".to_string(),
+            calls: 0,
+        });
+
+        let (funcs_mut, calls_mut) = token.borrow_mut_pair(&pass.0, &pass.1);
+        calls_mut.add(Call {
+            caller: main,
+            callee: helper,
+        });
+        *funcs_mut.get_calls_mut(helper).unwrap() += 1;
+
+        assert_eq!(funcs.borrow(&token).get_calls(helper).unwrap(), &1);
+        assert_eq!(calls.borrow(&token).get_callee(CallId(0)).unwrap(), &helper);
+        assert_eq!(funcs.borrow(&token).len(), 2);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            token.borrow_mut_pair(&funcs, &pass.0);
+        }));
+        assert!(result.is_err());
+
+        drop(pass);
+        let funcs = Rc::into_inner(funcs).unwrap().into_inner();
+        assert_eq!(funcs.name, vec!["main", "helper"]);
+    });
+}
+
+#[test]
+fn test_remove_cascade() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Block {
+        #[soa(delta)]
+        start: u64,
+        #[soa(rle)]
+        kind: u8,
+        #[soa(sparse)]
+        label: u32,
+        #[soa(segmented)]
+        size: u32,
+        #[soa(spill)]
+        comment: String,
+        #[soa(lazy)]
+        hot: bool,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Edge {
+        #[soa(references)]
+        from: BlockId,
+        #[soa(references)]
+        to: BlockId,
+    }
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Note {
+        #[soa(references)]
+        block: Option<BlockId>,
+        #[soa(references)]
+        edge: EdgeId,
+    }
+
+    let mut blocks = BlocksLayout::new();
+    let ids: Vec<BlockId> = (0..4)
+        .map(|i| {
+            blocks.add(Block {
+                start: 0x1000 + i * 0x10,
+                kind: (i / 2) as u8,
+                label: if i == 1 { 7 } else { 0 },
+                size: i as u32,
+                comment: format!("block {i}"),
+                hot: i == 3,
+            })
+        })
+        .collect();
+
+    let mut edges = EdgesLayout::new();
+    edges.add(Edge {
+        from: ids[0],
+        to: ids[1],
+    });
+    edges.add(Edge {
+        from: ids[1],
+        to: ids[2],
+    });
+    edges.add(Edge {
+        from: ids[2],
+        to: ids[3],
+    });
+
+    let mut notes = NotesLayout::new();
+    notes.add(Note {
+        block: Some(ids[1]),
+        edge: EdgeId(0),
+    });
+    notes.add(Note {
+        block: Some(ids[3]),
+        edge: EdgeId(2),
+    });
+
+    // Remove block 1, then everything referencing it
+    let (removed, _) = blocks.remove(ids[1]).unwrap();
+    assert_eq!(removed.label, 7);
+    assert_eq!(blocks.size().copied().collect::<Vec<_>>(), vec![0, 2, 3]);
+    assert_eq!(
+        blocks.start().collect::<Vec<_>>(),
+        vec![0x1000, 0x1020, 0x1030]
+    );
+    assert_eq!(blocks.kind().copied().collect::<Vec<_>>(), vec![0, 1, 1]);
+    assert_eq!(
+        blocks.comment().collect::<Vec<_>>(),
+        vec!["block 0", "block 2", "block 3"]
+    );
+    assert_eq!(
+        blocks.hot().copied().collect::<Vec<_>>(),
+        vec![false, false, true]
+    );
+    assert!(blocks.remove(BlockId(3)).is_err());
+
+    let mut removed_edges = edges.remove_cascade_from(ids[1]);
+    removed_edges.extend(edges.remove_cascade_to(ids[1]));
+    assert_eq!(removed_edges, vec![EdgeId(1), EdgeId(0)]);
+    assert_eq!(
+        edges.to_rows(),
+        vec![Edge {
+            from: BlockId(1),
+            to: BlockId(2)
+        }]
+    );
+
+    // Notes on removed edges are removed in turn, notes on the removed block lose their block
+    assert_eq!(notes.remove_cascade_block(ids[1]), vec![NoteId(0)]);
+    for edge in removed_edges {
+        notes.remove_cascade_edge(edge);
+    }
+    assert_eq!(
+        notes.to_rows(),
+        vec![Note {
+            block: Some(BlockId(2)),
+            edge: EdgeId(0)
+        }]
+    );
+}
+
+#[test]
+fn test_query() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Insn {
+        op: u8,
+        #[soa(delta)]
+        addr: u64,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Row {
+        op: u8,
+        arg1: u32,
+    }
+
+    let mut insns = InsnsLayout::new();
+    let mut rows = RowsLayout::new();
+    for i in 0..200u32 {
+        insns.add(Insn {
+            op: (i % 5) as u8,
+            addr: 0x1000 + u64::from(i) * 4,
+            name: format!("insn{i}"),
+        });
+        rows.add(Row {
+            op: (i % 5) as u8,
+            arg1: i,
+        });
+    }
+
+    let query = insns.query().op_eq(3).addr_gt(0x1000 + 100 * 4);
+    assert_eq!(query.count(), 20);
+    assert_eq!(query.ids()[0], InsnId(103));
+    assert_eq!(
+        query
+            .iter()
+            .map(|insn| insn.name.clone())
+            .take(2)
+            .collect::<Vec<_>>(),
+        vec!["insn103", "insn108"]
+    );
+
+    let names = insns
+        .query()
+        .name_ne("insn3".to_string())
+        .op_le(0)
+        .filter(|insn| insn.addr < 0x1000 + 20 * 4)
+        .ids();
+    assert_eq!(names, vec![InsnId(0), InsnId(5), InsnId(10), InsnId(15)]);
+    assert_eq!(
+        insns
+            .query()
+            .op_eq(2)
+            .filter(|insn| insn.addr == 0x1000 + 7 * 4)
+            .ids(),
+        vec![InsnId(7)]
+    );
+
+    let ids = rows.query().op_eq(3).arg1_ge(100).arg1_lt(110).ids();
+    assert_eq!(ids, vec![RowId(103), RowId(108)]);
+    assert_eq!(rows.query().op_ne(0).op_ne(1).op_gt(3).count(), 40);
+    assert_eq!(RowsLayout::new().query().op_eq(1).count(), 0);
+}
+
+#[cfg(feature = "roaring")]
+#[test]
+fn test_bitmap_index() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Insn {
+        #[soa(bitmap_index)]
+        op: u8,
+        #[soa(bitmap_index)]
+        class: u8,
+        arg1: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Row {
+        #[soa(bitmap_index)]
+        op: u8,
+        arg1: u32,
+    }
+
+    let mut insns = InsnsLayout::new();
+    let mut rows = RowsLayout::new();
+    for i in 0..100u32 {
+        insns.add(Insn {
+            op: (i % 4) as u8,
+            class: (i % 3) as u8,
+            arg1: i,
+        });
+        rows.add(Row {
+            op: (i % 4) as u8,
+            arg1: i,
+        });
+    }
+
+    assert_eq!(insns.op.cardinality(), 4);
+    assert_eq!(insns.op_bitmap(&1).len(), 25);
+    assert_eq!(insns.op_bitmap(&9).len(), 0);
+    assert_eq!(rows.op_bitmap(&1), insns.op_bitmap(&1));
+
+    // Both equality predicates are bitmap intersections, then only the candidates are scanned
+    let ids = insns.query().op_eq(1).class_eq(2).arg1_lt(50).ids();
+    assert_eq!(ids, vec![InsnId(5), InsnId(17), InsnId(29), InsnId(41)]);
+    let ids = rows.query().op_eq(1).arg1_lt(10).ids();
+    assert_eq!(ids, vec![RowId(1), RowId(5), RowId(9)]);
+
+    // Writes move the node between bitmaps
+    insns.set_op(InsnId(5), 3).unwrap();
+    assert!(!insns.op_bitmap(&1).contains(5));
+    assert!(insns.op_bitmap(&3).contains(5));
+    insns.remove(InsnId(0)).unwrap();
+    assert_eq!(insns.query().op_eq(3).class_eq(2).count(), 9);
+    assert_eq!(insns.query().op_eq(3).class_eq(2).ids()[0], InsnId(4));
+}
+
+#[test]
+fn test_btree_index() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Event {
+        #[soa(btree_index)]
+        time: u64,
+        kind: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Span {
+        #[soa(btree_index)]
+        start: u64,
+        kind: u8,
+    }
+
+    let mut events = EventsLayout::new();
+    let mut spans = SpansLayout::new();
+    for (i, time) in [50u64, 10, 40, 20, 30, 20].into_iter().enumerate() {
+        events.add(Event {
+            time,
+            kind: i as u8,
+        });
+        spans.add(Span {
+            start: time,
+            kind: i as u8,
+        });
+    }
+
+    // Ids are returned ordered by the field, ties by id
+    let ids: Vec<_> = events.range_by_time(15..45).collect();
+    assert_eq!(ids, vec![EventId(3), EventId(5), EventId(4), EventId(2)]);
+    let ids: Vec<_> = spans.range_by_start(15..45).map(|id| id.0).collect();
+    assert_eq!(ids, vec![3, 5, 4, 2]);
+    assert_eq!(events.range_by_time(..=10).count(), 1);
+
+    // Writes and removals keep the index up to date
+    events.set_time(EventId(0), 25).unwrap();
+    let ids: Vec<_> = events.range_by_time(21..).collect();
+    assert_eq!(ids, vec![EventId(0), EventId(4), EventId(2)]);
+    events.remove(EventId(1)).unwrap();
+    let ids: Vec<_> = events.range_by_time(..25).collect();
+    assert_eq!(ids, vec![EventId(2), EventId(4)]);
+    assert_eq!(events.time.cardinality(), 4);
+}
+
+#[test]
+#[cfg(feature = "search")]
+fn test_search_index() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Log {
+        #[soa(search_index)]
+        message: String,
+        level: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Symbol {
+        #[soa(search_index)]
+        name: String,
+        address: u64,
+    }
+
+    let messages = [
+        "Connection refused by peer",
+        "connection reset",
+        "Timeout waiting for peer",
+        "peer connection: refused again",
+    ];
+
+    let mut logs = LogsLayout::new();
+    let mut symbols = SymbolsLayout::new();
+    for (level, message) in messages.iter().enumerate() {
+        logs.add(Log {
+            message: message.to_string(),
+            level: level as u8,
+        });
+        symbols.add(Symbol {
+            name: message.to_string(),
+            address: level as u64 * 0x10,
+        });
+    }
+
+    // Terms are matched case insensitively, as whole words
+    assert_eq!(
+        logs.search_message("connection"),
+        vec![LogId(0), LogId(1), LogId(3)]
+    );
+    assert_eq!(
+        logs.search_message("REFUSED peer"),
+        vec![LogId(0), LogId(3)]
+    );
+    assert_eq!(logs.search_message("connect"), vec![]);
+    assert_eq!(logs.search_message(""), vec![]);
+    let ids: Vec<_> = symbols
+        .search_name("refused peer")
+        .iter()
+        .map(|id| id.0)
+        .collect();
+    assert_eq!(ids, vec![0, 3]);
+
+    // Writes and removals keep the index up to date
+    logs.set_message(LogId(1), "Peer went away".to_string())
+        .unwrap();
+    assert_eq!(
+        logs.search_message("peer"),
+        vec![LogId(0), LogId(1), LogId(2), LogId(3)]
+    );
+    assert_eq!(logs.search_message("reset"), vec![]);
+    logs.remove(LogId(0)).unwrap();
+    assert_eq!(logs.search_message("refused"), vec![LogId(2)]);
+}
+
+#[test]
+fn test_bloom_filter() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Input {
+        #[soa(bloom)]
+        hash: u64,
+        size: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Seen {
+        #[soa(bloom)]
+        hash: u64,
+        size: u32,
+    }
+
+    let mut inputs = InputsLayout::new();
+    let mut seen = SeensLayout::new();
+    assert!(!inputs.maybe_contains_hash(&0));
+    for i in 0..1000u64 {
+        inputs.add(Input {
+            hash: i * 7919,
+            size: i as u32,
+        });
+        seen.add(Seen {
+            hash: i * 7919,
+            size: i as u32,
+        });
+    }
+
+    // No false negatives, and most misses are rejected by the filter alone
+    assert!((0..1000).all(|i| inputs.maybe_contains_hash(&(i * 7919))));
+    let false_positives = (0..1000)
+        .filter(|i| inputs.maybe_contains_hash(&(i * 7919 + 1)))
+        .count();
+    assert!(false_positives < 50, "{false_positives} false positives");
+    assert!(inputs.contains_hash(&7919));
+    assert!(!inputs.contains_hash(&7920));
+    assert!(seen.contains_hash(&7919) && !seen.maybe_contains_hash(&7920));
+
+    // Overwritten values may stay in the filter, but never in the exact check
+    inputs.set_hash(InputId(1), 1).unwrap();
+    assert!(inputs.contains_hash(&1));
+    assert!(!inputs.contains_hash(&7919));
+    inputs.hash.rebuild();
+    assert!(inputs.maybe_contains_hash(&1));
+}
+
+#[test]
+fn test_random_rows() {
+    use rand::SeedableRng;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Seed {
+        input: u32,
+        #[soa(rle)]
+        energy: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Testcase {
+        input: u32,
+        energy: u8,
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+    let mut seeds = SeedsLayout::new();
+    let mut testcases = TestcasesLayout::new();
+    assert_eq!(seeds.choose(&mut rng), None);
+    assert!(seeds.sample(&mut rng, 3).is_empty());
+
+    for i in 0..20u32 {
+        seeds.add(Seed {
+            input: i,
+            energy: (i / 5) as u8,
+        });
+        testcases.add(Testcase {
+            input: i,
+            energy: (i / 5) as u8,
+        });
+    }
+
+    // Samples are distinct, and capped at the number of nodes
+    let mut sample: Vec<_> = seeds.sample(&mut rng, 5).iter().map(|id| id.0).collect();
+    sample.sort();
+    sample.dedup();
+    assert_eq!(sample.len(), 5);
+    assert!(sample.iter().all(|index| *index < 20));
+    assert_eq!(testcases.sample(&mut rng, 100).len(), 20);
+    assert!(seeds.choose(&mut rng).unwrap().0 < 20);
+
+    // Shuffling moves every field of a node together
+    seeds.shuffle(&mut rng);
+    testcases.shuffle(&mut rng);
+    for layout_rows in [
+        seeds
+            .to_rows()
+            .iter()
+            .map(|seed| (seed.input, seed.energy))
+            .collect::<Vec<_>>(),
+        testcases
+            .to_rows()
+            .iter()
+            .map(|testcase| (testcase.input, testcase.energy))
+            .collect(),
+    ] {
+        assert!(
+            layout_rows
+                .iter()
+                .all(|(input, energy)| *energy == (input / 5) as u8)
+        );
+        let mut inputs: Vec<_> = layout_rows.iter().map(|(input, _)| *input).collect();
+        assert_ne!(inputs, (0..20).collect::<Vec<_>>());
+        inputs.sort();
+        assert_eq!(inputs, (0..20).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_sample_weighted() {
+    use rand::SeedableRng;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Entry {
+        #[soa(rle)]
+        score: u32,
+        coverage: f64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Input {
+        score: i64,
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+    let mut entries = EntrysLayout::new();
+    assert!(entries.sample_weighted_by_score(&mut rng, 4).is_empty());
+    for (score, coverage) in [(0, 0.5), (1, 0.0), (3, f64::NAN), (0, 0.5)] {
+        entries.add(Entry { score, coverage });
+    }
+
+    // Nodes are drawn in proportion to their score, never with a weight of 0
+    let draws = entries.sample_weighted_by_score(&mut rng, 4000);
+    assert_eq!(draws.len(), 4000);
+    let third = draws.iter().filter(|id| **id == EntryId(2)).count();
+    assert!((2800..3200).contains(&third));
+    assert!(draws.iter().all(|id| [EntryId(1), EntryId(2)].contains(id)));
+
+    // NaN weights count as 0, and weights can be computed from the whole node
+    let draws = entries.sample_weighted_by_coverage(&mut rng, 100);
+    assert!(draws.iter().all(|id| [EntryId(0), EntryId(3)].contains(id)));
+    let draws = entries.sample_weighted(&mut rng, 100, |entry| f64::from(*entry.score) - 2.0);
+    assert_eq!(draws, vec![EntryId(2); 100]);
+    assert!(entries.sample_weighted(&mut rng, 5, |_| 0.0).is_empty());
+
+    let mut inputs = InputsLayout::new();
+    for score in [-4, 2, 2] {
+        inputs.add(Input { score });
+    }
+    let draws = inputs.sample_weighted_by_score(&mut rng, 50);
+    assert!(draws.contains(&InputId(1)) && draws.contains(&InputId(2)));
+    assert!(!draws.contains(&InputId(0)));
+}
+
+#[test]
+fn test_iter_ids() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Block {
+        start: u64,
+        size: u32,
+    }
+
+    #[layout("aos", brand)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Page {
+        address: u64,
+    }
+
+    let mut blocks = BlocksLayout::new();
+    let mut pages = PagesLayout::<()>::new();
+    assert_eq!(blocks.iter_ids().len(), 0);
+    for i in 0..4 {
+        blocks.add(Block {
+            start: i * 0x10,
+            size: 0x10,
+        });
+        pages.add(Page {
+            address: i * 0x1000,
+        });
+    }
+
+    let ids: Vec<_> = blocks.iter_ids().rev().collect();
+    assert_eq!(ids, vec![BlockId(3), BlockId(2), BlockId(1), BlockId(0)]);
+    assert!(blocks.is_valid(BlockId(3)));
+    assert!(!blocks.is_valid(BlockId(4)));
+    assert!(pages.iter_ids().all(|id| pages.is_valid(id)));
+
+    // The ids don't borrow the layout
+    for id in blocks.iter_ids() {
+        blocks.set_size(id, id.0 * 2).unwrap();
+    }
+    assert_eq!(blocks.size().copied().collect::<Vec<_>>(), vec![0, 2, 4, 6]);
+
+    blocks.remove(BlockId(0)).unwrap();
+    assert!(!blocks.is_valid(BlockId(3)));
+}
+
+#[test]
+fn test_iter_pairs_and_windows() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sample {
+        time: u64,
+        #[soa(delta)]
+        value: i64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Insn {
+        address: u64,
+        size: u8,
+    }
+
+    let mut samples = SamplesLayout::new();
+    let mut insns = InsnsLayout::new();
+    assert_eq!(samples.iter_pairs().count(), 0);
+    for (i, value) in [3i64, 5, 4, 10, 2].into_iter().enumerate() {
+        samples.add(Sample {
+            time: i as u64,
+            value,
+        });
+        insns.add(Insn {
+            address: 0x1000 + i as u64 * 4,
+            size: 4,
+        });
+    }
+
+    let deltas: Vec<_> = samples
+        .iter_pairs()
+        .map(|(prev, next)| next.value - prev.value)
+        .collect();
+    assert_eq!(deltas, vec![2, -1, 6, -8]);
+    assert!(
+        insns
+            .iter_pairs()
+            .all(|(prev, next)| *prev.address + *prev.size as u64 == *next.address)
+    );
+
+    let sums: Vec<i64> = samples
+        .windows(3)
+        .map(|window| window.iter().map(|sample| sample.value).sum())
+        .collect();
+    assert_eq!(sums, vec![12, 19, 16]);
+    assert_eq!(insns.windows(5).count(), 1);
+    assert_eq!(insns.windows(6).count(), 0);
+}
+
+#[test]
+fn test_composite_sort() {
+    use core::cmp::Reverse;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Insn {
+        #[soa(rle)]
+        op: u8,
+        arg1: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sym {
+        module: u8,
+        address: u64,
+    }
+
+    let mut insns = InsnsLayout::new();
+    let mut syms = SymsLayout::new();
+    for (op, arg1) in [(2u8, 1u32), (1, 5), (2, 7), (1, 3), (1, 5)] {
+        insns.add(Insn { op, arg1 });
+        syms.add(Sym {
+            module: op,
+            address: arg1 as u64,
+        });
+    }
+
+    // Ascending by op, then descending by arg1
+    insns.sort_by_key(|insn| (*insn.op, Reverse(*insn.arg1)));
+    let rows: Vec<_> = insns.iter().map(|insn| (*insn.op, *insn.arg1)).collect();
+    assert_eq!(rows, vec![(1, 5), (1, 5), (1, 3), (2, 7), (2, 1)]);
+
+    syms.sort_by(|a, b| b.address.cmp(a.address).then(a.module.cmp(b.module)));
+    let rows: Vec<_> = syms.iter().map(|sym| (*sym.module, *sym.address)).collect();
+    assert_eq!(rows, vec![(2, 7), (1, 5), (1, 5), (1, 3), (2, 1)]);
+
+    // Reordering moves the node at each id to its index
+    syms.reorder(&[SymId(4), SymId(3), SymId(2), SymId(1), SymId(0)]);
+    assert_eq!(*syms.get(SymId(0)).unwrap().address, 1);
+    insns.reorder(&[InsnId(3), InsnId(4), InsnId(0), InsnId(1), InsnId(2)]);
+    assert_eq!(*insns.get(InsnId(0)).unwrap().arg1, 7);
+}
+
+#[test]
+fn test_sort_variants() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Event {
+        #[soa(delta)]
+        time: u64,
+        kind: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Func {
+        name: String,
+        size: u32,
+    }
+
+    let mut events = EventsLayout::new();
+    let mut funcs = FuncsLayout::new();
+    for (time, kind) in [(30u64, 1u8), (10, 2), (20, 1), (10, 1)] {
+        events.add(Event { time, kind });
+        funcs.add(Func {
+            name: format!("f{time}_{kind}"),
+            size: time as u32 * kind as u32,
+        });
+    }
+
+    // The stable sort keeps equal times in insertion order
+    events.sort_by_time();
+    let rows: Vec<_> = events
+        .iter()
+        .map(|event| (event.time, *event.kind))
+        .collect();
+    assert_eq!(rows, vec![(10, 2), (10, 1), (20, 1), (30, 1)]);
+    events.sort_unstable_by_kind();
+    assert!(events.iter().map(|event| *event.kind).is_sorted());
+    events.sort_unstable_by_key(|event| (event.time, *event.kind));
+    let rows: Vec<_> = events
+        .iter()
+        .map(|event| (event.time, *event.kind))
+        .collect();
+    assert_eq!(rows, vec![(10, 1), (10, 2), (20, 1), (30, 1)]);
+
+    // Cached keys are computed once per node
+    let mut calls = 0;
+    funcs.sort_by_cached_key(|func| {
+        calls += 1;
+        func.name.len() as u32 * 100 + *func.size
+    });
+    assert_eq!(calls, 4);
+    let sizes: Vec<_> = funcs.iter().map(|func| *func.size).collect();
+    assert_eq!(sizes, vec![10, 20, 20, 30]);
+    funcs.sort_by_name();
+    assert_eq!(funcs.get(FuncId(0)).unwrap().name, "f10_1");
+    funcs.sort_unstable_by(|a, b| b.size.cmp(a.size));
+    assert_eq!(*funcs.get(FuncId(0)).unwrap().size, 30);
+}
+
+#[test]
+#[should_panic(expected = "order must hold every id of the layout exactly once")]
+fn test_reorder_duplicate_ids() {
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sym {
+        address: u64,
+    }
+
+    let mut syms = SymsLayout::new();
+    syms.add(Sym { address: 1 });
+    syms.add(Sym { address: 2 });
+    syms.reorder(&[SymId(1), SymId(1)]);
+}
+
+#[test]
+fn test_layout_derives() {
+    #[layout("soa", derives(Debug, Clone, PartialEq))]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Point {
+        x: f32,
+        y: f32,
+    }
+
+    #[layout("aos", derives(Clone, Default))]
+    #[derive(Clone)]
+    struct Sample {
+        value: f64,
+    }
+
+    let mut points = PointsLayout::new();
+    points.add(Point { x: 1.0, y: 2.5 });
+    let copy = points.clone();
+    assert_eq!(points, copy);
+    assert!(format!("{:?}", points.get(PointId(0)).unwrap()).contains("2.5"));
+
+    let mut samples = SamplesLayout::default();
+    samples.add(Sample { value: 0.5 });
+    assert_eq!(*samples.clone().get(SampleId(0)).unwrap().value, 0.5);
+}
+
+#[test]
+fn test_conditional_traits() {
+    use std::collections::HashSet;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Particle {
+        mass: f32,
+        charge: i8,
+    }
+
+    /// Neither `Debug` nor `PartialEq`
+    #[derive(Clone)]
+    struct Handle(u32);
+
+    #[layout("aos")]
+    #[derive(Clone)]
+    struct Resource {
+        handle: Handle,
+        size: u64,
+    }
+
+    #[layout("aos", brand)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Tag {
+        value: u8,
+    }
+
+    // `f32` drops `Eq` and `Hash`, but keeps everything else
+    let mut particles = ParticlesLayout::new();
+    particles.add(Particle {
+        mass: 1.5,
+        charge: -1,
+    });
+    let mut heavier = particles.clone();
+    heavier.set_mass(ParticleId(0), 2.0).unwrap();
+    assert_ne!(particles, heavier);
+    assert!(particles.diff(&heavier).unwrap().contains("1.5 vs 2.0"));
+    assert!(format!("{particles:?}").contains("charge"));
+
+    let mut resources = ResourcesLayout::new();
+    resources.add(Resource {
+        handle: Handle(3),
+        size: 0x1000,
+    });
+    assert_eq!(resources.clone().get(ResourceId(0)).unwrap().handle.0, 3);
+
+    // Layouts of types implementing every trait still implement them all
+    let mut tags = TagsLayout::<()>::new();
+    tags.add(Tag { value: 1 });
+    let set: HashSet<_> = [tags.clone(), tags].into_iter().collect();
+    assert_eq!(set.len(), 1);
+}
+
+#[test]
+fn test_clear_keep_capacity() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Input {
+        data: u64,
+        #[soa(btree_index)]
+        energy: u32,
+        #[soa(rle)]
+        stage: u8,
+        #[soa(delta)]
+        time: u64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Entity {
+        x: i32,
+        y: i32,
+    }
+
+    let mut inputs = InputsLayout::new();
+    let mut entities = EntitysLayout::new();
+    for i in 0..100 {
+        inputs.add(Input {
+            data: i,
+            energy: i as u32 % 7,
+            stage: (i / 10) as u8,
+            time: i * 3,
+        });
+        entities.add(Entity { x: i as i32, y: 0 });
+    }
+
+    let capacity = inputs.data.capacity();
+    inputs.clear_keep_capacity();
+    entities.reset();
+    assert!(inputs.is_empty() && entities.is_empty());
+    assert_eq!(inputs.data.capacity(), capacity);
+    assert!(entities.data.capacity() >= 100);
+    assert!(!inputs.is_valid(InputId(0)));
+    assert_eq!(inputs.range_by_energy(..).count(), 0);
+
+    // The cleared layout is reused from the first index
+    let id = inputs.add(Input {
+        data: 1,
+        energy: 2,
+        stage: 3,
+        time: 4,
+    });
+    assert_eq!(id, InputId(0));
+    assert_eq!(inputs.get_time(id).unwrap(), 4);
+    assert_eq!(inputs.range_by_energy(2..).collect::<Vec<_>>(), vec![id]);
+    assert_eq!(entities.add(Entity { x: 1, y: 1 }), EntityId(0));
+
+    // An Id kept from before the reset aliases the new node at its index
+    let kept = EntityId(0);
+    entities.reset();
+    entities.add(Entity { x: 7, y: 7 });
+    assert!(entities.is_valid(kept));
+    assert_eq!(entities.get_x(kept).unwrap(), &7);
+}
+
+#[test]
+#[cfg(feature = "stats")]
+fn test_debug_stats() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Packet {
+        size: u16,
+        #[soa(rle)]
+        proto: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Frame {
+        index: u32,
+    }
+
+    let mut packets = PacketsLayout::new();
+    let mut frames = FramesLayout::with_capacity(64);
+    for i in 0..100 {
+        packets.add(Packet { size: i, proto: 6 });
+        frames.add(Frame { index: i as u32 });
+    }
+    packets.remove(PacketId(0)).unwrap();
+
+    // Only the `Vec` column is tracked, doubling from its first allocation
+    let stats = packets.debug_stats();
+    assert_eq!((stats.len, stats.high_water), (99, 100));
+    assert_eq!(stats.columns.len(), 1);
+    assert_eq!(stats.columns[0].name, "size");
+    assert_eq!(stats.columns[0].capacity, packets.size.capacity());
+    assert_eq!(stats.reallocations(), stats.growth.len() - 1);
+    assert!(
+        stats
+            .growth
+            .windows(2)
+            .all(|pair| pair[0].to == pair[1].from)
+    );
+    assert!(stats.to_string().contains("high water 100"));
+
+    // A pre-sized layout grows once past its initial capacity
+    let stats = frames.debug_stats();
+    assert_eq!(stats.reallocations(), 1);
+    assert_eq!(stats.growth[1].len, 65);
+}
+
+#[test]
+#[cfg(feature = "stats")]
+fn test_allocation_report() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sample {
+        time: u64,
+        #[soa(rle)]
+        sensor: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Reading {
+        value: u32,
+        channel: u16,
+    }
+
+    let mut samples = SamplesLayout::with_capacity(128);
+    let mut readings = ReadingsLayout::with_capacity(16);
+    for i in 0..100 {
+        samples.add(Sample {
+            time: i,
+            sensor: (i / 50) as u8,
+        });
+        readings.add(Reading {
+            value: i as u32,
+            channel: 1,
+        });
+    }
+
+    // Each column is attributed to its field, the run-length column only holding its two runs
+    let report = samples.allocation_report();
+    assert_eq!(report.layout, "SamplesLayout");
+    assert_eq!(report.columns[0].name, "time");
+    assert_eq!(report.columns[0].bytes, samples.time.capacity() * 8);
+    assert_eq!(report.columns[1].name, "sensor");
+    assert!(report.columns[1].bytes < report.columns[0].bytes / 10);
+    assert_eq!(
+        report.total(),
+        report.columns[0].bytes + report.columns[1].bytes
+    );
+    assert!(report.to_string().contains("SamplesLayout.time"));
+
+    // The rows of an array-of-structs layout are a single column
+    let report = readings.allocation_report();
+    assert_eq!(report.columns.len(), 1);
+    assert_eq!(report.columns[0].name, "data");
+    assert!(report.total() >= 100 * size_of::<Reading>());
+}
+
+#[test]
+fn test_default_rows() {
+    #[layout("soa")]
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+    struct Cell {
+        height: i32,
+        #[soa(rle)]
+        terrain: u8,
+        label: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+    struct Tile {
+        height: i32,
+        label: String,
+    }
+
+    let cells = CellsLayout::default_rows(64 * 64);
+    let tiles = TilesLayout::default_rows(3);
+    assert_eq!(cells.len(), 4096);
+    assert_eq!(cells.terrain_runs().count(), 1);
+    let cell = cells.get(CellId(4095)).unwrap();
+    assert_eq!((*cell.height, cell.label.as_str()), (0, ""));
+    assert_eq!(tiles.len(), 3);
+    assert!(
+        tiles
+            .iter()
+            .all(|tile| *tile.height == 0 && tile.label.is_empty())
+    );
+}
+
+#[test]
+fn test_layout_ordering() {
+    use std::collections::BTreeSet;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Version {
+        major: u32,
+        #[soa(delta)]
+        minor: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Release {
+        major: u32,
+        minor: u32,
+    }
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Weight {
+        value: f32,
+    }
+
+    let soa = |rows: &[(u32, u32)]| {
+        let mut layout = VersionsLayout::new();
+        for &(major, minor) in rows {
+            layout.add(Version { major, minor });
+        }
+        layout
+    };
+    let aos = |rows: &[(u32, u32)]| {
+        let mut layout = ReleasesLayout::new();
+        for &(major, minor) in rows {
+            layout.add(Release { major, minor });
+        }
+        layout
+    };
+
+    // Rows compare field by field, then a prefix orders before the longer layout, the same way
+    // for both layouts even though the SoA layout holds `major` in a single column
+    let cases = [
+        (&[(1, 2)][..], &[(1, 3)][..]),
+        (&[(1, 9), (0, 0)][..], &[(2, 0)][..]),
+        (&[(1, 2)][..], &[(1, 2), (0, 0)][..]),
+    ];
+    for (smaller, larger) in cases {
+        assert!(soa(smaller) < soa(larger));
+        assert!(aos(smaller) < aos(larger));
+    }
+    assert_eq!(
+        soa(&[(3, 1)]).cmp(&soa(&[(3, 1)])),
+        core::cmp::Ordering::Equal
+    );
+
+    let set: BTreeSet<_> = [soa(&[(2, 0)]), soa(&[]), soa(&[(1, 5)])]
+        .into_iter()
+        .collect();
+    let firsts: Vec<_> = set
+        .iter()
+        .map(|layout| layout.major.first().copied())
+        .collect();
+    assert_eq!(firsts, vec![None, Some(1), Some(2)]);
+
+    // A float field only gives a partial order
+    let mut nan = WeightsLayout::new();
+    let mut one = WeightsLayout::new();
+    nan.add(Weight { value: f32::NAN });
+    one.add(Weight { value: 1.0 });
+    assert!(nan.partial_cmp(&one).is_none());
+    assert!(WeightsLayout::new() < one);
+}
+
+#[test]
+fn test_row_access() {
+    use soaaos::RowAccess;
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Task {
+        cost: u32,
+        after: Option<TaskId>,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Step {
+        cost: u32,
+        after: Option<StepId>,
+    }
+
+    /// Order the rows of any layout so each row comes after the row it depends on
+    fn topological_order<L>(layout: &L, after: impl Fn(&L::Ref<'_>) -> Option<L::Id>) -> Vec<L::Id>
+    where
+        L: RowAccess,
+        L::Id: Eq + Hash,
+    {
+        let index: HashMap<L::Id, usize> = (0..layout.len()).map(|i| (L::id(i), i)).collect();
+        let deps: Vec<Option<usize>> = layout
+            .rows()
+            .map(|row| after(&row).map(|id| index[&id]))
+            .collect();
+
+        let mut order = Vec::new();
+        let mut placed = vec![false; deps.len()];
+        while order.len() < deps.len() {
+            for (i, dep) in deps.iter().enumerate() {
+                if !placed[i] && dep.is_none_or(|dep| placed[dep]) {
+                    placed[i] = true;
+                    order.push(L::id(i));
+                }
+            }
+        }
+        order
+    }
+
+    let mut tasks = TasksLayout::new();
+    let mut steps = StepsLayout::new();
+    for (cost, after) in [(1, Some(2)), (2, None), (3, Some(1))] {
+        tasks.add(Task {
+            cost,
+            after: after.map(TaskId),
+        });
+        steps.add(Step {
+            cost,
+            after: after.map(StepId),
+        });
+    }
+
+    let order = topological_order(&tasks, |task: &TaskRef| *task.after);
+    assert_eq!(order, vec![TaskId(1), TaskId(2), TaskId(0)]);
+    let order = topological_order(&steps, |step: &StepRef| *step.after);
+    assert_eq!(order, vec![StepId(1), StepId(2), StepId(0)]);
+
+    *tasks.row_mut(TaskId(1)).unwrap().cost += 10;
+    assert_eq!(*tasks.row(TaskId(1)).unwrap().cost, 12);
+    assert!(steps.row(StepId(3)).is_none());
+}
+
+#[test]
+fn test_get_many() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Inst {
+        op: u8,
+        #[soa(delta)]
+        imm: i64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Operand {
+        op: u8,
+        imm: i64,
+    }
+
+    let mut insts = InstsLayout::new();
+    let mut operands = OperandsLayout::new();
+    for i in 0..4 {
+        insts.add(Inst {
+            op: i,
+            imm: -(i as i64),
+        });
+        operands.add(Operand {
+            op: i,
+            imm: -(i as i64),
+        });
+    }
+
+    let [lhs, rhs, out] = insts.get_many([InstId(3), InstId(1), InstId(3)]).unwrap();
+    assert_eq!((*lhs.op, rhs.imm, *out.op), (3, -1, 3));
+    let [lhs, rhs] = operands.get_many([OperandId(0), OperandId(2)]).unwrap();
+    assert_eq!((*lhs.op, *rhs.imm), (0, -2));
+
+    assert!(matches!(
+        insts.get_many([InstId(0), InstId(4)]),
+        Err(InstsError::NotFound)
+    ));
+    assert!(operands.get_many([OperandId(9)]).is_err());
+    assert!(insts.get_many::<0>([]).unwrap().is_empty());
+
+    // A missing node is reported as a missing row, not as its first field
+    assert!(matches!(insts.get(InstId(4)), Err(InstsError::NotFound)));
+    assert!(matches!(
+        insts.get_mut(InstId(4)),
+        Err(InstsError::NotFound)
+    ));
+    assert!(matches!(
+        operands.get(OperandId(4)),
+        Err(OperandsError::NotFound)
+    ));
+    assert!(matches!(
+        operands.get_mut(OperandId(4)),
+        Err(OperandsError::NotFound)
+    ));
+    assert!(matches!(
+        operands.remove(OperandId(4)),
+        Err(OperandsError::NotFound)
+    ));
+    assert_eq!(OperandsError::NotFound.to_string(), "Not Found");
+    assert!(matches!(
+        operands.get_imm(OperandId(4)),
+        Err(OperandsError::NotFound_imm)
+    ));
+}
+
+#[test]
+fn test_id_of_min_max() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Job {
+        priority: u8,
+        #[soa(rle)]
+        queue: u8,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Worker {
+        load: u32,
+        name: String,
+    }
+
+    let mut jobs = JobsLayout::new();
+    assert_eq!(jobs.id_of_min_priority(), None);
+    for (priority, queue, name) in [
+        (3, 0, "c"),
+        (1, 0, "a"),
+        (7, 1, "b"),
+        (1, 1, "d"),
+        (7, 1, "a"),
+    ] {
+        jobs.add(Job {
+            priority,
+            queue,
+            name: name.to_string(),
+        });
+    }
+
+    // Ties resolve to the first node holding the value
+    assert_eq!(jobs.id_of_min_priority(), Some(JobId(1)));
+    assert_eq!(jobs.id_of_max_priority(), Some(JobId(2)));
+    assert_eq!(jobs.id_of_max_queue(), Some(JobId(2)));
+    assert_eq!(jobs.id_of_min_name(), Some(JobId(1)));
+
+    let mut workers = WorkersLayout::new();
+    for (load, name) in [(5, "x"), (9, "y"), (9, "z"), (2, "w")] {
+        workers.add(Worker {
+            load,
+            name: name.to_string(),
+        });
+    }
+    assert_eq!(workers.id_of_min_load(), Some(WorkerId(3)));
+    assert_eq!(workers.id_of_max_load(), Some(WorkerId(1)));
+    assert_eq!(workers.id_of_max_name(), Some(WorkerId(2)));
+}
+
+#[test]
+fn test_id_of_reference() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Token {
+        text: String,
+        #[soa(page_aligned)]
+        offset: u32,
+        #[soa(rle)]
+        kind: u8,
+    }
+
+    let mut tokens = TokensLayout::new();
+    for (text, offset) in [("fn", 0), ("main", 3), ("(", 7)] {
+        tokens.add(Token {
+            text: text.to_string(),
+            offset,
+            kind: 0,
+        });
+    }
+
+    let text = tokens.get_text(TokenId(1)).unwrap();
+    assert_eq!(tokens.id_of_text(text), Some(TokenId(1)));
+    let last = tokens.offset().last().unwrap();
+    assert_eq!(tokens.id_of_offset(last), Some(TokenId(2)));
+    let (_, rest) = tokens.offset_chunks::<2>();
+    assert_eq!(tokens.id_of_offset(&rest[0]), Some(TokenId(2)));
+
+    // References from elsewhere, even to equal values, aren't found
+    let copy = tokens.get_text(TokenId(0)).unwrap().clone();
+    assert_eq!(tokens.id_of_text(&copy), None);
+    assert_eq!(tokens.id_of_offset(&0), None);
+    let offsets = [0u32, 3, 7];
+    assert_eq!(tokens.id_of_offset(&offsets[1]), None);
+}
+
+#[test]
+fn test_range_getters() {
+    #[layout("soa", versioned)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Sample {
+        time: u64,
+        #[soa(page_aligned)]
+        value: f32,
+        #[soa(frozen)]
+        channel: u8,
+    }
+
+    let mut samples = SamplesLayout::new();
+    for time in 0..5 {
+        samples.add(Sample {
+            time,
+            value: time as f32 / 2.0,
+            channel: 1,
+        });
+    }
+
+    assert_eq!(
+        samples.get_time_range(SampleId(1)..SampleId(4)).unwrap(),
+        &[1, 2, 3]
+    );
+    assert_eq!(
+        samples.get_channel_range(SampleId(0)..SampleId(5)).unwrap(),
+        &[1; 5]
+    );
+    assert!(
+        samples
+            .get_time_range(SampleId(5)..SampleId(5))
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        samples
+            .get_value_range(SampleId(3)..SampleId(6))
+            .unwrap_err()
+            .to_string(),
+        "Not Found: NotFound_value"
+    );
+    assert!(samples.get_time_range(SampleId(3)..SampleId(2)).is_err());
+
+    // Borrowing a range mutably counts as writing each node in it
+    let before = samples.current_version();
+    for value in samples
+        .get_value_range_mut(SampleId(2)..SampleId(4))
+        .unwrap()
+    {
+        *value *= 10.0;
+    }
+    assert_eq!(
+        samples.value().copied().collect::<Vec<_>>(),
+        vec![0.0, 0.5, 10.0, 15.0, 2.0]
+    );
+    assert_eq!(
+        samples.changed_since(before).collect::<Vec<_>>(),
+        vec![SampleId(2), SampleId(3)]
+    );
+    assert!(
+        samples
+            .get_time_range_mut(SampleId(4)..SampleId(6))
+            .is_err()
+    );
+}
+
+#[test]
+fn test_static_layout() {
+    use std::sync::Mutex;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Opcode {
+        name: &'static str,
+        #[soa(rle)]
+        operands: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Register {
+        name: &'static str,
+        width: u32,
+    }
+
+    static OPCODES: StaticOpcodesLayout = soaaos::static_layout![
+        Opcode {
+            name: "nop",
+            operands: 0
+        },
+        Opcode {
+            operands: 2,
+            name: "add"
+        },
+        Opcode {
+            name: "neg",
+            operands: 1
+        },
+    ];
+    static REGISTERS: StaticRegistersLayout = soaaos::static_layout![
+        Register {
+            name: "al",
+            width: 8
+        },
+        Register {
+            name: "eax",
+            width: 32
+        },
+    ];
+
+    // `new()` is a `const fn`, so an empty layout can start out in a static
+    static HISTORY: Mutex<OpcodesLayout> = Mutex::new(OpcodesLayout::new());
+
+    assert_eq!(OPCODES.len(), 3);
+    assert_eq!(OPCODES.name, &["nop", "add", "neg"]);
+    assert_eq!(OPCODES.get_operands(OpcodeId(1)).unwrap(), &2);
+    assert!(OPCODES.get_name(OpcodeId(3)).is_err());
+    assert_eq!(OPCODES.operands().sum::<u8>(), 3);
+    assert_eq!(OPCODES.iter_ids().last(), Some(OpcodeId(2)));
+
+    let opcodes = OPCODES.to_layout();
+    assert_eq!(opcodes.operands().collect::<Vec<_>>(), vec![&0, &2, &1]);
+    HISTORY
+        .lock()
+        .unwrap()
+        .extend_from_layout(&opcodes, &[OpcodeId(2)]);
+    assert_eq!(HISTORY.lock().unwrap().name, vec!["neg"]);
+
+    assert_eq!(
+        REGISTERS.get(RegisterId(1)).unwrap(),
+        &Register {
+            name: "eax",
+            width: 32
+        }
+    );
+    assert_eq!(REGISTERS.get_width(RegisterId(0)).unwrap(), &8);
+    assert_eq!(REGISTERS.width().max(), Some(&32));
+    assert_eq!(REGISTERS.to_layout().data, REGISTERS.data);
+}
+
+#[test]
+fn test_value_counts_and_histogram() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Request {
+        #[soa(rle)]
+        status: u16,
+        latency: f32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Response {
+        status: u16,
+        latency: f32,
+    }
+
+    let mut requests = RequestsLayout::new();
+    let mut responses = ResponsesLayout::new();
+    for (status, latency) in [
+        (200, 1.0),
+        (200, 2.5),
+        (404, 9.0),
+        (200, 10.0),
+        (500, f32::NAN),
+    ] {
+        requests.add(Request { status, latency });
+        responses.add(Response { status, latency });
+    }
+
+    let counts = requests.value_counts_status();
+    assert_eq!(counts.len(), 3);
+    assert_eq!((counts[&200], counts[&404], counts[&500]), (3, 1, 1));
+    assert_eq!(responses.value_counts_status(), counts);
+
+    // 1..10 in 3 buckets of 3, the maximum counted in the last bucket and the NaN skipped
+    let histogram = requests.histogram_latency(3);
+    assert_eq!((histogram.min, histogram.max), (1.0, 10.0));
+    assert_eq!(histogram.counts, vec![2, 0, 2]);
+    assert_eq!(histogram.bin_range(1), (4.0, 7.0));
+    assert_eq!(responses.histogram_latency(3), histogram);
+    assert_eq!(requests.histogram_status(2).counts, vec![3, 2]);
+
+    let empty = RequestsLayout::new().histogram_latency(4);
+    assert_eq!((empty.total(), empty.counts.len()), (0, 4));
+}
+
+#[test]
+fn test_join_by_field() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Order {
+        customer: u32,
+        total: u64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Customer {
+        id: u32,
+        name: String,
+    }
+
+    let mut orders = OrdersLayout::new();
+    for (customer, total) in [(2, 10), (1, 25), (3, 5), (2, 40)] {
+        orders.add(Order { customer, total });
+    }
+
+    let mut customers = CustomersLayout::new();
+    for (id, name) in [(1, "ada"), (2, "bob"), (2, "bobby")] {
+        customers.add(Customer {
+            id,
+            name: name.to_string(),
+        });
+    }
+
+    // Pairs follow the orders, then the customers sharing a key; order 3 has no customer
+    let pairs: Vec<_> = orders
+        .join_by_customer(&customers, |customer| customer.id)
+        .map(|(order, customer)| (*order.total, customer.name.as_str()))
+        .collect();
+    assert_eq!(
+        pairs,
+        vec![
+            (10, "bob"),
+            (10, "bobby"),
+            (25, "ada"),
+            (40, "bob"),
+            (40, "bobby")
+        ]
+    );
+
+    // Keys can be borrowed from the other layout
+    let names: Vec<_> = customers
+        .join_by_name(&customers, |customer| customer.name)
+        .map(|(a, b)| (a.name.clone(), *b.id))
+        .collect();
+    assert_eq!(names.len(), 3);
+    assert!(
+        orders
+            .join_by_total(&orders, |order| *order.total + 1)
+            .next()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_select() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Particle {
+        x: f32,
+        #[soa(delta)]
+        cell: u32,
+        tag: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Body {
+        mass: f64,
+        velocity: f64,
+    }
+
+    let mut particles = ParticlesLayout::new();
+    for index in 0..6u32 {
+        particles.add(Particle {
+            x: index as f32 * 0.5,
+            cell: index / 2,
+            tag: format!("p{index}"),
+        });
+    }
+
+    // The selected nodes are dense, in the order of the Ids
+    let ids = [ParticleId(4), ParticleId(1), ParticleId(4)];
+    let selection = particles.select(&ids);
+    assert_eq!(selection.len(), 3);
+    assert_eq!(selection.x, [2.0, 0.5, 2.0]);
+    assert_eq!(selection.cell, [2, 0, 2]);
+    assert_eq!(selection.tag, ["p4", "p1", "p4"]);
+    assert_eq!(selection.x.iter().sum::<f32>(), 4.5);
+
+    // Selecting again reuses the buffers of the selection
+    let mut selection = selection;
+    let buffer = selection.x.as_ptr();
+    particles.select_into(&[ParticleId(0), ParticleId(5)], &mut selection);
+    assert_eq!(selection.x, [0.0, 2.5]);
+    assert_eq!(selection.x.as_ptr(), buffer);
+    particles.select_into(&[], &mut selection);
+    assert!(selection.is_empty());
+
+    let mut bodies = BodysLayout::new();
+    bodies.add(Body {
+        mass: 1.0,
+        velocity: 3.0,
+    });
+    bodies.add(Body {
+        mass: 2.0,
+        velocity: -1.0,
+    });
+    let selection = bodies.select(&[BodyId(1), BodyId(0)]);
+    let momentum: Vec<f64> = selection
+        .mass
+        .iter()
+        .zip(&selection.velocity)
+        .map(|(mass, velocity)| mass * velocity)
+        .collect();
+    assert_eq!(momentum, [-2.0, 3.0]);
+}
+
+#[test]
+fn test_find_row_and_dedup() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Value {
+        op: u8,
+        lhs: u32,
+        name: String,
+    }
+
+    let value = |op, lhs, name: &str| Value {
+        op,
+        lhs,
+        name: name.to_string(),
+    };
+    let mut values = ValuesLayout::new();
+    for (op, lhs, name) in [
+        (1, 10, "a"),
+        (2, 10, "a"),
+        (1, 10, "a"),
+        (1, 10, "b"),
+        (2, 10, "a"),
+    ] {
+        values.add(value(op, lhs, name));
+    }
+
+    assert_eq!(values.find_row(&value(1, 10, "b")), Some(ValueId(3)));
+    assert_eq!(values.find_row(&value(2, 10, "a")), Some(ValueId(1)));
+    assert_eq!(values.find_row(&value(2, 11, "a")), None);
+
+    let remap = values.dedup_rows();
+    assert_eq!(
+        remap.remap_slice(&(0..5).map(ValueId).collect::<Vec<_>>()),
+        vec![ValueId(0), ValueId(1), ValueId(0), ValueId(2), ValueId(1)]
+    );
+    assert_eq!(values.len(), 3);
+    assert_eq!(values.find_row(&value(1, 10, "b")), Some(ValueId(2)));
+    assert!(values.dedup_rows().is_identity());
+}
+
+#[test]
+#[cfg(feature = "crossbeam")]
+fn test_dedup_rows_watch() {
+    #[layout("aos", watch)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Term {
+        op: u8,
+        #[soa(delta)]
+        lhs: u32,
+    }
+
+    // Every removed duplicate is reported to the subscribers
+    let mut terms = TermsLayout::new();
+    let events = terms.subscribe();
+    for (op, lhs) in [(7, 1), (7, 1), (7, 1)] {
+        terms.add(Term { op, lhs });
+    }
+    let remap = terms.dedup_rows();
+    assert_eq!(
+        remap.remap_slice(&[TermId(0), TermId(1), TermId(2)]),
+        vec![TermId(0); 3]
+    );
+    assert_eq!(terms.len(), 1);
+    let removed = events
+        .try_iter()
+        .filter(|event| matches!(event, soaaos::watch::Event::Removed(_)))
+        .count();
+    assert_eq!(removed, 2);
+}
+
+#[test]
+fn test_stable_ids() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Span {
+        start: u32,
+        end: u32,
+        #[soa(rle)]
+        file: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Frame {
+        pc: u64,
+        depth: u16,
+    }
+
+    let mut spans = SpansLayout::new();
+    for (start, end) in [(0, 4), (4, 9), (9, 12), (12, 20)] {
+        spans.add(Span {
+            start,
+            end,
+            file: 1,
+        });
+    }
+    let second = spans.stable_id(SpanId(1)).unwrap();
+    let last = spans.stable_id(SpanId(3)).unwrap();
+    assert_ne!(second, last);
+    assert!(spans.stable_id(SpanId(4)).is_err());
+
+    // Stable Ids survive removals and reorders, which change the dense Ids
+    spans.remove(SpanId(0)).unwrap();
+    assert_eq!(spans.resolve(second), Some(SpanId(0)));
+    spans.reverse();
+    assert_eq!(spans.resolve(second), Some(SpanId(2)));
+    assert_eq!(spans.resolve(last), Some(SpanId(0)));
+    spans.swap_remove(SpanId(1)).unwrap();
+    assert_eq!(spans.resolve(second), Some(SpanId(1)));
+
+    // Writing or removing the node leaves nothing to resolve
+    spans.set_end(SpanId(1), 10).unwrap();
+    assert_eq!(spans.resolve(second), None);
+    spans.remove(SpanId(0)).unwrap();
+    assert_eq!(spans.resolve(last), None);
+
+    let mut frames = FramesLayout::new();
+    for depth in 0..3 {
+        frames.add(Frame {
+            pc: 0x400000 + u64::from(depth) * 16,
+            depth,
+        });
+    }
+    let top = frames.stable_id(FrameId(2)).unwrap();
+    frames.retain(|frame| *frame.depth != 0);
+    frames.rotate_left(1);
+    assert_eq!(frames.resolve(top), Some(FrameId(0)));
+}
+
+#[test]
+fn test_hashcons() {
+    #[layout("soa", hashcons)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Expr {
+        op: u8,
+        lhs: u32,
+        rhs: u32,
+    }
+
+    #[layout("aos", hashcons)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Type {
+        kind: u8,
+        name: String,
+    }
+
+    let expr = |op, lhs, rhs| Expr { op, lhs, rhs };
+    let mut exprs = ExprsLayout::new();
+    let a = exprs.add_unique(expr(1, 0, 0));
+    let b = exprs.add_unique(expr(2, 0, 1));
+    assert_eq!(exprs.add_unique(expr(1, 0, 0)), a);
+    assert_eq!(exprs.add_unique(expr(2, 0, 1)), b);
+    assert_eq!(exprs.len(), 2);
+
+    // Plain adds are indexed by the next lookup
+    let c = exprs.add(expr(3, 1, 1));
+    assert_eq!(exprs.add_unique(expr(3, 1, 1)), c);
+
+    // Writes and removals rebuild the index
+    exprs.set_op(a, 9).unwrap();
+    assert_eq!(exprs.add_unique(expr(9, 0, 0)), a);
+    assert_eq!(exprs.add_unique(expr(1, 0, 0)), ExprId(3));
+    exprs.remove(a).unwrap();
+    assert_eq!(exprs.add_unique(expr(3, 1, 1)), ExprId(1));
+    *exprs.get_mut(ExprId(0)).unwrap().rhs = 5;
+    assert_eq!(exprs.add_unique(expr(2, 0, 5)), ExprId(0));
+    assert_eq!(exprs.len(), 3);
+
+    // The index isn't part of the layout's value
+    assert!(!format!("{exprs:?}").contains("hashcons"));
+    assert_eq!(exprs.clone(), exprs);
+
+    let mut types = TypesLayout::new();
+    let int = types.add_unique(Type {
+        kind: 0,
+        name: "int".to_string(),
+    });
+    types.add_unique(Type {
+        kind: 0,
+        name: "bool".to_string(),
+    });
+    assert_eq!(
+        types.add_unique(Type {
+            kind: 0,
+            name: "int".to_string()
+        }),
+        int
+    );
+    types.reverse();
+    assert_eq!(
+        types.add_unique(Type {
+            kind: 0,
+            name: "int".to_string()
+        }),
+        TypeId(1)
+    );
+    assert_eq!(types.len(), 2);
+}
+
+#[test]
+fn test_ring_layout() {
+    use std::sync::{Arc, Mutex};
+
+    #[layout(soa, ring = 3)]
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct Event {
+        time: u64,
+        #[soa(rle)]
+        level: u8,
+    }
+
+    #[layout(aos, ring = 2)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Span {
+        start: u64,
+        end: u64,
+    }
+
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let mut events = EventsLayout::new();
+    let sink = evicted.clone();
+    events.on_evict(move |event: Event| sink.lock().unwrap().push(event.time));
+    assert_eq!(EventsLayout::RING_CAPACITY, 3);
+
+    // Adding to a full layout overwrites the oldest node in its slot, keeping the Ids of the
+    // other nodes
+    let first = events.add(Event { time: 0, level: 1 });
+    let second = events.add(Event { time: 1, level: 1 });
+    let third = events.add(Event { time: 2, level: 1 });
+    assert!(events.is_full());
+    assert_eq!(events.add(Event { time: 3, level: 1 }), first);
+    assert_eq!(events.add(Event { time: 4, level: 2 }), second);
+    assert_eq!(events.get_time(third).unwrap(), &2);
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&3, &4, &2]);
+    assert_eq!(
+        events.iter_ids_by_age().collect::<Vec<_>>(),
+        vec![third, first, second]
+    );
+    assert_eq!(*evicted.lock().unwrap(), vec![0, 1]);
+
+    let id = events.push_with(|event| *event.time = 9);
+    assert_eq!(id, third);
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&3, &4, &9]);
+    assert_eq!(events.level().collect::<Vec<_>>(), vec![&1, &2, &0]);
+
+    // Copying many nodes adds them one at a time, returning the Ids of those still held. The
+    // callback isn't part of the layout's value.
+    let other = events.clone();
+    assert!(other == events);
+    let ids = events.extend_from_layout(&other, &[EventId(2), EventId(0)]);
+    assert_eq!(ids, vec![EventId(0), EventId(1)]);
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&9, &3, &9]);
+    assert_eq!(*evicted.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+
+    // Removing a node brings the oldest node back to the first slot, which the map of the moved
+    // Ids follows
+    let (removed, map) = events.remove(EventId(0)).unwrap();
+    assert_eq!(removed.time, 9);
+    assert!(!events.is_full());
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&9, &3]);
+    assert_eq!(map.remap(EventId(0)), None);
+    assert_eq!(map.remap(EventId(1)), Some(EventId(1)));
+    assert_eq!(map.remap(EventId(2)), Some(EventId(0)));
+    assert_eq!(events.add(Event { time: 5, level: 0 }), EventId(2));
+    assert_eq!(events.add(Event { time: 6, level: 0 }), EventId(0));
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&6, &3, &5]);
+
+    // Reordering the nodes makes their new order the order of eviction
+    events.reverse();
+    assert_eq!(events.add(Event { time: 7, level: 0 }), EventId(0));
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&7, &3, &6]);
+
+    // Clones keep the order of eviction, but start without the callback
+    let mut other = events.clone();
+    assert_eq!(other.add(Event { time: 10, level: 0 }), EventId(1));
+    assert_eq!(evicted.lock().unwrap().len(), 7);
+
+    let mut spans = SpansLayout::new();
+    for start in 0..4 {
+        spans.add(Span {
+            start,
+            end: start + 1,
+        });
+    }
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans.get_start(SpanId(0)).unwrap(), &2);
+    let ids = spans.extend_from_layout(&spans.clone(), &[SpanId(1), SpanId(0), SpanId(1)]);
+    assert_eq!(ids, vec![SpanId(1), SpanId(0)]);
+    assert_eq!(spans.start().collect::<Vec<_>>(), vec![&3, &2]);
+    assert_eq!(
+        spans.iter_ids_by_age().collect::<Vec<_>>(),
+        vec![SpanId(1), SpanId(0)]
+    );
+
+    // Layouts holding the same rows in the same slots are equal, whatever their oldest node
+    let mut wrapped = SpansLayout::new();
+    let mut filled = SpansLayout::new();
+    for start in [0, 1, 2] {
+        wrapped.add(Span { start, end: 0 });
+    }
+    for start in [2, 1] {
+        filled.add(Span { start, end: 0 });
+    }
+    assert_ne!(
+        wrapped.iter_ids_by_age().collect::<Vec<_>>(),
+        filled.iter_ids_by_age().collect::<Vec<_>>()
+    );
+    assert_eq!(wrapped, filled);
+    assert_eq!(
+        wrapped.partial_cmp(&filled),
+        Some(std::cmp::Ordering::Equal)
+    );
+
+    // Building a layout from more nodes than it holds only keeps the newest of them
+    assert_eq!(EventsLayout::default_rows(5).len(), 3);
+    let events = EventsLayout::from_columns(EventsColumns {
+        time: (0..5).collect(),
+        level: vec![0; 5],
+    });
+    assert_eq!(events.time().collect::<Vec<_>>(), vec![&2, &3, &4]);
+    let ids: Vec<SpanId> = [0, 1, 0, 1].into_iter().map(SpanId).collect();
+    let gathered = spans.gather(&ids);
+    assert_eq!(gathered.start().collect::<Vec<_>>(), vec![&3, &2]);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = r#"{"time":[0,1,2,3,4],"level":[1,1,1,1,2]}"#;
+        let loaded: EventsLayout = serde_json::from_str(json).unwrap();
+        assert_eq!(loaded.time().collect::<Vec<_>>(), vec![&2, &3, &4]);
+        assert_eq!(loaded.level().collect::<Vec<_>>(), vec![&1, &1, &2]);
+    }
+}
+
+#[test]
+fn test_key_field() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Setting {
+        #[soa(key)]
+        name: String,
+        value: i64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Symbol {
+        #[soa(key)]
+        addr: u64,
+        size: u32,
+    }
+
+    let setting = |name: &str, value| Setting {
+        name: name.to_string(),
+        value,
+    };
+    let mut settings = SettingsLayout::new();
+    settings.add(setting("width", 80));
+    settings.add(setting("height", 24));
+    settings.add(setting("depth", 8));
+
+    // Keys are looked up through `Borrow`, in the order the nodes were added
+    assert_eq!(settings.get_index_of("height"), Some(1));
+    assert_eq!(settings.get_index_of("color"), None);
+    assert_eq!(*settings.get_index(2).unwrap().value, 8);
+    assert!(settings.get_index(3).is_none());
+
+    // Moving a node shifts the nodes in between, and the index follows
+    let map = settings.move_index(0, 2);
+    assert_eq!(
+        settings.name().collect::<Vec<_>>(),
+        vec!["height", "depth", "width"]
+    );
+    assert_eq!(
+        map.remap_slice(&[SettingId(0), SettingId(1), SettingId(2)]),
+        vec![SettingId(2), SettingId(0), SettingId(1)]
+    );
+    assert_eq!(settings.get_index_of("width"), Some(2));
+    settings.move_index(2, 1);
+    assert_eq!(settings.get_index_of("width"), Some(1));
+    assert_eq!(settings.get_index_of("depth"), Some(2));
+
+    // Writes and removals rebuild the index
+    settings.set_name(SettingId(0), "rows".to_string()).unwrap();
+    assert_eq!(settings.get_index_of("height"), None);
+    assert_eq!(settings.get_index_of("rows"), Some(0));
+    settings.remove(SettingId(0)).unwrap();
+    assert_eq!(settings.get_index_of("depth"), Some(1));
+    assert_eq!(settings.clone(), settings);
+
+    // Lookups only borrow the layout, so they work while iterating over it
+    let shared = &settings;
+    let indexes: Vec<_> = shared
+        .iter()
+        .map(|setting| shared.get_index_of(setting.name.as_str()))
+        .collect();
+    assert_eq!(indexes, vec![Some(0), Some(1)]);
+
+    let mut symbols = SymbolsLayout::new();
+    for (addr, size) in [(0x1000, 16), (0x2000, 32), (0x3000, 8)] {
+        symbols.add(Symbol { addr, size });
+    }
+    symbols.move_index(2, 0);
+    assert_eq!(symbols.get_index_of(&0x3000), Some(0));
+    assert_eq!(symbols.get_index_of(&0x1000), Some(1));
+    assert_eq!(*symbols.get_index(2).unwrap().size, 32);
+}
+
+#[test]
+fn test_versioned() {
+    #[layout("soa", versioned)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Mesh {
+        name: String,
+        vertices: u32,
+    }
+
+    #[layout("aos", versioned)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Texture {
+        width: u32,
+        height: u32,
+    }
+
+    let mesh = |name: &str, vertices| Mesh {
+        name: name.to_string(),
+        vertices,
+    };
+    let mut meshes = MeshsLayout::new();
+    assert_eq!(meshes.current_version(), 0);
+    let cube = meshes.add(mesh("cube", 8));
+    let quad = meshes.add(mesh("quad", 4));
+    let tri = meshes.add(mesh("tri", 3));
+    assert_eq!(meshes.changed_since(0).count(), 3);
+
+    // Nothing changed since the version a cache was built at
+    let built = meshes.current_version();
+    assert_eq!(meshes.version(quad).unwrap(), built);
+    assert_eq!(meshes.changed_since(built).count(), 0);
+
+    meshes.set_vertices(quad, 6).unwrap();
+    meshes.get_mut(cube).unwrap().name.push('!');
+    assert_eq!(
+        meshes.changed_since(built).collect::<Vec<_>>(),
+        vec![cube, quad]
+    );
+    assert!(meshes.version(cube).unwrap() > meshes.version(quad).unwrap());
+    assert_eq!(meshes.version(tri).unwrap(), built);
+
+    // Removing a node moves every later node to another Id
+    let before_remove = meshes.current_version();
+    meshes.remove(cube).unwrap();
+    assert_eq!(meshes.changed_since(before_remove).count(), 2);
+    assert!(meshes.version(MeshId(2)).is_err());
+
+    // Versions aren't part of the layout's value
+    assert_eq!(meshes.clone(), meshes);
+
+    let mut textures = TexturesLayout::new();
+    let small = textures.add(Texture {
+        width: 16,
+        height: 16,
+    });
+    let large = textures.add(Texture {
+        width: 1024,
+        height: 1024,
+    });
+    let built = textures.current_version();
+    textures.set_width(small, 32).unwrap();
+    assert_eq!(
+        textures.changed_since(built).collect::<Vec<_>>(),
+        vec![small]
+    );
+    textures.reverse();
+    assert_eq!(textures.changed_since(built).count(), 2);
+    assert!(textures.version(large).unwrap() > built);
+}
+
+#[test]
+fn test_epochs() {
+    #[layout("soa", epochs)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Body {
+        x: i32,
+        y: i32,
+    }
+
+    let mut bodies = BodysLayout::new();
+    let a = bodies.add(Body { x: 0, y: 0 });
+    let b = bodies.add(Body { x: 5, y: 5 });
+    assert_eq!(bodies.current_epoch(), 0);
+    assert_eq!(bodies.changed_since_epoch(0).count(), 2);
+
+    // Added nodes keep the epoch they were added in
+    assert_eq!(bodies.advance_epoch(), 1);
+    let c = bodies.add(Body { x: 9, y: 9 });
+    assert_eq!(bodies.epoch(a).unwrap(), 0);
+    assert_eq!(bodies.epoch(c).unwrap(), 1);
+    assert_eq!(bodies.changed_since_epoch(1).collect::<Vec<_>>(), vec![c]);
+
+    // Only the nodes written this frame are reported
+    bodies.advance_epoch();
+    bodies.set_x(b, 6).unwrap();
+    *bodies.get_mut(a).unwrap().y += 1;
+    let frame = bodies.current_epoch();
+    assert_eq!(
+        bodies.changed_since_epoch(frame).collect::<Vec<_>>(),
+        vec![a, b]
+    );
+    assert_eq!(bodies.epoch(c).unwrap(), 1);
+
+    bodies.advance_epoch();
+    assert_eq!(
+        bodies.changed_since_epoch(bodies.current_epoch()).count(),
+        0
+    );
+    bodies.swap_remove(a).unwrap();
+    assert_eq!(
+        bodies
+            .changed_since_epoch(bodies.current_epoch())
+            .collect::<Vec<_>>(),
+        vec![a]
+    );
+    assert!(bodies.epoch(BodyId(2)).is_err());
+}
+
+#[test]
+fn test_persistent_layout() {
+    #[layout("persistent")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Cell {
+        glyph: char,
+        color: u8,
+    }
+
+    let empty = CellsLayout::new();
+    let (one, first) = empty.add(Cell {
+        glyph: 'a',
+        color: 1,
+    });
+    assert!(empty.is_empty());
+    assert_eq!(one.len(), 1);
+
+    // Every edit is a new layout, and every old layout stays usable for undo
+    let mut history = vec![one];
+    for index in 1..2000u32 {
+        let (next, id) = history.last().unwrap().add(Cell {
+            glyph: char::from(b'a' + (index % 26) as u8),
+            color: (index % 7) as u8,
+        });
+        assert_eq!(id, CellId(index));
+        history.push(next);
+    }
+    let latest = history.last().unwrap();
+    assert_eq!(latest.len(), 2000);
+    assert_eq!(*latest.get_glyph(CellId(1999)).unwrap(), 'x');
+    assert_eq!(*latest.get_color(CellId(1500)).unwrap(), 2);
+    assert_eq!(history[999].len(), 1000);
+
+    let edited = latest.set_color(CellId(700), 42).unwrap();
+    assert_eq!(*edited.get_color(CellId(700)).unwrap(), 42);
+    assert_eq!(*latest.get_color(CellId(700)).unwrap(), 0);
+    assert!(edited.glyph.ptr_eq(&latest.glyph));
+    assert_ne!(&edited, latest);
+    assert_eq!(edited.set_color(CellId(700), 0).unwrap(), *latest);
+    assert!(latest.set_color(CellId(2000), 0).is_err());
+
+    assert_eq!(edited.get(first).unwrap().glyph, &'a',);
+    assert_eq!(edited.color().filter(|color| **color == 42).count(), 1);
+    assert_eq!(
+        edited
+            .iter()
+            .map(|cell| Cell {
+                glyph: *cell.glyph,
+                color: *cell.color,
+            })
+            .collect::<CellsLayout>(),
+        edited
+    );
+}
+
+#[test]
+#[cfg(all(feature = "slotmap", feature = "generational_arena"))]
+fn test_slotmap_and_arena_interop() {
+    use generational_arena::Arena;
+    use slotmap::{DefaultKey, SlotMap};
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Body {
+        mass: u32,
+        radius: u32,
+    }
+
+    // Rows are numbered densely, so keys after a removal move to lower Ids
+    let mut map = SlotMap::new();
+    let keys: Vec<DefaultKey> = (0..4)
+        .map(|i| map.insert(Body { mass: i, radius: 1 }))
+        .collect();
+    map.remove(keys[1]);
+    let reused = map.insert(Body { mass: 9, radius: 1 });
+    let (bodies, ids) = BodysLayout::from_slotmap(map);
+    assert_eq!(bodies.len(), 4);
+    assert_eq!(ids[keys[0]], BodyId(0));
+    assert_eq!(bodies.get(ids[keys[3]]).unwrap().mass, &3);
+    assert_eq!(bodies.get(ids[reused]).unwrap().mass, &9);
+    assert!(!ids.contains_key(keys[1]));
+
+    let mut arena = Arena::new();
+    let indexes: Vec<_> = (0..3)
+        .map(|i| arena.insert(Body { mass: i, radius: 2 }))
+        .collect();
+    arena.remove(indexes[0]);
+    let (bodies, ids) = BodysLayout::from_arena(arena);
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(ids[&indexes[2]], BodyId(1));
+    assert_eq!(bodies.get(ids[&indexes[1]]).unwrap().mass, &1);
+    assert!(!ids.contains_key(&indexes[0]));
+}
+
+#[test]
+fn test_derived_layout() {
+    use soaaos::derived::Derived;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Shape {
+        x: i32,
+        y: i32,
+        #[soa(delta)]
+        size: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Rect {
+        min: (i32, i32),
+        max: (i32, i32),
+    }
+
+    let mut shapes = ShapesLayout::new();
+    for i in 0..4 {
+        shapes.add(Shape {
+            x: i * 10,
+            y: 0,
+            size: 2,
+        });
+    }
+
+    let mut computed = 0;
+    let mut boxes = Derived::new(RectsLayout::new(), |shapes: &ShapesLayout, id| {
+        computed += 1;
+        let (x, y) = (*shapes.get_x(id).unwrap(), *shapes.get_y(id).unwrap());
+        let size = shapes.get_size(id).unwrap() as i32;
+        Rect {
+            min: (x, y),
+            max: (x + size, y + size),
+        }
+    });
+    assert!(boxes.is_empty());
+
+    assert_eq!(boxes.refresh(&shapes), 4);
+    assert_eq!(boxes.get_max(RectId(3)).unwrap(), &(32, 2));
+
+    // Only the changed and added rows are recomputed
+    assert_eq!(boxes.refresh(&shapes), 0);
+    shapes.set_size(ShapeId(1), 5).unwrap();
+    *shapes.get_y_mut(ShapeId(2)).unwrap() = 0;
+    shapes.add(Shape {
+        x: 100,
+        y: 100,
+        size: 1,
+    });
+    assert_eq!(boxes.refresh(&shapes), 2);
+    assert_eq!(boxes.get_max(RectId(1)).unwrap(), &(15, 5));
+    assert_eq!(boxes.get_min(RectId(4)).unwrap(), &(100, 100));
+
+    // Removed rows are dropped, and the rows shifted into their place recomputed
+    shapes.remove(ShapeId(0)).unwrap();
+    assert_eq!(boxes.refresh(&shapes), 4);
+    assert_eq!(boxes.len(), 4);
+    assert_eq!(boxes.get_min(RectId(0)).unwrap(), &(10, 0));
+
+    let boxes = boxes.into_layout();
+    assert_eq!(boxes.len(), 4);
+    drop(boxes);
+    assert_eq!(computed, 10);
+}
+
+#[test]
+#[cfg(feature = "crossbeam")]
+fn test_watch_layout() {
+    use soaaos::watch::Event;
+
+    #[layout(soa, watch)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Sensor {
+        value: u32,
+        name: String,
+    }
+
+    #[layout(aos, watch)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Probe {
+        value: u32,
+    }
+
+    let mut sensors = SensorsLayout::new();
+    let events = sensors.subscribe();
+
+    let id = sensors.add(Sensor {
+        value: 1,
+        name: "a".to_string(),
+    });
+    sensors.set_value(id, 2).unwrap();
+    sensors.get_name_mut(id).unwrap().push('b');
+    assert!(sensors.get_value_mut(SensorId(5)).is_err());
+    sensors.reverse();
+    sensors.remove(id).unwrap();
+
+    let received: Vec<_> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            Event::Added(id),
+            Event::Changed { id, field: "value" },
+            Event::Changed { id, field: "name" },
+            Event::Reordered,
+            Event::Removed(id),
+        ]
+    );
+
+    // Dropped receivers are unsubscribed on the next event
+    drop(events);
+    sensors.add(Sensor {
+        value: 3,
+        name: String::new(),
+    });
+
+    let mut probes = ProbesLayout::new();
+    let events = probes.subscribe();
+    let id = probes.add(Probe { value: 1 });
+    *probes.get_value_mut(id).unwrap() += 1;
+    probes.scatter_value(&[(id, 5)]).unwrap();
+    assert_eq!(
+        events.try_iter().collect::<Vec<_>>(),
+        vec![
+            Event::Added(id),
+            Event::Changed { id, field: "value" },
+            Event::Changed { id, field: "value" },
+        ]
+    );
+}
+
+#[test]
+#[cfg(feature = "color")]
+fn test_diff_pretty() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Sample {
+        value: u32,
+        label: &'static str,
+    }
+
+    let mut old = SamplesLayout::new();
+    for value in 0..6 {
+        old.add(Sample { value, label: "a" });
+    }
+
+    let mut new = old.clone();
+    assert!(old.diff_pretty(&new, 1).is_none());
+
+    new.set_value(SampleId(4), 40).unwrap();
+    new.add(Sample {
+        value: 6,
+        label: "b",
+    });
+
+    let diff = old.diff_pretty(&new, 1).unwrap();
+    let plain = diff
+        .split('\x1b')
+        .enumerate()
+        .map(|(i, part)| {
+            if i == 0 {
+                part
+            } else {
+                &part[part.find('m').unwrap() + 1..]
+            }
+        })
+        .collect::<String>();
+    assert_eq!(
+        plain,
+        "@@ rows 3..7 @@\n  \
+           row 3 { value: 3, label: \"a\" }\n  \
+           row 4\n\
+         -     value: 4\n\
+         +     value: 40\n      \
+               label: \"a\"\n  \
+           row 5 { value: 5, label: \"a\" }\n  \
+           row 6\n\
+         +     value: 6\n\
+         +     label: \"b\"\n"
+    );
+    assert!(diff.contains("\x1b[31m-     value: 4"));
+    assert!(diff.contains("\x1b[32m+     value: 40"));
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_diff_json_patch() {
+    use serde_json::json;
+
+    #[layout(aos)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Reading {
+        sensor: u8,
+        value: i32,
+    }
+
+    let mut old = ReadingsLayout::new();
+    for value in 0..3 {
+        old.add(Reading { sensor: 1, value });
+    }
+
+    let mut new = old.clone();
+    assert_eq!(old.diff_json_patch(&new).unwrap(), json!([]));
+
+    new.set_value(ReadingId(1), 10).unwrap();
+    new.add(Reading {
+        sensor: 2,
+        value: 3,
+    });
+    assert_eq!(
+        old.diff_json_patch(&new).unwrap(),
+        json!([
+            { "op": "replace", "path": "/value/1", "value": 10 },
+            { "op": "add", "path": "/sensor/-", "value": 2 },
+            { "op": "add", "path": "/value/-", "value": 3 },
+        ])
+    );
+
+    // Rows past the end of `other` are removed from the last
+    assert_eq!(
+        new.diff_json_patch(&old).unwrap(),
+        json!([
+            { "op": "replace", "path": "/value/1", "value": 1 },
+            { "op": "remove", "path": "/sensor/3" },
+            { "op": "remove", "path": "/value/3" },
+        ])
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn test_json_chunks() {
+    use std::io::BufRead;
+
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sample {
+        #[soa(rle)]
+        sensor: u8,
+        label: String,
+    }
+
+    let mut samples = SamplesLayout::new();
+    for index in 0..5 {
+        samples.add(Sample {
+            sensor: index / 2,
+            label: format!("s{index}"),
+        });
+    }
+
+    let mut bytes = Vec::new();
+    assert_eq!(samples.write_chunks(&mut bytes, 2).unwrap(), 3);
+    assert_eq!(bytes.lines().count(), 3);
+
+    // Loading can stop after any chunk, and resume with the same reader
+    let mut reader = bytes.as_slice();
+    let mut loaded = SamplesLayout::new();
+    assert!(loaded.append_chunk(&mut reader).unwrap());
+    assert_eq!(loaded.len(), 2);
+    while loaded.append_chunk(&mut reader).unwrap() {}
+    assert_eq!(loaded, samples);
+    assert!(!loaded.append_chunk(&mut reader).unwrap());
+
+    // A truncated chunk is an error instead of a partial load
+    let mut reader = &bytes[..bytes.len() - 4];
+    let mut loaded = SamplesLayout::new();
+    assert!(loaded.append_chunk(&mut reader).unwrap());
+    assert!(loaded.append_chunk(&mut reader).unwrap());
+    assert!(loaded.append_chunk(&mut reader).is_err());
+    assert_eq!(loaded.len(), 4);
+}
+
+#[test]
+fn test_transaction() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Account {
+        #[soa(unique)]
+        name: String,
+        balance: i64,
+        #[soa(references)]
+        parent: Option<AccountId>,
+    }
+
+    let mut accounts = AccountsLayout::new();
+    let root = accounts.add(Account {
+        name: "root".to_string(),
+        balance: 100,
+        parent: None,
+    });
+
+    let child = accounts
+        .transaction(|tx| {
+            let child = tx.add(Account {
+                name: "child".to_string(),
+                balance: 0,
+                parent: Some(root),
+            });
+            tx.set_balance(root, 60);
+            tx.set_balance(child, 40);
+            child
+        })
+        .unwrap();
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(accounts.get_balance(root).unwrap(), &60);
+    assert_eq!(accounts.get_balance(child).unwrap(), &40);
+
+    // A failing transaction leaves the layout unchanged
+    let before = accounts.clone();
+    let result = accounts.transaction(|tx| {
+        tx.set_balance(root, 0);
+        tx.add(Account {
+            name: "child".to_string(),
+            balance: 0,
+            parent: None,
+        });
+    });
+    assert_eq!(
+        result,
+        Err(AccountsTransactionError::Duplicate {
+            row: 2,
+            field: "name"
+        })
+    );
+    assert_eq!(accounts, before);
+
+    let result = accounts.transaction(|tx| {
+        tx.remove(child);
+        tx.set_balance(child, 1);
+    });
+    assert_eq!(
+        result,
+        Err(AccountsTransactionError::NotFound { op: 1, row: 1 })
+    );
+
+    let result = accounts.transaction(|tx| tx.set_parent(root, Some(AccountId(5))));
+    assert_eq!(
+        result,
+        Err(AccountsTransactionError::DanglingReference {
+            row: 0,
+            field: "parent"
+        })
+    );
+    assert_eq!(accounts, before);
+}
+
+#[test]
+fn test_compare_and_set() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Slot {
+        version: u32,
+        #[soa(rle)]
+        owner: String,
+    }
+
+    let mut slots = SlotsLayout::new();
+    let id = slots.add(Slot {
+        version: 1,
+        owner: "a".to_string(),
+    });
+
+    assert_eq!(slots.compare_and_set_version(id, &1, 2).unwrap(), Ok(1));
+    assert_eq!(slots.compare_and_set_version(id, &1, 3).unwrap(), Err(2));
+    assert_eq!(slots.get_version(id).unwrap(), &2);
+
+    assert_eq!(
+        slots
+            .compare_and_set_owner(id, &"b".to_string(), "c".to_string())
+            .unwrap(),
+        Err("a".to_string())
+    );
+    assert_eq!(
+        slots
+            .compare_and_set_owner(id, &"a".to_string(), "c".to_string())
+            .unwrap(),
+        Ok("a".to_string())
+    );
+    assert_eq!(slots.get_owner(id).unwrap(), "c");
+
+    assert!(slots.compare_and_set_version(SlotId(1), &0, 1).is_err());
+}
+
+#[test]
+fn test_atomic_column() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Block {
+        address: u64,
+        #[soa(atomic)]
+        hits: u64,
+    }
+
+    let mut blocks = BlocksLayout::new();
+    let ids: Vec<_> = (0..4)
+        .map(|address| blocks.add(Block { address, hits: 0 }))
+        .collect();
+
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let blocks = &blocks;
+            let ids = &ids;
+            scope.spawn(move || {
+                for _ in 0..1000 {
+                    for &id in ids {
+                        blocks.fetch_add_hits(id, 1).unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    assert!(blocks.hits().all(|hits| hits == 4000));
+    assert_eq!(blocks.load_hits_acquire(ids[2]).unwrap(), 4000);
+
+    blocks.store_hits_release(ids[1], 7).unwrap();
+    blocks
+        .get_hits_atomic(ids[1])
+        .unwrap()
+        .fetch_max(9, std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(blocks.get_hits(ids[1]).unwrap(), 9);
+
+    blocks.set_hits(ids[0], 1).unwrap();
+    assert_eq!(blocks.clone().get_hits(ids[0]).unwrap(), 1);
+    assert!(blocks.fetch_add_hits(BlockId(4), 1).is_err());
+}
+
+#[test]
+fn test_cell_column() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sprite {
+        name: String,
+        #[soa(cell)]
+        visible: bool,
+        #[soa(cell)]
+        frame: u32,
+    }
+
+    let mut sprites = SpritesLayout::new();
+    let player = sprites.add(Sprite {
+        name: "player".to_string(),
+        visible: true,
+        frame: 0,
+    });
+    let enemy = sprites.add(Sprite {
+        name: "enemy".to_string(),
+        visible: true,
+        frame: 3,
+    });
+
+    // Written while the names are borrowed from the same layout
+    for (id, name) in sprites.get_name_enumerated() {
+        if name == "enemy" {
+            sprites.set_visible(id, false).unwrap();
+        }
+        sprites
+            .set_frame(id, sprites.get_frame(id).unwrap() + 1)
+            .unwrap();
+    }
+
+    assert!(sprites.get_visible(player).unwrap());
+    assert!(!sprites.get_visible(enemy).unwrap());
+    assert_eq!(sprites.frame().collect::<Vec<_>>(), vec![1, 4]);
+    assert!(sprites.set_frame(SpriteId(2), 0).is_err());
+
+    sprites.remove(player).unwrap();
+    assert_eq!(sprites.get(SpriteId(0)).unwrap().frame, 4);
+    assert!(!sprites.clone().get_visible(SpriteId(0)).unwrap());
+}
+
+#[test]
+fn test_frozen_fields() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Node {
+        #[soa(frozen)]
+        kind: u8,
+        value: u32,
+    }
+
+    #[layout(aos)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Token {
+        #[soa(frozen)]
+        kind: u8,
+        #[soa(frozen)]
+        start: usize,
+        end: usize,
+    }
+
+    let mut nodes = NodesLayout::new();
+    let first = nodes.add(Node { kind: 1, value: 10 });
+    nodes.add(Node { kind: 2, value: 5 });
+
+    // The mutable view only holds the fields that aren't frozen
+    let NodeRefMut { value } = nodes.get_mut(first).unwrap();
+    *value += 1;
+    nodes.set_value(first, 20).unwrap();
+    nodes.scatter_value(&[(NodeId(1), 30)]).unwrap();
+    assert_eq!(nodes.get_kind(first).unwrap(), &1);
+    assert_eq!(nodes.value().collect::<Vec<_>>(), vec![&20, &30]);
+
+    // Frozen values still move with their node
+    nodes.reverse();
+    assert_eq!(nodes.kind().collect::<Vec<_>>(), vec![&2, &1]);
+    nodes.remove(NodeId(0)).unwrap();
+    assert_eq!(nodes.get_kind(NodeId(0)).unwrap(), &1);
+
+    let mut tokens = TokensLayout::new();
+    let token = tokens.add(Token {
+        kind: 3,
+        start: 0,
+        end: 4,
+    });
+    let TokenRefMut { end } = tokens.get_mut(token).unwrap();
+    *end = 5;
+    tokens.set_end(token, 6).unwrap();
+    assert_eq!(tokens.get_start(token).unwrap(), &0);
+    assert_eq!(tokens.get_end(token).unwrap(), &6);
+}
+
+#[test]
+fn test_grouped_fields() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Particle {
+        #[soa(group = "pos")]
+        x: f32,
+        mass: f32,
+        #[soa(group = "pos")]
+        y: f32,
+        #[soa(group = "vel")]
+        dx: f32,
+        #[soa(group = "vel")]
+        dy: f32,
+    }
+
+    #[layout(aos)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Point {
+        #[soa(group = "pos")]
+        x: f32,
+        #[soa(group = "pos")]
+        y: f32,
+    }
+
+    let particle = |i: u32| Particle {
+        x: i as f32,
+        mass: 1.0,
+        y: -(i as f32),
+        dx: 0.5,
+        dy: 0.25,
+    };
+    let mut particles = ParticlesLayout::new();
+    for i in 0..4 {
+        particles.add(particle(i));
+    }
+
+    // `x` and `y` are stored together in a single column
+    assert_eq!(particles.pos[2], ParticlePos(2.0, -2.0));
+    assert_eq!(particles.vel.len(), 4);
+    assert_eq!(particles.get_y(ParticleId(1)).unwrap(), &-1.0);
+    assert_eq!(
+        particles.x().collect::<Vec<_>>(),
+        vec![&0.0, &1.0, &2.0, &3.0]
+    );
+
+    // The fields of a group are written and borrowed like any other field
+    particles.set_x(ParticleId(0), 10.0).unwrap();
+    *particles.get_dy_mut(ParticleId(0)).unwrap() = 1.0;
+    let view = particles.get_mut(ParticleId(3)).unwrap();
+    *view.x += *view.dx;
+    *view.y += *view.dy;
+    particles
+        .update(ParticleId(1), |particle| *particle.mass = 2.0)
+        .unwrap();
+    assert_eq!(particles.pos[3], ParticlePos(3.5, -2.75));
+
+    // Rows keep the fields of their groups through removals and reorders
+    let (removed, _) = particles.swap_remove(ParticleId(0)).unwrap();
+    assert_eq!(
+        removed,
+        Particle {
+            x: 10.0,
+            dy: 1.0,
+            ..particle(0)
+        }
+    );
+    particles.reverse();
+    assert_eq!(particles.get_x(ParticleId(0)).unwrap(), &2.0);
+    assert_eq!(particles.get_mass(ParticleId(1)).unwrap(), &2.0);
+    assert_eq!(
+        particles.gather(&[ParticleId(0)]).iter_cloned().next(),
+        Some(particle(2))
+    );
+
+    let columns = particles.clone().into_columns();
+    assert_eq!(columns.y, vec![-2.0, -1.0, -2.75]);
+    assert_eq!(ParticlesLayout::from_columns(columns), particles);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&particles).unwrap();
+        assert!(json.contains(r#""x":[2.0,1.0,3.5]"#));
+        let loaded: ParticlesLayout = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, particles);
+    }
+
+    // Array-of-structs layouts already store every field of a node together
+    let mut points = PointsLayout::new();
+    let point = points.add(Point { x: 1.0, y: 2.0 });
+    assert_eq!(points.get_y(point).unwrap(), &2.0);
+}
+
+#[test]
+fn test_sharded_layout() {
+    #[layout(aos)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Event {
+        thread: u32,
+        seq: u32,
+    }
+
+    let sharded = ShardedEventsLayout::with_flush_len(EventsLayout::new(), 16);
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let sharded = &sharded;
+            scope.spawn(move || {
+                let mut shard = sharded.shard();
+                for seq in 0..100 {
+                    shard.push(Event { thread, seq });
+                }
+                assert_eq!(shard.len(), 100 % 16);
+            });
+        }
+    });
+
+    let mut shard = sharded.shard();
+    shard.push(Event { thread: 9, seq: 0 });
+    assert_eq!(sharded.lock().len(), 400);
+    assert_eq!(shard.flush(), vec![EventId(400)]);
+    assert!(shard.flush().is_empty());
+    drop(shard);
+
+    let events = sharded.into_inner();
+    assert_eq!(events.len(), 401);
+
+    // The rows of each thread keep their order
+    for thread in 0..4 {
+        let seqs: Vec<_> = events
+            .iter()
+            .filter(|event| *event.thread == thread)
+            .map(|event| *event.seq)
+            .collect();
+        assert_eq!(seqs, (0..100).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_profile_scan() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Particle {
+        x: i32,
+        y: i32,
+        mass: u64,
+    }
+
+    let particles = ParticlesLayout::new();
+    let profile = particles.profile_scan();
+    assert_eq!(profile.rows.passes, 1);
+    assert_eq!(profile.rows.bytes, 0);
+
+    let mut particles = ParticlesLayout::new();
+    for x in 0..1000 {
+        particles.add(Particle { x, y: 0, mass: 1 });
+    }
+    let profile = particles.profile_scan();
+    let names: Vec<_> = profile.columns.iter().map(|column| column.name).collect();
+    assert_eq!(names, ["x", "y", "mass"]);
+
+    let mass = &profile.columns[2];
+    assert_eq!(mass.bytes, mass.passes * 8000);
+    assert!(mass.elapsed >= std::time::Duration::from_millis(1));
+    assert!(mass.bytes_per_sec() > 0.0);
+    assert_eq!(profile.to_string().lines().count(), 4);
+}
+
+#[test]
+fn test_chunks() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sample {
+        left: i32,
+        right: i32,
+        #[soa(rle)]
+        channel: u8,
+    }
+
+    let mut samples = SamplesLayout::new();
+    for i in 0..19 {
+        samples.add(Sample {
+            left: i,
+            right: -i,
+            channel: 0,
+        });
+    }
+
+    let mut sums = Vec::new();
+    for chunk in samples.chunks::<8>() {
+        let sum: [i32; 8] = core::array::from_fn(|lane| chunk.left[lane] + chunk.right[lane] + 1);
+        sums.push(sum);
+    }
+    assert_eq!(sums, [[1; 8], [1; 8]]);
+
+    let remainder: Vec<_> = samples
+        .chunks_remainder::<8>()
+        .map(|(id, sample)| (id, *sample.left))
+        .collect();
+    assert_eq!(
+        remainder,
+        [(SampleId(16), 16), (SampleId(17), 17), (SampleId(18), 18)]
+    );
+
+    let (blocks, rest) = samples.left_chunks::<4>();
+    assert_eq!(blocks.len(), 4);
+    assert_eq!(blocks[1], [4, 5, 6, 7]);
+    assert_eq!(rest, [16, 17, 18]);
+}
+
+#[test]
+#[cfg(feature = "portable_simd")]
+fn test_simd_chunks() {
+    use std::simd::Simd;
+
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Pixel {
+        red: u8,
+        green: u8,
+    }
+
+    let mut pixels = PixelsLayout::new();
+    for i in 0..10 {
+        pixels.add(Pixel { red: i, green: 0 });
+    }
+
+    let (lanes, rest) = pixels.red_simd_chunks::<4>();
+    let sum = lanes.fold(Simd::splat(0), |sum, lanes| sum + lanes);
+    assert_eq!(sum.to_array(), [4, 6, 8, 10]);
+    assert_eq!(rest, [8, 9]);
+
+    let rest = pixels.red_simd_chunks_mut::<4>(|lanes| *lanes *= Simd::splat(2));
+    assert_eq!(rest, [8, 9]);
+    assert_eq!(pixels.get_red(PixelId(3)).unwrap(), &6);
+}
+
+#[test]
+fn test_page_aligned_layout() {
+    use soaaos::column::PAGE_SIZE;
+
+    #[layout(soa, page_aligned)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Packet {
+        length: u16,
+        payload: Vec<u8>,
+    }
+
+    let mut packets = PacketsLayout::new();
+    assert_eq!(packets.length.allocation_len(), 0);
+
+    for length in 0..3000 {
+        packets.add(Packet {
+            length,
+            payload: vec![length as u8],
+        });
+    }
+    packets.remove(PacketId(0)).unwrap();
+
+    assert_eq!(packets.length.as_ptr() as usize % PAGE_SIZE, 0);
+    assert_eq!(packets.length.allocation_len() % PAGE_SIZE, 0);
+    assert!(packets.length.allocation_len() >= 2999 * 2);
+    assert_eq!(packets.payload.as_ptr() as usize % PAGE_SIZE, 0);
+
+    assert_eq!(packets.get_length(PacketId(0)).unwrap(), &1);
+    *packets.get_payload_mut(PacketId(1)).unwrap() = vec![9];
+    assert_eq!(packets.clone(), packets);
+    assert_eq!(packets.length_chunks::<1000>().1.len(), 999);
+
+    packets.reverse();
+    assert_eq!(packets.get_length(PacketId(0)).unwrap(), &2999);
+    assert_eq!(packets.get_payload(PacketId(2997)).unwrap(), &[9]);
+}
+
+#[test]
+fn test_memory_advice() {
+    use soaaos::column::Advice;
+
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Trace {
+        #[soa(page_aligned)]
+        addr: u64,
+        #[soa(page_aligned)]
+        cycles: u32,
+        name: String,
+    }
+
+    // Advising a column that hasn't allocated does nothing
+    let mut traces = TracesLayout::new();
+    traces.advise_sequential_addr().unwrap();
+
+    for addr in 0..100_000 {
+        traces.add(Trace {
+            addr,
+            cycles: addr as u32 % 13,
+            name: String::new(),
+        });
+    }
+
+    // Hints never change the values, even when the pages are given up
+    traces.advise_sequential_addr().unwrap();
+    assert_eq!(traces.addr().sum::<u64>(), 99_999 * 100_000 / 2);
+    traces.advise_dontneed_cycles().unwrap();
+    traces.advise_dontneed_addr().unwrap();
+    assert_eq!(traces.get_addr(TraceId(77_777)).unwrap(), &77_777);
+    assert_eq!(traces.get_cycles(TraceId(26)).unwrap(), &0);
+
+    traces.cycles.advise(Advice::WillNeed).unwrap();
+    traces.cycles.advise(Advice::Normal).unwrap();
+}
+
+#[test]
+fn test_c_header() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Edge {
+        from: EdgeId,
+        weight: u32,
+        #[soa(rle)]
+        kind: u8,
+        label: String,
+    }
+
+    #[layout(aos)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Vertex {
+        x: i64,
+        degree: u16,
+    }
+
+    assert_eq!(
+        EdgesLayout::C_HEADER,
+        "#ifndef EDGESLAYOUT_H\n\
+         #define EDGESLAYOUT_H\n\
+         \n\
+         #include <stdbool.h>\n\
+         #include <stddef.h>\n\
+         #include <stdint.h>\n\
+         \n\
+         /* The index of a node in a EdgesLayout */\n\
+         typedef uint32_t EdgeId;\n\
+         \n\
+         typedef struct EdgesDescriptor {\n    \
+             size_t len;\n    \
+             const EdgeId *from;\n    \
+             const uint32_t *weight;\n    \
+             const void *label;\n\
+         } EdgesDescriptor;\n\
+         \n\
+         #endif /* EDGESLAYOUT_H */\n"
+    );
+    assert!(VertexsLayout::C_HEADER.contains(
+        "    size_t stride;\n    const void *data;\n    /* int64_t */\n    size_t x_offset;\n"
+    ));
+
+    let mut edges = EdgesLayout::new();
+    edges.add(Edge {
+        from: EdgeId(0),
+        weight: 7,
+        kind: 0,
+        label: String::new(),
+    });
+    let descriptor = edges.descriptor();
+    assert_eq!(descriptor.len, 1);
+    assert_eq!(unsafe { *descriptor.weight }, 7);
+
+    let mut vertices = VertexsLayout::new();
+    vertices.add(Vertex { x: -3, degree: 2 });
+    let descriptor = vertices.descriptor();
+    assert_eq!(descriptor.stride, std::mem::size_of::<Vertex>());
+    let degree = unsafe {
+        *descriptor
+            .data
+            .byte_add(descriptor.degree_offset)
+            .cast::<u16>()
+    };
+    assert_eq!(degree, 2);
+}
+
+#[test]
+fn test_flatbuffers() {
+    #[layout(soa, flatbuffers)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Edge {
+        from: EdgeId,
+        #[soa(delta)]
+        weight: u64,
+        #[soa(rle)]
+        kind: u8,
+        label: String,
+    }
+
+    #[layout(aos, flatbuffers)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Vertex {
+        x: f64,
+        marked: bool,
+    }
+
+    assert_eq!(
+        EdgesLayout::FLATBUFFERS_SCHEMA,
+        "// The FlatBuffers schema of EdgesLayout, with a vector per field\n\
+         \n\
+         table EdgesLayout {\n    \
+             from:[uint];\n    \
+             weight:[ulong];\n    \
+             kind:[ubyte];\n    \
+             label:[string];\n\
+         }\n\
+         \n\
+         root_type EdgesLayout;\n"
+    );
+
+    let mut edges = EdgesLayout::new();
+    for (index, label) in ["entry", "", "loop"].into_iter().enumerate() {
+        edges.add(Edge {
+            from: EdgeId(index as u32 / 2),
+            weight: 1 << (index * 20),
+            kind: 3,
+            label: label.to_string(),
+        });
+    }
+    let bytes = edges.to_flatbuffer();
+    assert_eq!(EdgesLayout::from_flatbuffer(&bytes).unwrap(), edges);
+
+    // The root table points back to its vtable, with an entry per field
+    let table = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+    assert_eq!(
+        i32::from_le_bytes(bytes[table..table + 4].try_into().unwrap()),
+        table as i32 - 4
+    );
+    assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), 4 + 2 * 4);
+
+    let mut vertices = VertexsLayout::new();
+    vertices.add(Vertex {
+        x: -1.5,
+        marked: true,
+    });
+    vertices.add(Vertex {
+        x: 2.0,
+        marked: false,
+    });
+    let bytes = vertices.to_flatbuffer();
+    assert_eq!(VertexsLayout::from_flatbuffer(&bytes).unwrap(), vertices);
+    assert_eq!(
+        VertexsLayout::from_flatbuffer(&VertexsLayout::new().to_flatbuffer()).unwrap(),
+        VertexsLayout::new()
+    );
+
+    // Truncated buffers and columns of different lengths are rejected
+    assert_eq!(
+        VertexsLayout::from_flatbuffer(&bytes[..bytes.len() - 1]),
+        Err(soaaos::flatbuffers::Error::OutOfBounds)
+    );
+    let mut builder = soaaos::flatbuffers::Builder::new(2);
+    builder.push_scalars(0, [1.0f64].into_iter());
+    assert_eq!(
+        VertexsLayout::from_flatbuffer(&builder.finish()),
+        Err(soaaos::flatbuffers::Error::ColumnLength {
+            field: "marked",
+            len: 0,
+            expected: 1
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "prost")]
+fn test_proto() {
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Sample {
+        id: u32,
+        #[soa(delta)]
+        delta: i32,
+        name: String,
+        parent: SampleId,
+    }
+
+    assert_eq!(
+        SamplesLayout::PROTO_SCHEMA,
+        "// The protobuf schema of SamplesLayout, with a repeated field per field\n\
+         \n\
+         syntax = \"proto3\";\n\
+         \n\
+         message SamplesLayout {\n    \
+             repeated uint32 id = 1;\n    \
+             repeated sint32 delta = 2;\n    \
+             repeated string name = 3;\n    \
+             repeated uint32 parent = 4;\n\
+         }\n"
+    );
+
+    let mut samples = SamplesLayout::new();
+    samples.add(Sample {
+        id: 1,
+        delta: -1,
+        name: "a".to_string(),
+        parent: SampleId(0),
+    });
+    samples.add(Sample {
+        id: 300,
+        delta: 2,
+        name: String::new(),
+        parent: SampleId(0),
+    });
+
+    // Scalars are packed and zigzag encoded, strings are one value each
+    let bytes = samples.to_proto();
+    assert_eq!(
+        bytes,
+        [
+            0x0a, 3, 0x01, 0xac, 0x02, //
+            0x12, 2, 0x01, 0x04, //
+            0x1a, 1, b'a', 0x1a, 0, //
+            0x22, 2, 0, 0,
+        ]
+    );
+    assert_eq!(SamplesLayout::from_proto(&bytes).unwrap(), samples);
+    assert_eq!(
+        SamplesLayout::from_proto(&[]).unwrap(),
+        SamplesLayout::new()
+    );
+
+    // Unpacked scalars and unknown fields are read as any protobuf implementation would write them
+    let unpacked = [
+        0x08, 0x01, 0x10, 0x01, 0x1a, 1, b'a', 0x20, 0, 0x28, 7, //
+        0x08, 0xac, 0x02, 0x10, 0x04, 0x1a, 0, 0x20, 0,
+    ];
+    assert_eq!(SamplesLayout::from_proto(&unpacked).unwrap(), samples);
+
+    assert!(matches!(
+        SamplesLayout::from_proto(&bytes[..bytes.len() - 1]),
+        Err(soaaos::prost::Error::Decode(_))
+    ));
+    assert_eq!(
+        SamplesLayout::from_proto(&bytes[5..]),
+        Err(soaaos::prost::Error::ColumnLength {
+            field: "delta",
+            len: 2,
+            expected: 0
+        })
+    );
+}
+
+#[test]
+#[cfg(all(feature = "rayon", feature = "prost"))]
+fn test_par_deserialize() {
+    #[layout("soa", flatbuffers)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Reading {
+        sensor: u32,
+        value: f64,
+        unit: String,
+        previous: ReadingId,
+    }
+
+    let mut readings = ReadingsLayout::new();
+    for index in 0..1000u32 {
+        readings.add(Reading {
+            sensor: index % 7,
+            value: f64::from(index) / 4.0,
+            unit: ["C", "hPa", "%"][index as usize % 3].to_string(),
+            previous: ReadingId(index.saturating_sub(1)),
+        });
+    }
+
+    // Each column is read on its own task, into the same layout as the sequential read
+    let bytes = readings.to_flatbuffer();
+    assert_eq!(
+        ReadingsLayout::par_from_flatbuffer(&bytes).unwrap(),
+        readings
+    );
+    let bytes = readings.to_proto();
+    assert_eq!(ReadingsLayout::par_from_proto(&bytes).unwrap(), readings);
+    assert_eq!(
+        ReadingsLayout::par_from_proto(&[]).unwrap(),
+        ReadingsLayout::new()
+    );
+
+    // Errors of any task are returned, and lengths are checked once every column is read
+    assert!(matches!(
+        ReadingsLayout::par_from_proto(&bytes[..bytes.len() - 1]),
+        Err(soaaos::prost::Error::Decode(_))
+    ));
+    let mut builder = soaaos::flatbuffers::Builder::new(4);
+    builder.push_scalars(0, [1u32, 2].into_iter());
+    builder.push_scalars(1, [1.0f64].into_iter());
+    assert_eq!(
+        ReadingsLayout::par_from_flatbuffer(&builder.finish()),
+        Err(soaaos::flatbuffers::Error::ColumnLength {
+            field: "value",
+            len: 1,
+            expected: 2
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn test_sqlite() {
+    use soaaos::sqlite::Connection;
+
+    #[layout(soa)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Block {
+        #[soa(delta)]
+        addr: u64,
+        name: String,
+        weight: f64,
+        parent: Option<BlockId>,
+    }
+
+    let mut blocks = BlocksLayout::new();
+    blocks.add(Block {
+        addr: 0x1000,
+        name: "entry".to_string(),
+        weight: 1.5,
+        parent: None,
+    });
+    blocks.add(Block {
+        addr: u64::MAX,
+        name: "it's".to_string(),
+        weight: -2.0,
+        parent: Some(BlockId(0)),
+    });
+
+    let conn = Connection::open_in_memory().unwrap();
+    blocks.to_sqlite(&conn, "blocks").unwrap();
+    assert_eq!(BlocksLayout::from_sqlite(&conn, "blocks").unwrap(), blocks);
+
+    // The table can be queried with plain SQL
+    let names = conn
+        .prepare("SELECT name FROM blocks WHERE parent IS NOT NULL")
+        .unwrap()
+        .query_map([], |row| row.get::<_, String>(0))
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(names, ["it's"]);
+    let addr: i64 = conn
+        .query_row("SELECT addr FROM blocks WHERE name = 'it''s'", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(addr, -1);
+
+    // The table already exists, so nothing is inserted
+    assert!(blocks.to_sqlite(&conn, "blocks").is_err());
+    assert_eq!(BlocksLayout::from_sqlite(&conn, "blocks").unwrap().len(), 2);
+    assert!(BlocksLayout::from_sqlite(&conn, "missing").is_err());
+
+    // Columns of another type are rejected instead of converted
+    conn.execute_batch(
+        "CREATE TABLE wrong (addr TEXT, name TEXT, weight REAL, parent INTEGER);\
+         INSERT INTO wrong VALUES ('a', 'b', 1.0, NULL);",
+    )
+    .unwrap();
+    assert!(BlocksLayout::from_sqlite(&conn, "wrong").is_err());
+}
+
+#[test]
+#[cfg(feature = "zerocopy")]
+fn test_zerocopy() {
+    use zerocopy::{FromBytes, IntoBytes};
+
+    #[layout(aos, zerocopy)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Header {
+        kind: u32,
+        parent: HeaderId,
+    }
+
+    let bytes = [1, 0, 0, 0, 7, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+    let headers = <[Header]>::ref_from_bytes(&bytes).unwrap();
+    assert_eq!(
+        headers[0],
+        Header {
+            kind: 1,
+            parent: HeaderId(7)
+        }
+    );
+
+    let mut layout = HeadersLayout::new();
+    for header in headers {
+        layout.add(header.clone());
+    }
+    assert_eq!(layout.data.as_bytes(), bytes);
+    assert_eq!(
+        HeaderId::read_from_bytes(&[3, 0, 0, 0]).unwrap(),
+        HeaderId(3)
+    );
+    assert_eq!(layout.get_parent(HeaderId(1)).unwrap().as_bytes(), [0; 4]);
+}
+
+#[test]
+#[cfg(feature = "fuzz")]
+fn test_fuzz() {
+    use soaaos::fuzz::{Op, check};
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, arbitrary::Arbitrary)]
+    struct Span {
+        #[soa(sparse)]
+        label: Option<String>,
+        #[soa(rle)]
+        kind: u8,
+        #[soa(group = "range")]
+        start: u32,
+        #[soa(group = "range")]
+        end: u32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, arbitrary::Arbitrary)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let span = |label: Option<&str>, kind| Span {
+        label: label.map(str::to_string),
+        kind,
+        start: kind as u32,
+        end: kind as u32 * 2,
+    };
+    let ops = vec![
+        Op::Add(span(None, 1)),
+        Op::Add(span(Some("b"), 1)),
+        Op::Add(span(None, 2)),
+        Op::Snapshot,
+        Op::Get(1),
+        Op::Get(3),
+        Op::Sort(vec![2, 0, 1]),
+        Op::Diff,
+        Op::Remove(0),
+        Op::SwapRemove(0),
+        Op::Remove(7),
+        Op::Add(span(Some("c"), 2)),
+        Op::Reverse,
+        Op::Snapshot,
+        Op::Diff,
+    ];
+    assert_eq!(check::<SpansLayout, Span>(&ops), Ok(()));
+
+    // Every sequence of bytes is a valid, possibly empty, sequence of operations
+    let data: Vec<u8> = (0..=255).chain((0..=255).rev()).collect();
+    for start in 0..64 {
+        SpansLayout::fuzz(&data[start..]);
+        PointsLayout::fuzz(&data[start * 2..]);
+    }
+}
+
+#[test]
+fn test_extend_from_layout() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Inst {
+        op: u8,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Edge {
+        from: u32,
+        to: u32,
+    }
+
+    let mut corpus = InstsLayout::new();
+    for (op, name) in [(1, "load"), (2, "add"), (3, "store")] {
+        corpus.add(Inst {
+            op,
+            name: name.to_string(),
+        });
+    }
+
+    // Only the selected rows are copied, in the given order, after the existing ones
+    let mut subset = InstsLayout::new();
+    subset.add(Inst {
+        op: 0,
+        name: "nop".to_string(),
+    });
+    let ids = subset.extend_from_layout(&corpus, &[InstId(2), InstId(0)]);
+    assert_eq!(ids, vec![InstId(1), InstId(2)]);
+    assert_eq!(
+        subset.iter().map(|inst| *inst.op).collect::<Vec<_>>(),
+        vec![0, 3, 1]
+    );
+    assert_eq!(subset.get_name(ids[0]).unwrap(), "store");
+    assert!(subset.extend_from_layout(&corpus, &[]).is_empty());
+
+    let mut edges = EdgesLayout::new();
+    edges.add(Edge { from: 0, to: 1 });
+    edges.add(Edge { from: 1, to: 2 });
+    let mut copy = EdgesLayout::new();
+    assert_eq!(
+        copy.extend_from_layout(&edges, &[EdgeId(1), EdgeId(1)]),
+        vec![EdgeId(0), EdgeId(1)]
+    );
+    assert_eq!(copy.to_rows(), vec![Edge { from: 1, to: 2 }; 2]);
+
+    // An out of bounds id panics before anything is copied
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        copy.extend_from_layout(&edges, &[EdgeId(0), EdgeId(2)])
+    }));
+    assert!(result.is_err());
+    assert_eq!(copy.len(), 2);
+}
+
+#[test]
+fn test_bulk_load() {
+    use soaaos::bulk::BulkLoadOptions;
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Trade {
+        symbol: String,
+        time: u64,
+        price: f64,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Quote {
+        bid: u32,
+        ask: u32,
+    }
+
+    let trade = |symbol: &str, time, price| Trade {
+        symbol: symbol.to_string(),
+        time,
+        price,
+    };
+    let rows = vec![
+        trade("b", 3, 1.5),
+        trade("a", 1, 2.0),
+        trade("b", 3, 9.9),
+        trade("a", 2, 2.5),
+        trade("c", 1, 7.0),
+    ];
+
+    // Deduplication keeps the first row of each key, then sorting keeps equal keys in order
+    let mut trades = TradesLayout::new();
+    let mut reports = Vec::new();
+    let options = BulkLoadOptions::new()
+        .dedup_by_key(|trade: &Trade| (trade.symbol.clone(), trade.time))
+        .sort_by_key(|trade: &Trade| trade.time)
+        .progress(3, |loaded| reports.push(loaded));
+    assert_eq!(trades.bulk_load(rows.clone(), options), 4);
+    assert_eq!(reports, vec![3, 4]);
+    assert_eq!(
+        trades.symbol().map(String::as_str).collect::<Vec<_>>(),
+        vec!["a", "c", "a", "b"]
+    );
+    assert_eq!(trades.get_price(TradeId(3)).unwrap(), &1.5);
+
+    // Without options, rows are streamed in order after the existing ones, with room reserved
+    // for the exact size of the iterator
+    assert_eq!(trades.bulk_load(rows, BulkLoadOptions::new()), 5);
+    assert_eq!(trades.len(), 9);
+    assert!(trades.symbol.capacity() >= 9);
+    assert_eq!(trades.get_time(TradeId(4)).unwrap(), &3);
+
+    let mut quotes = QuotesLayout::new();
+    let loaded = quotes.bulk_load(
+        (0..100)
+            .map(|bid| Quote { bid, ask: bid + 1 })
+            .filter(|quote| quote.bid % 2 == 0),
+        BulkLoadOptions::new().capacity(50),
+    );
+    assert_eq!(loaded, 50);
+    assert!(quotes.data.capacity() >= 50);
+    assert_eq!(quotes.get_ask(QuoteId(49)).unwrap(), &99);
+}
+
+#[test]
+fn test_extract() {
+    #[layout("soa", extract(OpArg = (op, arg1)), extract(Block = (block)))]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Inst {
+        op: u8,
+        arg1: u32,
+        arg2: u32,
+        #[soa(rle)]
+        block: u32,
+    }
+
+    #[layout("aos", extract(Bid = (bid)))]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Quote {
+        bid: u32,
+        ask: u32,
+    }
+
+    let mut insts = InstsLayout::new();
+    for i in 0..4 {
+        insts.add(Inst {
+            op: i as u8,
+            arg1: i * 10,
+            arg2: i * 100,
+            block: i / 2,
+        });
+    }
+
+    let op_args = insts.extract::<OpArg>();
+    assert_eq!(op_args.len(), 4);
+    assert_eq!(op_args.op().collect::<Vec<_>>(), vec![&0, &1, &2, &3]);
+    assert_eq!(op_args.get_arg1(OpArgId(2)).unwrap(), &20);
+    assert_eq!(op_args.to_rows()[3], OpArg { op: 3, arg1: 30 });
+
+    // Columns stored as something else than a `Vec` are copied by value
+    let blocks = insts.extract::<Block>();
+    assert_eq!(blocks.block().collect::<Vec<_>>(), vec![&0, &0, &1, &1]);
+
+    let mut quotes = QuotesLayout::new();
+    for bid in 0..3 {
+        quotes.add(Quote { bid, ask: bid + 1 });
+    }
+    let bids = quotes.extract::<Bid>();
+    assert_eq!(
+        bids.data,
+        vec![Bid { bid: 0 }, Bid { bid: 1 }, Bid { bid: 2 }]
+    );
+}
+
+#[test]
+fn test_into_columns() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Inst<R> {
+        op: u8,
+        arg: R,
+        #[soa(rle)]
+        block: u32,
+    }
+
+    let mut insts = InstsLayout::new();
+    insts.add(Inst {
+        op: 1,
+        arg: "a".to_string(),
+        block: 0,
+    });
+    insts.add(Inst {
+        op: 2,
+        arg: "b".to_string(),
+        block: 0,
+    });
+    let arg_ptr = insts.arg.as_ptr();
+
+    // The `Vec` columns are moved out without copying them
+    let columns = insts.into_columns();
+    assert_eq!(columns.op, vec![1, 2]);
+    assert_eq!(columns.arg, vec!["a", "b"]);
+    assert_eq!(columns.arg.as_ptr(), arg_ptr);
+    assert_eq!(columns.block, vec![0, 0]);
+
+    let insts = InstsLayout::from_columns(columns);
+    assert_eq!(insts.arg.as_ptr(), arg_ptr);
+    assert_eq!(
+        insts.to_rows()[1],
+        Inst {
+            op: 2,
+            arg: "b".to_string(),
+            block: 0,
+        }
+    );
+
+    let result = std::panic::catch_unwind(|| {
+        InstsLayout::from_columns(InstsColumns {
+            op: vec![1],
+            arg: vec![0u32; 2],
+            block: vec![0],
+        })
+    });
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_filter_map_field() {
+    use std::ops::ControlFlow;
+
+    #[layout("soa")]
+    struct Inst {
+        op: u8,
+        #[soa(delta)]
+        addr: u64,
+    }
+
+    #[layout("aos")]
+    struct Edge {
+        weight: u32,
+    }
+
+    let mut insts = InstsLayout::new();
+    for (op, addr) in [(1, 0x10), (2, 0x14), (1, 0x18), (0, 0x1c), (1, 0x20)] {
+        insts.add(Inst { op, addr });
+    }
+
+    // Every `op == 1` until the first `op == 0`, with the number of nodes scanned
+    let mut scanned = 0;
+    let loads: Vec<_> = insts
+        .filter_map_op(|_, op| {
+            scanned += 1;
+            match op {
+                0 => ControlFlow::Break(()),
+                1 => ControlFlow::Continue(Some(*op as u32 * 10)),
+                _ => ControlFlow::Continue(None),
+            }
+        })
+        .collect();
+    assert_eq!(loads, vec![(InstId(0), 10), (InstId(2), 10)]);
+    assert_eq!(scanned, 4);
+
+    // By-value columns are handed out by value
+    let first_past = insts
+        .filter_map_addr(|id, addr| ControlFlow::Continue((addr > 0x14).then_some(id)))
+        .next();
+    assert_eq!(first_past, Some((InstId(2), InstId(2))));
+
+    let mut edges = EdgesLayout::new();
+    edges.add(Edge { weight: 3 });
+    edges.add(Edge { weight: 8 });
+    assert_eq!(
+        edges
+            .filter_map_weight(|_, weight| ControlFlow::Continue(Some(*weight)))
+            .map(|(_, weight)| weight)
+            .sum::<u32>(),
+        11
+    );
+}
+
+#[test]
+fn test_with_field_mut() {
+    #[layout("soa")]
+    struct Node<R> {
+        #[soa(page_aligned)]
+        rank: f32,
+        out_degree: u32,
+        #[soa(rle)]
+        kind: u8,
+        label: R,
+    }
+
+    let mut nodes = NodesLayout::new();
+    for (out_degree, kind) in [(1, 0), (2, 0), (4, 1)] {
+        nodes.add(Node {
+            rank: 1.0,
+            out_degree,
+            kind,
+            label: "node",
+        });
+    }
+
+    // Write one column while reading the others
+    let kinds = nodes.with_rank_mut(|rank, rest: NodesWithoutRank<&str>| {
+        for (rank, out_degree) in rank.iter_mut().zip(rest.out_degree) {
+            *rank /= *out_degree as f32;
+        }
+        assert_eq!(rest.label, &vec!["node"; 3]);
+        *rest.kind.get(2).unwrap()
+    });
+    assert_eq!(kinds, 1);
+    assert_eq!(
+        nodes.rank().copied().collect::<Vec<_>>(),
+        vec![1.0, 0.5, 0.25]
+    );
+
+    nodes.with_out_degree_mut(|out_degree, rest| {
+        out_degree.copy_from_slice(&[3, 2, 1]);
+        assert_eq!(rest.rank.len(), 3);
+    });
+    assert_eq!(nodes.get_out_degree(NodeId(0)).unwrap(), &3);
+}
+
+#[test]
+fn test_update() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Particle {
+        pos: f32,
+        vel: f32,
+        #[soa(lazy)]
+        hits: u32,
+        #[soa(rle)]
+        kind: u8,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Body {
+        pos: f32,
+        vel: f32,
+    }
+
+    let mut particles = ParticlesLayout::new();
+    let mut bodies = BodysLayout::new();
+    for index in 0..3 {
+        particles.add(Particle {
+            pos: index as f32,
+            vel: 1.0,
+            hits: 0,
+            kind: 1,
+        });
+        bodies.add(Body {
+            pos: index as f32,
+            vel: 1.0,
+        });
+    }
+
+    let moved = particles
+        .update(ParticleId(1), |particle| {
+            *particle.pos += *particle.vel;
+            *particle.hits += 1;
+            *particle.pos
+        })
+        .unwrap();
+    assert_eq!(moved, 2.0);
+    assert_eq!(*particles.get_hits(ParticleId(1)).unwrap(), 1);
+    assert!(particles.update(ParticleId(3), |_| ()).is_err());
+
+    bodies
+        .update(BodyId(2), |body| *body.vel = -*body.pos)
+        .unwrap();
+    assert_eq!(*bodies.get(BodyId(2)).unwrap().vel, -2.0);
+    assert!(bodies.update(BodyId(3), |_| ()).is_err());
+}
+
+#[test]
+fn test_view_conversions() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Word {
+        text: String,
+        hits: u32,
+    }
+
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Sample {
+        #[soa(delta)]
+        time: u64,
+        #[soa(frozen)]
+        source: String,
+        value: f32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Pixel {
+        x: u16,
+        y: u16,
+    }
+
+    let word = |text: &str, hits| Word {
+        text: text.to_string(),
+        hits,
+    };
+    let mut words = WordsLayout::new();
+    let a = words.add(word("a", 1));
+    words.add(word("b", 2));
+
+    let owned: Vec<Word> = words.iter().map(|view| view.to_owned()).collect();
+    assert_eq!(owned, words.to_rows());
+
+    let old = words.get_mut(a).unwrap().replace(word("c", 3));
+    assert_eq!(old, word("a", 1));
+    assert_eq!(words.get(a).unwrap().to_owned(), word("c", 3));
+
+    // Fields read by value are cloned too
+    let mut samples = SamplesLayout::new();
+    let id = samples.add(Sample {
+        time: 40,
+        source: "probe".to_string(),
+        value: 0.5,
+    });
+    assert_eq!(
+        samples.get(id).unwrap().to_owned(),
+        Sample {
+            time: 40,
+            source: "probe".to_string(),
+            value: 0.5,
+        }
+    );
+
+    let mut pixels = PixelsLayout::new();
+    let id = pixels.add(Pixel { x: 1, y: 2 });
+    let old = pixels.get_mut(id).unwrap().replace(Pixel { x: 3, y: 4 });
+    assert_eq!(old, Pixel { x: 1, y: 2 });
+    assert_eq!(pixels.get(id).unwrap().to_owned(), Pixel { x: 3, y: 4 });
+}
+
+#[test]
+fn test_view_display() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Request {
+        path: String,
+        #[soa(delta)]
+        status: u16,
+        millis: f32,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let mut requests = RequestsLayout::new();
+    let id = requests.add(Request {
+        path: "/index".to_string(),
+        status: 200,
+        millis: 1.5,
+    });
+    assert_eq!(
+        requests.get(id).unwrap().to_string(),
+        "path=/index status=200 millis=1.5"
+    );
+    assert_eq!(
+        format!("{}", requests.get_mut(id).unwrap()),
+        "path=/index millis=1.5"
+    );
+
+    let mut points = PointsLayout::new();
+    points.add(Point { x: 1, y: -2 });
+    let lines: Vec<String> = points.iter().map(|point| point.to_string()).collect();
+    assert_eq!(lines, vec!["x=1 y=-2"]);
+}
+
+#[test]
+fn test_link_fields() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Node {
+        op: u8,
+        inputs: [NodeId; 2],
+        uses: [Option<NodeId>; 2],
+    }
+
+    #[layout("aos")]
+    struct Block {
+        succs: [Option<BlockId>; 2],
+    }
+
+    let mut nodes = NodesLayout::new();
+    let a = nodes.add(Node {
+        op: 1,
+        inputs: [NodeId(0), NodeId(0)],
+        uses: [Some(NodeId(2)), None],
+    });
+    let b = nodes.add(Node {
+        op: 1,
+        inputs: [NodeId(0), NodeId(0)],
+        uses: [Some(NodeId(2)), None],
+    });
+    let c = nodes.add(Node {
+        op: 2,
+        inputs: [a, b],
+        uses: [None, None],
+    });
+    assert_eq!(nodes.neighbors(c).unwrap().collect::<Vec<_>>(), vec![a, b]);
+    assert_eq!(
+        nodes.neighbors(a).unwrap().collect::<Vec<_>>(),
+        vec![a, a, c]
+    );
+    assert!(nodes.neighbors(NodeId(3)).is_err());
+
+    // After merging the duplicate `b` into `a`, the links follow the remap
+    let remap = nodes.dedup_rows();
+    nodes.remap_links(&remap);
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(
+        nodes.neighbors(NodeId(1)).unwrap().collect::<Vec<_>>(),
+        vec![a, a]
+    );
+    assert_eq!(nodes.get_uses(NodeId(0)).unwrap(), &[Some(NodeId(1)), None]);
+
+    let mut blocks = BlocksLayout::new();
+    blocks.add(Block {
+        succs: [Some(BlockId(1)), None],
+    });
+    blocks.add(Block { succs: [None; 2] });
+    let mut swapped = BlocksIdMap::identity(2);
+    swapped.insert(BlockId(0), Some(BlockId(1)));
+    swapped.insert(BlockId(1), Some(BlockId(0)));
+    blocks.remap_links(&swapped);
+    assert_eq!(
+        blocks.neighbors(BlockId(0)).unwrap().collect::<Vec<_>>(),
+        vec![BlockId(0)]
+    );
+}
+
+#[test]
+fn test_id_map() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Node {
+        #[soa(rle)]
+        op: u8,
+        next: Option<NodeId>,
+        inputs: [NodeId; 1],
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Edge {
+        to: u32,
+    }
+
+    let mut nodes = NodesLayout::new();
+    for (op, next) in [(0, Some(1)), (1, Some(2)), (2, Some(3)), (3, None)] {
+        nodes.add(Node {
+            op,
+            next: next.map(NodeId),
+            inputs: [NodeId(0)],
+        });
+    }
+
+    // The last node moves into the place of the removed one
+    let (removed, map) = nodes.swap_remove(NodeId(1)).unwrap();
+    assert_eq!(removed.op, 1);
+    assert_eq!(map.remap(NodeId(1)), None);
+    assert_eq!(map.remap(NodeId(3)), Some(NodeId(1)));
+    assert_eq!(map.remap(NodeId(2)), Some(NodeId(2)));
+    assert_eq!(map.remap(NodeId(4)), None);
+    assert_eq!(
+        map.remap_slice(&[NodeId(3), NodeId(1), NodeId(0)]),
+        vec![NodeId(1), NodeId(0)]
+    );
+
+    nodes.apply_id_map(&map);
+    assert_eq!(
+        nodes
+            .get_next_enumerated()
+            .map(|(_, next)| *next)
+            .collect::<Vec<_>>(),
+        vec![None, None, Some(NodeId(1))]
+    );
+    assert_eq!(nodes.op().copied().collect::<Vec<_>>(), vec![0, 3, 2]);
+
+    // Keeping some nodes shifts the later ones down
+    for index in 0..3 {
+        nodes.set_inputs(NodeId(index), [NodeId(2)]).unwrap();
+    }
+    let map = nodes.retain(|node| *node.op != 0);
+    assert_eq!(map.remap(NodeId(0)), None);
+    assert_eq!(map.remap(NodeId(2)), Some(NodeId(1)));
+    nodes.apply_id_map(&map);
+    assert_eq!(nodes.get_next(NodeId(1)).unwrap(), &Some(NodeId(0)));
+    assert_eq!(nodes.get_inputs(NodeId(0)).unwrap(), &[NodeId(1)]);
+    assert!(nodes.retain(|_| true).is_identity());
+
+    let mut edges = EdgesLayout::new();
+    for to in 0..3 {
+        edges.add(Edge { to });
+    }
+    let (removed, map) = edges.swap_remove(EdgeId(2)).unwrap();
+    assert_eq!(removed, Edge { to: 2 });
+    assert!(map.remap(EdgeId(2)).is_none());
+    assert!(edges.swap_remove(EdgeId(2)).is_err());
+    let map = edges.retain(|edge| *edge.to == 1);
+    assert_eq!(map.remap(EdgeId(1)), Some(EdgeId(0)));
+    assert_eq!(edges.to_rows(), vec![Edge { to: 1 }]);
+
+    // Every other operation moving nodes returns its map too, each following the node to its
+    // new Id
+    let mut edges = EdgesLayout::new();
+    for to in 0..5 {
+        edges.add(Edge { to });
+    }
+    let follows = |edges: &EdgesLayout, map: &EdgesIdMap, before: &[u32]| {
+        for (old, to) in before.iter().enumerate() {
+            if let Some(new) = map.remap(EdgeId(old as u32)) {
+                assert_eq!(edges.get_to(new).unwrap(), to);
+            }
+        }
+    };
+    let ops: [fn(&mut EdgesLayout) -> EdgesIdMap; 8] = [
+        |edges| edges.remove(EdgeId(1)).unwrap().1,
+        |edges| edges.reverse(),
+        |edges| edges.rotate_left(1),
+        |edges| edges.rotate_right(2),
+        |edges| edges.reorder(&[EdgeId(1), EdgeId(0), EdgeId(3), EdgeId(2)]),
+        |edges| edges.sort_by_to(),
+        |edges| edges.sort_unstable_by_to(),
+        |edges| edges.sort_by_key(|edge| core::cmp::Reverse(*edge.to)),
+    ];
+    for op in ops {
+        let before: Vec<u32> = edges.to().copied().collect();
+        let map = op(&mut edges);
+        follows(&edges, &map, &before);
+    }
+    assert_eq!(edges.to().copied().collect::<Vec<_>>(), vec![4, 3, 2, 0]);
+
+    // Maps compose, following a node through several operations
+    let before: Vec<u32> = edges.to().copied().collect();
+    let map = edges.reverse().then(&edges.remove(EdgeId(0)).unwrap().1);
+    assert_eq!(map.remap(EdgeId(3)), None);
+    follows(&edges, &map, &before);
+    assert_eq!(
+        map.remap_slice(&[EdgeId(0), EdgeId(1), EdgeId(2)]),
+        vec![EdgeId(2), EdgeId(1), EdgeId(0)]
+    );
+}
+
+#[test]
+fn test_to_dot() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Block {
+        name: String,
+        succs: [Option<BlockId>; 2],
+        #[soa(rle)]
+        idom: Option<BlockId>,
+    }
+
+    let mut blocks = BlocksLayout::new();
+    let entry = blocks.add(Block {
+        name: "entry".to_string(),
+        succs: [Some(BlockId(1)), Some(BlockId(2))],
+        idom: None,
+    });
+    blocks.add(Block {
+        name: "\"then\"".to_string(),
+        succs: [None, Some(BlockId(2))],
+        idom: Some(entry),
+    });
+    blocks.add(Block {
+        name: "exit".to_string(),
+        succs: [None, None],
+        idom: Some(entry),
+    });
+
+    let mut dot = Vec::new();
+    blocks.to_dot(&mut dot, |block| block.name.clone()).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "digraph BlocksLayout {\n    \
+             n0 [label=\"entry\"];\n    \
+             n1 [label=\"\\\"then\\\"\"];\n    \
+             n2 [label=\"exit\"];\n    \
+             n0 -> n1 [label=\"succs[0]\"];\n    \
+             n0 -> n2 [label=\"succs[1]\"];\n    \
+             n1 -> n2 [label=\"succs[1]\"];\n    \
+             n1 -> n0 [label=\"idom\"];\n    \
+             n2 -> n0 [label=\"idom\"];\n\
+         }\n"
+    );
+}
+
+#[test]
+#[cfg(feature = "rayon")]
+fn test_par_sort() {
+    #[layout("soa")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Event {
+        time: u64,
+        kind: u8,
+        name: String,
+    }
+
+    #[layout("aos")]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Func {
+        name: String,
+        size: u32,
+    }
+
+    let mut events = EventsLayout::new();
+    let mut funcs = FuncsLayout::new();
+    for (time, kind) in [(30u64, 1u8), (10, 2), (20, 1), (10, 1)] {
+        events.add(Event {
+            time,
+            kind,
+            name: format!("e{time}_{kind}"),
+        });
+        funcs.add(Func {
+            name: format!("f{time}_{kind}"),
+            size: time as u32 * kind as u32,
+        });
+    }
+
+    // Every column follows the parallel sort, keeping equal times in insertion order
+    events.par_sort_by_time();
+    let rows: Vec<_> = events
+        .iter()
+        .map(|event| (*event.time, *event.kind, event.name.clone()))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            (10, 2, "e10_2".to_string()),
+            (10, 1, "e10_1".to_string()),
+            (20, 1, "e20_1".to_string()),
+            (30, 1, "e30_1".to_string()),
+        ]
+    );
+    events.par_sort_by_name();
+    let kinds: Vec<_> = events.iter().map(|event| *event.kind).collect();
+    assert_eq!(kinds, vec![1, 2, 1, 1]);
+
+    funcs.par_sort_by_size();
+    let sizes: Vec<_> = funcs.iter().map(|func| *func.size).collect();
+    assert_eq!(sizes, vec![10, 20, 20, 30]);
+    assert_eq!(funcs.get(FuncId(1)).unwrap().name, "f10_2");
+}
@@ -0,0 +1,90 @@
+//! Round trips of layouts through the messages of a prost `Message` of their `PROTO_SCHEMA`
+
+#![cfg(feature = "prost")]
+
+use prost::Message;
+use soaaos::layout;
+
+#[test]
+fn test_prost_round_trip() {
+    #[layout(aos)]
+    #[derive(Debug, Clone, PartialEq)]
+    struct Reading {
+        sensor: u8,
+        offset: i16,
+        time: u64,
+        drift: i64,
+        value: f32,
+        total: f64,
+        valid: bool,
+        unit: String,
+        previous: ReadingId,
+    }
+
+    // The message prost generates for `PROTO_SCHEMA`
+    #[derive(Clone, PartialEq, Message)]
+    struct ReadingsMessage {
+        #[prost(uint32, repeated, tag = "1")]
+        sensor: Vec<u32>,
+        #[prost(sint32, repeated, tag = "2")]
+        offset: Vec<i32>,
+        #[prost(uint64, repeated, tag = "3")]
+        time: Vec<u64>,
+        #[prost(sint64, repeated, tag = "4")]
+        drift: Vec<i64>,
+        #[prost(float, repeated, tag = "5")]
+        value: Vec<f32>,
+        #[prost(double, repeated, tag = "6")]
+        total: Vec<f64>,
+        #[prost(bool, repeated, tag = "7")]
+        valid: Vec<bool>,
+        #[prost(string, repeated, tag = "8")]
+        unit: Vec<String>,
+        #[prost(uint32, repeated, tag = "9")]
+        previous: Vec<u32>,
+    }
+
+    let mut readings = ReadingsLayout::new();
+    for index in 0..300u32 {
+        readings.add(Reading {
+            sensor: index as u8,
+            offset: -(index as i16) * 100,
+            time: u64::MAX - u64::from(index),
+            drift: i64::MIN + i64::from(index),
+            value: index as f32 / 3.0,
+            total: -f64::from(index) * 1e10,
+            valid: index % 3 == 0,
+            unit: ["C", "", "hPa"][index as usize % 3].to_string(),
+            previous: ReadingId(index.saturating_sub(1)),
+        });
+    }
+    let message = ReadingsMessage {
+        sensor: (0..300).map(|index| index % 256).collect(),
+        offset: (0..300).map(|index| -index * 100).collect(),
+        time: (0..300).map(|index| u64::MAX - index).collect(),
+        drift: (0..300).map(|index| i64::MIN + index).collect(),
+        value: (0..300).map(|index| index as f32 / 3.0).collect(),
+        total: (0..300).map(|index| -f64::from(index) * 1e10).collect(),
+        valid: (0..300).map(|index| index % 3 == 0).collect(),
+        unit: (0..300)
+            .map(|index| ["C", "", "hPa"][index % 3].to_string())
+            .collect(),
+        previous: (0..300u32).map(|index| index.saturating_sub(1)).collect(),
+    };
+
+    // The layout and the message encode the same bytes, and each decodes the bytes of the other
+    let bytes = message.encode_to_vec();
+    assert_eq!(readings.to_proto(), bytes);
+    assert_eq!(ReadingsLayout::from_proto(&bytes).unwrap(), readings);
+    assert_eq!(
+        ReadingsMessage::decode(readings.to_proto().as_slice()).unwrap(),
+        message
+    );
+
+    // An empty layout is an empty message
+    assert!(ReadingsLayout::new().to_proto().is_empty());
+    assert_eq!(
+        ReadingsLayout::from_proto(&ReadingsMessage::default().encode_to_vec()).unwrap(),
+        ReadingsLayout::new()
+    );
+}